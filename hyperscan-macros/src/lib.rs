@@ -0,0 +1,163 @@
+//! Compile time checking of Hyperscan patterns.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! extern crate hyperscan;
+//! #[macro_use]
+//! extern crate hyperscan_macros;
+//!
+//! use hyperscan::*;
+//!
+//! fn main() {
+//!     let db: BlockDatabase = pattern!{verify!("test\\d+"), flags => HS_FLAG_CASELESS}.build().unwrap();
+//!     let pattern: Pattern = verify!("1:/foo.*bar/is").parse().unwrap();
+//! }
+//! ```
+//!
+//! A typo in the expression, like `verify!("test(\\d+")`, fails the build of the crate
+//! with the message reported by the Hyperscan compiler.
+
+extern crate proc_macro;
+extern crate hyperscan;
+
+use std::str::FromStr;
+
+use proc_macro::{TokenStream, TokenTree};
+
+use hyperscan::{Pattern, Expression};
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e {
+        Some(v) => v,
+        None => return None,
+    })
+}
+
+/// Validate the syntax of a pattern literal at build time, and expand to the literal itself.
+///
+/// The literal is parsed with `Pattern::parse`, so both a bare expression and
+/// the `id:/expression/flags` form are accepted.
+#[proc_macro]
+pub fn verify(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+
+    let literal = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal,
+        (Some(TokenTree::Group(ref group)), None) if group.delimiter() == proc_macro::Delimiter::None => {
+            return verify(group.stream());
+        }
+        _ => return compile_error("verify! expects a single string literal"),
+    };
+
+    let source = literal.to_string();
+
+    let s = match unquote(&source) {
+        Some(s) => s,
+        None => return compile_error("verify! expects a string literal"),
+    };
+
+    match check(&s) {
+        Ok(_) => TokenStream::from(TokenTree::Literal(literal)),
+        Err(msg) => compile_error(&msg),
+    }
+}
+
+/// Parse the pattern and analyse it with `hs_expression_info`.
+fn check(s: &str) -> Result<Pattern, String> {
+    let pattern = try!(Pattern::parse(s).map_err(|err| format!("invalid pattern `{}`, {}", s, err)));
+
+    try!(pattern.info().map_err(|err| format!("invalid pattern `{}`, {}", s, err)));
+
+    Ok(pattern)
+}
+
+fn compile_error(msg: &str) -> TokenStream {
+    TokenStream::from_str(&format!("compile_error!({:?})", msg)).unwrap()
+}
+
+/// Decode the source form of a string literal, either a normal or a raw string.
+fn unquote(source: &str) -> Option<String> {
+    if source.starts_with('r') {
+        let hashes = source[1..].chars().take_while(|&c| c == '#').count();
+        let start = 1 + hashes + 1;
+        let end = source.len() - hashes - 1;
+
+        if source.len() < start + hashes + 1 || !source[start - 1..].starts_with('"') || !source[end..].starts_with('"') {
+            return None;
+        }
+
+        return Some(String::from(&source[start..end]));
+    }
+
+    if source.len() < 2 || !source.starts_with('"') || !source.ends_with('"') {
+        return None;
+    }
+
+    let mut s = String::with_capacity(source.len());
+    let mut chars = source[1..source.len() - 1].chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => s.push('\n'),
+            Some('r') => s.push('\r'),
+            Some('t') => s.push('\t'),
+            Some('0') => s.push('\0'),
+            Some('\\') => s.push('\\'),
+            Some('\'') => s.push('\''),
+            Some('"') => s.push('"'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+
+                s.push(try_opt!(u8::from_str_radix(&hex, 16).ok()) as char);
+            }
+            Some('u') => {
+                if chars.next() != Some('{') {
+                    return None;
+                }
+
+                let hex: String = chars.by_ref().take_while(|&c| c != '}').filter(|&c| c != '_').collect();
+                let code = try_opt!(u32::from_str_radix(&hex, 16).ok());
+
+                s.push(try_opt!(std::char::from_u32(code)));
+            }
+            Some('\n') => {
+                while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    Some(s)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote(r#""test""#), Some(String::from("test")));
+        assert_eq!(unquote(r#""test\\d+\n""#), Some(String::from("test\\d+\n")));
+        assert_eq!(unquote(r#""\x41\u{e9}\"""#), Some(String::from("Aé\"")));
+        assert_eq!(unquote(r#"r"test\d+""#), Some(String::from("test\\d+")));
+        assert_eq!(unquote(r###"r##"a"#b"##"###), Some(String::from("a\"#b")));
+        assert_eq!(unquote(r#"b"test""#), None);
+        assert_eq!(unquote("42"), None);
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check("test\\d+").unwrap().expression, "test\\d+");
+        assert_eq!(check("1:/foo.*bar/is").unwrap().id, 1);
+        assert!(check("test(\\d+").is_err());
+        assert!(check("/test/z").is_err());
+    }
+}
@@ -0,0 +1,43 @@
+/* C API of the hyperscan crate, built with the `capi` feature. */
+
+#ifndef HSRS_H
+#define HSRS_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The functions return the HS_* error codes of hs_common.h. */
+
+typedef struct hsrs_database hsrs_database;
+
+/* Called for each match, a non-zero return value stops the scan. */
+typedef int (*hsrs_match_callback)(unsigned int id,
+                                   unsigned long long from,
+                                   unsigned long long to,
+                                   void *context);
+
+/* Compile the patterns, one `id:/expression/flags` per line, to a block database.
+ *
+ * On failure, the message is stored in `error` unless it is NULL, and must be freed with hsrs_free_error.
+ */
+int hsrs_compile(const char *patterns, hsrs_database **db, char **error);
+
+/* Scan a buffer, the database may be scanned from several threads at once. */
+int hsrs_scan(const hsrs_database *db,
+              const char *data,
+              size_t length,
+              hsrs_match_callback on_match,
+              void *context);
+
+void hsrs_free_database(hsrs_database *db);
+
+void hsrs_free_error(char *error);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* HSRS_H */
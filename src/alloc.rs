@@ -0,0 +1,181 @@
+use std::sync::{Arc, Once, RwLock};
+use std::os::raw::c_void;
+
+use libc;
+
+use raw::*;
+use constants::*;
+use errors::Error;
+
+/// The kind of memory Hyperscan failed to allocate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AllocDomain {
+    /// Database bytecode, allocated by the compile and deserialize calls.
+    Database,
+    /// Scratch space, allocated by `hs_alloc_scratch` and `hs_clone_scratch`.
+    Scratch,
+    /// Stream state, allocated by `hs_open_stream` and `hs_copy_stream`.
+    Stream,
+    /// Other data structures, like the information strings and compile errors.
+    Misc,
+}
+
+type Handler = Arc<Fn(AllocDomain, usize) -> bool + Send + Sync>;
+
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+static INSTALL: Once = Once::new();
+
+fn handler() -> Option<Handler> {
+    HANDLER.read().unwrap().clone()
+}
+
+/// Allocate memory, invoking the handler until the allocation succeeds or the handler gives up.
+unsafe fn allocate(domain: AllocDomain, size: usize, malloc: unsafe fn(usize) -> *mut c_void) -> *mut c_void {
+    loop {
+        let p = malloc(size);
+
+        if !p.is_null() {
+            return p;
+        }
+
+        warn!("fail to allocate {} bytes for {:?}", size, domain);
+
+        match handler() {
+            Some(ref handler) if handler(domain, size) => continue,
+            _ => return p,
+        }
+    }
+}
+
+unsafe fn malloc(size: usize) -> *mut c_void {
+    libc::malloc(size)
+}
+
+unsafe extern "C" fn free(p: *mut c_void) {
+    libc::free(p)
+}
+
+unsafe extern "C" fn alloc_database(size: usize) -> *mut c_void {
+    allocate(AllocDomain::Database, size, malloc)
+}
+
+unsafe extern "C" fn alloc_scratch(size: usize) -> *mut c_void {
+    allocate(AllocDomain::Scratch, size, malloc)
+}
+
+unsafe extern "C" fn alloc_stream(size: usize) -> *mut c_void {
+    allocate(AllocDomain::Stream, size, malloc)
+}
+
+unsafe extern "C" fn alloc_misc(size: usize) -> *mut c_void {
+    allocate(AllocDomain::Misc, size, malloc)
+}
+
+/// Set a handler invoked when Hyperscan fails to allocate memory,
+/// with the domain and the requested size in bytes.
+///
+/// When the handler returns `true`, the allocation is retried, so a flow manager can
+/// shed load, by closing idle streams for example, and let the call succeed.
+/// Otherwise the call fails with `Error::NoMem`.
+///
+/// The handler is invoked from within the failing Hyperscan call, it must not use
+/// the stream or the scratch space of that call.
+///
+/// The first call replaces the Hyperscan allocators with `malloc` based ones,
+/// so it should be done before any database, scratch or stream is allocated.
+pub fn set_alloc_failure_handler<F>(handler: F) -> Result<(), Error>
+    where F: Fn(AllocDomain, usize) -> bool + Send + Sync + 'static
+{
+    let mut result = Ok(());
+
+    INSTALL.call_once(|| unsafe {
+        for &(set, alloc) in &[(hs_set_database_allocator as unsafe extern "C" fn(hs_alloc_t, hs_free_t) -> hs_error_t,
+                                alloc_database as unsafe extern "C" fn(usize) -> *mut c_void),
+                               (hs_set_scratch_allocator, alloc_scratch),
+                               (hs_set_stream_allocator, alloc_stream),
+                               (hs_set_misc_allocator, alloc_misc)] {
+            let ret = set(Some(alloc), Some(free));
+
+            if ret != HS_SUCCESS {
+                result = Err(Error::from(ret));
+            }
+        }
+    });
+
+    try!(result);
+
+    *HANDLER.write().unwrap() = Some(Arc::new(handler));
+
+    Ok(())
+}
+
+/// Remove the allocation failure handler, allocation failures are reported immediately.
+pub fn clear_alloc_failure_handler() {
+    *HANDLER.write().unwrap() = None;
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::ptr;
+    use std::cell::Cell;
+    use std::os::raw::c_void;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::allocate;
+    use super::super::*;
+
+    thread_local! {
+        static FAILURES: Cell<usize> = Cell::new(0);
+    }
+
+    unsafe fn failing_malloc(size: usize) -> *mut c_void {
+        if FAILURES.with(|n| n.get()) > 0 {
+            FAILURES.with(|n| n.set(n.get() - 1));
+
+            ptr::null_mut()
+        } else {
+            ::libc::malloc(size)
+        }
+    }
+
+    #[test]
+    fn test_alloc_failure_handler() {
+        let _ = env_logger::init();
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        set_alloc_failure_handler(|domain, size| {
+                assert_eq!(domain, AllocDomain::Stream);
+                assert_eq!(size, 64);
+
+                CALLS.fetch_add(1, Ordering::SeqCst) == 0
+            })
+            .unwrap();
+
+        unsafe {
+            FAILURES.with(|n| n.set(1));
+
+            let p = allocate(AllocDomain::Stream, 64, failing_malloc);
+
+            assert!(!p.is_null());
+            assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+            ::libc::free(p);
+
+            FAILURES.with(|n| n.set(1));
+
+            assert!(allocate(AllocDomain::Stream, 64, failing_malloc).is_null());
+            assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        }
+
+        clear_alloc_failure_handler();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+        let st = db.open_stream(0).unwrap();
+
+        st.close(&s, None, None::<&()>).unwrap();
+    }
+}
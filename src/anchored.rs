@@ -0,0 +1,86 @@
+use std::ops::Deref;
+
+use api::*;
+use errors::Error;
+use compile::Patterns;
+use common::BlockDatabase;
+
+/// A block database whose patterns can only match at the start of the data.
+///
+/// Every pattern is rewritten with `Pattern::anchored` before compiling,
+/// which is what protocol detectors usually want when looking at the first bytes of a payload.
+#[derive(Debug)]
+pub struct AnchoredDatabase(BlockDatabase);
+
+impl AnchoredDatabase {
+    /// Compile the anchored variant of the patterns for the given platform.
+    pub fn compile(patterns: &Patterns, platform: &PlatformInfo) -> Result<AnchoredDatabase, Error> {
+        let anchored: Patterns = patterns.iter().map(|p| p.anchored()).collect();
+
+        Ok(AnchoredDatabase(try!(anchored.build_for_platform(platform))))
+    }
+
+    /// Scan the data, reporting only the matches that start at offset 0.
+    pub fn scan_anchored<T: Scannable, S: Scratch, D>(&self,
+                                                      data: T,
+                                                      scratch: &S,
+                                                      callback: Option<MatchEventCallback<D>>,
+                                                      context: Option<&D>)
+                                                      -> Result<&Self, Error> {
+        try!(self.0.scan(data, 0, scratch, callback, context));
+
+        Ok(self)
+    }
+
+    /// Check whether any pattern matches a prefix of the data.
+    ///
+    /// The scan is terminated as soon as the first match is found.
+    pub fn is_prefix_match<T: Scannable, S: Scratch>(&self, data: T, scratch: &S) -> Result<bool, Error> {
+        fn on_match(_: u32, _: u64, _: u64, _: u32, _: &()) -> u32 {
+            1
+        }
+
+        match self.0.scan(data, 0, scratch, Some(on_match), Some(&())) {
+            Ok(_) => Ok(false),
+            Err(Error::ScanTerminated) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Deref for AnchoredDatabase {
+    type Target = BlockDatabase;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_anchored_database() {
+        let _ = env_logger::init();
+
+        let db = AnchoredDatabase::compile(&patterns!(["GET ", "POST "]), &PlatformInfo::null()).unwrap();
+        let s = db.alloc().unwrap();
+
+        assert!(db.is_prefix_match("GET / HTTP/1.1", &s).unwrap());
+        assert!(db.is_prefix_match("POST /form HTTP/1.1", &s).unwrap());
+        assert!(!db.is_prefix_match("HTTP/1.1 200 OK GET ", &s).unwrap());
+
+        fn callback(id: u32, _: u64, to: u64, _: u32, _: &()) -> u32 {
+            assert_eq!(id, 1);
+            assert_eq!(to, 4);
+
+            0
+        }
+
+        db.scan_anchored("GET / GET ", &s, Some(callback), Some(&())).unwrap();
+    }
+}
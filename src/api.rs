@@ -3,8 +3,10 @@ use std::fmt;
 use std::mem;
 use std::cell::RefCell;
 use std::ops::Deref;
+use std::str::FromStr;
 use std::os::raw::c_char;
 use std::ffi::CStr;
+use std::io::IoSlice;
 
 use libc;
 
@@ -69,6 +71,104 @@ impl Type for Vectored {
 /// Raw `Database` pointer
 pub type RawDatabasePtr = *mut hs_database_t;
 
+/// The version of Hyperscan which compiled a database.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Version {
+    /// The version of the Hyperscan library linked at runtime.
+    pub fn library() -> Result<Version, Error> {
+        let version = unsafe { CStr::from_ptr(hs_version()) };
+
+        match version.to_str() {
+            Ok(s) => s.split_whitespace().next().unwrap_or_default().parse(),
+            Err(_) => Err(Error::Invalid),
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, '.');
+        let mut next = || parts.next().ok_or(Error::Invalid).and_then(|n| n.parse().map_err(Error::from));
+
+        Ok(Version {
+            major: try!(next()),
+            minor: try!(next()),
+            patch: try!(next()),
+        })
+    }
+}
+
+/// Information about a database, parsed from the string provided by `hs_database_info`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DatabaseInfo {
+    /// The version of Hyperscan which compiled the database.
+    pub version: Version,
+
+    /// The CPU features (`HS_CPU_FEATURES_*`) the database was compiled for.
+    pub cpu_features: u64,
+
+    /// The mode (`HS_MODE_*`) the database was compiled for.
+    pub mode: u32,
+}
+
+impl FromStr for DatabaseInfo {
+    type Err = Error;
+
+    /// Parse a database information string, e.g. `Version: 4.4.1 Features:  AVX2 Mode: BLOCK`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field = |name: &str, next: Option<&str>| -> Result<&str, Error> {
+            let start = try!(s.find(name).ok_or(Error::Invalid)) + name.len();
+            let end = match next {
+                Some(next) => try!(s[start..].find(next).ok_or(Error::Invalid)) + start,
+                None => s.len(),
+            };
+
+            Ok(s[start..end].trim())
+        };
+
+        let version = try!(try!(field("Version:", Some("Features:"))).parse());
+        let features = try!(field("Features:", Some("Mode:")));
+        let mode = try!(field("Mode:", None));
+
+        let mut cpu_features = 0;
+
+        for feature in features.split_whitespace() {
+            cpu_features |= match feature {
+                "AVX2" => HS_CPU_FEATURES_AVX2 as u64,
+                "AVX512" => (HS_CPU_FEATURES_AVX2 | HS_CPU_FEATURES_AVX512) as u64,
+                _ => 0,
+            }
+        }
+
+        let mode = match mode.split_whitespace().next() {
+            Some("BLOCK") => HS_MODE_BLOCK,
+            Some("STREAM") => HS_MODE_STREAM,
+            Some("VECTORED") => HS_MODE_VECTORED,
+            _ => return Err(Error::Invalid),
+        };
+
+        Ok(DatabaseInfo {
+            version: version,
+            cpu_features: cpu_features,
+            mode: mode,
+        })
+    }
+}
+
 /// A Hyperscan pattern database.
 pub trait Database: Deref<Target = RawDatabasePtr> {
     /// Provides the id of compiled mode of the given database.
@@ -82,6 +182,89 @@ pub trait Database: Deref<Target = RawDatabasePtr> {
 
     /// Utility function providing information about a database.
     fn database_info(&self) -> Result<String, Error>;
+
+    /// Parse the information about a database.
+    fn info(&self) -> Result<DatabaseInfo, Error> {
+        try!(self.database_info()).parse()
+    }
+
+    /// Provides the version of Hyperscan which compiled the database.
+    fn hyperscan_version(&self) -> Result<Version, Error> {
+        self.info().map(|info| info.version)
+    }
+
+    /// Provides the CPU features (`HS_CPU_FEATURES_*`) the database was compiled for.
+    fn compiled_for(&self) -> Result<u64, Error> {
+        self.info().map(|info| info.cpu_features)
+    }
+
+    /// Provides the mode (`HS_MODE_*`) the database was actually compiled for.
+    fn mode(&self) -> Result<u32, Error> {
+        self.info().map(|info| info.mode)
+    }
+
+    /// Check whether the database can be used on the current host,
+    /// comparing the platform it was compiled for with the host platform.
+    fn runs_on_current_host(&self) -> Result<(), IncompatibleReason> {
+        if !PlatformInfo::is_valid() {
+            return Err(IncompatibleReason::UnsupportedHost);
+        }
+
+        let info = try!(self.info().map_err(IncompatibleReason::Unknown));
+        let library = try!(Version::library().map_err(IncompatibleReason::Unknown));
+
+        if info.version != library {
+            return Err(IncompatibleReason::Version {
+                database: info.version,
+                library: library,
+            });
+        }
+
+        let available = PlatformInfo::host().cpu_features();
+
+        if info.cpu_features & !available != 0 {
+            return Err(IncompatibleReason::CpuFeatures {
+                required: info.cpu_features,
+                available: available,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The reason why a database can't be used on the current host.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncompatibleReason {
+    /// The host doesn't support the minimum platform required by Hyperscan (SSSE3).
+    UnsupportedHost,
+    /// The database was built for a different version of Hyperscan.
+    Version { database: Version, library: Version },
+    /// The database was built for CPU features (`HS_CPU_FEATURES_*`) the host doesn't provide.
+    CpuFeatures { required: u64, available: u64 },
+    /// The platform of the database could not be determined.
+    Unknown(Error),
+}
+
+impl fmt::Display for IncompatibleReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IncompatibleReason::UnsupportedHost => write!(f, "the host platform is not supported by Hyperscan"),
+            IncompatibleReason::Version { ref database, ref library } => {
+                write!(f,
+                       "the database was built by Hyperscan {}, but the library is {}",
+                       database,
+                       library)
+            }
+            IncompatibleReason::CpuFeatures { required, available } => {
+                write!(f,
+                       "the database requires CPU features {:#x}, but the host provides {:#x}",
+                       required,
+                       available)
+            }
+            IncompatibleReason::Unknown(ref err) => write!(f, "unknown database platform, {}", err),
+        }
+    }
 }
 
 /// A pattern database can be serialized to a stream of bytes.
@@ -134,6 +317,11 @@ pub trait SerializedDatabase {
             result
         }
     }
+
+    /// Parse the information about the serialized database.
+    fn info(&self) -> Result<DatabaseInfo, Error> {
+        try!(self.database_info()).parse()
+    }
 }
 
 /// A type containing information on the target platform
@@ -177,6 +365,16 @@ impl PlatformInfo {
         })))
     }
 
+    /// The tuning family (`HS_TUNE_FAMILY_*`) of the platform.
+    pub fn tune(&self) -> u32 {
+        self.0.as_ref().map_or(HS_TUNE_FAMILY_GENERIC, |info| info.borrow().tune)
+    }
+
+    /// The CPU features (`HS_CPU_FEATURES_*`) of the platform.
+    pub fn cpu_features(&self) -> u64 {
+        self.0.as_ref().map_or(0, |info| info.borrow().cpu_features)
+    }
+
     pub fn as_ptr(&self) -> RawPlatformInfoPtr {
         match self.0 {
             Some(ref info) => &*info.borrow(),
@@ -185,6 +383,114 @@ impl PlatformInfo {
     }
 }
 
+impl From<Platform> for PlatformInfo {
+    fn from(platform: Platform) -> Self {
+        PlatformInfo::new(platform.tune, platform.cpu_features)
+    }
+}
+
+/// A target platform builder, to compile a database on a build server
+/// for the hosts with a different microarchitecture.
+///
+/// The CPU features are the instructions the database may use, the tuning family only affects the performance.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Platform {
+    tune: u32,
+    cpu_features: u64,
+}
+
+impl Platform {
+    /// Any CPU supported by Hyperscan (SSSE3), with the generic tuning.
+    pub fn generic() -> Platform {
+        Platform::default()
+    }
+
+    /// The platform of the current host.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the platform of the host can't be determined, see `current_platform`.
+    pub fn host() -> Platform {
+        current_platform().unwrap()
+    }
+
+    /// Tune for a CPU family (`HS_TUNE_FAMILY_*`).
+    pub fn with_tune(mut self, tune: u32) -> Self {
+        self.tune = tune;
+        self
+    }
+
+    /// Use the CPU features (`HS_CPU_FEATURES_*`).
+    pub fn with_cpu_features(mut self, cpu_features: u64) -> Self {
+        self.cpu_features |= cpu_features;
+        self
+    }
+
+    /// Use the AVX2 instructions (Haswell and later).
+    pub fn with_avx2(self) -> Self {
+        self.with_cpu_features(HS_CPU_FEATURES_AVX2 as u64)
+    }
+
+    /// Use the AVX2 and AVX512 instructions (Skylake server and later).
+    pub fn with_avx512(self) -> Self {
+        self.with_cpu_features((HS_CPU_FEATURES_AVX2 | HS_CPU_FEATURES_AVX512) as u64)
+    }
+
+    /// The tuning family (`HS_TUNE_FAMILY_*`).
+    pub fn tune(&self) -> u32 {
+        self.tune
+    }
+
+    /// The CPU features (`HS_CPU_FEATURES_*`).
+    pub fn cpu_features(&self) -> u64 {
+        self.cpu_features
+    }
+
+    /// Whether the CPU features are a subset of the platform features, so a database compiled for this platform
+    /// can run on it.
+    pub fn runs_on(&self, platform: &Platform) -> bool {
+        self.cpu_features & !platform.cpu_features == 0
+    }
+
+    /// The platform information passed to the compiler.
+    pub fn info(&self) -> PlatformInfo {
+        PlatformInfo::from(*self)
+    }
+}
+
+/// Whether the current host supports the minimum instruction set required by Hyperscan (SSSE3).
+pub fn is_platform_supported() -> bool {
+    PlatformInfo::is_valid()
+}
+
+/// Check that the current host supports the minimum instruction set required by Hyperscan (SSSE3),
+/// so an application can fail fast on an unsupported host.
+///
+/// Returns `Error::ArchError` if the host is not supported.
+pub fn check_platform() -> Result<(), Error> {
+    unsafe {
+        check_hs_error!(hs_valid_platform());
+    }
+
+    Ok(())
+}
+
+/// The platform of the current host, with its CPU features and tuning family.
+pub fn current_platform() -> Result<Platform, Error> {
+    let mut info: hs_platform_info_t = unsafe { mem::zeroed() };
+
+    unsafe {
+        check_hs_error!(hs_populate_platform(&mut info));
+    }
+
+    Ok(Platform::generic().with_tune(info.tune).with_cpu_features(info.cpu_features))
+}
+
+/// The platform information of an optional target, which defaults to the current host.
+pub(crate) fn platform_info(platform: Option<Platform>) -> PlatformInfo {
+    platform.map_or_else(PlatformInfo::null, PlatformInfo::from)
+}
+
 /// The regular expression pattern database builder.
 pub trait DatabaseBuilder<D: Database> {
     /// This is the function call with which an expression is compiled into
@@ -197,7 +503,7 @@ pub trait DatabaseBuilder<D: Database> {
 }
 
 /// A type containing information related to an expression
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct ExpressionInfo {
     /// The minimum length in bytes of a match for the pattern.
     pub min_width: usize,
@@ -216,6 +522,36 @@ pub struct ExpressionInfo {
     pub matches_only_at_eod: bool,
 }
 
+impl ExpressionInfo {
+    /// The `max_width` of an expression whose matches have no maximum length.
+    pub const UNBOUNDED: usize = ::std::u32::MAX as usize;
+
+    /// Whether the matches of the expression have no maximum length.
+    #[inline]
+    pub fn is_unbounded(&self) -> bool {
+        self.max_width == Self::UNBOUNDED
+    }
+
+    /// The maximum length in bytes of a match, or `None` if it is unbounded.
+    #[inline]
+    pub fn bounded_width(&self) -> Option<usize> {
+        if self.is_unbounded() { None } else { Some(self.max_width) }
+    }
+
+    /// Whether all the matches of the expression have the same length.
+    #[inline]
+    pub fn is_fixed_width(&self) -> bool {
+        self.min_width == self.max_width
+    }
+
+    /// Whether the matches of the expression are all reported in order before the end of data,
+    /// so they can be consumed as soon as they are located in a stream.
+    #[inline]
+    pub fn is_streamable(&self) -> bool {
+        !self.unordered_matches && !self.matches_at_eod
+    }
+}
+
 /// Providing expression information.
 pub trait Expression {
     ///
@@ -279,6 +615,39 @@ impl<'a> Scannable for &'a Vec<u8> {
         &self
     }
 }
+impl Scannable for String {
+    #[inline]
+    fn as_bytes(&self) -> &[u8] {
+        self.as_str().as_bytes()
+    }
+}
+impl Scannable for Vec<u8> {
+    #[inline]
+    fn as_bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+/// The buffers filled by `readv` or `recvmsg` are scanned in place,
+/// as the slices of a vectored scan or the chunks fed to a stream.
+impl<'a> Scannable for IoSlice<'a> {
+    #[inline]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+impl<'a, 'b> Scannable for &'b IoSlice<'a> {
+    #[inline]
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+#[cfg(feature = "bytes")]
+impl Scannable for ::bytes::Bytes {
+    #[inline]
+    fn as_bytes(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
 
 /// Flags modifying the behaviour of scan function
 pub type ScanFlags = u32;
@@ -305,6 +674,70 @@ pub type ScanFlags = u32;
 pub type MatchEventCallback<D> = fn(id: u32, from: u64, to: u64, flags: u32, data: &D) -> u32;
 pub type MatchEventCallbackMut<D> = fn(id: u32, from: u64, to: u64, flags: u32, data: &mut D) -> u32;
 
+/// The flags of a match event.
+///
+/// Hyperscan reserves the `flags` argument of the match event callback for future use,
+/// and always passes zero at present, so no flag is defined yet.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MatchFlags(u32);
+
+impl MatchFlags {
+    /// The raw value passed to the match event callback.
+    #[inline]
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Check whether no flag is set, which is always the case with the current Hyperscan releases.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    pub fn contains(&self, flags: u32) -> bool {
+        (self.0 & flags) == flags
+    }
+}
+
+impl From<u32> for MatchFlags {
+    fn from(flags: u32) -> Self {
+        MatchFlags(flags)
+    }
+}
+
+/// A match event located by a scan.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Match {
+    /// The ID number of the expression that matched.
+    pub id: u32,
+    /// The offset of the start of the match, only accurate with `HS_FLAG_SOM_LEFTMOST`.
+    pub from: u64,
+    /// The offset after the last byte of the match.
+    pub to: u64,
+    /// The flags of the match event.
+    pub flags: MatchFlags,
+}
+
+impl Match {
+    /// Decode the arguments of the match event callback.
+    #[inline]
+    pub fn new(id: u32, from: u64, to: u64, flags: u32) -> Match {
+        Match {
+            id: id,
+            from: from,
+            to: to,
+            flags: MatchFlags(flags),
+        }
+    }
+
+    /// The range of the matched data.
+    #[inline]
+    pub fn range(&self) -> ::std::ops::Range<u64> {
+        self.from..self.to
+    }
+}
+
 /// The block (non-streaming) regular expression scanner.
 pub trait BlockScanner<T: Scannable, S: Scratch> {
     /// This is the function call in which the actual pattern matching
@@ -337,7 +770,7 @@ pub trait VectoredScanner<T: Scannable, S: Scratch> {
     /// This is the function call in which the actual pattern matching
     /// takes place for vectoring-mode pattern databases.
     fn scan<D>(&self,
-               data: &Vec<T>,
+               data: &[T],
                flags: ScanFlags,
                scratch: &S,
                callback: Option<MatchEventCallback<D>>,
@@ -0,0 +1,350 @@
+// hsbench-rs: a simple Hyperscan benchmark tool
+//
+// This program loads a pattern file and a corpus file, compiles the patterns
+// into block, vectored and streaming mode databases, and measures the
+// throughput of scanning the corpus in each mode. It is intended to give
+// operators a quick way to evaluate a rule set on their target hardware.
+//
+// The corpus is split into blocks of a fixed size, and consecutive blocks are
+// grouped into streams:
+//
+//  - block mode scans every block independently;
+//  - vectored mode scans the blocks of each stream with a single call;
+//  - streaming mode writes the blocks of each stream to a Hyperscan stream.
+//
+// Build instructions:
+//
+//     cargo build --release --features hsbench --bin hsbench-rs
+//
+// Usage:
+//
+//     ./hsbench-rs [-n repeats] [-b block size] [-s blocks per stream] <pattern file> <corpus file>
+//
+extern crate getopts;
+extern crate hyperscan;
+
+use std::env;
+use std::io;
+use std::io::{Read, Write, BufRead};
+use std::fs::File;
+use std::path::Path;
+use std::process::exit;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use getopts::Options;
+
+use hyperscan::{Pattern, Patterns, Database, DatabaseBuilder, BlockDatabase, VectoredDatabase, StreamingDatabase,
+                RawScratch, Scratch, ScratchAllocator, BlockScanner, VectoredScanner, StreamingScanner, Stream};
+
+trait Seconds {
+    fn secs(&self) -> f64;
+}
+
+impl Seconds for Duration {
+    fn secs(&self) -> f64 {
+        self.as_secs() as f64 + self.subsec_nanos() as f64 / 1_000_000_000.0
+    }
+}
+
+/**
+ * Read the pattern file with an expression per line, ignoring empty lines
+ * and lines starting with '#'.
+ */
+fn parse_file(filename: &str) -> Result<Patterns, io::Error> {
+    let f = try!(File::open(filename));
+    let mut patterns = Patterns::new();
+
+    for (lineno, line) in io::BufReader::new(f).lines().enumerate() {
+        let line = try!(line);
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match Pattern::parse(line) {
+            Ok(pattern) => patterns.push(pattern),
+            Err(err) => {
+                let _ = write!(io::stderr(),
+                               "WARNING: skip pattern `{}` at line {}: {}\n",
+                               line,
+                               lineno + 1,
+                               err);
+            }
+        }
+    }
+
+    Ok(patterns)
+}
+
+fn read_corpus(filename: &str) -> Result<Vec<u8>, io::Error> {
+    let mut f = try!(File::open(filename));
+    let mut buf = Vec::new();
+
+    try!(f.read_to_end(&mut buf));
+
+    Ok(buf)
+}
+
+macro_rules! build_database {
+    ($patterns:expr, $mode:expr) => ({
+        let now = Instant::now();
+
+        let db = match $patterns.build() {
+            Ok(db) => db,
+            Err(err) => {
+                let _ = write!(io::stderr(), "ERROR: Unable to compile {} mode database: {}\n", $mode, err);
+                exit(-1);
+            }
+        };
+
+        println!("{:>9} mode database compiled in {:.3}s, {} bytes.",
+                 $mode,
+                 now.elapsed().secs(),
+                 Database::database_size(&db).unwrap_or(0));
+
+        db
+    })
+}
+
+struct Benchmark {
+    /// Corpus data split into blocks.
+    blocks: Vec<Vec<u8>>,
+
+    /// The number of consecutive blocks forming a stream.
+    stream_blocks: usize,
+
+    db_block: BlockDatabase,
+
+    db_vectored: VectoredDatabase,
+
+    db_streaming: StreamingDatabase,
+
+    /// Hyperscan temporary scratch space (used in all modes)
+    scratch: RawScratch,
+
+    // Count of matches found during scanning
+    match_count: AtomicUsize,
+}
+
+struct Stats {
+    mode: &'static str,
+    matches: usize,
+    elapsed: Duration,
+}
+
+impl Benchmark {
+    fn on_match(_: u32, _: u64, _: u64, _: u32, match_count: &AtomicUsize) -> u32 {
+        match_count.fetch_add(1, Ordering::Relaxed);
+
+        0
+    }
+
+    fn bytes(&self) -> usize {
+        self.blocks.iter().fold(0, |bytes, b| bytes + b.len())
+    }
+
+    fn streams(&self) -> usize {
+        (self.blocks.len() + self.stream_blocks - 1) / self.stream_blocks
+    }
+
+    fn take_matches(&self) -> usize {
+        self.match_count.swap(0, Ordering::Relaxed)
+    }
+
+    fn scan_block(&self) -> Result<(), hyperscan::Error> {
+        for block in &self.blocks {
+            try!(self.db_block.scan(block.as_slice(),
+                                    0,
+                                    &self.scratch,
+                                    Some(Self::on_match),
+                                    Some(&self.match_count)));
+        }
+
+        Ok(())
+    }
+
+    fn scan_vectored(&self) -> Result<(), hyperscan::Error> {
+        for blocks in self.blocks.chunks(self.stream_blocks) {
+            try!(self.db_vectored.scan(blocks,
+                                       0,
+                                       &self.scratch,
+                                       Some(Self::on_match),
+                                       Some(&self.match_count)));
+        }
+
+        Ok(())
+    }
+
+    fn scan_streaming(&self) -> Result<(), hyperscan::Error> {
+        for blocks in self.blocks.chunks(self.stream_blocks) {
+            let stream = try!(self.db_streaming.open_stream(0));
+
+            for block in blocks {
+                try!(stream.scan(block.as_slice(),
+                                 0,
+                                 &self.scratch,
+                                 Some(Self::on_match),
+                                 Some(&self.match_count)));
+            }
+
+            try!(stream.close(&self.scratch, Some(Self::on_match), Some(&self.match_count)));
+        }
+
+        Ok(())
+    }
+
+    fn run<F>(&self, mode: &'static str, repeats: usize, scan: F) -> Stats
+        where F: Fn(&Self) -> Result<(), hyperscan::Error>
+    {
+        let _ = self.take_matches();
+
+        let now = Instant::now();
+
+        for _ in 0..repeats {
+            if let Err(err) = scan(self) {
+                let _ = write!(io::stderr(), "ERROR: Unable to scan in {} mode. Exiting. {}\n", mode, err);
+                exit(-1);
+            }
+        }
+
+        Stats {
+            mode: mode,
+            matches: self.take_matches(),
+            elapsed: now.elapsed(),
+        }
+    }
+
+    fn display_stats(&self, stats: &Stats, repeats: usize) {
+        let bytes = (self.bytes() * repeats) as f64;
+        let secs = stats.elapsed.secs();
+
+        println!("\n{} mode:\n", stats.mode);
+        println!("  Total matches: {}", stats.matches);
+        println!("  Match rate:    {:.4} matches/kilobyte",
+                 stats.matches as f64 / (bytes / 1024.0));
+        println!("  Time spent:    {:.3}s", secs);
+        println!("  Throughput:    {:.2} megabits/sec",
+                 if secs > 0.0 { bytes * 8.0 / secs / 1000000.0 } else { 0.0 });
+    }
+}
+
+fn parse_opt(matches: &getopts::Matches, name: &str, default: usize) -> usize {
+    match matches.opt_str(name) {
+        Some(s) => {
+            match s.parse() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    let _ = write!(io::stderr(), "ERROR: Invalid value `{}` for -{}\n", s, name);
+                    exit(-1);
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+// Main entry point.
+fn main() {
+    // Process command line arguments.
+    let args: Vec<String> = env::args().collect();
+    let prog = Path::new(&args[0]).file_name().unwrap().to_str().unwrap();
+    let mut opts = Options::new();
+
+    opts.optopt("n", "", "repeat times", "repeats");
+    opts.optopt("b", "", "block size in bytes (default 2048)", "bytes");
+    opts.optopt("s", "", "blocks per stream (default 16)", "blocks");
+
+    let usage = || {
+        let brief = format!("Usage: {} [options] <pattern file> <corpus file>", prog);
+
+        print!("{}", opts.usage(&brief));
+    };
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(_) => {
+            usage();
+            exit(-1);
+        }
+    };
+
+    if matches.free.len() != 2 {
+        usage();
+        exit(-1);
+    }
+
+    let repeats = parse_opt(&matches, "n", 1);
+    let block_size = parse_opt(&matches, "b", 2048);
+    let stream_blocks = parse_opt(&matches, "s", 16);
+
+    let pattern_file = matches.free[0].as_str();
+    let corpus_file = matches.free[1].as_str();
+
+    println!("Pattern file: {}", pattern_file);
+
+    let patterns = match parse_file(pattern_file) {
+        Ok(patterns) => patterns,
+        Err(err) => {
+            let _ = write!(io::stderr(), "ERROR: Unable to read pattern file: {}\n", err);
+            exit(-1);
+        }
+    };
+
+    println!("Compiling Hyperscan databases with {} patterns.\n", patterns.len());
+
+    let db_block: BlockDatabase = build_database!(patterns, "Block");
+    let db_vectored: VectoredDatabase = build_database!(patterns, "Vectored");
+    let db_streaming: StreamingDatabase = build_database!(patterns, "Streaming");
+
+    let mut scratch = db_block.alloc().unwrap();
+
+    db_vectored.realloc(&mut scratch).unwrap();
+    db_streaming.realloc(&mut scratch).unwrap();
+
+    println!("\nCorpus file: {}", corpus_file);
+
+    let corpus = match read_corpus(corpus_file) {
+        Ok(corpus) => corpus,
+        Err(err) => {
+            let _ = write!(io::stderr(), "ERROR: Unable to read corpus file: {}\n", err);
+            exit(-1);
+        }
+    };
+
+    let bench = Benchmark {
+        blocks: corpus.chunks(block_size).map(|b| b.to_vec()).collect(),
+        stream_blocks: stream_blocks,
+        db_block: db_block,
+        db_vectored: db_vectored,
+        db_streaming: db_streaming,
+        scratch: scratch,
+        match_count: AtomicUsize::new(0),
+    };
+
+    println!("{} bytes in {} blocks of {} bytes, {} streams of {} blocks.",
+             bench.bytes(),
+             bench.blocks.len(),
+             block_size,
+             bench.streams(),
+             stream_blocks);
+    println!("Scratch size: {} bytes, stream state size: {} bytes (per stream).",
+             bench.scratch.size().unwrap_or(0),
+             bench.db_streaming.stream_size().unwrap_or(0));
+
+    if repeats != 1 {
+        println!("Repeating corpus scan {} times.", repeats);
+    }
+
+    for stats in &[bench.run("Block", repeats, Benchmark::scan_block),
+                   bench.run("Vectored", repeats, Benchmark::scan_vectored),
+                   bench.run("Streaming", repeats, Benchmark::scan_streaming)] {
+        bench.display_stats(stats, repeats);
+    }
+
+    if bench.bytes() < (2 * 1024 * 1024) {
+        println!("\nWARNING: Input corpus is less than 2MB in size.\n
+                  This test may have been too short to calculate accurate results.");
+    }
+}
@@ -0,0 +1,514 @@
+use std::cmp;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use api::*;
+use constants::*;
+use errors::Error;
+use common::BlockDatabase;
+use compile::Pattern;
+
+/// An atom of an expression, a literal character or anything else.
+enum Atom {
+    Literal(Vec<u8>),
+    Other,
+}
+
+/// How many times an atom may repeat.
+enum Repeat {
+    One,
+    Optional,
+    Many,
+}
+
+/// Skip to the end of a group, returns `false` if it isn't closed.
+fn skip_group(chars: &mut Peekable<Chars>) -> bool {
+    let mut depth = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => {
+                if !skip_class(chars) {
+                    return false;
+                }
+            }
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Skip to the end of a character class, returns `false` if it isn't closed.
+fn skip_class(chars: &mut Peekable<Chars>) -> bool {
+    if chars.peek() == Some(&'^') {
+        chars.next();
+    }
+    if chars.peek() == Some(&']') {
+        chars.next();
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            ']' => return true,
+            '\\' => {
+                chars.next();
+            }
+            '[' if chars.peek() == Some(&':') => {
+                while let Some(c) = chars.next() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+fn push_char(c: char, bytes: &mut Vec<u8>) {
+    let mut buf = [0; 4];
+
+    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// Parse an escape sequence (the leading `\` has already been consumed).
+fn parse_escape(chars: &mut Peekable<Chars>) -> Option<Atom> {
+    let c = match chars.next() {
+        Some(c) => c,
+        None => return None,
+    };
+
+    let byte = match c {
+        'n' => b'\n',
+        't' => b'\t',
+        'r' => b'\r',
+        'f' => b'\x0c',
+        'e' => b'\x1b',
+        'a' => b'\x07',
+        'x' => {
+            let mut digits = String::new();
+
+            if chars.peek() == Some(&'{') {
+                chars.next();
+
+                while let Some(c) = chars.next() {
+                    if c == '}' {
+                        break;
+                    }
+
+                    digits.push(c);
+                }
+            } else {
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(&c) if c.is_digit(16) => digits.push(c),
+                        _ => break,
+                    }
+
+                    chars.next();
+                }
+            }
+
+            match u32::from_str_radix(&digits, 16) {
+                Ok(b) if b <= 0xff => b as u8,
+                _ => return None,
+            }
+        }
+        'p' | 'P' | 'o' | 'N' | 'g' | 'k' if chars.peek() == Some(&'{') => {
+            while let Some(c) = chars.next() {
+                if c == '}' {
+                    break;
+                }
+            }
+
+            return Some(Atom::Other);
+        }
+        'c' => {
+            chars.next();
+
+            return Some(Atom::Other);
+        }
+        '0'..='9' => {
+            while chars.peek().map_or(false, |c| c.is_digit(10)) {
+                chars.next();
+            }
+
+            return Some(Atom::Other);
+        }
+        'Q' | 'E' => return None,
+        c if c.is_alphanumeric() => return Some(Atom::Other),
+        c => {
+            let mut bytes = Vec::new();
+
+            push_char(c, &mut bytes);
+
+            return Some(Atom::Literal(bytes));
+        }
+    };
+
+    Some(Atom::Literal(vec![byte]))
+}
+
+/// Parse the quantifier following an atom, if any.
+fn parse_repeat(chars: &mut Peekable<Chars>) -> Repeat {
+    let repeat = match chars.peek() {
+        Some(&'*') | Some(&'?') => Repeat::Optional,
+        Some(&'+') => Repeat::Many,
+        Some(&'{') => {
+            let mut lookahead = chars.clone();
+
+            lookahead.next();
+
+            let mut bounds = String::new();
+            let mut closed = false;
+
+            while let Some(c) = lookahead.next() {
+                if c == '}' {
+                    closed = true;
+                    break;
+                }
+
+                bounds.push(c);
+            }
+
+            let mut parts = bounds.splitn(2, ',');
+            let min = parts.next().and_then(|min| min.parse::<u32>().ok());
+            let max = parts.next();
+
+            if !closed || min.is_none() {
+                return Repeat::One;
+            }
+
+            *chars = lookahead;
+
+            let repeat = match (min, max) {
+                (Some(0), _) => Repeat::Optional,
+                (Some(1), None) => Repeat::One,
+                _ => Repeat::Many,
+            };
+
+            if chars.peek() == Some(&'?') || chars.peek() == Some(&'+') {
+                chars.next();
+            }
+
+            return repeat;
+        }
+        _ => return Repeat::One,
+    };
+
+    chars.next();
+
+    if chars.peek() == Some(&'?') || chars.peek() == Some(&'+') {
+        chars.next();
+    }
+
+    repeat
+}
+
+/// Extract the longest literal which must appear in any match of the pattern.
+///
+/// The extraction is conservative, `None` is returned for the approximate patterns, the patterns
+/// with a top level alternation, inline options, quotes or anything it doesn't understand.
+pub fn required_literal(pattern: &Pattern) -> Option<Vec<u8>> {
+    required_literals(pattern).into_iter().max_by_key(|literal| literal.len())
+}
+
+/// Extract the literals which must all appear in any match of the pattern, in the order of the expression.
+///
+/// The literals are compared caseless if the pattern has `HS_FLAG_CASELESS`. The extraction is conservative,
+/// no literal is returned for the logical combinations and the patterns `required_literal` doesn't understand.
+/// The caseless literals with non-ASCII bytes are left out, since only the ASCII letters are folded
+/// when they are compared.
+pub fn required_literals(pattern: &Pattern) -> Vec<Vec<u8>> {
+    if pattern.flags.is_set(HS_FLAG_COMBINATION) || pattern.flags.is_set(HS_FLAG_PREFILTER) ||
+       pattern.ext.is_approximate() || pattern.expression.contains("(?") {
+        return vec![];
+    }
+
+    let mut chars = pattern.expression.chars().peekable();
+    let mut runs = vec![Vec::new()];
+
+    while let Some(c) = chars.next() {
+        let atom = match c {
+            '\\' => {
+                match parse_escape(&mut chars) {
+                    Some(atom) => atom,
+                    None => return vec![],
+                }
+            }
+            '(' => {
+                if !skip_group(&mut chars) {
+                    return vec![];
+                }
+
+                Atom::Other
+            }
+            '[' => {
+                if !skip_class(&mut chars) {
+                    return vec![];
+                }
+
+                Atom::Other
+            }
+            '|' => return vec![],
+            '.' | '^' | '$' => Atom::Other,
+            c => {
+                let mut bytes = Vec::new();
+
+                push_char(c, &mut bytes);
+
+                Atom::Literal(bytes)
+            }
+        };
+
+        match (atom, parse_repeat(&mut chars)) {
+            (Atom::Literal(bytes), Repeat::One) => runs.last_mut().unwrap().extend(bytes),
+            (Atom::Literal(bytes), Repeat::Many) => {
+                runs.last_mut().unwrap().extend(bytes);
+                runs.push(Vec::new());
+            }
+            _ => runs.push(Vec::new()),
+        }
+    }
+
+    let caseless = pattern.flags.is_set(HS_FLAG_CASELESS);
+
+    runs.retain(|run| !run.is_empty() && !(caseless && !run.is_ascii()));
+    runs
+}
+
+impl Pattern {
+    /// The literals which must all appear in any match of the pattern, see `required_literals`.
+    pub fn required_literals(&self) -> Vec<Vec<u8>> {
+        required_literals(self)
+    }
+}
+
+/// The longest n-gram indexed by the filter.
+const MAX_GRAM: usize = 4;
+
+/// The number of bits per literal of the filter.
+const BITS_PER_LITERAL: usize = 16;
+
+fn hash_gram(gram: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for &b in gram {
+        h ^= b.to_ascii_lowercase() as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    h
+}
+
+/// A cheap membership filter of the literals required by the patterns, which tells the buffers
+/// that can't match any pattern, so the full scan can be skipped on mostly clean traffic.
+///
+/// The filter indexes the first bytes of each literal in a Bloom filter, and compares the letters caseless,
+/// so it may let through buffers which don't match, but never rejects a buffer which could match.
+/// A set with a pattern without a required literal lets every buffer through.
+#[derive(Debug, Clone)]
+pub struct LiteralFilter {
+    bits: Vec<u64>,
+    grams: [bool; MAX_GRAM + 1],
+    always: bool,
+}
+
+impl LiteralFilter {
+    /// Build the filter of the required literals of the patterns.
+    pub fn new(patterns: &[Pattern]) -> LiteralFilter {
+        let literals = patterns.iter().map(required_literal).collect::<Vec<_>>();
+        let always = literals.iter().any(|literal| literal.is_none());
+
+        let size = cmp::max(literals.len() * BITS_PER_LITERAL, 1024).next_power_of_two();
+
+        let mut filter = LiteralFilter {
+            bits: vec![0; size / 64],
+            grams: [false; MAX_GRAM + 1],
+            always: always,
+        };
+
+        if !always {
+            for literal in literals.into_iter().filter_map(|literal| literal) {
+                let n = cmp::min(literal.len(), MAX_GRAM);
+
+                filter.grams[n] = true;
+                filter.insert(hash_gram(&literal[..n]));
+            }
+        }
+
+        debug!("literal filter of {} patterns with {} bits, always: {}",
+               patterns.len(),
+               size,
+               always);
+
+        filter
+    }
+
+    /// Whether every buffer is let through, because a pattern has no required literal.
+    pub fn is_always(&self) -> bool {
+        self.always
+    }
+
+    #[inline]
+    fn positions(&self, h: u64) -> (usize, usize) {
+        let mask = self.bits.len() * 64 - 1;
+
+        ((h as usize) & mask, ((h >> 32) as usize) & mask)
+    }
+
+    fn insert(&mut self, h: u64) {
+        let (a, b) = self.positions(h);
+
+        self.bits[a / 64] |= 1 << (a % 64);
+        self.bits[b / 64] |= 1 << (b % 64);
+    }
+
+    fn contains(&self, h: u64) -> bool {
+        let (a, b) = self.positions(h);
+
+        self.bits[a / 64] & (1 << (a % 64)) != 0 && self.bits[b / 64] & (1 << (b % 64)) != 0
+    }
+
+    /// Whether the data may match one of the patterns.
+    pub fn may_match(&self, data: &[u8]) -> bool {
+        if self.always {
+            return true;
+        }
+
+        (1..MAX_GRAM + 1)
+            .filter(|&n| self.grams[n])
+            .any(|n| data.windows(n).any(|gram| self.contains(hash_gram(gram))))
+    }
+
+    /// Scan the data unless the filter tells it can't match, returns whether it was scanned.
+    pub fn scan<T, S, D>(&self,
+                         db: &BlockDatabase,
+                         data: T,
+                         flags: ScanFlags,
+                         scratch: &S,
+                         callback: Option<MatchEventCallback<D>>,
+                         context: Option<&D>)
+                         -> Result<bool, Error>
+        where T: Scannable,
+              S: Scratch
+    {
+        if !self.may_match(data.as_bytes()) {
+            trace!("skipped {} bytes without literals", data.as_bytes().len());
+
+            return Ok(false);
+        }
+
+        try!(db.scan(data.as_bytes(), flags, scratch, callback, context));
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::Cell;
+
+    use super::super::*;
+
+    fn literal(expr: &str) -> Option<String> {
+        required_literal(&pattern!{expr}).map(|literal| String::from_utf8(literal).unwrap())
+    }
+
+    #[test]
+    fn test_required_literal() {
+        assert_eq!(literal("foobar"), Some(String::from("foobar")));
+        assert_eq!(literal(r"\d+ password=\w+"), Some(String::from(" password=")));
+        assert_eq!(literal("ab?cd"), Some(String::from("cd")));
+        assert_eq!(literal("ab{0,2}cdef"), Some(String::from("cdef")));
+        assert_eq!(literal("xy+z"), Some(String::from("xy")));
+        assert_eq!(literal(r"evil(\.exe|\.dll)"), Some(String::from("evil")));
+        assert_eq!(literal(r"[a-z]+\.example\.com"), Some(String::from(".example.com")));
+        assert_eq!(literal(r"\x41\x42c"), Some(String::from("ABc")));
+        assert_eq!(literal("foo|bar"), None);
+        assert_eq!(literal("(?i)foo"), None);
+        assert_eq!(literal(r"\d+"), None);
+        assert_eq!(literal("a*"), None);
+    }
+
+    fn literals(expr: &str) -> Vec<String> {
+        pattern!{expr}
+            .required_literals()
+            .into_iter()
+            .map(|literal| String::from_utf8(literal).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_required_literals() {
+        assert_eq!(literals("foobar"), vec!["foobar"]);
+        assert_eq!(literals(r"GET /\w+\.php\?id=\d+"), vec!["GET /", ".php?id="]);
+        assert_eq!(literals(r"user=\w+&pass(word)?=.*admin"), vec!["user=", "&pass", "=", "admin"]);
+        assert_eq!(literals("ab?cd"), vec!["a", "cd"]);
+        assert_eq!(literals("xy+z"), vec!["xy", "z"]);
+        assert!(literals("foo|bar").is_empty());
+        assert!(literals(r"\d+").is_empty());
+
+        let approximate = pattern!{"foobar", flags => 0, id => 1, ext => ExprExt::new().with_edit_distance(1)};
+
+        assert!(approximate.required_literals().is_empty());
+        assert_eq!(required_literals(&pattern!{"foo.*bar", flags => HS_FLAG_CASELESS}),
+                   vec![b"foo".to_vec(), b"bar".to_vec()]);
+        assert_eq!(required_literals(&pattern!{"caf\u{e9}.*bar", flags => HS_FLAG_CASELESS | HS_FLAG_UTF8}),
+                   vec![b"bar".to_vec()]);
+        assert_eq!(literals("caf\u{e9}"), vec!["caf\u{e9}"]);
+        assert!(required_literals(&pattern!{"1 & 2", flags => HS_FLAG_COMBINATION, id => 3}).is_empty());
+    }
+
+    fn on_match(_: u32, _: u64, _: u64, _: u32, matches: &Cell<usize>) -> u32 {
+        matches.set(matches.get() + 1);
+
+        0
+    }
+
+    #[test]
+    fn test_literal_filter() {
+        let _ = env_logger::init();
+
+        let patterns = patterns!([r"\d+ password=\w+", "evil", "ab"]);
+        let filter = LiteralFilter::new(&patterns);
+
+        assert!(!filter.is_always());
+        assert!(filter.may_match(b"user PASSWORD=secret"));
+        assert!(filter.may_match(b"something EVIL"));
+        assert!(filter.may_match(b"xxaBxx"));
+        assert!(!filter.may_match(b"nothing to see here"));
+        assert!(!filter.may_match(b""));
+
+        assert!(LiteralFilter::new(&patterns!(["foo|bar"])).is_always());
+
+        let filter = LiteralFilter::new(&patterns!(["stra\u{df}e"], flags => HS_FLAG_CASELESS | HS_FLAG_UTF8));
+
+        assert!(filter.is_always());
+        assert!(filter.may_match("STRASSE".as_bytes()));
+
+        let db: BlockDatabase = patterns.build().unwrap();
+        let s = db.alloc().unwrap();
+        let matches = Cell::new(0);
+
+        assert!(filter.scan(&db, "42 password=x", 0, &s, Some(on_match), Some(&matches)).unwrap());
+        assert!(!filter.scan(&db, "clean traffic", 0, &s, Some(on_match), Some(&matches)).unwrap());
+        assert_eq!(matches.get(), 1);
+    }
+}
@@ -0,0 +1,351 @@
+use std::ops::Deref;
+
+use api::*;
+use errors::Error;
+use common::{RawDatabase, BlockDatabase, StreamingDatabase, VectoredDatabase};
+use compile::{ExprExt, FlagBits, Pattern, Patterns, compile_checked, compile_patterns};
+use combination::check_combinations;
+use ids::check_ids;
+
+/// The scanning mode of a database chosen at runtime.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Block,
+    Streaming,
+    Vectored,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Block
+    }
+}
+
+impl Mode {
+    /// The `HS_MODE_*` bits of the mode.
+    pub fn bits(&self) -> u32 {
+        match *self {
+            Mode::Block => Block::mode(),
+            Mode::Streaming => Streaming::mode(),
+            Mode::Vectored => Vectored::mode(),
+        }
+    }
+}
+
+/// A database compiled in the mode chosen at runtime.
+#[derive(Debug)]
+pub enum AnyDatabase {
+    Block(BlockDatabase),
+    Streaming(StreamingDatabase),
+    Vectored(VectoredDatabase),
+}
+
+impl AnyDatabase {
+    pub fn scan_mode(&self) -> Mode {
+        match *self {
+            AnyDatabase::Block(_) => Mode::Block,
+            AnyDatabase::Streaming(_) => Mode::Streaming,
+            AnyDatabase::Vectored(_) => Mode::Vectored,
+        }
+    }
+
+    pub fn as_block(&self) -> Option<&BlockDatabase> {
+        match *self {
+            AnyDatabase::Block(ref db) => Some(db),
+            _ => None,
+        }
+    }
+
+    pub fn as_streaming(&self) -> Option<&StreamingDatabase> {
+        match *self {
+            AnyDatabase::Streaming(ref db) => Some(db),
+            _ => None,
+        }
+    }
+
+    pub fn as_vectored(&self) -> Option<&VectoredDatabase> {
+        match *self {
+            AnyDatabase::Vectored(ref db) => Some(db),
+            _ => None,
+        }
+    }
+
+    /// Take the block database, returns the database back in another mode.
+    pub fn into_block(self) -> Result<BlockDatabase, AnyDatabase> {
+        match self {
+            AnyDatabase::Block(db) => Ok(db),
+            db => Err(db),
+        }
+    }
+
+    /// Take the streaming database, returns the database back in another mode.
+    pub fn into_streaming(self) -> Result<StreamingDatabase, AnyDatabase> {
+        match self {
+            AnyDatabase::Streaming(db) => Ok(db),
+            db => Err(db),
+        }
+    }
+
+    /// Take the vectored database, returns the database back in another mode.
+    pub fn into_vectored(self) -> Result<VectoredDatabase, AnyDatabase> {
+        match self {
+            AnyDatabase::Vectored(db) => Ok(db),
+            db => Err(db),
+        }
+    }
+
+    fn database(&self) -> &Database<Target = RawDatabasePtr> {
+        match *self {
+            AnyDatabase::Block(ref db) => db,
+            AnyDatabase::Streaming(ref db) => db,
+            AnyDatabase::Vectored(ref db) => db,
+        }
+    }
+}
+
+impl Deref for AnyDatabase {
+    type Target = RawDatabasePtr;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.database()
+    }
+}
+
+impl Database for AnyDatabase {
+    fn database_mode(&self) -> u32 {
+        self.database().database_mode()
+    }
+
+    fn database_name(&self) -> &'static str {
+        self.database().database_name()
+    }
+
+    fn database_size(&self) -> Result<usize, Error> {
+        self.database().database_size()
+    }
+
+    fn database_info(&self) -> Result<String, Error> {
+        self.database().database_info()
+    }
+}
+
+/// A fluent builder of a database, collecting the patterns, the target platform and the mode.
+///
+/// ```ignore
+/// let db = Builder::new()
+///     .pattern("foo", HS_FLAG_CASELESS)
+///     .pattern_with_id(7, "bar", 0)
+///     .mode(Mode::Streaming)
+///     .build()?;
+/// ```
+///
+/// The patterns added without an ID are numbered in order after the largest ID,
+/// the IDs and the flags are checked when the database is built.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    patterns: Patterns,
+    platform: Option<Platform>,
+    mode: Mode,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// Add an expression with the next ID.
+    pub fn pattern<F: FlagBits>(mut self, expression: &str, flags: F) -> Self {
+        self.patterns.add(pattern!{expression, flags => flags});
+        self
+    }
+
+    /// Add an expression with its own ID.
+    pub fn pattern_with_id<F: FlagBits>(self, id: usize, expression: &str, flags: F) -> Self {
+        self.pattern_with_ext(id, expression, flags, ExprExt::default())
+    }
+
+    /// Add an expression with its own ID and extended parameters.
+    pub fn pattern_with_ext<F: FlagBits>(mut self, id: usize, expression: &str, flags: F, ext: ExprExt) -> Self {
+        self.patterns.push(pattern!{expression, flags => flags, id => id, ext => ext});
+        self
+    }
+
+    /// Add the patterns with their own IDs.
+    pub fn patterns<I: IntoIterator<Item = Pattern>>(mut self, patterns: I) -> Self {
+        self.patterns.extend(patterns);
+        self
+    }
+
+    /// Compile for the target platform, the current host by default.
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Compile in the mode, `Mode::Block` by default.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The patterns added so far.
+    pub fn get_patterns(&self) -> &Patterns {
+        &self.patterns
+    }
+
+    /// Compile the patterns in the mode of the builder.
+    pub fn build(&self) -> Result<AnyDatabase, Error> {
+        Ok(match self.mode {
+            Mode::Block => AnyDatabase::Block(try!(self.build_as())),
+            Mode::Streaming => AnyDatabase::Streaming(try!(self.build_as())),
+            Mode::Vectored => AnyDatabase::Vectored(try!(self.build_as())),
+        })
+    }
+
+    /// Compile the patterns in the mode of the database type, ignoring the mode of the builder.
+    pub fn build_as<T: Type>(&self) -> Result<RawDatabase<T>, Error> {
+        try!(self.patterns.check_ids());
+
+        compile_patterns(&self.patterns, 0, &platform_info(self.platform))
+    }
+
+    /// Compile the patterns into a database for each mode, see `compile_modes`.
+    pub fn build_modes(&self, modes: &[Mode]) -> Result<Vec<AnyDatabase>, Error> {
+        compile_modes(&self.patterns, modes, &platform_info(self.platform))
+    }
+
+    /// Compile the patterns into the databases of two modes, e.g. `build_pair::<Block, Streaming>()`.
+    pub fn build_pair<A: Type, B: Type>(&self) -> Result<(RawDatabase<A>, RawDatabase<B>), Error> {
+        let platform = platform_info(self.platform);
+
+        try!(check_patterns(&self.patterns));
+
+        Ok((try!(compile_in(&self.patterns, &platform)), try!(compile_in(&self.patterns, &platform))))
+    }
+}
+
+fn check_patterns(patterns: &[Pattern]) -> Result<(), Error> {
+    try!(check_ids(patterns));
+
+    check_combinations(patterns)
+}
+
+fn compile_in<T: Type>(patterns: &[Pattern], platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+    compile_checked(patterns, 0, platform).map_err(|err| {
+        let mode = T::name();
+
+        match err {
+            Error::PatternError(mut err) => {
+                err.message = format!("{} (in {} mode)", err.message, mode);
+
+                Error::PatternError(err)
+            }
+            Error::CompilerError(message) => Error::CompilerError(format!("{} (in {} mode)", message, mode)),
+            err => err,
+        }
+    })
+}
+
+/// Compile the patterns into a database for each mode, in order, e.g. a block database for
+/// the reassembled objects and a streaming database for the live flows.
+///
+/// The IDs and the logical combinations are checked once for all the modes.
+/// Compilation stops at the first error, whose message names the mode it failed in,
+/// since some patterns only fail in a mode, e.g. a large bounded repeat in streaming mode.
+pub fn compile_modes(patterns: &[Pattern], modes: &[Mode], platform: &PlatformInfo) -> Result<Vec<AnyDatabase>, Error> {
+    try!(check_patterns(patterns));
+
+    modes.iter()
+        .map(|mode| {
+            Ok(match *mode {
+                Mode::Block => AnyDatabase::Block(try!(compile_in(patterns, platform))),
+                Mode::Streaming => AnyDatabase::Streaming(try!(compile_in(patterns, platform))),
+                Mode::Vectored => AnyDatabase::Vectored(try!(compile_in(patterns, platform))),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_builder() {
+        let _ = env_logger::init();
+
+        let builder = Builder::new()
+            .pattern("foo", HS_FLAG_CASELESS)
+            .pattern_with_id(7, "bar", 0)
+            .pattern("baz", CompileFlags::DOTALL);
+
+        assert_eq!(builder.get_patterns().iter().map(|p| p.id).collect::<Vec<_>>(),
+                   vec![1, 7, 8]);
+
+        let db = builder.build().unwrap();
+
+        assert_eq!(db.scan_mode(), Mode::Block);
+        assert_eq!(db.database_mode(), HS_MODE_BLOCK);
+
+        validate_database(&db);
+
+        let db = db.into_block().unwrap();
+
+        assert_matches!(&db, "FOO bar baz", [(1, 3), (7, 7), (8, 11)]);
+
+        let db = builder.clone().mode(Mode::Streaming).platform(Platform::host()).build().unwrap();
+
+        assert!(db.as_streaming().is_some());
+        assert!(db.as_block().is_none());
+        assert_eq!(db.database_name(), "Streaming");
+
+        let db: VectoredDatabase = builder.build_as().unwrap();
+
+        validate_database(&db);
+
+        let err = Builder::new().pattern_with_id(1, "foo", 0).pattern_with_id(1, "bar", 0).build();
+
+        assert!(err.is_err());
+        assert!(Builder::new().pattern("(", 0).mode(Mode::Vectored).build().is_err());
+
+        match Builder::new().pattern("foo", HS_MODE_SOM_HORIZON_SMALL).build() {
+            Err(Error::PatternError(err)) => assert_eq!(err.id, 1),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_build_modes() {
+        let _ = env_logger::init();
+
+        let builder = Builder::new().pattern("foo", 0).pattern("bar", 0);
+
+        let dbs = builder.build_modes(&[Mode::Block, Mode::Streaming]).unwrap();
+
+        assert_eq!(dbs.iter().map(|db| db.scan_mode()).collect::<Vec<_>>(),
+                   vec![Mode::Block, Mode::Streaming]);
+
+        for db in &dbs {
+            validate_database(db);
+        }
+
+        let (block, streaming) = builder.build_pair::<Block, Streaming>().unwrap();
+
+        assert_matches!(&block, "foo bar", [(1, 3), (2, 7)]);
+        assert_eq!(streaming.database_mode(), HS_MODE_STREAM);
+
+        assert!(compile_modes(&builder.get_patterns(), &[], &PlatformInfo::null()).unwrap().is_empty());
+
+        let err = builder.clone().pattern("(", 0).build_modes(&[Mode::Streaming, Mode::Block]).unwrap_err();
+
+        assert!(err.to_string().contains("in Streaming mode"), "{}", err);
+
+        let err = builder.clone().pattern_with_id(1, "baz", 0).build_pair::<Block, Vectored>();
+
+        assert!(err.is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use std::mem;
+
+use constants::*;
+use api::*;
+use errors::Error;
+use compile::{Pattern, compile_patterns};
+use common::RawDatabase;
+
+const BUNDLE_MAGIC: &'static [u8] = b"HSBUNDLE";
+
+/// A target platform the patterns of a bundle are compiled for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// The tuning family (`HS_TUNE_FAMILY_*`).
+    pub tune: u32,
+    /// The CPU features (`HS_CPU_FEATURES_*`).
+    pub cpu_features: u64,
+}
+
+impl Target {
+    pub fn new(tune: u32, cpu_features: u64) -> Target {
+        Target {
+            tune: tune,
+            cpu_features: cpu_features,
+        }
+    }
+
+    /// Any CPU supported by Hyperscan (SSSE3).
+    pub fn generic() -> Target {
+        Target::new(HS_TUNE_FAMILY_GENERIC, 0)
+    }
+
+    /// CPUs with AVX2 (Haswell and later).
+    pub fn avx2() -> Target {
+        Target::new(HS_TUNE_FAMILY_HSW, HS_CPU_FEATURES_AVX2 as u64)
+    }
+
+    /// CPUs with AVX2 and AVX512 (Skylake server and later).
+    pub fn avx512() -> Target {
+        Target::new(HS_TUNE_FAMILY_HSW,
+                    (HS_CPU_FEATURES_AVX2 | HS_CPU_FEATURES_AVX512) as u64)
+    }
+
+    pub fn platform(&self) -> PlatformInfo {
+        PlatformInfo::new(self.tune, self.cpu_features)
+    }
+
+    /// Check whether a database compiled for the target can run on the platform.
+    pub fn runs_on(&self, platform: &PlatformInfo) -> bool {
+        self.cpu_features & !platform.cpu_features() == 0
+    }
+}
+
+/// A serialized database compiled for a target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub target: Target,
+    pub bytes: Vec<u8>,
+}
+
+/// The same pattern set compiled for several targets,
+/// so a single artifact can be deployed to hosts with different CPU features.
+///
+/// The serialized format is the `HSBUNDLE` magic, followed by the number of variants,
+/// and the tune, CPU features, length and bytes of each serialized database,
+/// with all the integers in little endian.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bundle {
+    variants: Vec<Variant>,
+}
+
+impl Bundle {
+    /// Compile the patterns for each target.
+    pub fn compile<T: Type>(patterns: &[Pattern], targets: &[Target]) -> Result<Bundle, Error> {
+        let mut variants = Vec::with_capacity(targets.len());
+
+        for target in targets {
+            let db: RawDatabase<T> = try!(compile_patterns(patterns, 0, &target.platform()));
+            let serialized = try!(db.serialize());
+
+            debug!("compiled {} database for {:?}, {} bytes",
+                   T::name(),
+                   target,
+                   serialized.len());
+
+            variants.push(Variant {
+                target: *target,
+                bytes: serialized.as_slice().to_vec(),
+            });
+        }
+
+        Ok(Bundle { variants: variants })
+    }
+
+    pub fn variants(&self) -> &[Variant] {
+        &self.variants
+    }
+
+    /// Select the variant using the most CPU features the platform provides,
+    /// preferring the variant tuned for the platform.
+    pub fn best_for(&self, platform: &PlatformInfo) -> Option<&Variant> {
+        self.variants
+            .iter()
+            .filter(|v| v.target.runs_on(platform))
+            .max_by_key(|v| (v.target.cpu_features.count_ones(), v.target.tune == platform.tune()))
+    }
+
+    /// Load the best variant for the current host.
+    pub fn load<T: Type>(&self) -> Result<RawDatabase<T>, Error> {
+        let host = PlatformInfo::host();
+
+        match self.best_for(&host) {
+            Some(variant) => {
+                debug!("load {} database for {:?}", T::name(), variant.target);
+
+                RawDatabase::load(&variant.bytes)
+            }
+            None => Err(Error::DbPlatformError),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::from(BUNDLE_MAGIC);
+
+        buf.extend_from_slice(&(self.variants.len() as u32).to_le_bytes());
+
+        for variant in &self.variants {
+            buf.extend_from_slice(&variant.target.tune.to_le_bytes());
+            buf.extend_from_slice(&variant.target.cpu_features.to_le_bytes());
+            buf.extend_from_slice(&(variant.bytes.len() as u64).to_le_bytes());
+            buf.extend_from_slice(&variant.bytes);
+        }
+
+        buf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bundle, Error> {
+        if !bytes.starts_with(BUNDLE_MAGIC) {
+            return Err(Error::Invalid);
+        }
+
+        let mut reader = Reader(&bytes[BUNDLE_MAGIC.len()..]);
+        let count = try!(reader.read_u32());
+        let mut variants = Vec::new();
+
+        for _ in 0..count {
+            let tune = try!(reader.read_u32());
+            let cpu_features = try!(reader.read_u64());
+            let len = try!(reader.read_u64());
+
+            variants.push(Variant {
+                target: Target::new(tune, cpu_features),
+                bytes: try!(reader.read(len as usize)).to_vec(),
+            });
+        }
+
+        if !reader.0.is_empty() {
+            return Err(Error::Invalid);
+        }
+
+        Ok(Bundle { variants: variants })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::Invalid);
+        }
+
+        let (head, tail) = self.0.split_at(len);
+
+        self.0 = tail;
+
+        Ok(head)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        let mut buf = [0; 4];
+
+        buf.copy_from_slice(try!(self.read(mem::size_of::<u32>())));
+
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        let mut buf = [0; 8];
+
+        buf.copy_from_slice(try!(self.read(mem::size_of::<u64>())));
+
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_bundle() {
+        let _ = env_logger::init();
+
+        let patterns = patterns!(["foo", "bar\\d+"]);
+        let bundle = Bundle::compile::<Block>(&patterns, &[Target::generic(), Target::avx2()]).unwrap();
+
+        assert_eq!(bundle.variants().len(), 2);
+
+        let bytes = bundle.to_bytes();
+
+        assert_eq!(Bundle::from_bytes(&bytes).unwrap(), bundle);
+        assert_eq!(Bundle::from_bytes(&bytes[..bytes.len() - 1]).err(), Some(Error::Invalid));
+
+        let sse = PlatformInfo::new(HS_TUNE_FAMILY_GENERIC, 0);
+        let avx2 = PlatformInfo::new(HS_TUNE_FAMILY_HSW, HS_CPU_FEATURES_AVX2 as u64);
+
+        assert_eq!(bundle.best_for(&sse).unwrap().target, Target::generic());
+        assert_eq!(bundle.best_for(&avx2).unwrap().target, Target::avx2());
+
+        let db: BlockDatabase = bundle.load().unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        assert_eq!(db.runs_on_current_host(), Ok(()));
+        assert_eq!(Bundle::default().load::<Block>().err(), Some(Error::DbPlatformError));
+    }
+}
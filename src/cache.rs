@@ -0,0 +1,222 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use api::*;
+use errors::Error;
+use common::RawDatabase;
+use compile::{Pattern, compile_patterns};
+use ids::fnv1a;
+
+/// A builder caching the compiled databases in a directory, so the following runs
+/// deserialize the database instead of compiling the patterns again.
+///
+/// A database is keyed by the hash of the patterns, the mode, the target platform and the Hyperscan version.
+/// The whole key is stored with the database and compared on load, so a hash collision is compiled again.
+/// A cached database which can't be read or deserialized is compiled again and replaced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedBuilder {
+    dir: PathBuf,
+    platform: Option<Platform>,
+}
+
+impl CachedBuilder {
+    /// Cache the databases in the directory, which is created on the first write.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> CachedBuilder {
+        CachedBuilder {
+            dir: dir.into(),
+            platform: None,
+        }
+    }
+
+    /// Compile the databases for the target platform, the current host by default.
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The cache key of the patterns compiled in the mode.
+    pub fn key<T: Type>(&self, patterns: &[Pattern]) -> Result<u64, Error> {
+        Ok(fnv1a(try!(self.describe::<T>(patterns)).as_bytes()))
+    }
+
+    /// The whole cache key, hashed by `key` and stored with the cached database.
+    fn describe<T: Type>(&self, patterns: &[Pattern]) -> Result<String, Error> {
+        let platform = match self.platform {
+            Some(platform) => platform,
+            None => try!(current_platform()),
+        };
+
+        let mut key = format!("{} {} {} {:x}\n",
+                              try!(Version::library()),
+                              T::name(),
+                              platform.tune(),
+                              platform.cpu_features());
+
+        for pattern in patterns {
+            key.push_str(&pattern.to_string());
+            key.push('\n');
+        }
+
+        Ok(key)
+    }
+
+    /// The path of the cached database of the patterns compiled in the mode.
+    pub fn path<T: Type>(&self, patterns: &[Pattern]) -> Result<PathBuf, Error> {
+        Ok(self.path_of::<T>(fnv1a(try!(self.describe::<T>(patterns)).as_bytes())))
+    }
+
+    fn path_of<T: Type>(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}.hsdb", key, T::name().to_lowercase()))
+    }
+
+    /// Whether the database of the patterns compiled in the mode is cached.
+    pub fn is_cached<T: Type>(&self, patterns: &[Pattern]) -> bool {
+        self.path::<T>(patterns).map(|path| path.is_file()).unwrap_or(false)
+    }
+
+    /// Deserialize the cached database of the patterns, or compile and cache it.
+    ///
+    /// Failing to write the cache doesn't fail the build, the compiled database is returned anyway.
+    pub fn build<T: Type>(&self, patterns: &[Pattern]) -> Result<RawDatabase<T>, Error> {
+        let key = try!(self.describe::<T>(patterns));
+        let path = self.path_of::<T>(fnv1a(key.as_bytes()));
+
+        if let Some(db) = self.load(&path, &key) {
+            return Ok(db);
+        }
+
+        let db = try!(compile_patterns(patterns, 0, &platform_info(self.platform)));
+
+        match self.store(&path, &key, &db) {
+            Ok(size) => debug!("cached {} patterns to {:?}, {} bytes", patterns.len(), path, size),
+            Err(err) => warn!("fail to cache {} patterns to {:?}, {}", patterns.len(), path, err),
+        }
+
+        Ok(db)
+    }
+
+    /// The cached file holds the length of the key as 8 bytes in little endian, the key and the serialized database.
+    fn load<T: Type>(&self, path: &Path, key: &str) -> Option<RawDatabase<T>> {
+        let mut bytes = Vec::new();
+
+        if let Err(err) = File::open(path).and_then(|mut f| f.read_to_end(&mut bytes)) {
+            trace!("database {:?} not cached, {}", path, err);
+
+            return None;
+        }
+
+        let header = 8 + key.len();
+
+        if bytes.len() < header || bytes[..8] != (key.len() as u64).to_le_bytes() ||
+           &bytes[8..header] != key.as_bytes() {
+            warn!("cached database {:?} was compiled from other patterns", path);
+
+            return None;
+        }
+
+        let bytes = &bytes[header..];
+
+        match RawDatabase::<T>::deserialize(bytes) {
+            Ok(db) => {
+                debug!("loaded cached database {:?}, {} bytes", path, bytes.len());
+
+                Some(db)
+            }
+            Err(err) => {
+                warn!("fail to load cached database {:?}, {}", path, err);
+
+                None
+            }
+        }
+    }
+
+    /// Write the database to a temporary file renamed into place, so a concurrent build never reads a partial file.
+    fn store<T: Type>(&self, path: &Path, key: &str, db: &RawDatabase<T>) -> io::Result<usize> {
+        let data = try!(db.serialize());
+        let tmp = path.with_extension(format!("tmp.{}", process::id()));
+
+        try!(fs::create_dir_all(&self.dir));
+        try!(File::create(&tmp).and_then(|mut f| {
+            try!(f.write_all(&(key.len() as u64).to_le_bytes()));
+            try!(f.write_all(key.as_bytes()));
+            f.write_all(data.as_slice())
+        }));
+
+        if let Err(err) = fs::rename(&tmp, path) {
+            let _ = fs::remove_file(&tmp);
+
+            return Err(err);
+        }
+
+        Ok(data.len())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::super::*;
+
+    #[test]
+    fn test_cached_builder() {
+        let _ = env_logger::init();
+
+        let dir = env::temp_dir().join(format!("test_cached_builder.{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let builder = CachedBuilder::new(&dir);
+        let patterns = patterns!(["foo", "bar\\d+"]);
+
+        assert!(!builder.is_cached::<Block>(&patterns));
+
+        let db: BlockDatabase = builder.build(&patterns).unwrap();
+
+        assert!(builder.is_cached::<Block>(&patterns));
+        assert!(!builder.is_cached::<Streaming>(&patterns));
+        assert!(!builder.is_cached::<Block>(&patterns!(["foo", "bar\\d*"])));
+
+        let cached: BlockDatabase = builder.build(&patterns).unwrap();
+
+        assert_eq!(database_hash(&cached).unwrap(), database_hash(&db).unwrap());
+        assert_matches!(&cached, "foo bar12", [(1, 3), (2, 8), (2, 9)]);
+
+        let generic = builder.clone().with_platform(Platform::generic());
+
+        assert!(generic.key::<Block>(&patterns).unwrap() != builder.key::<Block>(&patterns).unwrap() ||
+                Platform::host() == Platform::generic());
+
+        let path = builder.path::<Block>(&patterns).unwrap();
+
+        fs::write(&path, b"garbage").unwrap();
+
+        let db: BlockDatabase = builder.build(&patterns).unwrap();
+
+        assert_eq!(database_hash(&db).unwrap(), database_hash(&cached).unwrap());
+        assert!(fs::metadata(&path).unwrap().len() > 7);
+
+        let other = patterns!(["baz"]);
+        let _: BlockDatabase = builder.build(&other).unwrap();
+
+        fs::copy(builder.path::<Block>(&other).unwrap(), &path).unwrap();
+
+        let db: BlockDatabase = builder.build(&patterns).unwrap();
+
+        assert_eq!(database_hash(&db).unwrap(), database_hash(&cached).unwrap());
+        assert_matches!(&db, "foo baz", [(1, 3)]);
+
+        assert!(builder.build::<Block>(&patterns!(["("])).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use api::*;
+use errors::Error;
+use common::BlockDatabase;
+use runtime::{Feed, RawStream};
+
+/// A handle to abort scans from another thread.
+///
+/// Hyperscan can only be stopped from the match event callback, so the token is checked
+/// when a match is located, and between chunks when feeding a stream.
+/// A scan which doesn't match runs to the end of its buffer.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+/// A match event callback and its context, aborted by a `CancellationToken`.
+pub struct Cancellable<'a, D: 'a> {
+    token: &'a CancellationToken,
+    callback: Option<MatchEventCallback<D>>,
+    context: Option<&'a D>,
+}
+
+impl<'a, D> Cancellable<'a, D> {
+    /// The match event callback to pass to the scan functions with the `Cancellable` context.
+    pub fn on_match(id: u32, from: u64, to: u64, flags: u32, cancellable: &Cancellable<D>) -> u32 {
+        if cancellable.token.is_cancelled() {
+            return 1;
+        }
+
+        match (cancellable.callback, cancellable.context) {
+            (Some(callback), Some(context)) => callback(id, from, to, flags, context),
+            _ => 0,
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Request that the scans using the token cease.
+    pub fn cancel(&self) {
+        debug!("scan cancelled");
+
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns `Error::Cancelled` if the token was cancelled.
+    #[inline]
+    pub fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wrap a match event callback and its context, so the scan ceases once the token is cancelled.
+    pub fn wrap<'a, D>(&'a self,
+                       callback: Option<MatchEventCallback<D>>,
+                       context: Option<&'a D>)
+                       -> Cancellable<'a, D> {
+        Cancellable {
+            token: self,
+            callback: callback,
+            context: context,
+        }
+    }
+
+    /// Wrap a match handler, so the scan ceases once the token is cancelled.
+    pub fn handler<'a, F>(&'a self, mut handler: F) -> impl FnMut(u32, u64, u64, u32) -> u32 + 'a
+        where F: FnMut(u32, u64, u64, u32) -> u32 + 'a
+    {
+        move |id, from, to, flags| if self.is_cancelled() {
+            1
+        } else {
+            handler(id, from, to, flags)
+        }
+    }
+
+    /// Scan a block, returns `Error::Cancelled` if the scan was aborted with the token.
+    pub fn scan<T, S, D>(&self,
+                         db: &BlockDatabase,
+                         data: T,
+                         flags: ScanFlags,
+                         scratch: &S,
+                         callback: Option<MatchEventCallback<D>>,
+                         context: Option<&D>)
+                         -> Result<(), Error>
+        where T: Scannable,
+              S: Scratch
+    {
+        try!(self.check());
+
+        let cancellable = self.wrap(callback, context);
+
+        match db.scan(data, flags, scratch, Some(Cancellable::on_match), Some(&cancellable)) {
+            Err(Error::ScanTerminated) if self.is_cancelled() => Err(Error::Cancelled),
+            Err(err) => Err(err),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Feed chunks to a stream, checking the token between the chunks,
+    /// returns `Error::Cancelled` if the feed was aborted with the token.
+    pub fn feed<I, S, F>(&self, stream: &RawStream, chunks: I, scratch: &S, handler: F) -> Result<Feed, Error>
+        where I: IntoIterator,
+              I::Item: Scannable,
+              S: Scratch,
+              F: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let feed = try!(stream.feed(chunks.into_iter().take_while(|_| !self.is_cancelled()),
+                                    scratch,
+                                    self.handler(handler)));
+
+        try!(self.check());
+
+        Ok(feed)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::Cell;
+
+    use super::super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"a"}.build().unwrap();
+        let s = db.alloc().unwrap();
+        let token = CancellationToken::new();
+
+        fn callback(_: u32, _: u64, _: u64, _: u32, ctx: &(CancellationToken, Cell<usize>)) -> u32 {
+            ctx.1.set(ctx.1.get() + 1);
+
+            if ctx.1.get() == 2 {
+                ctx.0.cancel();
+            }
+
+            0
+        }
+
+        let ctx = (token.clone(), Cell::new(0));
+
+        assert_eq!(token.scan(&db, "aaaa", 0, &s, Some(callback), Some(&ctx)).err(),
+                   Some(Error::Cancelled));
+        assert_eq!(ctx.1.get(), 2);
+        assert_eq!(token.scan(&db, "aaaa", 0, &s, None, None::<&()>).err(),
+                   Some(Error::Cancelled));
+
+        let token = CancellationToken::new();
+
+        token.scan(&db, "aaaa", 0, &s, None, None::<&()>).unwrap();
+
+        let db: StreamingDatabase = pattern!{"a"}.build().unwrap();
+        let s = db.alloc().unwrap();
+        let st = db.open_stream(0).unwrap();
+
+        let mut pulled = 0;
+
+        assert_eq!(token.feed(&st,
+                        vec!["b", "b", "b"].into_iter().inspect(|_| {
+                pulled += 1;
+
+                if pulled == 2 {
+                    token.cancel();
+                }
+            }),
+                        &s,
+                        |_, _, _, _| 0)
+                       .err(),
+                   Some(Error::Cancelled));
+        assert_eq!(pulled, 2);
+        assert_eq!(st.scan_stats().bytes, 1);
+
+        st.close(&s, None, None::<&()>).unwrap();
+    }
+}
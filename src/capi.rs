@@ -0,0 +1,283 @@
+//! A small C ABI over the block scanner, so non-Rust applications can embed this crate instead of `libhs`.
+//!
+//! Enable the `capi` feature and build the crate as a C library,
+//! e.g. `cargo rustc --release --features capi --crate-type cdylib`; the declarations are in `include/hsrs.h`.
+//!
+//! The functions return the `HS_*` error codes of Hyperscan, a failed compile also returns
+//! the error message, which must be freed with `hsrs_free_error`.
+#![allow(non_camel_case_types)]
+
+use std::ptr;
+use std::panic::{self, AssertUnwindSafe};
+use std::ffi::{CStr, CString};
+use std::io::Cursor;
+
+use libc::{c_char, c_int, c_uint, c_ulonglong, c_void, size_t};
+
+use api::*;
+use constants::*;
+use errors::Error;
+use common::BlockDatabase;
+use compile::Patterns;
+use pool::ScratchPool;
+
+/// The callback called for each match, a non-zero return value stops the scan.
+pub type hsrs_match_callback = extern "C" fn(id: c_uint,
+                                             from: c_ulonglong,
+                                             to: c_ulonglong,
+                                             context: *mut c_void)
+                                             -> c_int;
+
+/// A compiled block database with its scratch pool, opaque to C.
+///
+/// A database may be scanned from several threads at once, each scan takes its own scratch.
+pub struct hsrs_database {
+    db: BlockDatabase,
+    pool: ScratchPool,
+}
+
+struct MatchContext {
+    on_match: hsrs_match_callback,
+    context: *mut c_void,
+}
+
+fn on_match(id: u32, from: u64, to: u64, _: u32, ctxt: &MatchContext) -> u32 {
+    ((ctxt.on_match)(id, from, to, ctxt.context) != 0) as u32
+}
+
+/// The `HS_*` code of the error.
+fn error_code(err: &Error) -> c_int {
+    match *err {
+        Error::Invalid => HS_INVALID,
+        Error::NoMem => HS_NOMEM,
+        Error::ScanTerminated => HS_SCAN_TERMINATED,
+        Error::CompilerError(_) |
+        Error::PatternError(_) => HS_COMPILER_ERROR,
+        Error::DbVersionError => HS_DB_VERSION_ERROR,
+        Error::DbPlatformError => HS_DB_PLATFORM_ERROR,
+        Error::DbModeError => HS_DB_MODE_ERROR,
+        Error::BadAlign => HS_BAD_ALIGN,
+        Error::BadAlloc => HS_BAD_ALLOC,
+        Error::ScratchInUse => HS_SCRATCH_IN_USE,
+        Error::ArchError => HS_ARCH_ERROR,
+        Error::Failed(code) => code,
+        _ => HS_INVALID,
+    }
+}
+
+/// Run the body without unwinding a panic into the C caller.
+fn guarded<F: FnOnce() -> c_int>(f: F) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        error!("panic in the C API");
+
+        HS_INVALID
+    })
+}
+
+fn compile(patterns: &[u8]) -> Result<hsrs_database, String> {
+    let patterns = try!(Patterns::read(Cursor::new(patterns)).map_err(|err| err.to_string()));
+    let db: BlockDatabase = try!(patterns.build().map_err(|err| err.to_string()));
+    let pool = try!(ScratchPool::new(&db).map_err(|err| err.to_string()));
+
+    Ok(hsrs_database { db: db, pool: pool })
+}
+
+/// Compile the patterns to a block database.
+///
+/// The patterns are a NUL-terminated string with a pattern per line in the `id:/expression/flags` format,
+/// ignoring empty lines and lines starting with '#'.
+///
+/// On success, the database is stored in `db`; otherwise, the message is stored in `error` unless it is NULL.
+///
+/// # Safety
+///
+/// `patterns` must point to a NUL-terminated string, valid for reads for the duration of the call.
+/// `db` must be valid for a write of a pointer, and `error` must be NULL or valid for a write of a pointer.
+/// The returned database must be freed once with `hsrs_free_database` and the message with `hsrs_free_error`.
+#[no_mangle]
+pub unsafe extern "C" fn hsrs_compile(patterns: *const c_char,
+                                      db: *mut *mut hsrs_database,
+                                      error: *mut *mut c_char)
+                                      -> c_int {
+    guarded(|| {
+        if !error.is_null() {
+            *error = ptr::null_mut();
+        }
+
+        if patterns.is_null() || db.is_null() {
+            return HS_INVALID;
+        }
+
+        match compile(CStr::from_ptr(patterns).to_bytes()) {
+            Ok(compiled) => {
+                *db = Box::into_raw(Box::new(compiled));
+
+                HS_SUCCESS
+            }
+            Err(message) => {
+                debug!("fail to compile patterns, {}", message);
+
+                if !error.is_null() {
+                    *error = CString::new(message.replace('\0', " ")).unwrap().into_raw();
+                }
+
+                HS_COMPILER_ERROR
+            }
+        }
+    })
+}
+
+/// Scan a buffer with the database, calling `on_match` for each match unless it is NULL.
+///
+/// Returns `HS_SCAN_TERMINATED` if the callback stopped the scan.
+///
+/// # Safety
+///
+/// `db` must be a database returned by `hsrs_compile` and not yet freed, which must not be freed during the scan.
+/// Unless `length` is 0, `data` must point to `length` bytes valid for reads for the duration of the call.
+/// `context` is passed as is to the callback, which is called on the scanning thread before `hsrs_scan` returns.
+#[no_mangle]
+pub unsafe extern "C" fn hsrs_scan(db: *const hsrs_database,
+                                   data: *const c_char,
+                                   length: size_t,
+                                   on_match_cb: Option<hsrs_match_callback>,
+                                   context: *mut c_void)
+                                   -> c_int {
+    guarded(|| {
+        if db.is_null() || (data.is_null() && length > 0) {
+            return HS_INVALID;
+        }
+
+        let db = &*db;
+        let data: &[u8] = if length == 0 {
+            &[]
+        } else {
+            ::std::slice::from_raw_parts(data as *const u8, length)
+        };
+
+        let result = db.pool.with_scratch(|scratch| {
+            match on_match_cb {
+                Some(f) => {
+                    let ctxt = MatchContext {
+                        on_match: f,
+                        context: context,
+                    };
+
+                    db.db.scan(data, 0, scratch, Some(on_match), Some(&ctxt)).map(|_| ())
+                }
+                None => db.db.scan(data, 0, scratch, None, None::<&()>).map(|_| ()),
+            }
+        });
+
+        match result {
+            Ok(()) => HS_SUCCESS,
+            Err(err) => error_code(&err),
+        }
+    })
+}
+
+/// Free a database returned by `hsrs_compile`, NULL is ignored.
+///
+/// # Safety
+///
+/// `db` must be NULL or a database returned by `hsrs_compile` which wasn't freed yet,
+/// and no scan may use it during or after the call.
+#[no_mangle]
+pub unsafe extern "C" fn hsrs_free_database(db: *mut hsrs_database) {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+}
+
+/// Free an error message returned by `hsrs_compile`, NULL is ignored.
+///
+/// # Safety
+///
+/// `error` must be NULL or a message returned by `hsrs_compile` which wasn't freed yet,
+/// and it must not be used after the call.
+#[no_mangle]
+pub unsafe extern "C" fn hsrs_free_error(error: *mut c_char) {
+    if !error.is_null() {
+        drop(CString::from_raw(error));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::ptr;
+    use std::ffi::CStr;
+
+    use libc::{c_int, c_uint, c_ulonglong, c_void};
+
+    use super::super::*;
+
+    extern "C" fn collect(id: c_uint, _: c_ulonglong, to: c_ulonglong, context: *mut c_void) -> c_int {
+        let matches = unsafe { &mut *(context as *mut Vec<(u32, u64)>) };
+
+        matches.push((id, to));
+
+        (id == 3) as c_int
+    }
+
+    #[test]
+    fn test_capi() {
+        let _ = env_logger::init();
+
+        unsafe {
+            let mut db = ptr::null_mut();
+            let mut error = ptr::null_mut();
+
+            assert_eq!(hsrs_compile(b"# rules\n1:/foo/\n2:/bar\\d+/i\n\n3:/stop/\n\0".as_ptr() as *const _,
+                                    &mut db,
+                                    &mut error),
+                       HS_SUCCESS);
+            assert!(!db.is_null());
+            assert!(error.is_null());
+
+            let mut matches: Vec<(u32, u64)> = Vec::new();
+            let data = b"foo BAR12";
+
+            assert_eq!(hsrs_scan(db,
+                                 data.as_ptr() as *const _,
+                                 data.len(),
+                                 Some(collect),
+                                 &mut matches as *mut _ as *mut c_void),
+                       HS_SUCCESS);
+            assert_eq!(matches, vec![(1, 3), (2, 8), (2, 9)]);
+
+            matches.clear();
+
+            let data = b"stop foo";
+
+            assert_eq!(hsrs_scan(db,
+                                 data.as_ptr() as *const _,
+                                 data.len(),
+                                 Some(collect),
+                                 &mut matches as *mut _ as *mut c_void),
+                       HS_SCAN_TERMINATED);
+            assert_eq!(matches, vec![(3, 4)]);
+
+            assert_eq!(hsrs_scan(db, ptr::null(), 0, None, ptr::null_mut()), HS_SUCCESS);
+            assert_eq!(hsrs_scan(db, ptr::null(), 1, None, ptr::null_mut()), HS_INVALID);
+
+            hsrs_free_database(db);
+
+            let mut db = ptr::null_mut();
+
+            assert_eq!(hsrs_compile(b"1:/foo(/\n\0".as_ptr() as *const _, &mut db, &mut error),
+                       HS_COMPILER_ERROR);
+            assert!(db.is_null());
+            assert!(!error.is_null());
+            assert!(!CStr::from_ptr(error).to_bytes().is_empty());
+
+            hsrs_free_error(error);
+
+            assert_eq!(hsrs_compile(ptr::null(), &mut db, ptr::null_mut()), HS_INVALID);
+
+            hsrs_free_database(ptr::null_mut());
+            hsrs_free_error(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Collect the simple case folding variants of a character.
+///
+/// Only single character mappings are considered, so `ß` (which upper cases to `SS`)
+/// is left untouched while `ſ`, `s` and `S` all fold together.
+fn case_variants(c: char) -> Vec<char> {
+    fn single<I: Iterator<Item = char>>(mut it: I) -> Option<char> {
+        match (it.next(), it.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    }
+
+    let mut variants = vec![c];
+
+    let lower = single(c.to_lowercase());
+    let upper = single(c.to_uppercase());
+
+    let candidates = vec![lower,
+                          upper,
+                          lower.and_then(|l| single(l.to_uppercase())),
+                          upper.and_then(|u| single(u.to_lowercase()))];
+
+    for v in candidates.into_iter().filter_map(|v| v) {
+        if !variants.contains(&v) {
+            variants.push(v);
+        }
+    }
+
+    variants
+}
+
+/// Copy an escape sequence (the leading `\` has already been consumed).
+fn copy_escape(chars: &mut Peekable<Chars>, out: &mut String) {
+    out.push('\\');
+
+    if let Some(c) = chars.next() {
+        out.push(c);
+
+        match c {
+            'x' | 'o' | 'p' | 'P' | 'N' | 'g' | 'k' if chars.peek() == Some(&'{') => {
+                while let Some(c) = chars.next() {
+                    out.push(c);
+
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Copy a character class, appending the case variants of its non-ASCII members.
+fn fold_class(chars: &mut Peekable<Chars>, out: &mut String) -> bool {
+    let mut folded = false;
+
+    out.push('[');
+
+    if chars.peek() == Some(&'^') {
+        out.push(chars.next().unwrap());
+    }
+    if chars.peek() == Some(&']') {
+        out.push(chars.next().unwrap());
+    }
+
+    let mut in_range = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            ']' => {
+                out.push(c);
+                break;
+            }
+            '\\' => copy_escape(chars, out),
+            '[' if chars.peek() == Some(&':') => {
+                out.push(c);
+
+                while let Some(c) = chars.next() {
+                    out.push(c);
+
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            '-' => {
+                in_range = true;
+                out.push(c);
+                continue;
+            }
+            _ => {
+                let starts_range = chars.peek() == Some(&'-');
+
+                out.push(c);
+
+                if !in_range && !starts_range && !c.is_ascii() {
+                    for v in case_variants(c).into_iter().skip(1) {
+                        out.push(v);
+
+                        folded = true;
+                    }
+                }
+            }
+        }
+
+        in_range = false;
+    }
+
+    folded
+}
+
+/// Push a literal character, replacing it with a class of its case variants if it has any.
+fn fold_literal(c: char, out: &mut String) -> bool {
+    let variants = case_variants(c);
+
+    if c.is_ascii() || variants.len() == 1 {
+        out.push(c);
+
+        false
+    } else {
+        out.push('[');
+        out.extend(variants);
+        out.push(']');
+
+        true
+    }
+}
+
+/// Apply Unicode simple case folding to the literal segments of an expression.
+///
+/// `HS_FLAG_CASELESS` only folds ASCII letters, so every non-ASCII literal character
+/// with case variants is rewritten as a class of its variants (`é` becomes `[éÉ]`),
+/// including characters inside existing classes and `\Q...\E` quotes.
+/// Escape sequences, ranges and comments are copied verbatim.
+///
+/// Returns `None` if nothing in the expression needed folding.
+pub fn fold_case(expression: &str) -> Option<String> {
+    let mut out = String::with_capacity(expression.len());
+    let mut chars = expression.chars().peekable();
+    let mut folded = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'Q') => {
+                chars.next();
+
+                out.push_str("\\Q");
+
+                while let Some(c) = chars.next() {
+                    if c == '\\' && chars.peek() == Some(&'E') {
+                        chars.next();
+                        break;
+                    }
+
+                    if !c.is_ascii() && case_variants(c).len() > 1 {
+                        out.push_str("\\E");
+                        fold_literal(c, &mut out);
+                        out.push_str("\\Q");
+
+                        folded = true;
+                    } else {
+                        out.push(c);
+                    }
+                }
+
+                out.push_str("\\E");
+            }
+            '\\' => copy_escape(&mut chars, &mut out),
+            '[' => folded |= fold_class(&mut chars, &mut out),
+            '(' if chars.peek() == Some(&'?') => {
+                out.push(c);
+                out.push(chars.next().unwrap());
+
+                if chars.peek() == Some(&'#') {
+                    while let Some(c) = chars.next() {
+                        out.push(c);
+
+                        if c == ')' {
+                            break;
+                        }
+                    }
+                }
+            }
+            _ => folded |= fold_literal(c, &mut out),
+        }
+    }
+
+    if folded { Some(out) } else { None }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_variants() {
+        assert_eq!(case_variants('a'), vec!['a', 'A']);
+        assert_eq!(case_variants('é'), vec!['é', 'É']);
+        assert_eq!(case_variants('Σ'), vec!['Σ', 'σ']);
+        assert_eq!(case_variants('ſ'), vec!['ſ', 'S', 's']);
+        assert_eq!(case_variants('ß'), vec!['ß']);
+        assert_eq!(case_variants('1'), vec!['1']);
+    }
+
+    #[test]
+    fn test_fold_case() {
+        assert_eq!(fold_case("test"), None);
+        assert_eq!(fold_case("straße"), None);
+        assert_eq!(fold_case("café"), Some(String::from("caf[éÉ]")));
+        assert_eq!(fold_case("ΣΑ+"), Some(String::from("[Σσ][Αα]+")));
+        assert_eq!(fold_case("[éa-z]"), Some(String::from("[éÉa-z]")));
+        assert_eq!(fold_case("[à-ÿ]"), None);
+        assert_eq!(fold_case("\\x{e9}é"), Some(String::from("\\x{e9}[éÉ]")));
+        assert_eq!(fold_case("\\Qé.\\E"), Some(String::from("\\Q\\E[éÉ]\\Q.\\E")));
+        assert_eq!(fold_case("(?#é)x"), None);
+    }
+}
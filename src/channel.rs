@@ -0,0 +1,261 @@
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::collections::VecDeque;
+
+use api::Match;
+
+/// What a sender does when the channel is full because the consumer lags.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Block the scanning thread until the consumer makes room.
+    Block,
+    /// Drop the oldest queued match to make room for the new one.
+    DropOldest,
+    /// Drop the new match and request that scanning cease.
+    Terminate,
+}
+
+struct State {
+    queue: VecDeque<Match>,
+    senders: usize,
+    receiver: bool,
+    dropped: u64,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: Backpressure,
+}
+
+/// Create a bounded channel of matches, with the policy applied when it is full.
+///
+/// The sending half is used as a match handler, so scanning threads never buffer
+/// more than `capacity` matches, however many the patterns produce.
+pub fn match_channel(capacity: usize, policy: Backpressure) -> (MatchSender, MatchReceiver) {
+    assert!(capacity > 0, "capacity must be positive");
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            senders: 1,
+            receiver: true,
+            dropped: 0,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        capacity: capacity,
+        policy: policy,
+    });
+
+    (MatchSender { shared: shared.clone() }, MatchReceiver { shared: shared })
+}
+
+/// The sending half of a match channel.
+pub struct MatchSender {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for MatchSender {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "MatchSender{{capacity: {}, policy: {:?}}}",
+               self.shared.capacity,
+               self.shared.policy)
+    }
+}
+
+impl MatchSender {
+    /// Send a match, returns whether scanning should continue.
+    ///
+    /// Scanning should cease when the receiver was dropped,
+    /// or the channel is full with the `Terminate` policy.
+    pub fn send(&self, m: Match) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+
+        while state.receiver && state.queue.len() >= self.shared.capacity {
+            match self.shared.policy {
+                Backpressure::Block => state = self.shared.not_full.wait(state).unwrap(),
+                Backpressure::DropOldest => {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                }
+                Backpressure::Terminate => {
+                    state.dropped += 1;
+
+                    debug!("match channel full, terminate scanning");
+
+                    return false;
+                }
+            }
+        }
+
+        if !state.receiver {
+            return false;
+        }
+
+        state.queue.push_back(m);
+
+        self.shared.not_empty.notify_one();
+
+        true
+    }
+
+    /// The match event callback to pass to the scan functions with the `MatchSender` context.
+    pub fn on_match(id: u32, from: u64, to: u64, flags: u32, sender: &MatchSender) -> u32 {
+        if sender.send(Match::new(id, from, to, flags)) { 0 } else { 1 }
+    }
+
+    /// A match handler forwarding the matches to the channel, for `RawStream::feed`.
+    pub fn handler<'a>(&'a self) -> impl FnMut(u32, u64, u64, u32) -> u32 + 'a {
+        move |id, from, to, flags| MatchSender::on_match(id, from, to, flags, self)
+    }
+}
+
+impl Clone for MatchSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+
+        MatchSender { shared: self.shared.clone() }
+    }
+}
+
+impl Drop for MatchSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+
+        state.senders -= 1;
+
+        if state.senders == 0 {
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+/// The receiving half of a match channel.
+pub struct MatchReceiver {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for MatchReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MatchReceiver{{pending: {}, dropped: {}}}", self.len(), self.dropped())
+    }
+}
+
+impl MatchReceiver {
+    /// Wait for a match, returns `None` once all the senders were dropped and the channel is drained.
+    pub fn recv(&self) -> Option<Match> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        loop {
+            if let Some(m) = state.queue.pop_front() {
+                self.shared.not_full.notify_one();
+
+                return Some(m);
+            }
+
+            if state.senders == 0 {
+                return None;
+            }
+
+            state = self.shared.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// Take a match if one is queued.
+    pub fn try_recv(&self) -> Option<Match> {
+        let m = self.shared.state.lock().unwrap().queue.pop_front();
+
+        if m.is_some() {
+            self.shared.not_full.notify_one();
+        }
+
+        m
+    }
+
+    /// The number of queued matches.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of matches dropped because the channel was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+}
+
+impl Iterator for MatchReceiver {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        self.recv()
+    }
+}
+
+impl Drop for MatchReceiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver = false;
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::thread;
+
+    use super::super::*;
+
+    #[test]
+    fn test_match_channel() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"a"}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let (tx, rx) = match_channel(2, Backpressure::DropOldest);
+
+        db.scan("aaaa", 0, &s, Some(MatchSender::on_match), Some(&tx)).unwrap();
+
+        assert_eq!(rx.dropped(), 2);
+        assert_eq!(rx.try_recv().map(|m| m.to), Some(3));
+        assert_eq!(rx.try_recv().map(|m| m.to), Some(4));
+        assert_eq!(rx.try_recv(), None);
+
+        let (tx, rx) = match_channel(2, Backpressure::Terminate);
+
+        assert_eq!(db.scan("aaaa", 0, &s, Some(MatchSender::on_match), Some(&tx)).err(),
+                   Some(Error::ScanTerminated));
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.dropped(), 1);
+
+        drop(rx);
+
+        assert!(!tx.send(Match::new(0, 0, 1, 0)));
+
+        let (tx, rx) = match_channel(1, Backpressure::Block);
+
+        let producer = thread::spawn(move || {
+            let db: StreamingDatabase = pattern!{"a"}.build().unwrap();
+            let s = db.alloc().unwrap();
+            let st = db.open_stream(0).unwrap();
+
+            let feed = st.feed(vec!["aa", "aaa"], &s, tx.handler()).unwrap();
+
+            st.close(&s, None, None::<&()>).unwrap();
+
+            feed
+        });
+
+        assert_eq!(rx.map(|m| m.to).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert!(!producer.join().unwrap().terminated);
+    }
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+use std::cmp;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use api::*;
+use constants::*;
+use errors::Error;
+use common::BlockDatabase;
+use compile::{Pattern, Patterns};
+
+/// A label detected by a classifier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection<L> {
+    /// The detected label.
+    pub label: L,
+    /// The weight of the fired patterns of the label over the weight of all its patterns, in `0.0..=1.0`.
+    pub confidence: f64,
+    /// The IDs of the fired patterns of the label.
+    pub ids: Vec<u32>,
+}
+
+struct Class<L> {
+    label: L,
+    weight: u32,
+}
+
+/// A classifier mapping the pattern IDs to labels, like the protocol of a flow,
+/// which scans only the first bytes of each stream.
+///
+/// A label is supported by all its patterns, an anchored pattern (starting with `\A` or `^`)
+/// weighs twice an unanchored one, since it can only fire at the beginning of the stream.
+pub struct Classifier<L> {
+    db: BlockDatabase,
+    classes: Vec<Class<L>>,
+    rules: HashMap<u32, (usize, u32)>,
+    window: usize,
+}
+
+impl<L: fmt::Debug> fmt::Debug for Classifier<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Classifier{{labels: {:?}, patterns: {}, window: {}}}",
+               self.classes.iter().map(|class| &class.label).collect::<Vec<_>>(),
+               self.rules.len(),
+               self.window)
+    }
+}
+
+fn is_anchored(pattern: &Pattern) -> bool {
+    pattern.expression.starts_with(r"\A") ||
+    (pattern.expression.starts_with('^') && !pattern.flags.is_set(HS_FLAG_MULTILINE))
+}
+
+fn on_fired(id: u32, _: u64, _: u64, _: u32, fired: &RefCell<Vec<u32>>) -> u32 {
+    fired.borrow_mut().push(id);
+
+    0
+}
+
+impl<L: Clone + PartialEq> Classifier<L> {
+    /// Compile the patterns of the labels, only the first `window` bytes of a stream are scanned.
+    ///
+    /// Returns `Error::Invalid` if two patterns have the same ID.
+    pub fn new<I>(rules: I, window: usize) -> Result<Classifier<L>, Error>
+        where I: IntoIterator<Item = (Pattern, L)>
+    {
+        let mut patterns = Patterns::new();
+        let mut classes: Vec<Class<L>> = Vec::new();
+        let mut ids = HashMap::new();
+
+        for (mut pattern, label) in rules {
+            let weight = if is_anchored(&pattern) { 2 } else { 1 };
+            let class = match classes.iter().position(|class| class.label == label) {
+                Some(class) => class,
+                None => {
+                    classes.push(Class {
+                        label: label,
+                        weight: 0,
+                    });
+
+                    classes.len() - 1
+                }
+            };
+
+            if ids.insert(pattern.id as u32, (class, weight)).is_some() {
+                return Err(Error::Invalid);
+            }
+
+            classes[class].weight += weight;
+
+            pattern.flags.set(HS_FLAG_SINGLEMATCH);
+            patterns.push(pattern);
+        }
+
+        debug!("compiling classifier with {} patterns of {} labels",
+               patterns.len(),
+               classes.len());
+
+        Ok(Classifier {
+            db: try!(patterns.build()),
+            classes: classes,
+            rules: ids,
+            window: window,
+        })
+    }
+
+    /// The labels of the classifier.
+    pub fn labels<'a>(&'a self) -> impl Iterator<Item = &'a L> + 'a {
+        self.classes.iter().map(|class| &class.label)
+    }
+
+    /// The number of bytes scanned at the beginning of a stream.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// The database of the classifier, to allocate the scratch space.
+    pub fn database(&self) -> &BlockDatabase {
+        &self.db
+    }
+
+    /// Classify the first bytes of a stream, returns the label with the best confidence,
+    /// or `None` if no pattern fired.
+    pub fn classify<S: Scratch>(&self, data: &[u8], scratch: &S) -> Result<Option<Detection<L>>, Error> {
+        let fired = RefCell::new(Vec::new());
+        let data = &data[..cmp::min(data.len(), self.window)];
+
+        try!(self.db.scan(data, 0, scratch, Some(on_fired), Some(&fired)));
+
+        let mut detections: Vec<(u32, Vec<u32>)> = vec![(0, Vec::new()); self.classes.len()];
+
+        for id in fired.into_inner() {
+            if let Some(&(class, weight)) = self.rules.get(&id) {
+                detections[class].0 += weight;
+                detections[class].1.push(id);
+            }
+        }
+
+        let mut best: Option<Detection<L>> = None;
+        let mut best_weight = 0;
+
+        for (class, (weight, ids)) in detections.into_iter().enumerate() {
+            if weight == 0 {
+                continue;
+            }
+
+            let confidence = weight as f64 / self.classes[class].weight as f64;
+            let better = best.as_ref().map_or(true, |best| {
+                confidence > best.confidence || (confidence == best.confidence && weight > best_weight)
+            });
+
+            if better {
+                best_weight = weight;
+                best = Some(Detection {
+                    label: self.classes[class].label.clone(),
+                    confidence: confidence,
+                    ids: ids,
+                });
+            }
+        }
+
+        trace!("classified {} bytes as {:?}",
+               data.len(),
+               best.as_ref().map(|detection| detection.confidence));
+
+        Ok(best)
+    }
+
+    /// Start buffering the first bytes of a new flow.
+    pub fn flow<'a>(&'a self) -> ClassifierFlow<'a, L> {
+        ClassifierFlow {
+            classifier: self,
+            prefix: Vec::with_capacity(self.window),
+        }
+    }
+}
+
+/// The first bytes of a flow received in chunks, buffered until the window of the classifier is full.
+pub struct ClassifierFlow<'a, L: 'a> {
+    classifier: &'a Classifier<L>,
+    prefix: Vec<u8>,
+}
+
+impl<'a, L: 'a> fmt::Debug for ClassifierFlow<'a, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "ClassifierFlow{{buffered: {}, window: {}}}",
+               self.prefix.len(),
+               self.classifier.window)
+    }
+}
+
+impl<'a, L: Clone + PartialEq + 'a> ClassifierFlow<'a, L> {
+    /// Buffer a chunk of the flow, returns `true` once the window is full,
+    /// the bytes after the window are ignored.
+    pub fn write(&mut self, data: &[u8]) -> bool {
+        let len = cmp::min(data.len(), self.classifier.window - self.prefix.len());
+
+        self.prefix.extend_from_slice(&data[..len]);
+
+        self.is_complete()
+    }
+
+    /// Whether the window of the classifier is full.
+    pub fn is_complete(&self) -> bool {
+        self.prefix.len() >= self.classifier.window
+    }
+
+    /// The buffered bytes.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Classify the buffered bytes, which may be less than the window if the flow was short.
+    pub fn classify<S: Scratch>(&self, scratch: &S) -> Result<Option<Detection<L>>, Error> {
+        self.classifier.classify(&self.prefix, scratch)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    fn rules() -> Vec<(Pattern, &'static str)> {
+        vec![(pattern!{r"\AGET /", flags => 0, id => 1}, "http"),
+             (pattern!{r"HTTP/1\.[01]\r\n", flags => 0, id => 2}, "http"),
+             (pattern!{r"\ASSH-2\.0-", flags => 0, id => 3}, "ssh"),
+             (pattern!{r"\A\x16\x03[\x00-\x03]", flags => 0, id => 4}, "tls"),
+             (pattern!{r"OpenSSH", flags => 0, id => 5}, "ssh")]
+    }
+
+    #[test]
+    fn test_classifier() {
+        let _ = env_logger::init();
+
+        let classifier = Classifier::new(rules(), 32).unwrap();
+        let s = classifier.database().alloc().unwrap();
+
+        assert_eq!(classifier.labels().cloned().collect::<Vec<_>>(),
+                   vec!["http", "ssh", "tls"]);
+
+        let detection = classifier.classify(b"GET / HTTP/1.1\r\nHost: example.com\r\n", &s).unwrap().unwrap();
+
+        assert_eq!(detection.label, "http");
+        assert_eq!(detection.confidence, 1.0);
+        assert_eq!(detection.ids, vec![1, 2]);
+
+        let detection = classifier.classify(b"SSH-2.0-libssh\r\n", &s).unwrap().unwrap();
+
+        assert_eq!(detection.label, "ssh");
+        assert!((detection.confidence - 2.0 / 3.0).abs() < 1e-9);
+
+        assert_eq!(classifier.classify(b"\x16\x03\x01\x02\x00", &s).unwrap().unwrap().label,
+                   "tls");
+        assert_eq!(classifier.classify(b"hello", &s).unwrap(), None);
+
+        // the version is after the window
+        let detection = classifier.classify(b"GET /0123456789012345678901234567890 HTTP/1.1\r\n", &s)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(detection.ids, vec![1]);
+
+        assert!(Classifier::new(vec![(pattern!{"a", flags => 0, id => 1}, "a"),
+                                     (pattern!{"b", flags => 0, id => 1}, "b")],
+                                32)
+            .is_err());
+    }
+
+    #[test]
+    fn test_classifier_flow() {
+        let _ = env_logger::init();
+
+        let classifier = Classifier::new(rules(), 8).unwrap();
+        let s = classifier.database().alloc().unwrap();
+
+        let mut flow = classifier.flow();
+
+        assert!(!flow.write(b"SSH-"));
+        assert!(flow.write(b"2.0-OpenSSH_7.4\r\n"));
+        assert_eq!(flow.prefix(), b"SSH-2.0-");
+        assert_eq!(flow.classify(&s).unwrap().unwrap().label, "ssh");
+    }
+}
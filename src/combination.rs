@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use constants::*;
+use errors::Error;
+use compile::Pattern;
+
+/// The flags allowed on a logical combination.
+const COMBINATION_FLAGS: u32 = HS_FLAG_COMBINATION | HS_FLAG_SINGLEMATCH | HS_FLAG_QUIET;
+
+struct Parser<'a> {
+    expression: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    ids: Vec<usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&mut self, reason: &str) -> Result<T, Error> {
+        let pos = self.chars.peek().map_or(self.expression.len(), |&(pos, _)| pos);
+
+        Err(Error::CompilerError(format!("invalid logical combination `{}` at {}, {}",
+                                         self.expression,
+                                         pos,
+                                         reason)))
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.chars.next();
+            } else {
+                return Some(c);
+            }
+        }
+
+        None
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    // expr := term ('|' term)*
+    fn expr(&mut self) -> Result<(), Error> {
+        try!(self.term());
+
+        while self.eat('|') {
+            try!(self.term());
+        }
+
+        Ok(())
+    }
+
+    // term := factor ('&' factor)*
+    fn term(&mut self) -> Result<(), Error> {
+        try!(self.factor());
+
+        while self.eat('&') {
+            try!(self.factor());
+        }
+
+        Ok(())
+    }
+
+    // factor := '!' factor | '(' expr ')' | id
+    fn factor(&mut self) -> Result<(), Error> {
+        match self.peek() {
+            Some('!') => {
+                self.chars.next();
+                self.factor()
+            }
+            Some('(') => {
+                self.chars.next();
+
+                try!(self.expr());
+
+                if self.eat(')') {
+                    Ok(())
+                } else {
+                    self.error("expect `)`")
+                }
+            }
+            Some(c) if c.is_digit(10) => {
+                let mut id = String::new();
+
+                while let Some(&(_, c)) = self.chars.peek() {
+                    if !c.is_digit(10) {
+                        break;
+                    }
+
+                    id.push(c);
+                    self.chars.next();
+                }
+
+                self.ids.push(try!(id.parse()));
+
+                Ok(())
+            }
+            _ => self.error("expect an ID, `!` or `(`"),
+        }
+    }
+}
+
+/// Parse a logical combination like `(1 & 2) | !3`, returning the IDs of the referenced patterns.
+pub fn combination_ids(expression: &str) -> Result<Vec<usize>, Error> {
+    let mut parser = Parser {
+        expression: expression,
+        chars: expression.char_indices().peekable(),
+        ids: Vec::new(),
+    };
+
+    try!(parser.expr());
+
+    if parser.peek().is_some() {
+        return parser.error("unexpected character");
+    }
+
+    Ok(parser.ids)
+}
+
+/// Check that the logical combinations of the patterns reference the existing patterns,
+/// and that the `HS_FLAG_COMBINATION` and `HS_FLAG_QUIET` flags are used consistently.
+///
+/// A combination may only have the `HS_FLAG_SINGLEMATCH` and `HS_FLAG_QUIET` flags and no extended parameters,
+/// a quiet pattern must be referenced by a combination, since its matches are never reported otherwise.
+pub fn check_combinations(patterns: &[Pattern]) -> Result<(), Error> {
+    let ids = patterns.iter().map(|pattern| pattern.id).collect::<HashSet<_>>();
+    let mut referenced = HashSet::new();
+
+    for pattern in patterns.iter().filter(|pattern| pattern.flags.is_set(HS_FLAG_COMBINATION)) {
+        if pattern.flags.0 & !COMBINATION_FLAGS != 0 {
+            return Err(Error::CompilerError(format!("logical combination `{}` only supports the `H` and `Q` flags",
+                                                    pattern)));
+        }
+
+        if !pattern.ext.is_empty() {
+            return Err(Error::CompilerError(format!("logical combination `{}` doesn't support extended parameters",
+                                                    pattern)));
+        }
+
+        for id in try!(combination_ids(&pattern.expression)) {
+            if id == pattern.id || !ids.contains(&id) {
+                return Err(Error::CompilerError(format!("logical combination `{}` references the unknown pattern ID {}",
+                                                        pattern,
+                                                        id)));
+            }
+
+            referenced.insert(id);
+        }
+    }
+
+    match patterns.iter()
+        .find(|pattern| {
+            pattern.flags.is_set(HS_FLAG_QUIET) && !pattern.flags.is_set(HS_FLAG_COMBINATION) &&
+            !referenced.contains(&pattern.id)
+        }) {
+        Some(pattern) => {
+            Err(Error::CompilerError(format!("quiet pattern `{}` is not referenced by any logical combination",
+                                             pattern)))
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_combination_ids() {
+        assert_eq!(combination_ids("(0 & 1) | !2").unwrap(), vec![0, 1, 2]);
+        assert_eq!(combination_ids("101&102&103|(104&!105)").unwrap(),
+                   vec![101, 102, 103, 104, 105]);
+        assert_eq!(combination_ids("!!7").unwrap(), vec![7]);
+
+        assert!(combination_ids("").is_err());
+        assert!(combination_ids("(1 & 2").is_err());
+        assert!(combination_ids("1 2").is_err());
+        assert!(combination_ids("1 & | 2").is_err());
+        assert!(combination_ids("foo").is_err());
+    }
+
+    #[test]
+    fn test_check_combinations() {
+        let patterns = vec![pattern!{"foo", flags => HS_FLAG_QUIET, id => 1},
+                            pattern!{"bar", flags => HS_FLAG_QUIET, id => 2},
+                            pattern!{"(1 & 2)", flags => HS_FLAG_COMBINATION | HS_FLAG_SINGLEMATCH, id => 3}];
+
+        assert!(check_combinations(&patterns).is_ok());
+
+        let mut unknown = patterns.clone();
+
+        unknown[2].expression = "1 & 4".to_owned();
+
+        assert!(check_combinations(&unknown).is_err());
+
+        let mut unreferenced = patterns.clone();
+
+        unreferenced[2].expression = "1".to_owned();
+
+        assert!(check_combinations(&unreferenced).is_err());
+
+        let mut flags = patterns.clone();
+
+        flags[2].flags.set(HS_FLAG_CASELESS);
+
+        assert!(check_combinations(&flags).is_err());
+
+        let mut recursive = patterns.clone();
+
+        recursive[2].expression = "1 & 2 & 3".to_owned();
+
+        assert!(check_combinations(&recursive).is_err());
+    }
+
+    #[test]
+    fn test_combination() {
+        let _ = env_logger::init();
+
+        let patterns: Patterns = vec![Pattern::parse("1:/foo/Q").unwrap(),
+                                      Pattern::parse("2:/bar/Q").unwrap(),
+                                      Pattern::parse("3:/baz/Q").unwrap(),
+                                      Pattern::parse("4:/(1 & 2) | 3/CH").unwrap()]
+            .into();
+
+        assert_eq!(patterns[3].expression, "(1 & 2) | 3");
+        assert_eq!(patterns[3].flags, CompileFlags::COMBINATION | CompileFlags::SINGLEMATCH);
+
+        assert_matches!(&patterns, "foo bar", [(4, 7)]);
+        assert_matches!(&patterns, "baz", [(4, 3)]);
+        assert_matches!(&patterns, "foo", []);
+
+        let db: Result<BlockDatabase, _> = patterns[..3].to_vec().build();
+
+        assert!(db.is_err());
+    }
+}
@@ -4,24 +4,46 @@ use std::slice;
 use std::ops::Deref;
 use std::ffi::CStr;
 use std::os::raw::c_char;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::marker::PhantomData;
 
 use libc;
 
 use raw::*;
+use constants::*;
 use api::*;
 use errors::Error;
 use cptr::CPtr;
+use stats::{ScanCounters, ScanStats};
 
 /// A compiled pattern database that can then be used to scan data.
+///
+/// The mode is a phantom type parameter, `Block`, `Streaming` or `Vectored`: the common operations
+/// like the size, the info and the serialization are written once for any mode, while the scanners
+/// are only implemented for the modes supporting them, so generic code can be written over `T: Type`.
 pub struct RawDatabase<T: Type> {
     db: RawDatabasePtr,
+    counters: Arc<ScanCounters>,
+    scratch_size: AtomicUsize,
     _marker: PhantomData<T>,
 }
 
 impl<T: Type> fmt::Debug for RawDatabase<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "RawDatabase<{}>{{db: {:p}}}", T::name(), self.db)
+        try!(write!(f, "RawDatabase<{}>{{db: {:p}", T::name(), self.db));
+
+        if !self.db.is_null() {
+            if let Ok(size) = self.database_size() {
+                try!(write!(f, ", size: {}", size));
+            }
+
+            if let Ok(info) = self.database_info() {
+                try!(write!(f, ", info: {:?}", info));
+            }
+        }
+
+        write!(f, "}}")
     }
 }
 
@@ -39,10 +61,39 @@ impl<T: Type> RawDatabase<T> {
 
         RawDatabase {
             db: db,
+            counters: Arc::new(ScanCounters::default()),
+            scratch_size: AtomicUsize::new(0),
             _marker: PhantomData,
         }
     }
 
+    /// The totals of the bytes scanned and the match events delivered with the database,
+    /// including its streams.
+    pub fn scan_stats(&self) -> ScanStats {
+        self.counters.stats()
+    }
+
+    pub(crate) fn counters(&self) -> &Arc<ScanCounters> {
+        &self.counters
+    }
+
+    /// The size in bytes of the scratch space required by the database, allocating a throwaway scratch
+    /// the first time, so a pool or a shared scratch can be sized ahead of the scans.
+    pub fn required_scratch_size(&self) -> Result<usize, Error> {
+        match self.scratch_size.load(Ordering::Relaxed) {
+            0 => {
+                let size = try!(try!(self.alloc()).size());
+
+                debug!("{} database {:p} requires {} bytes of scratch", T::name(), self.db, size);
+
+                self.scratch_size.store(size, Ordering::Relaxed);
+
+                Ok(size)
+            }
+            size => Ok(size),
+        }
+    }
+
     /// Free a compiled pattern database.
     pub fn free(&mut self) -> Result<(), Error> {
         unsafe {
@@ -161,6 +212,8 @@ impl<T: Type> SerializableDatabase<RawDatabase<T>, RawSerializedDatabase> for Ra
     }
 
     fn deserialize_at(&self, bytes: &[u8]) -> Result<&RawDatabase<T>, Error> {
+        self.scratch_size.store(0, Ordering::Relaxed);
+
         unsafe {
             check_hs_error!(hs_deserialize_database_at(
                 bytes.as_ptr() as *const i8,
@@ -180,6 +233,43 @@ impl<T: Type> SerializableDatabase<RawDatabase<T>, RawSerializedDatabase> for Ra
     }
 }
 
+impl<T: Type> RawDatabase<T> {
+    /// Reconstruct a pattern database from a stream of bytes previously generated by
+    /// RawDatabase::serialize(), checking its mode first.
+    ///
+    /// The database is deserialized in place into memory allocated with `malloc` for its deserialized size,
+    /// so it is freed like any other database. Returns `Error::DbModeError` if it was built for another mode.
+    pub fn load(bytes: &[u8]) -> Result<RawDatabase<T>, Error> {
+        let info = try!(bytes.info());
+
+        if info.mode != T::mode() {
+            return Err(Error::DbModeError);
+        }
+
+        let size = try!(bytes.database_size());
+
+        unsafe {
+            let db = libc::malloc(size) as RawDatabasePtr;
+
+            if db.is_null() {
+                return Err(Error::NoMem);
+            }
+
+            let ret = hs_deserialize_database_at(bytes.as_ptr() as *const i8, bytes.len(), db);
+
+            if ret != HS_SUCCESS {
+                libc::free(db as *mut libc::c_void);
+
+                return Err(ret.into());
+            }
+
+            debug!("loaded {} database at {:p} from {} bytes", T::name(), db, bytes.len());
+
+            Ok(Self::from_raw(db))
+        }
+    }
+}
+
 unsafe impl<T: Type> Send for RawDatabase<T> {}
 unsafe impl<T: Type> Sync for RawDatabase<T> {}
 
@@ -311,11 +401,106 @@ pub mod tests {
         validate_database_info(&db_info);
     }
 
+    #[test]
+    fn test_database_info_parse() {
+        let info: DatabaseInfo = "Version: 4.4.1 Features:  AVX2 Mode: STREAM".parse().unwrap();
+
+        assert_eq!(info.version,
+                   Version {
+                       major: 4,
+                       minor: 4,
+                       patch: 1,
+                   });
+        assert_eq!(info.version.to_string(), "4.4.1");
+        assert_eq!(info.cpu_features, HS_CPU_FEATURES_AVX2 as u64);
+        assert_eq!(info.mode, HS_MODE_STREAM);
+
+        let info: DatabaseInfo = "Version: 5.0.0 Features:  Mode: VECTORED".parse().unwrap();
+
+        assert_eq!(info.cpu_features, 0);
+        assert_eq!(info.mode, HS_MODE_VECTORED);
+
+        assert!("Version: 5.0 Features: Mode: BLOCK".parse::<DatabaseInfo>().is_err());
+        assert!("Version: 5.0.0 Features: Mode: UNKNOWN".parse::<DatabaseInfo>().is_err());
+        assert!("garbage".parse::<DatabaseInfo>().is_err());
+    }
+
+    #[test]
+    fn test_database_info_accessors() {
+        let _ = env_logger::init();
+
+        let db = StreamingDatabase::compile("test", 0, &PlatformInfo::null()).unwrap();
+
+        let info = db.info().unwrap();
+
+        assert_eq!(db.hyperscan_version().unwrap(), info.version);
+        assert!(info.version.major >= 4);
+        assert_eq!(db.compiled_for().unwrap(), info.cpu_features);
+        assert_eq!(db.mode().unwrap(), HS_MODE_STREAM);
+
+        assert_eq!(db.serialize().unwrap().info().unwrap(), info);
+    }
+
+    #[test]
+    fn test_match() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+        let matches = ::std::cell::RefCell::new(Vec::new());
+
+        fn callback(id: u32, from: u64, to: u64, flags: u32, matches: &::std::cell::RefCell<Vec<Match>>) -> u32 {
+            matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+            0
+        }
+
+        db.scan("some test data", 0, &s, Some(callback), Some(&matches)).unwrap();
+
+        let matches = matches.into_inner();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 5..9);
+        assert!(matches[0].flags.is_empty());
+        assert_eq!(matches[0].flags.bits(), 0);
+    }
+
+    #[test]
+    fn test_runs_on_current_host() {
+        let _ = env_logger::init();
+
+        let db = BlockDatabase::compile("test", 0, &PlatformInfo::null()).unwrap();
+
+        assert_eq!(db.runs_on_current_host(), Ok(()));
+        assert_eq!(db.hyperscan_version().unwrap(), Version::library().unwrap());
+
+        let reason = IncompatibleReason::CpuFeatures {
+            required: HS_CPU_FEATURES_AVX2 as u64,
+            available: 0,
+        };
+
+        assert_eq!(reason.to_string(),
+                   "the database requires CPU features 0x4, but the host provides 0x0");
+    }
+
     #[test]
     pub fn test_platform() {
         assert!(PlatformInfo::is_valid())
     }
 
+    #[test]
+    fn test_check_platform() {
+        assert!(is_platform_supported());
+        assert_eq!(check_platform(), Ok(()));
+
+        let platform = current_platform().unwrap();
+        let host = PlatformInfo::host();
+
+        assert_eq!(platform.tune(), host.tune());
+        assert_eq!(platform.cpu_features(), host.cpu_features());
+        assert_eq!(Error::from(HS_ARCH_ERROR), Error::ArchError);
+    }
+
     #[test]
     fn test_database() {
         let _ = env_logger::init();
@@ -327,7 +512,7 @@ pub mod tests {
         validate_database(&db);
 
         assert!(
-            Regex::new(r"RawDatabase<Block>\{db: \w+\}")
+            Regex::new(r#"RawDatabase<Block>\{db: \w+, size: \d+, info: "Version: [^"]+ Mode: BLOCK"\}"#)
                 .unwrap()
                 .is_match(&format!("{:?}", db))
         );
@@ -376,4 +561,48 @@ pub mod tests {
 
         validate_database(db.deserialize_at(data.as_slice()).unwrap());
     }
+
+    #[test]
+    fn test_required_scratch_size() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = patterns!(["foo", "bar\\d+"]).build().unwrap();
+        let size = db.required_scratch_size().unwrap();
+
+        assert!(size > 0);
+        assert_eq!(db.alloc().unwrap().size().unwrap(), size);
+        assert_eq!(db.required_scratch_size().unwrap(), size);
+    }
+
+    fn round_trip<T: Type>(expression: &str) -> RawDatabase<T> {
+        let db = RawDatabase::<T>::compile(expression, 0, &PlatformInfo::null()).unwrap();
+
+        assert_eq!(db.database_mode(), T::mode());
+        assert_eq!(db.info().unwrap().mode, T::mode());
+
+        let data = db.serialize().unwrap();
+
+        RawDatabase::<T>::load(data.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_database_mode() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = round_trip("test");
+
+        assert_matches!(&db, "a test", [(0, 6)]);
+
+        let db: StreamingDatabase = round_trip("test");
+
+        assert!(db.stream_size().unwrap() > 0);
+
+        let db = round_trip::<Vectored>("test");
+
+        validate_database(&db);
+
+        let data = db.serialize().unwrap();
+
+        assert_eq!(BlockDatabase::load(data.as_slice()).err(), Some(Error::DbModeError));
+    }
 }
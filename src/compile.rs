@@ -1,26 +1,143 @@
+use std::io::{self, BufRead, BufReader};
+use std::fs::File;
+use std::path::Path;
 use std::ptr;
+use std::vec;
+use std::slice;
 use std::fmt;
 use std::os::raw::c_uint;
 use std::str::FromStr;
 use std::ffi::CString;
+use std::convert::TryFrom;
 use std::iter::FromIterator;
-
-use regex_syntax;
+use std::ops::{BitAnd, BitOr, BitOrAssign, Deref, DerefMut};
 
 use raw::*;
 use constants::*;
 use api::*;
 use cptr::CPtr;
 use common::RawDatabase;
-use errors::{Error, RawCompileErrorPtr};
+use casefold;
+use combination::check_combinations;
+use ids::{IdStrategy, assign_ids, check_ids};
+use errors::{Error, PatternError, RawCompileErrorPtr};
 
 /// Flags which modify the behaviour of the expression.
-#[derive(Debug, Default, Copy, Clone, PartialEq)]
-pub struct CompileFlags(pub u32);
+///
+/// Only the `HS_FLAG_*` compile flags can be set, so the mode or the runtime flags
+/// can't be passed as the compile flags by mistake. The flags kept with `from_bits_retain`,
+/// like those of `pattern!`, are checked when the pattern is compiled.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CompileFlags(pub(crate) u32);
+
+/// The names of the compile flags, in the order of their bits.
+const FLAG_NAMES: [(u32, &'static str); 11] = [(HS_FLAG_CASELESS, "CASELESS"),
+                                               (HS_FLAG_DOTALL, "DOTALL"),
+                                               (HS_FLAG_MULTILINE, "MULTILINE"),
+                                               (HS_FLAG_SINGLEMATCH, "SINGLEMATCH"),
+                                               (HS_FLAG_ALLOWEMPTY, "ALLOWEMPTY"),
+                                               (HS_FLAG_UTF8, "UTF8"),
+                                               (HS_FLAG_UCP, "UCP"),
+                                               (HS_FLAG_PREFILTER, "PREFILTER"),
+                                               (HS_FLAG_SOM_LEFTMOST, "SOM_LEFTMOST"),
+                                               (HS_FLAG_COMBINATION, "COMBINATION"),
+                                               (HS_FLAG_QUIET, "QUIET")];
+
+/// The characters of the compile flags in the pattern files, in the order they are printed.
+const FLAG_CHARS: [(char, u32); 11] = [('i', HS_FLAG_CASELESS),
+                                       ('m', HS_FLAG_MULTILINE),
+                                       ('s', HS_FLAG_DOTALL),
+                                       ('H', HS_FLAG_SINGLEMATCH),
+                                       ('V', HS_FLAG_ALLOWEMPTY),
+                                       ('8', HS_FLAG_UTF8),
+                                       ('W', HS_FLAG_UCP),
+                                       ('C', HS_FLAG_COMBINATION),
+                                       ('Q', HS_FLAG_QUIET),
+                                       ('P', HS_FLAG_PREFILTER),
+                                       ('L', HS_FLAG_SOM_LEFTMOST)];
+
+impl CompileFlags {
+    pub const CASELESS: CompileFlags = CompileFlags(HS_FLAG_CASELESS);
+    pub const DOTALL: CompileFlags = CompileFlags(HS_FLAG_DOTALL);
+    pub const MULTILINE: CompileFlags = CompileFlags(HS_FLAG_MULTILINE);
+    pub const SINGLEMATCH: CompileFlags = CompileFlags(HS_FLAG_SINGLEMATCH);
+    pub const ALLOWEMPTY: CompileFlags = CompileFlags(HS_FLAG_ALLOWEMPTY);
+    pub const UTF8: CompileFlags = CompileFlags(HS_FLAG_UTF8);
+    pub const UCP: CompileFlags = CompileFlags(HS_FLAG_UCP);
+    pub const PREFILTER: CompileFlags = CompileFlags(HS_FLAG_PREFILTER);
+    pub const SOM_LEFTMOST: CompileFlags = CompileFlags(HS_FLAG_SOM_LEFTMOST);
+    pub const COMBINATION: CompileFlags = CompileFlags(HS_FLAG_COMBINATION);
+    pub const QUIET: CompileFlags = CompileFlags(HS_FLAG_QUIET);
+
+    /// All the compile flags.
+    pub const ALL: CompileFlags = CompileFlags(HS_FLAG_CASELESS | HS_FLAG_DOTALL | HS_FLAG_MULTILINE |
+                                               HS_FLAG_SINGLEMATCH | HS_FLAG_ALLOWEMPTY |
+                                               HS_FLAG_UTF8 | HS_FLAG_UCP | HS_FLAG_PREFILTER |
+                                               HS_FLAG_SOM_LEFTMOST | HS_FLAG_COMBINATION |
+                                               HS_FLAG_QUIET);
+
+    /// Construct the flags from the bits, returns `Error::Invalid` if an unknown bit is set.
+    pub fn from_bits(bits: u32) -> Result<CompileFlags, Error> {
+        if bits & !Self::ALL.0 == 0 {
+            Ok(CompileFlags(bits))
+        } else {
+            Err(Error::Invalid)
+        }
+    }
 
-impl From<u32> for CompileFlags {
-    fn from(flags: u32) -> Self {
-        CompileFlags(flags)
+    /// Construct the flags from the bits, keeping the unknown bits,
+    /// which are rejected when the pattern is compiled.
+    pub fn from_bits_retain(bits: u32) -> CompileFlags {
+        CompileFlags(bits)
+    }
+
+    /// Construct the flags from the bits, dropping the unknown bits.
+    pub fn from_bits_truncate(bits: u32) -> CompileFlags {
+        CompileFlags(bits & Self::ALL.0)
+    }
+
+    #[inline]
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all the flags of `other` are set.
+    #[inline]
+    pub fn contains(&self, other: CompileFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// The compile flags given as `HS_FLAG_*` bits or as `CompileFlags`, to `pattern!` or `Builder::pattern`.
+///
+/// The unknown bits are kept, the pattern fails to compile with `Error::PatternError`.
+pub trait FlagBits {
+    fn flag_bits(self) -> u32;
+}
+
+impl FlagBits for u32 {
+    fn flag_bits(self) -> u32 {
+        self
+    }
+}
+
+impl FlagBits for CompileFlags {
+    fn flag_bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// Convert the `HS_FLAG_*` bits, returns `Error::Invalid` if an unknown bit is set.
+impl TryFrom<u32> for CompileFlags {
+    type Error = Error;
+
+    fn try_from(flags: u32) -> Result<Self, Self::Error> {
+        CompileFlags::from_bits(flags)
     }
 }
 
@@ -30,29 +147,55 @@ impl Into<u32> for CompileFlags {
     }
 }
 
-impl fmt::Display for CompileFlags {
+impl BitOr for CompileFlags {
+    type Output = CompileFlags;
+
+    #[inline]
+    fn bitor(self, other: CompileFlags) -> CompileFlags {
+        CompileFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for CompileFlags {
+    #[inline]
+    fn bitor_assign(&mut self, other: CompileFlags) {
+        self.0 |= other.0
+    }
+}
+
+impl BitAnd for CompileFlags {
+    type Output = CompileFlags;
+
+    #[inline]
+    fn bitand(self, other: CompileFlags) -> CompileFlags {
+        CompileFlags(self.0 & other.0)
+    }
+}
+
+impl fmt::Debug for CompileFlags {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.is_set(HS_FLAG_CASELESS) {
-            try!(write!(f, "i"))
-        }
-        if self.is_set(HS_FLAG_MULTILINE) {
-            try!(write!(f, "m"))
-        }
-        if self.is_set(HS_FLAG_DOTALL) {
-            try!(write!(f, "s"))
-        }
-        if self.is_set(HS_FLAG_SINGLEMATCH) {
-            try!(write!(f, "H"))
-        }
-        if self.is_set(HS_FLAG_ALLOWEMPTY) {
-            try!(write!(f, "V"))
+        let names = FLAG_NAMES.iter()
+            .filter(|&&(flag, _)| self.is_set(flag))
+            .map(|&(_, name)| name)
+            .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            write!(f, "CompileFlags(0)")
+        } else {
+            write!(f, "CompileFlags({})", names.join(" | "))
         }
-        if self.is_set(HS_FLAG_UTF8) {
-            try!(write!(f, "8"))
-        }
-        if self.is_set(HS_FLAG_UCP) {
-            try!(write!(f, "W"))
+    }
+}
+
+/// The flags in the single-character notation of the pattern files, e.g. `Hi8W`, in the canonical order.
+impl fmt::Display for CompileFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(c, flag) in FLAG_CHARS.iter() {
+            if self.is_set(flag) {
+                try!(write!(f, "{}", c))
+            }
         }
+
         Ok(())
     }
 }
@@ -63,6 +206,12 @@ impl CompileFlags {
         self.0 & flag == flag
     }
 
+    /// Whether the expression is allowed to match against empty buffers (`HS_FLAG_ALLOWEMPTY`).
+    #[inline]
+    pub fn allows_empty(&self) -> bool {
+        self.is_set(HS_FLAG_ALLOWEMPTY)
+    }
+
     #[inline]
     pub fn set(&mut self, flag: u32) -> &mut Self {
         self.0 |= flag;
@@ -70,19 +219,14 @@ impl CompileFlags {
         self
     }
 
+    /// Parse the flags in the single-character notation of the pattern files, in any order.
     pub fn parse(s: &str) -> Result<CompileFlags, Error> {
         let mut flags: u32 = 0;
 
         for c in s.chars() {
-            match c {
-                'i' => flags |= HS_FLAG_CASELESS,
-                'm' => flags |= HS_FLAG_MULTILINE,
-                's' => flags |= HS_FLAG_DOTALL,
-                'H' => flags |= HS_FLAG_SINGLEMATCH,
-                'V' => flags |= HS_FLAG_ALLOWEMPTY,
-                '8' => flags |= HS_FLAG_UTF8,
-                'W' => flags |= HS_FLAG_UCP,
-                _ => return Err(Error::CompilerError(format!("invalid compile flag: {}", c))),
+            match FLAG_CHARS.iter().find(|&&(flag_char, _)| flag_char == c) {
+                Some(&(_, flag)) => flags |= flag,
+                None => return Err(Error::CompilerError(format!("invalid compile flag: {}", c))),
             }
         }
 
@@ -99,8 +243,239 @@ impl FromStr for CompileFlags {
     }
 }
 
+/// Flags governing which fields of the extended parameters are used by the compiler.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ExtFlags(pub u64);
+
+impl From<u64> for ExtFlags {
+    fn from(flags: u64) -> Self {
+        ExtFlags(flags)
+    }
+}
+
+impl Into<u64> for ExtFlags {
+    fn into(self) -> u64 {
+        self.0
+    }
+}
+
+impl ExtFlags {
+    #[inline]
+    pub fn is_set(&self, flag: u64) -> bool {
+        self.0 & flag == flag
+    }
+
+    #[inline]
+    pub fn set(&mut self, flag: u64) -> &mut Self {
+        self.0 |= flag;
+
+        self
+    }
+}
+
+/// Extended parameters which constrain the matches produced by a pattern.
+///
+/// The flags are derived from the parameters that have a value,
+/// so a value can never be silently ignored because its flag bit was not set.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ExprExt {
+    min_offset: Option<u64>,
+    max_offset: Option<u64>,
+    min_length: Option<u64>,
+    edit_distance: Option<u32>,
+    hamming_distance: Option<u32>,
+}
+
+impl ExprExt {
+    pub fn new() -> ExprExt {
+        ExprExt::default()
+    }
+
+    /// The minimum end offset in the data stream at which this expression should match successfully.
+    pub fn with_min_offset(mut self, offset: u64) -> Self {
+        self.min_offset = Some(offset);
+
+        self
+    }
+
+    /// The maximum end offset in the data stream at which this expression should match successfully.
+    pub fn with_max_offset(mut self, offset: u64) -> Self {
+        self.max_offset = Some(offset);
+
+        self
+    }
+
+    /// The minimum match length (from start to end) required to successfully match this expression.
+    pub fn with_min_length(mut self, length: u64) -> Self {
+        self.min_length = Some(length);
+
+        self
+    }
+
+    /// Allow patterns to approximately match within this edit distance.
+    pub fn with_edit_distance(mut self, distance: u32) -> Self {
+        self.edit_distance = Some(distance);
+
+        self
+    }
+
+    /// Allow patterns to approximately match within this Hamming distance (requires Hyperscan 5.0).
+    pub fn with_hamming_distance(mut self, distance: u32) -> Self {
+        self.hamming_distance = Some(distance);
+
+        self
+    }
+
+    #[inline]
+    pub fn min_offset(&self) -> Option<u64> {
+        self.min_offset
+    }
+
+    #[inline]
+    pub fn max_offset(&self) -> Option<u64> {
+        self.max_offset
+    }
+
+    #[inline]
+    pub fn min_length(&self) -> Option<u64> {
+        self.min_length
+    }
+
+    #[inline]
+    pub fn edit_distance(&self) -> Option<u32> {
+        self.edit_distance
+    }
+
+    #[inline]
+    pub fn hamming_distance(&self) -> Option<u32> {
+        self.hamming_distance
+    }
+
+    /// Whether the parameters allow approximate matches.
+    #[inline]
+    pub fn is_approximate(&self) -> bool {
+        self.edit_distance.map_or(false, |d| d > 0) || self.hamming_distance.map_or(false, |d| d > 0)
+    }
+
+    /// The flags matching the parameters that have a value.
+    pub fn flags(&self) -> ExtFlags {
+        let mut flags = ExtFlags::default();
+
+        if self.min_offset.is_some() {
+            flags.set(HS_EXT_FLAG_MIN_OFFSET);
+        }
+        if self.max_offset.is_some() {
+            flags.set(HS_EXT_FLAG_MAX_OFFSET);
+        }
+        if self.min_length.is_some() {
+            flags.set(HS_EXT_FLAG_MIN_LENGTH);
+        }
+        if self.edit_distance.is_some() {
+            flags.set(HS_EXT_FLAG_EDIT_DISTANCE);
+        }
+        if self.hamming_distance.is_some() {
+            flags.set(HS_EXT_FLAG_HAMMING_DISTANCE);
+        }
+
+        flags
+    }
+
+    /// Check whether no extended parameter has a value.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.flags() == ExtFlags::default()
+    }
+
+    /// Construct the extended parameters from a raw structure, ignoring the fields whose flag is not set.
+    pub fn from_raw(ext: &hs_expr_ext_t) -> ExprExt {
+        let flags = ExtFlags(ext.flags);
+        let field = |flag, value| if flags.is_set(flag) { Some(value) } else { None };
+
+        ExprExt {
+            min_offset: field(HS_EXT_FLAG_MIN_OFFSET, ext.min_offset),
+            max_offset: field(HS_EXT_FLAG_MAX_OFFSET, ext.max_offset),
+            min_length: field(HS_EXT_FLAG_MIN_LENGTH, ext.min_length),
+            edit_distance: if flags.is_set(HS_EXT_FLAG_EDIT_DISTANCE) {
+                Some(ext.edit_distance)
+            } else {
+                None
+            },
+            hamming_distance: if flags.is_set(HS_EXT_FLAG_HAMMING_DISTANCE) {
+                Some(ext.hamming_distance)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Convert the extended parameters to the raw structure passed to the compiler.
+    pub fn to_raw(&self) -> hs_expr_ext_t {
+        hs_expr_ext_t {
+            flags: self.flags().0,
+            min_offset: self.min_offset.unwrap_or(0),
+            max_offset: self.max_offset.unwrap_or(0),
+            min_length: self.min_length.unwrap_or(0),
+            edit_distance: self.edit_distance.unwrap_or(0),
+            hamming_distance: self.hamming_distance.unwrap_or(0),
+        }
+    }
+}
+
+/// Display the parameters that have a value, like `{min_offset=10,edit_distance=1}`,
+/// as in the Hyperscan pattern files.
+impl fmt::Display for ExprExt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut params = Vec::new();
+
+        if let Some(offset) = self.min_offset {
+            params.push(format!("min_offset={}", offset));
+        }
+        if let Some(offset) = self.max_offset {
+            params.push(format!("max_offset={}", offset));
+        }
+        if let Some(length) = self.min_length {
+            params.push(format!("min_length={}", length));
+        }
+        if let Some(distance) = self.edit_distance {
+            params.push(format!("edit_distance={}", distance));
+        }
+        if let Some(distance) = self.hamming_distance {
+            params.push(format!("hamming_distance={}", distance));
+        }
+
+        write!(f, "{{{}}}", params.join(","))
+    }
+}
+
+impl ExprExt {
+    /// Parse the parameters written like `{min_offset=10,edit_distance=1}`.
+    pub fn parse(s: &str) -> Result<ExprExt, Error> {
+        let params = match (s.starts_with('{'), s.ends_with('}')) {
+            (true, true) if s.len() >= 2 => &s[1..s.len() - 1],
+            _ => return Err(Error::CompilerError(format!("invalid extended parameters: {}", s))),
+        };
+
+        let mut ext = ExprExt::default();
+
+        for param in params.split(',').map(|param| param.trim()).filter(|param| !param.is_empty()) {
+            let mut kv = param.splitn(2, '=');
+
+            match (kv.next().map(|k| k.trim()), kv.next().map(|v| v.trim())) {
+                (Some("min_offset"), Some(v)) => ext.min_offset = Some(try!(v.parse())),
+                (Some("max_offset"), Some(v)) => ext.max_offset = Some(try!(v.parse())),
+                (Some("min_length"), Some(v)) => ext.min_length = Some(try!(v.parse())),
+                (Some("edit_distance"), Some(v)) => ext.edit_distance = Some(try!(v.parse())),
+                (Some("hamming_distance"), Some(v)) => ext.hamming_distance = Some(try!(v.parse())),
+                _ => return Err(Error::CompilerError(format!("invalid extended parameter: {}", param))),
+            }
+        }
+
+        Ok(ext)
+    }
+}
+
 /// Pattern that has matched.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Pattern {
     /// The NULL-terminated expression to parse.
     pub expression: String,
@@ -108,22 +483,37 @@ pub struct Pattern {
     pub flags: CompileFlags,
     /// ID number to be associated with the corresponding pattern in the expressions array.
     pub id: usize,
+    /// Extended parameters which constrain the matches of the expression.
+    pub ext: ExprExt,
 }
 
 impl Pattern {
+    /// Parse a pattern in the `id:/expression/flags{ext}` notation of the rule files and hsbench,
+    /// like `1:/foo(bar)?/is`, where the ID, the flags and the extended parameters are optional.
+    ///
+    /// A string without the slashes is a bare expression, the `Display` output parses back to the same pattern.
     pub fn parse(s: &str) -> Result<Pattern, Error> {
         unsafe {
             let (id, expr) = match s.find(':') {
-                Some(off) => (try!(s.slice_unchecked(0, off).parse()), s.slice_unchecked(off + 1, s.len())),
-                None => (0, s),
+                Some(off) if off > 0 && s[..off].bytes().all(|b| b.is_ascii_digit()) => {
+                    (try!(s.slice_unchecked(0, off).parse()), s.slice_unchecked(off + 1, s.len()))
+                }
+                _ => (0, s),
             };
 
             let pattern = match (expr.starts_with('/'), expr.rfind('/')) {
                 (true, Some(end)) if end > 0 => {
+                    let suffix = expr.slice_unchecked(end + 1, expr.len());
+                    let (flags, ext) = match suffix.find('{') {
+                        Some(off) => (&suffix[..off], try!(ExprExt::parse(&suffix[off..]))),
+                        None => (suffix, ExprExt::default()),
+                    };
+
                     Pattern {
                         expression: String::from(expr.slice_unchecked(1, end)),
-                        flags: try!(CompileFlags::parse(expr.slice_unchecked(end + 1, expr.len()))),
+                        flags: try!(CompileFlags::parse(flags)),
                         id: id,
+                        ext: ext,
                     }
                 }
 
@@ -132,6 +522,7 @@ impl Pattern {
                         expression: String::from(expr),
                         flags: CompileFlags::default(),
                         id: id,
+                        ext: ExprExt::default(),
                     }
                 }
             };
@@ -141,16 +532,75 @@ impl Pattern {
             Ok(pattern)
         }
     }
+
+    /// Apply Unicode simple case folding to the literal segments of the expression.
+    ///
+    /// The returned pattern is caseless, and enables UTF-8 mode when some literals were folded,
+    /// so the generated classes match whole characters rather than bytes.
+    pub fn fold_case(&self) -> Pattern {
+        let mut pattern = self.clone();
+
+        pattern.flags.set(HS_FLAG_CASELESS);
+
+        if let Some(expression) = casefold::fold_case(&self.expression) {
+            pattern.expression = expression;
+            pattern.flags.set(HS_FLAG_UTF8);
+        }
+
+        debug!("pattern `{}` folded to `{}`", self, pattern);
+
+        pattern
+    }
+
+    /// Rewrite the expression so that it can only match at the start of the data.
+    ///
+    /// `\A` is used rather than `^`, so the anchor holds even with `HS_FLAG_MULTILINE`.
+    pub fn anchored(&self) -> Pattern {
+        Pattern {
+            expression: format!("\\A(?:{})", self.expression),
+            flags: self.flags,
+            id: self.id,
+            ext: self.ext,
+        }
+    }
+
+    /// Constrain the matches of the pattern with the extended parameters.
+    pub fn with_ext(mut self, ext: ExprExt) -> Pattern {
+        self.ext = ext;
+
+        self
+    }
+
+    /// Allow the pattern to match with up to `distance` insertions, removals or replacements of characters,
+    /// to tolerate typos without enumerating the variants.
+    pub fn with_edit_distance(mut self, distance: u32) -> Pattern {
+        self.ext = self.ext.with_edit_distance(distance);
+
+        self
+    }
+
+    /// Allow the pattern to match with up to `distance` replaced characters (requires Hyperscan 5.0).
+    pub fn with_hamming_distance(mut self, distance: u32) -> Pattern {
+        self.ext = self.ext.with_hamming_distance(distance);
+
+        self
+    }
 }
 
 impl fmt::Display for Pattern {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,
-               "{}:/{}/{}",
-               self.id,
-               regex_syntax::escape(self.expression.as_str()),
-               self.flags)
+        try!(write!(f,
+                    "{}:/{}/{}",
+                    self.id,
+                    self.expression,
+                    self.flags));
+
+        if !self.ext.is_empty() {
+            try!(write!(f, "{}", self.ext));
+        }
+
+        Ok(())
     }
 }
 
@@ -164,17 +614,46 @@ impl FromStr for Pattern {
 }
 
 impl Expression for Pattern {
+    /// Provide the information about the expression, constrained by its extended parameters if any.
     fn info(&self) -> Result<ExpressionInfo, Error> {
+        self.expression_info(if self.ext.is_empty() {
+            None
+        } else {
+            Some(&self.ext)
+        })
+    }
+}
+
+impl Pattern {
+    /// Provide the information about the expression constrained by the extended parameters,
+    /// like the width of the matches with a `min_length` or an `edit_distance`,
+    /// which `hs_expression_info` rejects.
+    pub fn ext_info(&self, ext: &ExprExt) -> Result<ExpressionInfo, Error> {
+        self.expression_info(Some(ext))
+    }
+
+    fn expression_info(&self, ext: Option<&ExprExt>) -> Result<ExpressionInfo, Error> {
         let expr = try!(CString::new(self.expression.as_str()));
         let mut info: CPtr<hs_expr_info_t> = CPtr::null();
         let mut err: RawCompileErrorPtr = ptr::null_mut();
 
         unsafe {
-            check_compile_error!(hs_expression_info(expr.as_bytes_with_nul().as_ptr() as *const i8,
-                                                    self.flags.0,
-                                                    &mut *info,
-                                                    &mut err),
-                                 err);
+            if let Some(ext) = ext {
+                let ext = ext.to_raw();
+
+                check_compile_error!(hs_expression_ext_info(expr.as_bytes_with_nul().as_ptr() as *const i8,
+                                                            self.flags.0,
+                                                            &ext,
+                                                            &mut *info,
+                                                            &mut err),
+                                     err);
+            } else {
+                check_compile_error!(hs_expression_info(expr.as_bytes_with_nul().as_ptr() as *const i8,
+                                                        self.flags.0,
+                                                        &mut *info,
+                                                        &mut err),
+                                     err);
+            }
 
             let info = ExpressionInfo {
                 min_width: info.as_ref().min_width as usize,
@@ -189,42 +668,330 @@ impl Expression for Pattern {
             Ok(info)
         }
     }
+
+    /// Check whether the expression can match the empty string.
+    pub fn can_match_empty(&self) -> Result<bool, Error> {
+        let pattern = Pattern {
+            expression: self.expression.clone(),
+            flags: CompileFlags(self.flags.0 | HS_FLAG_ALLOWEMPTY),
+            id: self.id,
+            ext: self.ext,
+        };
+
+        Ok(try!(pattern.info()).min_width == 0)
+    }
+}
+
+/// Find the patterns which can match the empty string without `HS_FLAG_ALLOWEMPTY`,
+/// and so will be rejected by the compiler.
+///
+/// Patterns which fail to be analysed are skipped, the compiler will report them.
+pub fn empty_matching_patterns(patterns: &[Pattern]) -> Vec<&Pattern> {
+    patterns.iter()
+        .filter(|p| is_empty_matching(p))
+        .collect()
+}
+
+fn is_empty_matching(pattern: &Pattern) -> bool {
+    !pattern.flags.allows_empty() && pattern.can_match_empty().unwrap_or(false)
+}
+
+/// Replace a compile error with a targeted diagnostic when it is caused by a pattern
+/// which can match the empty string without `HS_FLAG_ALLOWEMPTY`.
+///
+/// Only the errors of a pattern are rewritten, a `CompilerError` is attributed to the pattern
+/// when it is the only one compiled, the other errors are passed through unchanged.
+fn diagnose_compile_error(patterns: &[Pattern], err: Error) -> Error {
+    match err {
+        Error::PatternError(mut err) => {
+            if patterns.get(err.index).map_or(false, is_empty_matching) {
+                err.message = String::from("can match the empty string, use HS_FLAG_ALLOWEMPTY (`V`) to allow it");
+            }
+
+            Error::PatternError(err)
+        }
+        Error::CompilerError(_) if patterns.len() == 1 && is_empty_matching(&patterns[0]) => {
+            Error::CompilerError(format!("pattern `{}` can match the empty string, \
+                                          use HS_FLAG_ALLOWEMPTY (`V`) to allow it",
+                                         patterns[0]))
+        }
+        _ => err,
+    }
+}
+
+/// A set of `Pattern`s compiled into a database together.
+///
+/// The patterns added with `add` are numbered in order after the largest ID of the set,
+/// the patterns inserted with `insert` keep their own ID, which mustn't collide with a different pattern.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Patterns(Vec<Pattern>);
+
+impl Patterns {
+    /// An empty pattern set.
+    pub fn new() -> Patterns {
+        Patterns(Vec::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Patterns {
+        Patterns(Vec::with_capacity(capacity))
+    }
+
+    /// The ID assigned to the next pattern added to the set, starting from 1.
+    pub fn next_id(&self) -> usize {
+        self.0.iter().map(|pattern| pattern.id + 1).max().unwrap_or(1)
+    }
+
+    /// Add a pattern with the next ID of the set, returning the assigned ID.
+    pub fn add(&mut self, mut pattern: Pattern) -> usize {
+        pattern.id = self.next_id();
+
+        let id = pattern.id;
+
+        self.0.push(pattern);
+
+        id
+    }
+
+    /// Insert a pattern with its own ID.
+    ///
+    /// Returns `Error::CompilerError` if a different pattern of the set has the same ID,
+    /// the same pattern inserted again is skipped.
+    pub fn insert(&mut self, pattern: Pattern) -> Result<(), Error> {
+        if let Some(other) = self.get(pattern.id) {
+            if other.expression != pattern.expression || other.flags != pattern.flags || other.ext != pattern.ext {
+                return Err(Error::CompilerError(format!("pattern ID {} is assigned to both `{}` and `{}`",
+                                                        pattern.id,
+                                                        other,
+                                                        pattern)));
+            }
+
+            trace!("skip pattern `{}` already in the set", pattern);
+
+            return Ok(());
+        }
+
+        self.0.push(pattern);
+
+        Ok(())
+    }
+
+    /// The first pattern with the ID.
+    pub fn get(&self, id: usize) -> Option<&Pattern> {
+        self.0.iter().find(|pattern| pattern.id == id)
+    }
+
+    /// Check that different patterns of the set have different IDs.
+    pub fn check_ids(&self) -> Result<(), Error> {
+        check_ids(&self.0)
+    }
+
+    /// Renumber the patterns of the set with the strategy.
+    pub fn assign_ids(&mut self, strategy: &IdStrategy) -> Result<(), Error> {
+        assign_ids(&mut self.0, strategy)
+    }
+
+    /// Compile the patterns for the platform, skipping the patterns which fail to compile,
+    /// so a rule set with a few bad rules can still be deployed.
+    ///
+    /// Returns the database of the other patterns, with the errors of the skipped patterns.
+    /// Returns the error if it can't be attributed to a pattern, or if every pattern fails.
+    pub fn build_lossy<T: Type>(&self, platform: &PlatformInfo) -> Result<(RawDatabase<T>, Vec<PatternError>), Error> {
+        compile_patterns_lossy(&self.0, 0, platform)
+    }
+
+    /// Unwrap the patterns.
+    pub fn into_inner(self) -> Vec<Pattern> {
+        self.0
+    }
+
+    /// Read a pattern file in the format of `hsbench`, with a pattern per line in the `Pattern::parse` format,
+    /// ignoring empty lines, lines starting with '#' and repeated patterns.
+    ///
+    /// Returns `io::ErrorKind::InvalidData` with the path, the line number and the offending line
+    /// if a pattern is invalid or reuses the ID of a different pattern.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Patterns> {
+        let f = try!(File::open(path.as_ref()));
+
+        Patterns::read(BufReader::new(f)).map_err(|err| {
+            io::Error::new(err.kind(), format!("{}:{}", path.as_ref().display(), err))
+        })
+    }
+
+    /// Read the patterns from the lines of a reader, like `from_file`.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Patterns> {
+        let mut patterns = Patterns::new();
+
+        for (lineno, line) in reader.lines().enumerate() {
+            let line = try!(line);
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            try!(Pattern::parse(line)
+                .and_then(|pattern| patterns.insert(pattern))
+                .map_err(|err| {
+                    io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("{}: invalid pattern `{}`, {}", lineno + 1, line, err))
+                }));
+        }
+
+        debug!("read {} patterns", patterns.len());
+
+        Ok(patterns)
+    }
+}
+
+impl Deref for Patterns {
+    type Target = Vec<Pattern>;
+
+    fn deref(&self) -> &Vec<Pattern> {
+        &self.0
+    }
+}
+
+impl DerefMut for Patterns {
+    fn deref_mut(&mut self) -> &mut Vec<Pattern> {
+        &mut self.0
+    }
+}
+
+impl From<Vec<Pattern>> for Patterns {
+    fn from(patterns: Vec<Pattern>) -> Self {
+        Patterns(patterns)
+    }
+}
+
+impl<'a> From<&'a [Pattern]> for Patterns {
+    fn from(patterns: &'a [Pattern]) -> Self {
+        Patterns(patterns.to_vec())
+    }
+}
+
+impl FromIterator<Pattern> for Patterns {
+    fn from_iter<I: IntoIterator<Item = Pattern>>(iter: I) -> Self {
+        Patterns(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Pattern> for Patterns {
+    fn extend<I: IntoIterator<Item = Pattern>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
 }
 
-/// Vec of `Pattern`
-pub type Patterns = Vec<Pattern>;
+impl IntoIterator for Patterns {
+    type Item = Pattern;
+    type IntoIter = vec::IntoIter<Pattern>;
 
-/// Define `Pattern` with flags
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Patterns {
+    type Item = &'a Pattern;
+    type IntoIter = slice::Iter<'a, Pattern>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Patterns {
+    type Item = &'a mut Pattern;
+    type IntoIter = slice::IterMut<'a, Pattern>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+/// Define `Pattern` with flags, ID and extended parameters
+///
+/// The options follow the expression in any order, `flags`, `id` and `ext`,
+/// or the extended parameters `min_offset`, `max_offset`, `min_length`, `edit_distance` and `hamming_distance`.
+///
+/// ```ignore
+/// pattern!{"foo", id => 42, flags => HS_FLAG_CASELESS, min_offset => 4}
+/// ```
 #[macro_export]
 macro_rules! pattern {
+    ( @set $p:ident, flags => $flags:expr ) => {
+        $p.flags = $crate::CompileFlags::from_bits_retain($crate::FlagBits::flag_bits($flags));
+    };
+    ( @set $p:ident, id => $id:expr ) => { $p.id = $id; };
+    ( @set $p:ident, ext => $ext:expr ) => { $p.ext = $ext; };
+    ( @set $p:ident, min_offset => $v:expr ) => { $p.ext = $p.ext.with_min_offset($v); };
+    ( @set $p:ident, max_offset => $v:expr ) => { $p.ext = $p.ext.with_max_offset($v); };
+    ( @set $p:ident, min_length => $v:expr ) => { $p.ext = $p.ext.with_min_length($v); };
+    ( @set $p:ident, edit_distance => $v:expr ) => { $p.ext = $p.ext.with_edit_distance($v); };
+    ( @set $p:ident, hamming_distance => $v:expr ) => { $p.ext = $p.ext.with_hamming_distance($v); };
     ( $expr:expr ) => {{
-        pattern!($expr, flags => 0, id => 0)
-    }};
-    ( $expr:expr, flags => $flags:expr ) => {{
-        pattern!($expr, flags => $flags, id => 0)
-    }};
-    ( $expr:expr, flags => $flags:expr, id => $id:expr ) => {{
         $crate::Pattern{
             expression: ::std::convert::From::from($expr),
-            flags: ::std::convert::From::from($flags),
-            id: $id
+            flags: $crate::CompileFlags::default(),
+            id: 0,
+            ext: $crate::ExprExt::default()
         }
+    }};
+    ( $expr:expr, $( $key:ident => $value:expr ),+ ) => {{
+        let mut p = pattern!($expr);
+        $(
+            pattern!(@set p, $key => $value);
+        )+
+
+        p
     }}
 }
 
 /// Define multi `Pattern` with flags and ID
+///
+/// An entry is an expression, or an expression with the options of `pattern!` in braces,
+/// which override the flags of the set. The entries with an `id` are inserted with their own ID,
+/// panicking if it is assigned to a different pattern, the others are numbered in order.
+///
+/// ```ignore
+/// patterns!(["foo", {"bar", id => 42, min_offset => 4}, {"baz", flags => HS_FLAG_CASELESS}])
+/// ```
 #[macro_export]
 macro_rules! patterns {
-    ( [ $( $expr:expr ), * ] ) => {{
-        patterns!([ $( $expr ), * ], flags => 0)
-    }};
-    ( [ $( $expr:expr ), * ], flags => $flags:expr ) => {{
-        let mut v = Vec::new();
+    ( @explicit ) => { false };
+    ( @explicit id $( $rest:ident )* ) => { true };
+    ( @explicit $key:ident $( $rest:ident )* ) => { patterns!(@explicit $( $rest )*) };
+    ( @entry $v:ident, $flags:expr; ) => {};
+    ( @entry $v:ident, $flags:expr; { $expr:expr, $( $key:ident => $value:expr ),+ } ) => {
+        let mut p = pattern!{$expr, flags => $flags};
         $(
-            let id = v.len() + 1;
+            pattern!(@set p, $key => $value);
+        )+
 
-            v.push(pattern!{$expr, flags => $flags, id => id});
-        )*
+        if patterns!(@explicit $( $key )+) {
+            $v.insert(p).unwrap();
+        } else {
+            $v.add(p);
+        }
+    };
+    ( @entry $v:ident, $flags:expr; { $( $pattern:tt )* }, $( $rest:tt )* ) => {
+        patterns!(@entry $v, $flags; { $( $pattern )* });
+        patterns!(@entry $v, $flags; $( $rest )*);
+    };
+    ( @entry $v:ident, $flags:expr; $expr:expr ) => {
+        $v.add(pattern!{$expr, flags => $flags});
+    };
+    ( @entry $v:ident, $flags:expr; $expr:expr, $( $rest:tt )* ) => {
+        patterns!(@entry $v, $flags; $expr);
+        patterns!(@entry $v, $flags; $( $rest )*);
+    };
+    ( [ $( $entry:tt )* ] ) => {{
+        patterns!([ $( $entry )* ], flags => 0)
+    }};
+    ( [ $( $entry:tt )* ], flags => $flags:expr ) => {{
+        #[allow(unused_mut)]
+        let mut v = $crate::Patterns::new();
+
+        patterns!(@entry v, $flags; $( $entry )*);
 
         v
     }};
@@ -236,13 +1003,14 @@ impl<T: Type> RawDatabase<T> {
     /// This is the function call with which an expression is compiled into a Hyperscan database
     // which can be passed to the runtime functions.
     pub fn compile(expression: &str, flags: u32, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+        let flags = try!(CompileFlags::from_bits(flags));
         let expr = try!(CString::new(expression));
         let mut db: RawDatabasePtr = ptr::null_mut();
         let mut err: RawCompileErrorPtr = ptr::null_mut();
 
         unsafe {
             check_compile_error!(hs_compile(expr.as_bytes_with_nul().as_ptr() as *const i8,
-                                            flags,
+                                            flags.0,
                                             T::mode(),
                                             platform.as_ptr(),
                                             &mut db,
@@ -252,12 +1020,93 @@ impl<T: Type> RawDatabase<T> {
 
         debug!("pattern `/{}/{}` compiled to {} database {:p}",
                expression,
-               CompileFlags(flags),
+               flags,
                T::name(),
                db);
 
         Ok(RawDatabase::from_raw(db))
     }
+
+    /// The multiple regular expression compiler.
+    ///
+    /// The expressions are compiled together with their flags and IDs, which must be as many as the expressions,
+    /// returns `Error::Invalid` otherwise. The failing expression is reported with `Error::PatternError`.
+    pub fn compile_multi(expressions: &[&str],
+                         flags: &[u32],
+                         ids: &[u32],
+                         platform: &PlatformInfo)
+                         -> Result<RawDatabase<T>, Error> {
+        Self::compile_ext_multi(expressions, flags, ids, &vec![None; expressions.len()], platform)
+    }
+
+    /// The multiple regular expression compiler with extended parameters.
+    ///
+    /// Only the expressions with `Some` extended parameters pass them to the compiler, the others pass a NULL pointer.
+    /// The flags, IDs and extended parameters must be as many as the expressions, returns `Error::Invalid` otherwise.
+    pub fn compile_ext_multi(expressions: &[&str],
+                             flags: &[u32],
+                             ids: &[u32],
+                             exts: &[Option<ExprExt>],
+                             platform: &PlatformInfo)
+                             -> Result<RawDatabase<T>, Error> {
+        let patterns = try!(expression_patterns(expressions, flags, ids, exts));
+
+        compile_patterns(&patterns, 0, platform)
+    }
+
+    /// The multiple regular expression compiler, skipping the expressions which fail to compile.
+    ///
+    /// Returns the database of the other expressions, with the errors of the skipped ones, see `Patterns::build_lossy`.
+    pub fn compile_multi_lossy(expressions: &[&str],
+                               flags: &[u32],
+                               ids: &[u32],
+                               platform: &PlatformInfo)
+                               -> Result<(RawDatabase<T>, Vec<PatternError>), Error> {
+        let patterns = try!(expression_patterns(expressions, flags, ids, &vec![None; expressions.len()]));
+
+        compile_patterns_lossy(&patterns, 0, platform)
+    }
+}
+
+fn expression_patterns(expressions: &[&str],
+                       flags: &[u32],
+                       ids: &[u32],
+                       exts: &[Option<ExprExt>])
+                       -> Result<Patterns, Error> {
+    if flags.len() != expressions.len() || ids.len() != expressions.len() || exts.len() != expressions.len() {
+        return Err(Error::Invalid);
+    }
+
+    expressions.iter()
+        .zip(flags.iter().zip(ids.iter()).zip(exts.iter()))
+        .map(|(&expression, ((&flags, &id), ext))| {
+            Ok(Pattern {
+                expression: String::from(expression),
+                flags: try!(CompileFlags::from_bits(flags)),
+                id: id as usize,
+                ext: ext.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+impl<T: Type> FromStr for RawDatabase<T> {
+    type Err = Error;
+
+    /// Compile a single expression with the default flags for the current host.
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RawDatabase::compile(s, 0, &PlatformInfo::null())
+    }
+}
+
+impl<'a, T: Type> TryFrom<&'a str> for RawDatabase<T> {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(s: &'a str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
 }
 
 impl<T: Type> DatabaseBuilder<RawDatabase<T>> for Pattern {
@@ -268,68 +1117,208 @@ impl<T: Type> DatabaseBuilder<RawDatabase<T>> for Pattern {
     /// into a Hyperscan database which can be passed to the runtime functions
     ///
     fn build_for_platform(&self, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
-        RawDatabase::compile(&self.expression, self.flags.0, platform)
+        if self.ext.is_empty() {
+            RawDatabase::compile(&self.expression, self.flags.0, platform)
+                .map_err(|err| diagnose_compile_error(&[self.clone()], err))
+        } else {
+            compile_patterns(&[self.clone()], 0, platform)
+        }
+    }
+}
+
+impl<T: Type> DatabaseBuilder<RawDatabase<T>> for [Pattern] {
+    ///
+    /// The multiple regular expression compiler.
+    ///
+    /// This is the function call with which a set of expressions is compiled into a database
+    /// which can be passed to the runtime functions.
+    /// Each expression can be labelled with a unique integer
+    // which is passed into the match callback to identify the pattern that has matched.
+    ///
+    fn build_for_platform(&self, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+        compile_patterns(self, 0, platform)
     }
 }
 
-impl<T: Type> DatabaseBuilder<RawDatabase<T>> for Patterns {
-    ///
-    /// The multiple regular expression compiler.
-    ///
-    /// This is the function call with which a set of expressions is compiled into a database
-    /// which can be passed to the runtime functions.
-    /// Each expression can be labelled with a unique integer
-    // which is passed into the match callback to identify the pattern that has matched.
-    ///
-    fn build_for_platform(&self, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
-        let mut expressions = Vec::with_capacity(self.len());
-        let mut ptrs = Vec::with_capacity(self.len());
-        let mut flags = Vec::with_capacity(self.len());
-        let mut ids = Vec::with_capacity(self.len());
+impl<T: Type> DatabaseBuilder<RawDatabase<T>> for Patterns {
+    fn build_for_platform(&self, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+        compile_patterns(self, 0, platform)
+    }
+}
+
+/// Compile a set of expressions, with additional mode flags (such as `HS_MODE_SOM_HORIZON_*`).
+pub fn compile_patterns<T: Type>(patterns: &[Pattern],
+                                 mode: u32,
+                                 platform: &PlatformInfo)
+                                 -> Result<RawDatabase<T>, Error> {
+    try!(check_combinations(patterns));
+
+    compile_checked(patterns, mode, platform)
+}
+
+/// Compile a set of expressions whose logical combinations were already checked.
+pub(crate) fn compile_checked<T: Type>(patterns: &[Pattern],
+                                       mode: u32,
+                                       platform: &PlatformInfo)
+                                       -> Result<RawDatabase<T>, Error> {
+    compile_multi(patterns, mode, platform).map_err(|err| diagnose_compile_error(patterns, err))
+}
+
+/// Compile a set of expressions, skipping the patterns which fail to compile.
+///
+/// The patterns rejected by the expression analysis are skipped first, then the set is compiled
+/// again without each pattern the compiler fails on. Returns the database with the errors
+/// of the skipped patterns, indexed in the given set, or the error which can't be attributed to a pattern.
+pub fn compile_patterns_lossy<T: Type>(patterns: &[Pattern],
+                                       mode: u32,
+                                       platform: &PlatformInfo)
+                                       -> Result<(RawDatabase<T>, Vec<PatternError>), Error> {
+    let mut indices = Vec::with_capacity(patterns.len());
+    let mut remaining = Vec::with_capacity(patterns.len());
+    let mut rejected = Vec::new();
+
+    for (index, pattern) in patterns.iter().enumerate() {
+        let checked = if pattern.flags.is_set(HS_FLAG_COMBINATION) {
+            Ok(())
+        } else {
+            pattern.info().map(|_| ())
+        };
+
+        match checked {
+            Err(Error::CompilerError(message)) => {
+                rejected.push(PatternError {
+                    index: index,
+                    id: pattern.id,
+                    expression: pattern.expression.clone(),
+                    message: message,
+                })
+            }
+            Err(err) => return Err(err),
+            Ok(()) => {
+                indices.push(index);
+                remaining.push(pattern.clone());
+            }
+        }
+    }
+
+    loop {
+        if remaining.is_empty() && !rejected.is_empty() {
+            return Err(Error::PatternError(rejected.remove(0)));
+        }
+
+        match compile_multi(&remaining, mode, platform).map_err(|err| diagnose_compile_error(&remaining, err)) {
+            Ok(db) => {
+                if !rejected.is_empty() {
+                    warn!("{} of {} patterns skipped", rejected.len(), patterns.len());
+                }
+
+                rejected.sort_by_key(|err| err.index);
+
+                return Ok((db, rejected));
+            }
+            Err(Error::PatternError(mut err)) => {
+                remaining.remove(err.index);
+                err.index = indices.remove(err.index);
 
-        for pattern in self {
-            let expr = try!(CString::new(pattern.expression.as_str()));
+                debug!("skip {}", err);
 
-            expressions.push(expr);
-            flags.push(pattern.flags.0 as c_uint);
-            ids.push(pattern.id as c_uint);
+                rejected.push(err);
+            }
+            Err(err) => return Err(err),
         }
+    }
+}
 
-        for expr in &expressions {
-            ptrs.push(expr.as_bytes_with_nul().as_ptr() as *const i8);
+fn compile_multi<T: Type>(patterns: &[Pattern], mode: u32, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+    let mut expressions = Vec::with_capacity(patterns.len());
+    let mut ptrs = Vec::with_capacity(patterns.len());
+    let mut flags = Vec::with_capacity(patterns.len());
+    let mut ids = Vec::with_capacity(patterns.len());
+    let mut exts = Vec::with_capacity(patterns.len());
+
+    for (index, pattern) in patterns.iter().enumerate() {
+        if CompileFlags::from_bits(pattern.flags.0).is_err() {
+            return Err(Error::PatternError(PatternError {
+                index: index,
+                id: pattern.id,
+                expression: pattern.expression.clone(),
+                message: format!("unknown compile flags {:#x}", pattern.flags.0 & !CompileFlags::ALL.0),
+            }));
         }
 
-        let mut db: RawDatabasePtr = ptr::null_mut();
-        let mut err: RawCompileErrorPtr = ptr::null_mut();
+        let expr = try!(CString::new(pattern.expression.as_str()));
 
-        unsafe {
+        expressions.push(expr);
+        flags.push(pattern.flags.0 as c_uint);
+        ids.push(pattern.id as c_uint);
+        exts.push(pattern.ext.to_raw());
+    }
+
+    for expr in &expressions {
+        ptrs.push(expr.as_bytes_with_nul().as_ptr() as *const i8);
+    }
+
+    let mut db: RawDatabasePtr = ptr::null_mut();
+    let mut err: RawCompileErrorPtr = ptr::null_mut();
+
+    unsafe {
+        if patterns.iter().all(|pattern| pattern.ext.is_empty()) {
             check_compile_error!(hs_compile_multi(ptrs.as_ptr(),
                                                   flags.as_ptr(),
                                                   ids.as_ptr(),
-                                                  self.len() as u32,
-                                                  T::mode(),
+                                                  patterns.len() as u32,
+                                                  T::mode() | mode,
                                                   platform.as_ptr(),
                                                   &mut db,
                                                   &mut err),
-                                 err);
+                                 err,
+                                 |index| patterns.get(index).map(|p| (p.id, p.expression.clone())));
+        } else {
+            // the patterns without extended parameters pass a NULL pointer
+            let ext_ptrs = patterns.iter()
+                .zip(exts.iter())
+                .map(|(pattern, ext)| if pattern.ext.is_empty() {
+                    ptr::null()
+                } else {
+                    ext as *const hs_expr_ext_t
+                })
+                .collect::<Vec<_>>();
+
+            check_compile_error!(hs_compile_ext_multi(ptrs.as_ptr(),
+                                                      flags.as_ptr(),
+                                                      ids.as_ptr(),
+                                                      ext_ptrs.as_ptr(),
+                                                      patterns.len() as u32,
+                                                      T::mode() | mode,
+                                                      platform.as_ptr(),
+                                                      &mut db,
+                                                      &mut err),
+                                 err,
+                                 |index| patterns.get(index).map(|p| (p.id, p.expression.clone())));
         }
+    }
 
-        debug!("patterns [{}] compiled to {} database {:p}",
-               Vec::from_iter(self.iter().map(|p| format!("`{}`", p))).join(", "),
-               T::name(),
-               db);
+    debug!("patterns [{}] compiled to {} database {:p}",
+           Vec::from_iter(patterns.iter().map(|p| format!("`{}`", p))).join(", "),
+           T::name(),
+           db);
 
-        Ok(RawDatabase::from_raw(db))
-    }
+    Ok(RawDatabase::from_raw(db))
 }
 
 #[cfg(test)]
 pub mod tests {
     extern crate env_logger;
 
+    use std::env;
     use std::ptr;
+    use std::fs::File;
+    use std::io::{self, Write};
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
 
     use super::super::*;
+    use super::FLAG_CHARS;
     use super::super::common::tests::*;
 
     const DATABASE_SIZE: usize = 2664;
@@ -351,6 +1340,107 @@ pub mod tests {
 
         assert_eq!(CompileFlags::parse("ism").unwrap(), flags);
         assert!(CompileFlags::parse("test").is_err());
+        assert_eq!(CompileFlags::CASELESS | CompileFlags::MULTILINE | CompileFlags::DOTALL, flags);
+        assert_eq!(flags & CompileFlags::DOTALL, CompileFlags::DOTALL);
+        assert!(flags.contains(CompileFlags::CASELESS | CompileFlags::DOTALL));
+        assert!(!flags.contains(CompileFlags::UTF8));
+        assert_eq!(format!("{:?}", flags), "CompileFlags(CASELESS | DOTALL | MULTILINE)");
+        assert_eq!(format!("{:?}", CompileFlags::default()), "CompileFlags(0)");
+
+        assert_eq!(CompileFlags::from_bits(HS_FLAG_UTF8 | HS_FLAG_UCP).unwrap().bits(),
+                   HS_FLAG_UTF8 | HS_FLAG_UCP);
+        assert_eq!(CompileFlags::from_bits(HS_MODE_STREAM << 16).err(), Some(Error::Invalid));
+        assert_eq!(CompileFlags::from_bits_truncate(HS_FLAG_QUIET | HS_MODE_SOM_HORIZON_LARGE),
+                   CompileFlags::QUIET);
+
+        let mut flags = CompileFlags::default();
+
+        flags |= CompileFlags::SOM_LEFTMOST;
+
+        assert_eq!(pattern!{"foo", flags => flags}.flags, CompileFlags::SOM_LEFTMOST);
+    }
+
+    #[test]
+    fn test_compile_flags_round_trip() {
+        let _ = env_logger::init();
+
+        assert_eq!(CompileFlags::parse("Hi8W").unwrap(),
+                   CompileFlags::SINGLEMATCH | CompileFlags::CASELESS | CompileFlags::UTF8 | CompileFlags::UCP);
+        assert_eq!(CompileFlags::parse("Hi8W").unwrap().to_string(), "iH8W");
+        assert_eq!(CompileFlags::ALL.to_string(), "imsHV8WCQPL");
+        assert_eq!(CompileFlags::default().to_string(), "");
+
+        for &(c, flag) in FLAG_CHARS.iter() {
+            assert_eq!(CompileFlags::parse(&c.to_string()).unwrap().bits(), flag);
+        }
+
+        for bits in 0..(1 << FLAG_CHARS.len()) {
+            let flags = FLAG_CHARS.iter()
+                .enumerate()
+                .filter(|&(i, _)| bits & (1 << i) != 0)
+                .fold(CompileFlags::default(), |flags, (_, &(_, flag))| flags | CompileFlags(flag));
+
+            assert_eq!(flags.to_string().parse::<CompileFlags>().unwrap(), flags);
+
+            let p = pattern!{"foo", flags => flags, id => 1};
+
+            assert_eq!(Pattern::parse(&p.to_string()).unwrap(), p);
+        }
+    }
+
+    #[test]
+    fn test_compile_flags_unknown() {
+        let _ = env_logger::init();
+
+        assert_eq!(CompileFlags::try_from(HS_MODE_SOM_HORIZON_SMALL), Err(Error::Invalid));
+        assert_eq!(CompileFlags::try_from(HS_FLAG_CASELESS), Ok(CompileFlags::CASELESS));
+
+        let p = pattern!{"foo", flags => HS_FLAG_CASELESS | HS_MODE_SOM_HORIZON_SMALL};
+
+        match p.build() as Result<BlockDatabase, Error> {
+            Err(Error::PatternError(err)) => assert_eq!(err.message, "unknown compile flags 0x1000000"),
+            result => panic!("unexpected {:?}", result),
+        }
+
+        assert_eq!(RawDatabase::<Block>::compile("foo", HS_MODE_SOM_HORIZON_SMALL, &PlatformInfo::null()).err(),
+                   Some(Error::Invalid));
+        assert_eq!(BlockDatabase::compile_multi(&["foo"], &[HS_MODE_SOM_HORIZON_SMALL], &[1], &PlatformInfo::null())
+                       .err(),
+                   Some(Error::Invalid));
+    }
+
+    #[test]
+    fn test_expr_ext() {
+        let _ = env_logger::init();
+
+        let ext = ExprExt::new();
+
+        assert!(ext.is_empty());
+        assert_eq!(ext.flags(), ExtFlags(0));
+
+        let ext = ExprExt::new().with_min_offset(10).with_min_length(4);
+
+        assert!(!ext.is_empty());
+        assert_eq!(ext.flags(), ExtFlags(HS_EXT_FLAG_MIN_OFFSET | HS_EXT_FLAG_MIN_LENGTH));
+        assert!(ext.flags().is_set(HS_EXT_FLAG_MIN_OFFSET));
+        assert!(!ext.flags().is_set(HS_EXT_FLAG_MAX_OFFSET));
+        assert_eq!(ext.min_offset(), Some(10));
+        assert_eq!(ext.max_offset(), None);
+        assert_eq!(ext.min_length(), Some(4));
+
+        let raw = ext.to_raw();
+
+        assert_eq!(raw.flags, HS_EXT_FLAG_MIN_OFFSET | HS_EXT_FLAG_MIN_LENGTH);
+        assert_eq!(raw.min_offset, 10);
+        assert_eq!(raw.min_length, 4);
+
+        assert_eq!(ExprExt::from_raw(&raw), ext);
+
+        let mut raw = ExprExt::new().to_raw();
+
+        raw.max_offset = 100;
+
+        assert_eq!(ExprExt::from_raw(&raw).max_offset(), None);
     }
 
     #[test]
@@ -364,6 +1454,98 @@ pub mod tests {
         validate_database(&db);
     }
 
+    #[test]
+    fn test_database_from_str() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = "foo\\d+".parse().unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        let db = StreamingDatabase::try_from("foo\\d+").unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        assert!("(foo".parse::<VectoredDatabase>().is_err());
+    }
+
+    #[test]
+    fn test_database_compile_multi() {
+        let _ = env_logger::init();
+
+        let db = BlockDatabase::compile_multi(&["foo", "bar\\d+"],
+                                              &[0, HS_FLAG_SOM_LEFTMOST],
+                                              &[10, 20],
+                                              &PlatformInfo::null())
+            .unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        assert_matches!(&db, "foo bar12", [(10, 3), (20, 8), (20, 9)]);
+
+        let db = StreamingDatabase::compile_multi(&["foo", "bar"], &[0, 0], &[1, 2], &PlatformInfo::null()).unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        assert_eq!(VectoredDatabase::compile_multi(&["foo", "bar"], &[0], &[1, 2], &PlatformInfo::null()).err(),
+                   Some(Error::Invalid));
+
+        match VectoredDatabase::compile_multi(&["foo", "(bar"], &[0, 0], &[1, 2], &PlatformInfo::null()) {
+            Err(Error::PatternError(err)) => assert_eq!((err.index, err.id), (1, 2)),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_database_compile_multi_lossy() {
+        let _ = env_logger::init();
+
+        let (db, rejected) = BlockDatabase::compile_multi_lossy(&["foo", "(bar", "baz", "a?", "qux"],
+                                                                &[0, 0, 0, 0, 0],
+                                                                &[1, 2, 3, 4, 5],
+                                                                &PlatformInfo::null())
+            .unwrap();
+
+        assert_matches!(&db, "foo bar baz qux", [(1, 3), (3, 11), (5, 15)]);
+
+        assert_eq!(rejected.iter().map(|err| (err.index, err.id)).collect::<Vec<_>>(),
+                   vec![(1, 2), (3, 4)]);
+        assert_eq!(rejected[0].expression, "(bar");
+        assert!(rejected[1].message.contains("HS_FLAG_ALLOWEMPTY"));
+
+        let patterns = patterns!(["foo", "bar"]);
+        let (db, rejected) = patterns.build_lossy::<Streaming>(&PlatformInfo::null()).unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        assert!(rejected.is_empty());
+
+        match patterns!(["(foo", "bar)"]).build_lossy::<Block>(&PlatformInfo::null()) {
+            Err(Error::PatternError(err)) => assert_eq!(err.index, 0),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_database_compile_ext_multi() {
+        let _ = env_logger::init();
+
+        let exts = [None, Some(ExprExt::new().with_min_offset(8)), Some(ExprExt::new())];
+        let db = BlockDatabase::compile_ext_multi(&["foo", "bar", "baz"],
+                                                  &[0, 0, 0],
+                                                  &[1, 2, 3],
+                                                  &exts,
+                                                  &PlatformInfo::null())
+            .unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        assert_matches!(&db, "foo bar baz bar", [(1, 3), (3, 11), (2, 15)]);
+
+        assert_eq!(BlockDatabase::compile_ext_multi(&["foo"], &[0], &[1], &[], &PlatformInfo::null()).err(),
+                   Some(Error::Invalid));
+    }
+
     #[test]
     fn test_pattern() {
         let _ = env_logger::init();
@@ -406,6 +1588,148 @@ pub mod tests {
         assert_eq!(p.id, 0);
     }
 
+    #[test]
+    fn test_pattern_round_trip() {
+        let _ = env_logger::init();
+
+        let p: Pattern = "/foo(bar)?/is".parse().unwrap();
+
+        assert_eq!(p.expression, "foo(bar)?");
+        assert_eq!(p.flags, CompileFlags(HS_FLAG_CASELESS | HS_FLAG_DOTALL));
+        assert_eq!(p.to_string(), "0:/foo(bar)?/is");
+
+        let p: Pattern = "/a:b/".parse().unwrap();
+
+        assert_eq!(p.expression, "a:b");
+        assert_eq!(p.id, 0);
+
+        for s in &["12:/^GET \\/[a-z]+:\\d+$/msHV8W",
+                   "3:/a\\/b/PL",
+                   "4:/(1 & 2)/CQ",
+                   "5:/foo.*bar/H{min_offset=2,max_offset=100}"] {
+            let p: Pattern = s.parse().unwrap();
+
+            assert_eq!(&p.to_string(), s);
+            assert_eq!(p.to_string().parse::<Pattern>().unwrap(), p);
+        }
+
+        assert!("1:/foo/x".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn test_pattern_ext() {
+        let _ = env_logger::init();
+
+        let p = Pattern::parse("1:/test/i{min_offset=10, edit_distance=1}").unwrap();
+
+        assert_eq!(p.expression, "test");
+        assert_eq!(p.flags, CompileFlags(HS_FLAG_CASELESS));
+        assert_eq!(p.ext, ExprExt::new().with_min_offset(10).with_edit_distance(1));
+        assert_eq!(p.to_string(), "1:/test/i{min_offset=10,edit_distance=1}");
+
+        assert!(Pattern::parse("/test/{min_offset=x}").is_err());
+        assert!(Pattern::parse("/test/{max_depth=1}").is_err());
+
+        let p = pattern!{"test", flags => 0, id => 1, ext => ExprExt::new().with_min_offset(10)};
+        let db: BlockDatabase = p.build().unwrap();
+        let s = db.alloc().unwrap();
+        let matches = RefCell::new(Vec::new());
+
+        db.scan("test data test", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(matches.into_inner(), vec![14]);
+
+        let db: BlockDatabase = vec![pattern!{"foo", flags => 0, id => 1},
+                                     pattern!{"test", flags => 0, id => 2, ext => ExprExt::new().with_max_offset(4)}]
+            .build()
+            .unwrap();
+        let s = db.alloc().unwrap();
+        let matches = RefCell::new(Vec::new());
+
+        db.scan("test foo test", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(matches.into_inner(), vec![4, 8]);
+    }
+
+    #[test]
+    fn test_pattern_approximate() {
+        let _ = env_logger::init();
+
+        let p = Pattern::parse("/hyperscan/{hamming_distance=1}").unwrap();
+
+        assert_eq!(p.ext.hamming_distance(), Some(1));
+        assert!(p.ext.is_approximate());
+        assert_eq!(p.ext.flags(), ExtFlags(HS_EXT_FLAG_HAMMING_DISTANCE));
+        assert_eq!(p.ext.to_raw().hamming_distance, 1);
+        assert_eq!(ExprExt::from_raw(&p.ext.to_raw()), p.ext);
+        assert_eq!(p.to_string(), "0:/hyperscan/{hamming_distance=1}");
+        assert!(!ExprExt::new().with_edit_distance(0).is_approximate());
+
+        let db: BlockDatabase = pattern!{"hyperscan", flags => HS_FLAG_SINGLEMATCH, id => 1}
+            .with_edit_distance(1)
+            .build()
+            .unwrap();
+        let s = db.alloc().unwrap();
+        let matches = RefCell::new(Vec::new());
+
+        db.scan("hyprescan", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert!(matches.borrow().is_empty());
+
+        db.scan("hypescan", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(matches.into_inner(), vec![8]);
+
+        let db: BlockDatabase = pattern!{"hyperscan", flags => HS_FLAG_SINGLEMATCH, id => 1}
+            .with_hamming_distance(1)
+            .build()
+            .unwrap();
+        let s = db.alloc().unwrap();
+        let matches = RefCell::new(Vec::new());
+
+        db.scan("hypescan", 0, &s, Some(on_match), Some(&matches)).unwrap();
+        db.scan("hyp3rscan", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(matches.into_inner(), vec![9]);
+    }
+
+    fn on_match(_: u32, _: u64, to: u64, _: u32, matches: &RefCell<Vec<u64>>) -> u32 {
+        matches.borrow_mut().push(to);
+
+        0
+    }
+
+    #[test]
+    fn test_pattern_fold_case() {
+        let _ = env_logger::init();
+
+        let p = pattern!{"café", flags => 0, id => 1}.fold_case();
+
+        assert_eq!(p.expression, "caf[éÉ]");
+        assert_eq!(p.flags, CompileFlags(HS_FLAG_CASELESS | HS_FLAG_UTF8));
+        assert_eq!(p.id, 1);
+
+        let p = pattern!{"test"}.fold_case();
+
+        assert_eq!(p.expression, "test");
+        assert_eq!(p.flags, CompileFlags(HS_FLAG_CASELESS));
+
+        let db: BlockDatabase = pattern!{"CAFÉ"}.fold_case().build().unwrap();
+
+        validate_database_with_size(&db, 0);
+    }
+
+    #[test]
+    fn test_pattern_anchored() {
+        let _ = env_logger::init();
+
+        let p = pattern!{"foo|bar", flags => HS_FLAG_MULTILINE, id => 2}.anchored();
+
+        assert_eq!(p.expression, "\\A(?:foo|bar)");
+        assert_eq!(p.flags, CompileFlags(HS_FLAG_MULTILINE));
+        assert_eq!(p.id, 2);
+    }
+
     #[test]
     fn test_pattern_build() {
         let _ = env_logger::init();
@@ -423,6 +1747,30 @@ pub mod tests {
         assert!(!info.unordered_matches);
         assert!(!info.matches_at_eod);
         assert!(!info.matches_only_at_eod);
+        assert!(info.is_fixed_width());
+        assert_eq!(info.bounded_width(), Some(4));
+        assert!(info.is_streamable());
+
+        let info = pattern!{"foo.*bar\\z"}.info().unwrap();
+
+        assert_eq!(info.min_width, 6);
+        assert_eq!(info.max_width, ExpressionInfo::UNBOUNDED);
+        assert!(info.is_unbounded());
+        assert_eq!(info.bounded_width(), None);
+        assert!(info.matches_at_eod);
+        assert!(info.matches_only_at_eod);
+        assert!(!info.is_streamable());
+
+        let ext = ExprExt::new().with_edit_distance(1);
+
+        assert!(pattern!{"foobar", flags => 0, id => 1, ext => ext}.info().is_ok());
+
+        let info = pattern!{"foobar"}.ext_info(&ext).unwrap();
+
+        assert_eq!(info.min_width, 5);
+        assert_eq!(info.max_width, 7);
+        assert_eq!(pattern!{"foobar"}.ext_info(&ExprExt::new()).unwrap(),
+                   pattern!{"foobar"}.info().unwrap());
 
         let db: BlockDatabase = p.build().unwrap();
 
@@ -444,6 +1792,48 @@ pub mod tests {
         validate_database(&db);
     }
 
+    #[test]
+    fn test_allow_empty() {
+        let _ = env_logger::init();
+
+        assert!(pattern!{".*"}.can_match_empty().unwrap());
+        assert!(!pattern!{"test"}.can_match_empty().unwrap());
+        assert!(CompileFlags(HS_FLAG_ALLOWEMPTY).allows_empty());
+
+        let patterns = patterns!(["test", "a?", "b*"]);
+
+        let empty = empty_matching_patterns(&patterns);
+
+        assert_eq!(empty.iter().map(|p| p.id).collect::<Vec<_>>(), vec![2, 3]);
+
+        let result: Result<BlockDatabase, Error> = patterns.build();
+
+        match result.err() {
+            Some(Error::PatternError(err)) => {
+                assert_eq!(err.index, 1);
+                assert_eq!(err.id, 2);
+                assert_eq!(err.expression, "a?");
+                assert!(err.message.contains("HS_FLAG_ALLOWEMPTY"));
+            }
+            err => panic!("unexpected result: {:?}", err),
+        }
+
+        let patterns = patterns!(["test", "a?"], flags => HS_FLAG_ALLOWEMPTY);
+
+        assert!(empty_matching_patterns(&patterns).is_empty());
+
+        let _: BlockDatabase = patterns.build().unwrap();
+
+        match (pattern!{"b*"}.build() as Result<BlockDatabase, Error>).err() {
+            Some(Error::CompilerError(message)) => assert!(message.contains("HS_FLAG_ALLOWEMPTY")),
+            err => panic!("unexpected result: {:?}", err),
+        }
+
+        let err = Error::CompilerError("Internal error.".to_owned());
+
+        assert_eq!(super::diagnose_compile_error(&patterns!(["test", "a?"]), err.clone()), err);
+    }
+
     #[test]
     fn test_patterns_build() {
         let _ = env_logger::init();
@@ -453,6 +1843,28 @@ pub mod tests {
         validate_database_with_size(&db, DATABASE_SIZE);
     }
 
+    #[test]
+    fn test_patterns_build_error() {
+        let _ = env_logger::init();
+
+        let mut patterns = patterns!(["test", "foo"]);
+
+        patterns.insert(pattern!{"bar(", flags => HS_FLAG_CASELESS, id => 42}).unwrap();
+
+        let result: Result<BlockDatabase, Error> = patterns.build();
+
+        match result.err() {
+            Some(Error::PatternError(err)) => {
+                assert_eq!(err.index, 2);
+                assert_eq!(err.id, 42);
+                assert_eq!(err.expression, "bar(");
+                assert!(!err.message.is_empty());
+                assert!(err.to_string().starts_with("pattern #2 (ID 42) `bar(`, "));
+            }
+            err => panic!("unexpected result: {:?}", err),
+        }
+    }
+
     #[test]
     fn test_patterns_build_with_flags() {
         let _ = env_logger::init();
@@ -463,4 +1875,99 @@ pub mod tests {
 
         validate_database_with_size(&db, DATABASE_SIZE);
     }
+
+    #[test]
+    fn test_patterns() {
+        let _ = env_logger::init();
+
+        let mut patterns = Patterns::new();
+
+        assert_eq!(patterns.next_id(), 1);
+        assert_eq!(patterns.add(pattern!{"foo"}), 1);
+        assert_eq!(patterns.add(pattern!{"bar"}), 2);
+
+        patterns.insert(pattern!{"baz", flags => 0, id => 10}).unwrap();
+
+        assert_eq!(patterns.add(pattern!{"qux"}), 11);
+        assert_eq!(patterns.get(10).unwrap().expression, "baz");
+        assert!(patterns.get(3).is_none());
+
+        assert!(patterns.insert(pattern!{"foo", flags => 0, id => 1}).is_ok());
+        assert!(patterns.insert(pattern!{"foo", flags => HS_FLAG_CASELESS, id => 2}).is_err());
+        assert_eq!(patterns.len(), 4);
+        assert!(patterns.check_ids().is_ok());
+
+        patterns.assign_ids(&IdStrategy::Sequential(100)).unwrap();
+
+        assert_eq!(patterns.iter().map(|p| p.id).collect::<Vec<_>>(),
+                   vec![100, 101, 102, 103]);
+
+        assert_eq!(patterns!(["foo", "bar"]).into_inner(),
+                   vec![pattern!{"foo", flags => 0, id => 1}, pattern!{"bar", flags => 0, id => 2}]);
+
+        let db: StreamingDatabase = patterns.build().unwrap();
+
+        validate_database(&db);
+    }
+
+    #[test]
+    fn test_pattern_macro_options() {
+        let _ = env_logger::init();
+
+        let p = pattern!{"foo", id => 42, min_offset => 4, flags => HS_FLAG_CASELESS, edit_distance => 1};
+
+        assert_eq!(p.id, 42);
+        assert_eq!(p.flags, CompileFlags::CASELESS);
+        assert_eq!(p.ext, ExprExt::new().with_min_offset(4).with_edit_distance(1));
+        assert_eq!(pattern!{"foo", id => 1}, pattern!{"foo", flags => 0, id => 1});
+
+        let patterns = patterns!(["foo",
+                                  {"bar", id => 42, max_offset => 10},
+                                  {r"baz\d", flags => HS_FLAG_DOTALL},
+                                  "qux",],
+                                 flags => HS_FLAG_CASELESS);
+
+        assert_eq!(patterns.into_inner(),
+                   vec![pattern!{"foo", flags => HS_FLAG_CASELESS, id => 1},
+                        pattern!{"bar", flags => HS_FLAG_CASELESS, id => 42, max_offset => 10},
+                        pattern!{r"baz\d", flags => HS_FLAG_DOTALL, id => 43},
+                        pattern!{"qux", flags => HS_FLAG_CASELESS, id => 44}]);
+
+        assert!(patterns!([]).is_empty());
+
+        let db: BlockDatabase = patterns!([{"test", id => 7, min_offset => 10}]).build().unwrap();
+
+        assert_matches!(&db, "test data test", [(7, 14)]);
+    }
+
+    #[test]
+    fn test_patterns_read() {
+        let _ = env_logger::init();
+
+        let rules = "# IDS rules\n\n1:/foo/i\n 2:/bar\\d+/s \n# 3:/baz/\n1:/foo/i\n";
+        let patterns = Patterns::read(rules.as_bytes()).unwrap();
+
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[1], pattern!{"bar\\d+", flags => HS_FLAG_DOTALL, id => 2});
+
+        let err = Patterns::read("1:/foo/\n2:/bar/{max=1}\n".as_bytes()).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("2: invalid pattern `2:/bar/{max=1}`"));
+
+        let err = Patterns::read("1:/foo/\n1:/bar/\n".as_bytes()).unwrap_err();
+
+        assert!(err.to_string().starts_with("2: invalid pattern `1:/bar/`"));
+
+        let path = env::temp_dir().join("test_patterns_read.txt");
+
+        File::create(&path).unwrap().write_all(b"1:/foo/\n2:/bar/x\n").unwrap();
+
+        let err = Patterns::from_file(&path).unwrap_err();
+
+        assert_eq!(err.to_string(),
+                   format!("{}:2: invalid pattern `2:/bar/x`, {}",
+                           path.display(),
+                           Pattern::parse("2:/bar/x").unwrap_err()));
+    }
 }
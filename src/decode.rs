@@ -0,0 +1,291 @@
+use std::fmt;
+use std::cell::RefCell;
+
+use api::*;
+use errors::Error;
+use common::BlockDatabase;
+
+/// An encoding removed by a decoding stage before scanning.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Decoding {
+    /// Base64, with the standard or the URL safe alphabet, the padding and whitespaces are optional.
+    Base64,
+    /// Hexadecimal digits in any case, whitespaces are ignored.
+    Hex,
+    /// URL percent encoding, the invalid escapes are kept as is.
+    Percent,
+}
+
+impl fmt::Display for Decoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}",
+               match *self {
+                   Decoding::Base64 => "base64",
+                   Decoding::Hex => "hex",
+                   Decoding::Percent => "percent",
+               })
+    }
+}
+
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn is_space(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\r' || c == b'\n'
+}
+
+impl Decoding {
+    /// Decode the data, returns `None` if it isn't encoded with this encoding.
+    pub fn decode(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match *self {
+            Decoding::Base64 => Self::decode_base64(data),
+            Decoding::Hex => Self::decode_hex(data),
+            Decoding::Percent => Self::decode_percent(data),
+        }
+    }
+
+    fn decode_base64(data: &[u8]) -> Option<Vec<u8>> {
+        let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+        let mut acc = 0u32;
+        let mut bits = 0;
+        let mut padding = false;
+
+        for &c in data {
+            if is_space(c) {
+                continue;
+            }
+
+            if c == b'=' {
+                padding = true;
+                continue;
+            }
+
+            if padding {
+                return None;
+            }
+
+            let v = match base64_value(c) {
+                Some(v) => v,
+                None => return None,
+            };
+
+            acc = (acc << 6) | v as u32;
+            bits += 6;
+
+            if bits >= 8 {
+                bits -= 8;
+                decoded.push((acc >> bits) as u8);
+            }
+        }
+
+        if bits >= 6 || decoded.is_empty() {
+            None
+        } else {
+            Some(decoded)
+        }
+    }
+
+    fn decode_hex(data: &[u8]) -> Option<Vec<u8>> {
+        let mut decoded = Vec::with_capacity(data.len() / 2);
+        let mut high = None;
+
+        for &c in data {
+            if is_space(c) {
+                continue;
+            }
+
+            let v = match hex_value(c) {
+                Some(v) => v,
+                None => return None,
+            };
+
+            match high.take() {
+                Some(h) => decoded.push(h << 4 | v),
+                None => high = Some(v),
+            }
+        }
+
+        if high.is_some() || decoded.is_empty() {
+            None
+        } else {
+            Some(decoded)
+        }
+    }
+
+    fn decode_percent(data: &[u8]) -> Option<Vec<u8>> {
+        let mut decoded = Vec::with_capacity(data.len());
+        let mut escaped = false;
+        let mut i = 0;
+
+        while i < data.len() {
+            if data[i] == b'%' && i + 2 < data.len() {
+                if let (Some(h), Some(l)) = (hex_value(data[i + 1]), hex_value(data[i + 2])) {
+                    decoded.push(h << 4 | l);
+                    escaped = true;
+                    i += 3;
+
+                    continue;
+                }
+            }
+
+            decoded.push(data[i]);
+            i += 1;
+        }
+
+        if escaped { Some(decoded) } else { None }
+    }
+}
+
+/// A match found in the data after some decoding stages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedMatch {
+    /// The ID number of the matched pattern.
+    pub id: u32,
+    /// The offset of the start of the match in the decoded data.
+    pub from: u64,
+    /// The offset of the end of the match in the decoded data.
+    pub to: u64,
+    /// The flags of the match event.
+    pub flags: MatchFlags,
+    /// The encodings removed before scanning, in the order they were decoded.
+    pub encodings: Vec<Decoding>,
+}
+
+/// A chain of decoding stages applied before scanning, for inspecting email and web content.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodeChain {
+    stages: Vec<Decoding>,
+}
+
+fn on_match(id: u32, from: u64, to: u64, flags: u32, matches: &RefCell<Vec<Match>>) -> u32 {
+    matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+    0
+}
+
+impl DecodeChain {
+    pub fn new() -> DecodeChain {
+        DecodeChain::default()
+    }
+
+    /// Append a decoding stage.
+    pub fn then(mut self, decoding: Decoding) -> DecodeChain {
+        self.stages.push(decoding);
+        self
+    }
+
+    pub fn stages(&self) -> &[Decoding] {
+        &self.stages
+    }
+
+    /// Apply all the stages, returns `None` if a stage fails to decode.
+    pub fn decode(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.stages.iter().fold(Some(data.to_vec()),
+                                |decoded, stage| decoded.and_then(|decoded| stage.decode(&decoded)))
+    }
+
+    /// Scan the data, then the output of each stage, until a stage fails to decode.
+    ///
+    /// The matches are tagged with the encodings removed before the data they were found in.
+    pub fn scan<T, S>(&self, db: &BlockDatabase, data: T, scratch: &S) -> Result<Vec<DecodedMatch>, Error>
+        where T: Scannable,
+              S: Scratch
+    {
+        let mut found = Vec::new();
+        let mut layer = data.as_bytes().to_vec();
+        let mut depth = 0;
+
+        loop {
+            let matches = RefCell::new(Vec::new());
+
+            try!(db.scan(&layer[..], 0, scratch, Some(on_match), Some(&matches)));
+
+            found.extend(matches.into_inner().into_iter().map(|m| {
+                DecodedMatch {
+                    id: m.id,
+                    from: m.from,
+                    to: m.to,
+                    flags: m.flags,
+                    encodings: self.stages[..depth].to_vec(),
+                }
+            }));
+
+            match self.stages.get(depth).and_then(|stage| stage.decode(&layer)) {
+                Some(decoded) => {
+                    layer = decoded;
+                    depth += 1;
+                }
+                None => break,
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_decoding() {
+        assert_eq!(Decoding::Base64.decode(b"dGVzdA=="), Some(b"test".to_vec()));
+        assert_eq!(Decoding::Base64.decode(b"dGVz\r\ndA"), Some(b"test".to_vec()));
+        assert_eq!(Decoding::Base64.decode(b"dGVzd"), None);
+        assert_eq!(Decoding::Base64.decode(b"dG=Vz"), None);
+        assert_eq!(Decoding::Base64.decode(b"t?st"), None);
+
+        assert_eq!(Decoding::Hex.decode(b"74 65 73 74"), Some(b"test".to_vec()));
+        assert_eq!(Decoding::Hex.decode(b"7465737"), None);
+        assert_eq!(Decoding::Hex.decode(b"tset"), None);
+
+        assert_eq!(Decoding::Percent.decode(b"a%20test%2"), Some(b"a test%2".to_vec()));
+        assert_eq!(Decoding::Percent.decode(b"%zz"), None);
+
+        let chain = DecodeChain::new().then(Decoding::Base64).then(Decoding::Percent);
+
+        assert_eq!(chain.decode(b"YSUyMHRlc3Q="), Some(b"a test".to_vec()));
+        assert_eq!(chain.decode(b"dGVzdA=="), None);
+    }
+
+    #[test]
+    fn test_decode_chain() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let chain = DecodeChain::new().then(Decoding::Hex).then(Decoding::Percent);
+
+        // hex encoded "a%20test"
+        let matches = chain.scan(&db, "6125323074657374", &s).unwrap();
+
+        assert_eq!(matches.iter().map(|m| (m.from, m.to, m.encodings.clone())).collect::<Vec<_>>(),
+                   vec![(4, 8, vec![Decoding::Hex]), (2, 6, vec![Decoding::Hex, Decoding::Percent])]);
+
+        let matches = chain.scan(&db, "test", &s).unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].encodings.is_empty());
+    }
+}
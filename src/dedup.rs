@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use constants::*;
+use compile::{Pattern, Patterns};
+use combination::combination_ids;
+
+/// A pattern dropped by `dedup_patterns` as equivalent to a kept pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    /// The dropped pattern, as it was before the normalization.
+    pub pattern: Pattern,
+    /// The ID of the kept pattern, which reports the matches of the dropped one.
+    pub kept: usize,
+}
+
+/// The flag of a leading inline modifier which applies to the whole expression.
+fn modifier_flag(c: char) -> Option<u32> {
+    match c {
+        'i' => Some(HS_FLAG_CASELESS),
+        's' => Some(HS_FLAG_DOTALL),
+        'm' => Some(HS_FLAG_MULTILINE),
+        _ => None,
+    }
+}
+
+impl Pattern {
+    /// The pattern with the leading inline modifiers lifted into the flags,
+    /// e.g. `(?i)foo` becomes `foo` with `HS_FLAG_CASELESS`, so equivalent patterns compare equal.
+    ///
+    /// Only the `i`, `s` and `m` modifiers of a group like `(?is)` at the start of the expression are lifted,
+    /// the logical combinations are returned unchanged.
+    pub fn normalized(&self) -> Pattern {
+        let mut pattern = self.clone();
+
+        if pattern.flags.is_set(HS_FLAG_COMBINATION) {
+            return pattern;
+        }
+
+        loop {
+            let lifted = {
+                let expression = &pattern.expression;
+
+                if !expression.starts_with("(?") {
+                    break;
+                }
+
+                let end = match expression.find(')') {
+                    Some(end) => end,
+                    None => break,
+                };
+                let modifiers = &expression[2..end];
+
+                if modifiers.is_empty() || !modifiers.chars().all(|c| modifier_flag(c).is_some()) {
+                    break;
+                }
+
+                (modifiers.chars().filter_map(modifier_flag).fold(0, |flags, flag| flags | flag), end + 1)
+            };
+
+            pattern.flags.set(lifted.0);
+            pattern.expression = pattern.expression[lifted.1..].to_owned();
+        }
+
+        pattern
+    }
+}
+
+/// Normalize the patterns and merge the equivalent ones, keeping the first one,
+/// so a database built from a messy rule feed doesn't compile the same expression several times.
+///
+/// Returns the kept patterns, normalized, and the dropped duplicates with the ID of the pattern reporting their matches.
+/// The logical combinations and the patterns they reference are never dropped.
+pub fn dedup_patterns(patterns: &[Pattern]) -> (Vec<Pattern>, Vec<Duplicate>) {
+    let referenced = patterns.iter()
+        .filter(|pattern| pattern.flags.is_set(HS_FLAG_COMBINATION))
+        .flat_map(|pattern| combination_ids(&pattern.expression).unwrap_or_default())
+        .collect::<HashSet<_>>();
+
+    let mut kept = Vec::with_capacity(patterns.len());
+    let mut duplicates = Vec::new();
+    let mut seen = HashMap::new();
+
+    for pattern in patterns {
+        let normalized = pattern.normalized();
+
+        if normalized.flags.is_set(HS_FLAG_COMBINATION) {
+            kept.push(normalized);
+            continue;
+        }
+
+        // the display of a pattern parses back to the same pattern, so it is a lossless key
+        let key = Pattern { id: 0, ..normalized.clone() }.to_string();
+
+        match seen.get(&key) {
+            Some(&id) if !referenced.contains(&pattern.id) => {
+                duplicates.push(Duplicate {
+                    pattern: pattern.clone(),
+                    kept: id,
+                });
+            }
+            _ => {
+                seen.entry(key).or_insert(normalized.id);
+                kept.push(normalized);
+            }
+        }
+    }
+
+    debug!("deduplicated {} patterns to {}", patterns.len(), kept.len());
+
+    (kept, duplicates)
+}
+
+impl Patterns {
+    /// Normalize the patterns and drop the equivalent ones, see `dedup_patterns`.
+    pub fn dedup(&mut self) -> Vec<Duplicate> {
+        let (kept, duplicates) = dedup_patterns(self);
+
+        **self = kept;
+
+        duplicates
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_normalized() {
+        let _ = env_logger::init();
+
+        assert_eq!(pattern!{"(?i)foo"}.normalized(), pattern!{"foo", flags => HS_FLAG_CASELESS});
+        assert_eq!(pattern!{"(?is)(?m)a.b"}.normalized(),
+                   pattern!{"a.b", flags => HS_FLAG_CASELESS | HS_FLAG_DOTALL | HS_FLAG_MULTILINE});
+        assert_eq!(pattern!{"(?i)foo", flags => HS_FLAG_CASELESS}.normalized(),
+                   pattern!{"foo", flags => HS_FLAG_CASELESS});
+
+        for expr in &["(?i:foo)bar", "(?x)foo", "(?-i)foo", "a(?i)b", "(?)foo", "foo"] {
+            assert_eq!(pattern!{*expr}.normalized(), pattern!{*expr});
+        }
+
+        let combination = pattern!{"(?i)1", flags => HS_FLAG_COMBINATION, id => 3};
+
+        assert_eq!(combination.normalized(), combination);
+    }
+
+    #[test]
+    fn test_dedup_patterns() {
+        let _ = env_logger::init();
+
+        let mut patterns = patterns!(["(?i)foo", {"foo", flags => HS_FLAG_CASELESS}, "foo", "(?s)a.b", "a.b", "(?I)foo"]);
+
+        let duplicates = patterns.dedup();
+
+        assert_eq!(duplicates,
+                   vec![Duplicate {
+                            pattern: pattern!{"foo", flags => HS_FLAG_CASELESS, id => 2},
+                            kept: 1,
+                        }]);
+        assert_eq!(patterns.iter().map(|p| (p.id, p.to_string())).collect::<Vec<_>>(),
+                   vec![(1, "1:/foo/i".to_owned()),
+                        (3, "3:/foo/".to_owned()),
+                        (4, "4:/a.b/s".to_owned()),
+                        (5, "5:/a.b/".to_owned()),
+                        (6, "6:/(?I)foo/".to_owned())]);
+
+        let patterns = vec![pattern!{"foo", flags => 0, id => 1},
+                            pattern!{"(?i)bar", flags => HS_FLAG_QUIET, id => 2},
+                            pattern!{"bar", flags => HS_FLAG_CASELESS | HS_FLAG_QUIET, id => 3},
+                            pattern!{"foo", flags => 0, id => 4},
+                            pattern!{"1 & 3", flags => HS_FLAG_COMBINATION, id => 5}];
+
+        let (kept, duplicates) = dedup_patterns(&patterns);
+
+        assert_eq!(kept.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1, 2, 3, 5]);
+        assert_eq!(duplicates.iter().map(|d| (d.pattern.id, d.kept)).collect::<Vec<_>>(),
+                   vec![(4, 1)]);
+    }
+}
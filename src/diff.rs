@@ -0,0 +1,151 @@
+use std::fmt;
+use std::collections::BTreeMap;
+
+use api::Type;
+use compile::Pattern;
+use source::PatternDatabase;
+
+/// A pattern whose expression, flags or extended parameters changed.
+#[derive(Debug, Clone)]
+pub struct PatternChange {
+    pub old: Pattern,
+    pub new: Pattern,
+}
+
+impl PatternChange {
+    pub fn expression_changed(&self) -> bool {
+        self.old.expression != self.new.expression
+    }
+
+    pub fn flags_changed(&self) -> bool {
+        self.old.flags != self.new.flags
+    }
+
+    pub fn ext_changed(&self) -> bool {
+        self.old.ext != self.new.ext
+    }
+}
+
+/// The differences between two pattern sets, with the patterns matched by ID.
+#[derive(Debug, Clone, Default)]
+pub struct PatternDiff {
+    /// The patterns only in the new set.
+    pub added: Vec<Pattern>,
+    /// The patterns only in the old set.
+    pub removed: Vec<Pattern>,
+    /// The patterns in both sets with a different expression, flags or extended parameters.
+    pub changed: Vec<PatternChange>,
+}
+
+impl PatternDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// One line per difference, `+` for added, `-` for removed and `~` for changed patterns.
+impl fmt::Display for PatternDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for pattern in &self.removed {
+            try!(write!(f, "- {}\n", pattern));
+        }
+
+        for pattern in &self.added {
+            try!(write!(f, "+ {}\n", pattern));
+        }
+
+        for change in &self.changed {
+            try!(write!(f, "~ {} -> {}\n", change.old, change.new));
+        }
+
+        Ok(())
+    }
+}
+
+fn by_id(patterns: &[Pattern]) -> BTreeMap<usize, Vec<&Pattern>> {
+    let mut map = BTreeMap::new();
+
+    for pattern in patterns {
+        map.entry(pattern.id).or_insert_with(Vec::new).push(pattern);
+    }
+
+    map
+}
+
+/// Compare two pattern sets, matching the patterns by ID.
+///
+/// Patterns sharing an ID are matched in the order of the sets.
+pub fn diff_patterns(old: &[Pattern], new: &[Pattern]) -> PatternDiff {
+    let mut diff = PatternDiff::default();
+
+    let old = by_id(old);
+    let mut new = by_id(new);
+
+    for (id, olds) in old {
+        let news = new.remove(&id).unwrap_or_default();
+
+        for (i, pattern) in olds.iter().enumerate() {
+            match news.get(i) {
+                Some(other) if other.expression == pattern.expression && other.flags == pattern.flags &&
+                               other.ext == pattern.ext => {}
+                Some(other) => {
+                    diff.changed.push(PatternChange {
+                        old: (*pattern).clone(),
+                        new: (*other).clone(),
+                    })
+                }
+                None => diff.removed.push((*pattern).clone()),
+            }
+        }
+
+        diff.added.extend(news.iter().skip(olds.len()).map(|&p| p.clone()));
+    }
+
+    for (_, news) in new {
+        diff.added.extend(news.into_iter().cloned());
+    }
+
+    diff
+}
+
+impl<T: Type> PatternDatabase<T> {
+    /// Compare the patterns of the database with those of another one.
+    pub fn diff(&self, other: &PatternDatabase<T>) -> PatternDiff {
+        diff_patterns(self.patterns(), other.patterns())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_diff_patterns() {
+        let _ = env_logger::init();
+
+        let old = vec![pattern!{"foo", flags => 0, id => 1},
+                       pattern!{"bar", flags => 0, id => 2},
+                       pattern!{"baz", flags => 0, id => 3}];
+        let new = vec![pattern!{"foo", flags => 0, id => 1},
+                       pattern!{"bar", flags => HS_FLAG_CASELESS, id => 2},
+                       pattern!{"qux", flags => 0, id => 4}];
+
+        assert!(diff_patterns(&old, &old).is_empty());
+
+        let diff = diff_patterns(&old, &new);
+
+        assert_eq!(diff.removed.iter().map(|p| p.id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(diff.added.iter().map(|p| p.id).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.changed[0].flags_changed());
+        assert!(!diff.changed[0].expression_changed());
+        assert_eq!(diff.to_string(), "- 3:/baz/\n+ 4:/qux/\n~ 2:/bar/ -> 2:/bar/i\n");
+
+        let db = PatternDatabase::<Block>::compile(old.into(), &PlatformInfo::null()).unwrap();
+        let extended = db.extended_with(&new[2..]).unwrap();
+
+        assert_eq!(db.diff(&extended).to_string(), "+ 4:/qux/\n");
+    }
+}
@@ -0,0 +1,527 @@
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+
+use glob;
+
+use api::*;
+use common::RawDatabase;
+use local::with_local_scratch;
+use file::{FileMatch, FileScanner};
+
+/// How symbolic links are handled while walking a directory.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Symlinks {
+    /// Skip the symbolic links.
+    Skip,
+    /// Follow the symbolic links, visiting each directory only once.
+    Follow,
+}
+
+impl Default for Symlinks {
+    fn default() -> Self {
+        Symlinks::Skip
+    }
+}
+
+/// The options of a directory scan.
+#[derive(Debug, Clone, Default)]
+pub struct DirOptions {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    ignore_files: Vec<String>,
+    max_size: Option<u64>,
+    symlinks: Symlinks,
+}
+
+impl DirOptions {
+    pub fn new() -> DirOptions {
+        DirOptions::default()
+    }
+
+    /// Only scan the files matching one of the include globs.
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_owned());
+        self
+    }
+
+    /// Skip the files and directories matching one of the exclude globs.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_owned());
+        self
+    }
+
+    /// Apply the gitignore-style rules of the files with the name, like `.gitignore`,
+    /// to the directory containing them and its descendants.
+    pub fn ignore_file(mut self, name: &str) -> Self {
+        self.ignore_files.push(name.to_owned());
+        self
+    }
+
+    /// Skip the files larger than the size in bytes.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+}
+
+/// The summary of a directory scan.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DirStats {
+    /// The number of scanned files.
+    pub files: usize,
+    /// The number of files skipped by the size limit.
+    pub skipped: usize,
+    /// The number of files which failed to be scanned and directories which failed to be read.
+    pub errors: usize,
+    /// The total number of matches.
+    pub matches: usize,
+}
+
+/// The result of scanning a file of a directory.
+pub type FileResult = (PathBuf, io::Result<Vec<FileMatch>>);
+
+/// A receiver of the per-file results of a directory scan.
+pub trait FileSink {
+    /// Report the result of a file, returns `false` to stop the scan.
+    fn report(&mut self, path: PathBuf, result: io::Result<Vec<FileMatch>>) -> bool;
+}
+
+impl<F: FnMut(PathBuf, io::Result<Vec<FileMatch>>)> FileSink for F {
+    fn report(&mut self, path: PathBuf, result: io::Result<Vec<FileMatch>>) -> bool {
+        self(path, result);
+
+        true
+    }
+}
+
+impl FileSink for Sender<FileResult> {
+    /// The scan is stopped when the receiver is dropped.
+    fn report(&mut self, path: PathBuf, result: io::Result<Vec<FileMatch>>) -> bool {
+        self.send((path, result)).is_ok()
+    }
+}
+
+fn invalid_glob(pattern: &str, err: glob::PatternError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+                   format!("invalid glob `{}`, {}", pattern, err))
+}
+
+fn match_options() -> glob::MatchOptions {
+    glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    }
+}
+
+/// A glob matching the path relative to a base directory, or the file name when it has no `/`.
+#[derive(Debug)]
+struct Glob {
+    pattern: glob::Pattern,
+    anchored: bool,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> io::Result<Glob> {
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_left_matches('/');
+
+        Ok(Glob {
+            pattern: try!(glob::Pattern::new(pattern).map_err(|err| invalid_glob(pattern, err))),
+            anchored: anchored,
+        })
+    }
+
+    fn matches(&self, relative: &Path) -> bool {
+        if self.anchored {
+            self.pattern.matches_path_with(relative, &match_options())
+        } else {
+            relative.file_name().map_or(false, |name| {
+                self.pattern.matches_path_with(Path::new(name), &match_options())
+            })
+        }
+    }
+}
+
+/// A rule of a gitignore-style file.
+#[derive(Debug)]
+struct IgnoreRule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules of an ignore file, relative to the directory containing it.
+#[derive(Debug)]
+struct IgnoreRules {
+    base: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    fn parse(base: &Path, path: &Path) -> io::Result<IgnoreRules> {
+        let mut rules = Vec::new();
+
+        for line in BufReader::new(try!(File::open(path))).lines() {
+            let line = try!(line);
+            let line = line.trim_right();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (negated, line) = if line.starts_with('!') {
+                (true, &line[1..])
+            } else {
+                (false, line.trim_left_matches('\\'))
+            };
+
+            let (dir_only, line) = if line.ends_with('/') {
+                (true, &line[..line.len() - 1])
+            } else {
+                (false, line)
+            };
+
+            rules.push(IgnoreRule {
+                glob: try!(Glob::new(line)),
+                negated: negated,
+                dir_only: dir_only,
+            });
+        }
+
+        Ok(IgnoreRules {
+            base: base.to_path_buf(),
+            rules: rules,
+        })
+    }
+
+    /// The decision of the last matching rule, if any.
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = match path.strip_prefix(&self.base) {
+            Ok(relative) => relative,
+            Err(_) => return None,
+        };
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.glob.matches(relative))
+            .map(|rule| !rule.negated)
+    }
+}
+
+struct Walker<'a, T: Type + 'a, S: FileSink + 'a>
+    where RawDatabase<T>: FileScanner
+{
+    root: PathBuf,
+    db: &'a RawDatabase<T>,
+    options: &'a DirOptions,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    ignores: Vec<IgnoreRules>,
+    visited: HashSet<PathBuf>,
+    sink: &'a mut S,
+    stats: DirStats,
+}
+
+impl<'a, T: Type, S: FileSink> Walker<'a, T, S>
+    where RawDatabase<T>: FileScanner
+{
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+
+        if self.exclude.iter().any(|glob| glob.matches(relative)) {
+            return true;
+        }
+
+        if !is_dir && !self.include.is_empty() && !self.include.iter().any(|glob| glob.matches(relative)) {
+            return true;
+        }
+
+        self.ignores.iter().rev().filter_map(|rules| rules.matched(path, is_dir)).next().unwrap_or(false)
+    }
+
+    /// Walk the directory, returns `false` if the sink stopped the scan.
+    fn walk(&mut self, dir: &Path) -> io::Result<bool> {
+        if self.options.symlinks == Symlinks::Follow && !self.visited.insert(try!(fs::canonicalize(dir))) {
+            debug!("skip visited directory {}", dir.display());
+
+            return Ok(true);
+        }
+
+        let mut ignores = 0;
+
+        for name in &self.options.ignore_files {
+            let path = dir.join(name);
+
+            if path.is_file() {
+                self.ignores.push(try!(IgnoreRules::parse(dir, &path)));
+                ignores += 1;
+            }
+        }
+
+        let mut entries = try!(try!(fs::read_dir(dir)).map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>());
+
+        entries.sort();
+
+        let mut proceed = true;
+
+        for path in entries {
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    proceed = self.error(path, err);
+
+                    if proceed {
+                        continue;
+                    } else {
+                        break;
+                    }
+                }
+            };
+
+            let metadata = if metadata.file_type().is_symlink() {
+                match self.options.symlinks {
+                    Symlinks::Skip => continue,
+                    Symlinks::Follow => {
+                        match fs::metadata(&path) {
+                            Ok(metadata) => metadata,
+                            Err(_) => continue,
+                        }
+                    }
+                }
+            } else {
+                metadata
+            };
+
+            if self.is_ignored(&path, metadata.is_dir()) {
+                trace!("ignore {}", path.display());
+
+                continue;
+            }
+
+            if metadata.is_dir() {
+                let len = self.ignores.len();
+
+                proceed = match self.walk(&path) {
+                    Ok(proceed) => proceed,
+                    Err(err) => {
+                        self.ignores.truncate(len);
+
+                        self.error(path, err)
+                    }
+                };
+            } else if metadata.is_file() {
+                if self.options.max_size.map_or(false, |size| metadata.len() > size) {
+                    self.stats.skipped += 1;
+
+                    continue;
+                }
+
+                proceed = self.scan(path);
+            }
+
+            if !proceed {
+                break;
+            }
+        }
+
+        let len = self.ignores.len();
+
+        self.ignores.truncate(len - ignores);
+
+        Ok(proceed)
+    }
+
+    /// Report a directory or an entry which can't be read, and skip it.
+    fn error(&mut self, path: PathBuf, err: io::Error) -> bool {
+        debug!("fail to read {}, {}", path.display(), err);
+
+        self.stats.errors += 1;
+
+        self.sink.report(path, Err(err))
+    }
+
+    fn scan(&mut self, path: PathBuf) -> bool {
+        let db = self.db;
+        let result = File::open(&path).and_then(|mut file| {
+            try!(with_local_scratch(db, |scratch| db.scan_file(&path, &mut file, scratch)))
+        });
+
+        match result {
+            Ok(ref matches) => {
+                self.stats.files += 1;
+                self.stats.matches += matches.len();
+            }
+            Err(ref err) => {
+                debug!("fail to scan {}, {}", path.display(), err);
+
+                self.stats.errors += 1;
+            }
+        }
+
+        self.sink.report(path, result)
+    }
+}
+
+/// Recursively scan the files of a directory with the database,
+/// reporting the result of each file to the sink, either a closure or a channel.
+///
+/// Files and directories are visited in the order of their names. The include and exclude globs
+/// are matched against the path relative to the root, or the file name for globs without `/`.
+///
+/// A subdirectory or an entry which can't be read, or whose ignore file is invalid, is reported
+/// to the sink with the error and skipped, only the errors of the root directory fail the scan.
+pub fn scan_dir<P, T, S>(root: P, db: &RawDatabase<T>, options: &DirOptions, mut sink: S) -> io::Result<DirStats>
+    where P: AsRef<Path>,
+          T: Type,
+          RawDatabase<T>: FileScanner,
+          S: FileSink
+{
+    let include = try!(options.include.iter().map(|pattern| Glob::new(pattern)).collect());
+    let exclude = try!(options.exclude.iter().map(|pattern| Glob::new(pattern)).collect());
+
+    let mut walker = Walker {
+        root: root.as_ref().to_path_buf(),
+        db: db,
+        options: options,
+        include: include,
+        exclude: exclude,
+        ignores: Vec::new(),
+        visited: HashSet::new(),
+        sink: &mut sink,
+        stats: DirStats::default(),
+    };
+
+    try!(walker.walk(root.as_ref()));
+
+    debug!("scanned directory {}, {:?}", root.as_ref().display(), walker.stats);
+
+    Ok(walker.stats)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+
+    use super::super::*;
+
+    fn tx_unused() -> ::std::sync::mpsc::Sender<FileResult> {
+        channel().0
+    }
+
+    fn create(path: PathBuf, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_scan_dir() {
+        let _ = env_logger::init();
+
+        let root = env::temp_dir().join("test_scan_dir");
+
+        let _ = fs::remove_dir_all(&root);
+
+        create(root.join("a.log"), "test");
+        create(root.join("b.txt"), "test test");
+        create(root.join("big.log"), "test test test test");
+        create(root.join("sub/c.log"), "no match");
+        create(root.join("sub/d.log"), "test");
+        create(root.join("sub/.ignore"), "d.log\n");
+        create(root.join("target/e.log"), "test");
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let options = DirOptions::new()
+            .include("*.log")
+            .exclude("target")
+            .ignore_file(".ignore")
+            .max_size(10);
+
+        let mut files = Vec::new();
+
+        let stats = scan_dir(&root,
+                             &db,
+                             &options,
+                             |path: PathBuf, result: ::std::io::Result<Vec<FileMatch>>| {
+                                 files.push((path, result.unwrap().len()))
+                             })
+            .unwrap();
+
+        assert_eq!(files, vec![(root.join("a.log"), 1), (root.join("sub/c.log"), 0)]);
+        assert_eq!(stats,
+                   DirStats {
+                       files: 2,
+                       skipped: 1,
+                       errors: 0,
+                       matches: 1,
+                   });
+
+        let (tx, rx) = channel();
+
+        scan_dir(&root, &db, &DirOptions::new(), tx).unwrap();
+
+        let files: Vec<_> = rx.iter().map(|(path, _)| path).collect();
+
+        assert_eq!(files.len(), 7);
+        assert_eq!(files[0], root.join("a.log"));
+
+        assert!(scan_dir(&root, &db, &DirOptions::new().include("[a"), tx_unused()).is_err());
+    }
+
+    #[test]
+    fn test_scan_dir_errors() {
+        let _ = env_logger::init();
+
+        let root = env::temp_dir().join("test_scan_dir_errors");
+
+        let _ = fs::remove_dir_all(&root);
+
+        create(root.join("a.log"), "test");
+        create(root.join("bad/.ignore"), "[a\n");
+        create(root.join("bad/b.log"), "test");
+        create(root.join("c.log"), "no match");
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let options = DirOptions::new().ignore_file(".ignore");
+
+        let mut files = Vec::new();
+
+        let stats = scan_dir(&root,
+                             &db,
+                             &options,
+                             |path: PathBuf, result: ::std::io::Result<Vec<FileMatch>>| {
+                                 files.push((path, result.map(|matches| matches.len()).map_err(|err| err.kind())))
+                             })
+            .unwrap();
+
+        assert_eq!(files,
+                   vec![(root.join("a.log"), Ok(1)),
+                        (root.join("bad"), Err(::std::io::ErrorKind::InvalidInput)),
+                        (root.join("c.log"), Ok(0))]);
+        assert_eq!(stats,
+                   DirStats {
+                       files: 2,
+                       skipped: 0,
+                       errors: 1,
+                       matches: 1,
+                   });
+
+        assert!(scan_dir(root.join("missing"), &db, &options, tx_unused()).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::cell::Cell;
+use std::collections::BTreeSet;
+
+use api::*;
+use errors::Error;
+use runtime::RawStream;
+
+/// A stream which settles on the first match of a flow, for classifying
+/// a connection once instead of reporting every match.
+///
+/// Once a match of one of the settling patterns (any pattern by default) is found,
+/// the scan is terminated and the stream is reset, later data is not scanned anymore
+/// until the stream is explicitly reset for a new flow.
+pub struct EarliestMatch {
+    stream: RawStream,
+    ids: Option<BTreeSet<u32>>,
+    settled: Option<Match>,
+}
+
+impl fmt::Debug for EarliestMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "EarliestMatch{{stream: {:?}, ids: {:?}, settled: {:?}}}",
+               self.stream,
+               self.ids,
+               self.settled)
+    }
+}
+
+struct Settling<'a> {
+    ids: &'a Option<BTreeSet<u32>>,
+    found: Cell<Option<Match>>,
+}
+
+impl<'a> Settling<'a> {
+    fn on_match(&self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        if self.ids.as_ref().map_or(true, |ids| ids.contains(&id)) {
+            self.found.set(Some(Match::new(id, from, to, flags)));
+
+            1
+        } else {
+            0
+        }
+    }
+}
+
+fn on_settling_event(id: u32, from: u64, to: u64, flags: u32, settling: &Settling) -> u32 {
+    settling.on_match(id, from, to, flags)
+}
+
+impl EarliestMatch {
+    /// Settle the stream on the first match of any pattern.
+    pub fn new(stream: RawStream) -> EarliestMatch {
+        EarliestMatch {
+            stream: stream,
+            ids: None,
+            settled: None,
+        }
+    }
+
+    /// Settle the stream only on the first match of the given patterns,
+    /// the matches of the other patterns are ignored.
+    pub fn with_ids<I: IntoIterator<Item = u32>>(stream: RawStream, ids: I) -> EarliestMatch {
+        EarliestMatch {
+            stream: stream,
+            ids: Some(ids.into_iter().collect()),
+            settled: None,
+        }
+    }
+
+    /// The match the stream settled on, if any.
+    pub fn settled(&self) -> Option<Match> {
+        self.settled
+    }
+
+    /// The underlying stream.
+    pub fn stream(&self) -> &RawStream {
+        &self.stream
+    }
+
+    /// Write data to the stream, returns the match the stream settled on, if any.
+    ///
+    /// The data is not scanned once the stream settled.
+    pub fn scan<T: Scannable, S: Scratch>(&mut self, data: T, scratch: &S) -> Result<Option<Match>, Error> {
+        if self.settled.is_some() {
+            return Ok(self.settled);
+        }
+
+        let settling = Settling {
+            ids: &self.ids,
+            found: Cell::new(None),
+        };
+
+        try!(self.stream.feed(Some(data), scratch, |id, from, to, flags| settling.on_match(id, from, to, flags)));
+
+        self.settle(settling.found.get(), scratch)
+    }
+
+    /// Reset the stream for a new flow, forgetting the settled match.
+    pub fn reset<S: Scratch>(&mut self, flags: StreamFlags, scratch: &S) -> Result<&Self, Error> {
+        try!(self.stream.reset(flags, scratch, None, None::<&()>));
+
+        self.settled = None;
+
+        Ok(self)
+    }
+
+    /// Close the stream, returns the match the stream settled on,
+    /// including the matches at the end of the data when it didn't settle before.
+    pub fn close<S: Scratch>(self, scratch: &S) -> Result<Option<Match>, Error> {
+        if self.settled.is_some() {
+            try!(self.stream.close(scratch, None, None::<&()>));
+
+            return Ok(self.settled);
+        }
+
+        let settling = Settling {
+            ids: &self.ids,
+            found: Cell::new(None),
+        };
+
+        try!(self.stream.close(scratch, Some(on_settling_event), Some(&settling)));
+
+        Ok(settling.found.get())
+    }
+
+    /// Unwrap the underlying stream.
+    pub fn into_inner(self) -> RawStream {
+        self.stream
+    }
+
+    fn settle<S: Scratch>(&mut self, found: Option<Match>, scratch: &S) -> Result<Option<Match>, Error> {
+        if let Some(m) = found {
+            debug!("stream at {:p} settled on pattern #{} at {}", *self.stream, m.id, m.to);
+
+            try!(self.stream.reset(0, scratch, None, None::<&()>));
+
+            self.settled = found;
+        }
+
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_earliest_match() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["GET ", "HTTP/1\\.1", "SSH-2\\.0"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut st = EarliestMatch::new(db.open_stream(0).unwrap());
+
+        assert_eq!(st.scan("GE", &s).unwrap(), None);
+        assert_eq!(st.scan("T / HTTP/1.1\r\n", &s).unwrap(), Some(Match::new(1, 0, 4, 0)));
+        assert_eq!(st.scan("GET / HTTP/1.1\r\n", &s).unwrap(), Some(Match::new(1, 0, 4, 0)));
+        assert_eq!(st.stream().scan_stats().bytes, 16);
+
+        st.reset(0, &s).unwrap();
+
+        assert_eq!(st.settled(), None);
+        assert_eq!(st.scan("SSH-2.0-OpenSSH\r\n", &s).unwrap().map(|m| m.id), Some(3));
+
+        let mut st = EarliestMatch::with_ids(db.open_stream(0).unwrap(), vec![2]);
+
+        assert_eq!(st.scan("GET / ", &s).unwrap(), None);
+        assert_eq!(st.scan("HTTP/1.1", &s).unwrap().map(|m| m.id), Some(2));
+        assert_eq!(st.close(&s).unwrap().map(|m| m.to), Some(14));
+
+        let st = EarliestMatch::with_ids(db.open_stream(0).unwrap(), vec![3]);
+
+        assert_eq!(st.close(&s).unwrap(), None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use api::*;
+use compile::{Pattern, Patterns, compile_patterns};
+use common::RawDatabase;
+
+fn other<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err.to_string())
+}
+
+/// Compile the patterns for the current host and write the serialized database
+/// and the code embedding it to `out_dir`, as `{name}.hsdb` and `{name}.rs`.
+///
+/// The generated code defines a `{NAME}_DATABASE` static with the serialized bytes,
+/// and a `{name}()` function loading the database with `RawDatabase::load`.
+///
+/// Returns the path of the generated code, to be used with `include!`.
+pub fn embed_patterns<T: Type, P: AsRef<Path>>(patterns: &[Pattern], name: &str, out_dir: P) -> io::Result<PathBuf> {
+    let db: RawDatabase<T> = try!(compile_patterns(patterns, 0, &PlatformInfo::host()).map_err(other));
+    let serialized = try!(db.serialize().map_err(other));
+
+    let db_path = out_dir.as_ref().join(format!("{}.hsdb", name));
+    let code_path = out_dir.as_ref().join(format!("{}.rs", name));
+
+    try!(try!(File::create(&db_path)).write_all(serialized.as_slice()));
+
+    let mut f = try!(File::create(&code_path));
+
+    try!(write!(f,
+                "// generated by hyperscan::embed_patterns from {} patterns, do not edit.\n\n\
+                 pub static {upper}_DATABASE: &'static [u8] = include_bytes!({path:?});\n\n\
+                 pub fn {name}() -> ::std::result::Result<::hyperscan::RawDatabase<::hyperscan::{mode}>, \
+                 ::hyperscan::Error> {{\n    \
+                 ::hyperscan::RawDatabase::load({upper}_DATABASE)\n\
+                 }}\n",
+                patterns.len(),
+                upper = name.to_uppercase(),
+                path = db_path.display().to_string(),
+                name = name,
+                mode = T::name()));
+
+    debug!("embedded {} database `{}` of {} bytes to {}",
+           T::name(),
+           name,
+           serialized.len(),
+           code_path.display());
+
+    Ok(code_path)
+}
+
+/// Compile a pattern file from a build script, embedding the database in the crate.
+///
+/// The code is generated in `OUT_DIR` and the build script is rerun when the pattern file changes.
+///
+/// In `build.rs`
+///
+/// ```ignore
+/// hyperscan::embed_database::<hyperscan::Block, _>("rules.txt", "rules").unwrap();
+/// ```
+///
+/// and in the crate
+///
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/rules.rs"));
+///
+/// let db = rules().unwrap();
+/// ```
+///
+/// The database is compiled for the build host, see `Database::runs_on_current_host`
+/// to validate it on the deployment host.
+pub fn embed_database<T: Type, P: AsRef<Path>>(pattern_file: P, name: &str) -> io::Result<PathBuf> {
+    let out_dir = try!(env::var("OUT_DIR").map_err(other));
+    let patterns = try!(Patterns::from_file(pattern_file.as_ref()));
+
+    println!("cargo:rerun-if-changed={}", pattern_file.as_ref().display());
+
+    embed_patterns::<T, _>(&patterns, name, out_dir)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::env;
+    use std::fs::File;
+    use std::io::{Read, Write};
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_embed_patterns() {
+        let _ = env_logger::init();
+
+        let out_dir = env::temp_dir();
+        let pattern_file = out_dir.join("test_embed_patterns.txt");
+
+        File::create(&pattern_file).unwrap().write_all(b"# comment\n\n1:/foo/i\n2:/bar\\d+/\n").unwrap();
+
+        let patterns = Patterns::from_file(&pattern_file).unwrap();
+
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[1].expression, "bar\\d+");
+
+        let code_path = embed_patterns::<Block, _>(&patterns, "test_embed", &out_dir).unwrap();
+
+        let mut code = String::new();
+
+        File::open(&code_path).unwrap().read_to_string(&mut code).unwrap();
+
+        assert!(code.contains("pub static TEST_EMBED_DATABASE: &'static [u8] = include_bytes!("));
+        assert!(code.contains("pub fn test_embed() -> ::std::result::Result<::hyperscan::RawDatabase<::hyperscan::Block>"));
+
+        let mut bytes = Vec::new();
+
+        File::open(out_dir.join("test_embed.hsdb")).unwrap().read_to_end(&mut bytes).unwrap();
+
+        let db = BlockDatabase::load(&bytes).unwrap();
+
+        validate_database_with_size(&db, 0);
+
+        assert_eq!(StreamingDatabase::load(&bytes).err(), Some(Error::DbModeError));
+    }
+}
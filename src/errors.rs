@@ -19,8 +19,24 @@ pub enum Error {
     /// This return value indicates that the target buffer was partially scanned,
     /// but that the callback function requested that scanning cease after a match was located.
     ScanTerminated,
+    /// The scan was aborted with a `CancellationToken`.
+    Cancelled,
+    /// The compiled database is larger than the limit, in bytes.
+    DatabaseTooLarge(usize),
+    /// The scratch space of the compiled database is larger than the limit, in bytes.
+    ScratchTooLarge(usize),
+    /// The compilation didn't complete within the time limit.
+    CompileTimeout,
+    /// Too many compilations with a time limit are running, with their number.
+    TooManyCompiles(usize),
+    /// A new stream would exceed the quota of the stream manager.
+    QuotaExceeded,
+    /// The data of a stream is missing at the sequence number.
+    SequenceGap(u64),
     /// The pattern compiler failed with more detail.
     CompilerError(String),
+    /// The pattern compiler failed on one of the patterns of the set.
+    PatternError(PatternError),
     /// The given database was built for a different version of Hyperscan.
     DbVersionError,
     /// The given database was built for a different platform (i.e., CPU type).
@@ -35,6 +51,10 @@ pub enum Error {
     /// did not correctly return memory suitably aligned
     /// for the largest representable data type on this platform.
     BadAlloc,
+    /// The scratch region was already in use by another Hyperscan API call.
+    ScratchInUse,
+    /// The host doesn't support the instruction set required by Hyperscan (SSSE3).
+    ArchError,
     /// Unknown error code
     Failed(i32),
     /// An error which can be returned when parsing an integer.
@@ -44,6 +64,30 @@ pub enum Error {
     NulError(::std::ffi::NulError),
 }
 
+/// A compile error attributed to one of the patterns of the set, so the bad rule of a large set can be found.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PatternError {
+    /// The index of the failing pattern in the set.
+    pub index: usize,
+    /// The ID of the failing pattern.
+    pub id: usize,
+    /// The source expression of the failing pattern.
+    pub expression: String,
+    /// The message of the compiler.
+    pub message: String,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "pattern #{} (ID {}) `{}`, {}",
+               self.index,
+               self.id,
+               self.expression,
+               self.message)
+    }
+}
+
 impl From<i32> for Error {
     fn from(err: i32) -> Error {
         match err {
@@ -57,6 +101,8 @@ impl From<i32> for Error {
             HS_DB_MODE_ERROR => Error::DbModeError,
             HS_BAD_ALIGN => Error::BadAlign,
             HS_BAD_ALLOC => Error::BadAlloc,
+            HS_SCRATCH_IN_USE => Error::ScratchInUse,
+            HS_ARCH_ERROR => Error::ArchError,
             _ => Error::Failed(err),
         }
     }
@@ -73,13 +119,24 @@ impl From<::std::ffi::NulError> for Error {
     }
 }
 
+impl From<Error> for ::std::io::Error {
+    fn from(err: Error) -> ::std::io::Error {
+        ::std::io::Error::new(::std::io::ErrorKind::Other, err)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         try!(write!(f, "{}", error::Error::description(self).to_string()));
 
         match *self {
             Error::CompilerError(ref reason) => try!(write!(f, " {}", reason)),
+            Error::PatternError(ref err) => try!(write!(f, " {}", err)),
             Error::Failed(ref code) => try!(write!(f, " Code: {}", code)),
+            Error::DatabaseTooLarge(size) |
+            Error::ScratchTooLarge(size) => try!(write!(f, " Size: {}", size)),
+            Error::SequenceGap(seq) => try!(write!(f, " Sequence: {}", seq)),
+            Error::TooManyCompiles(count) => try!(write!(f, " Count: {}", count)),
             _ => {}
         }
 
@@ -93,12 +150,22 @@ impl error::Error for Error {
             Error::Invalid => "A parameter passed to this function was invalid.",
             Error::NoMem => "A memory allocation failed.",
             Error::ScanTerminated => "The engine was terminated by callback.",
-            Error::CompilerError(..) => "The pattern compiler failed.",
+            Error::Cancelled => "The scan was cancelled.",
+            Error::DatabaseTooLarge(..) => "The compiled database exceeds the size limit.",
+            Error::ScratchTooLarge(..) => "The scratch space exceeds the size limit.",
+            Error::CompileTimeout => "The compilation exceeds the time limit.",
+            Error::TooManyCompiles(..) => "Too many compilations with a time limit are running.",
+            Error::QuotaExceeded => "The stream quota is exceeded.",
+            Error::SequenceGap(..) => "The stream data is missing.",
+            Error::CompilerError(..) |
+            Error::PatternError(..) => "The pattern compiler failed.",
             Error::DbVersionError => "The given database was built for a different version of Hyperscan.",
             Error::DbPlatformError => "The given database was built for a different platform.",
             Error::DbModeError => "The given database was built for a different mode of operation.",
             Error::BadAlign => "A parameter passed to this function was not correctly aligned.",
             Error::BadAlloc => "The memory allocator did not correctly return memory suitably aligned.",
+            Error::ScratchInUse => "The scratch region was already in use.",
+            Error::ArchError => "The host doesn't support the SSSE3 instructions required by Hyperscan.",
             Error::Failed(..) => "Internal operation failed.",
             Error::ParseError(ref err) => err.description(),
             Error::NulError(ref err) => err.description(),
@@ -140,6 +207,30 @@ impl CompileError for RawCompileError {
     }
 }
 
+impl RawCompileError {
+    /// Convert to an `Error`, attributed to the pattern at the reported index with its `(id, expression)`.
+    ///
+    /// The errors which don't concern a particular pattern remain `Error::CompilerError`.
+    pub fn into_error<F>(self, source: F) -> Error
+        where F: FnOnce(usize) -> Option<(usize, String)>
+    {
+        let message = self.to_string();
+        let index = unsafe { (*self.0).expression };
+
+        match if index < 0 { None } else { source(index as usize) } {
+            Some((id, expression)) => {
+                Error::PatternError(PatternError {
+                    index: index as usize,
+                    id: id,
+                    expression: expression,
+                    message: message,
+                })
+            }
+            None => Error::CompilerError(message),
+        }
+    }
+}
+
 impl ToString for RawCompileError {
     #[inline]
     fn to_string(&self) -> String {
@@ -171,5 +262,14 @@ macro_rules! check_compile_error {
                     Err(::std::convert::From::from($expr)),
             }
         }
-    }
+    };
+    ($expr:expr, $err:ident, $source:expr) => {
+        match $expr {
+            $crate::HS_SUCCESS => {}
+            $crate::HS_COMPILER_ERROR => {
+                return Err($crate::errors::RawCompileError($err).into_error($source));
+            }
+            ret => return Err(::std::convert::From::from(ret)),
+        }
+    };
 }
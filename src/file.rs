@@ -0,0 +1,279 @@
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::ops::Deref;
+
+use libc;
+
+use api::*;
+use common::{RawDatabase, BlockDatabase, StreamingDatabase};
+use runtime::{RawScratch, RawStream};
+use local::with_local_scratch;
+
+/// The chunk size used to read files which can't be mapped into memory.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The chunk size used to write mapped files to a stream.
+const MAPPED_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The largest file a block database can scan, Hyperscan takes the block length as 32 bits.
+const MAX_BLOCK_SIZE: usize = u32::max_value() as usize;
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+                   "file too large for block mode, use a streaming database")
+}
+
+/// A match found in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileMatch {
+    /// The path of the scanned file.
+    pub path: PathBuf,
+    /// The ID number of the matched pattern.
+    pub id: u32,
+    /// The absolute offset in bytes of the start of the match in the file.
+    pub from: u64,
+    /// The absolute offset in bytes of the end of the match in the file.
+    pub to: u64,
+    /// The flags of the match event.
+    pub flags: MatchFlags,
+}
+
+struct Collector<'a> {
+    path: &'a Path,
+    matches: RefCell<Vec<FileMatch>>,
+}
+
+impl FileMatch {
+    /// The match event, without the path.
+    pub fn as_match(&self) -> Match {
+        Match {
+            id: self.id,
+            from: self.from,
+            to: self.to,
+            flags: self.flags,
+        }
+    }
+}
+
+impl<'a> Collector<'a> {
+    fn new(path: &'a Path) -> Collector<'a> {
+        Collector {
+            path: path,
+            matches: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn on_match(id: u32, from: u64, to: u64, flags: u32, collector: &Collector) -> u32 {
+        collector.matches.borrow_mut().push(FileMatch {
+            path: collector.path.to_path_buf(),
+            id: id,
+            from: from,
+            to: to,
+            flags: MatchFlags::from(flags),
+        });
+
+        0
+    }
+}
+
+/// A read only memory mapping of a whole file.
+struct Mmap {
+    p: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    #[cfg(unix)]
+    fn map(file: &File, len: usize) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+
+        let p = unsafe {
+            libc::mmap(::std::ptr::null_mut(),
+                       len,
+                       libc::PROT_READ,
+                       libc::MAP_PRIVATE,
+                       file.as_raw_fd(),
+                       0)
+        };
+
+        if p == libc::MAP_FAILED {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Mmap { p: p, len: len })
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn map(_: &File, _: usize) -> io::Result<Mmap> {
+        Err(io::Error::new(io::ErrorKind::Other, "memory mapping is not supported"))
+    }
+
+    /// Map the file if it is a non-empty regular file.
+    fn open(file: &File) -> Option<Mmap> {
+        match file.metadata() {
+            Ok(ref metadata) if metadata.is_file() && metadata.len() > 0 &&
+                                metadata.len() <= usize::max_value() as u64 => {
+                Mmap::map(file, metadata.len() as usize).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.p as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::munmap(self.p, self.len);
+        }
+    }
+}
+
+/// A database which can scan a whole file.
+pub trait FileScanner {
+    /// Scan the opened file, returning the matches annotated with the path.
+    fn scan_file(&self, path: &Path, file: &mut File, scratch: &RawScratch) -> io::Result<Vec<FileMatch>>;
+}
+
+impl FileScanner for BlockDatabase {
+    /// The file is mapped into memory when possible, otherwise it is read as a whole.
+    fn scan_file(&self, path: &Path, file: &mut File, scratch: &RawScratch) -> io::Result<Vec<FileMatch>> {
+        let collector = Collector::new(path);
+
+        if let Some(mmap) = Mmap::open(file) {
+            if mmap.len() > MAX_BLOCK_SIZE {
+                return Err(too_large());
+            }
+
+            try!(self.scan(&mmap[..], 0, scratch, Some(Collector::on_match), Some(&collector)));
+        } else {
+            let mut buf = Vec::new();
+
+            // read one more byte than allowed to detect a larger file or pipe without buffering all of it
+            try!(file.take(MAX_BLOCK_SIZE as u64 + 1).read_to_end(&mut buf));
+
+            if buf.len() > MAX_BLOCK_SIZE {
+                return Err(too_large());
+            }
+
+            try!(self.scan(&buf, 0, scratch, Some(Collector::on_match), Some(&collector)));
+        }
+
+        Ok(collector.matches.into_inner())
+    }
+}
+
+impl FileScanner for StreamingDatabase {
+    /// The file is mapped into memory when possible, otherwise it is read in chunks,
+    /// so pipes and devices are supported.
+    fn scan_file(&self, path: &Path, file: &mut File, scratch: &RawScratch) -> io::Result<Vec<FileMatch>> {
+        let collector = Collector::new(path);
+        let stream = try!(self.open_stream(0));
+
+        if let Err(err) = scan_stream(&stream, file, scratch, &collector) {
+            // the stream has no destructor, close it without the end of data matches
+            if let Err(err) = stream.close::<()>(scratch, None, None) {
+                warn!("fail to close stream, {}", err);
+            }
+
+            return Err(err);
+        }
+
+        try!(stream.close(scratch, Some(Collector::on_match), Some(&collector)));
+
+        Ok(collector.matches.into_inner())
+    }
+}
+
+/// Write the mapped or read file to the stream.
+fn scan_stream(stream: &RawStream, file: &mut File, scratch: &RawScratch, collector: &Collector) -> io::Result<()> {
+    if let Some(mmap) = Mmap::open(file) {
+        for chunk in mmap.chunks(MAPPED_CHUNK_SIZE) {
+            try!(stream.scan(chunk, 0, scratch, Some(Collector::on_match), Some(collector)));
+        }
+    } else {
+        let mut buf = vec![0; CHUNK_SIZE];
+
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+
+            try!(stream.scan(&buf[..n], 0, scratch, Some(Collector::on_match), Some(collector)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Scan a file with the database, returning the matches annotated with
+/// the file path and their absolute byte offsets in the file.
+///
+/// Regular files are mapped into memory, while other files like pipes are read in chunks
+/// with a streaming database. The thread local scratch of the database is used.
+pub fn scan_path<P, T>(path: P, db: &RawDatabase<T>) -> io::Result<Vec<FileMatch>>
+    where P: AsRef<Path>,
+          T: Type,
+          RawDatabase<T>: FileScanner
+{
+    let path = path.as_ref();
+    let mut file = try!(File::open(path));
+
+    let matches = try!(try!(with_local_scratch(db, |scratch| db.scan_file(path, &mut file, scratch))));
+
+    debug!("found {} matches in {}", matches.len(), path.display());
+
+    Ok(matches)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scan_path() {
+        let _ = env_logger::init();
+
+        let path = env::temp_dir().join("test_scan_path.txt");
+
+        File::create(&path).unwrap().write_all(b"some test data\nanother test\n").unwrap();
+
+        let db: BlockDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let matches = scan_path(&path, &db).unwrap();
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].path, path);
+        assert_eq!((matches[0].from, matches[0].to), (5, 9));
+        assert_eq!((matches[1].from, matches[1].to), (23, 27));
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let matches = scan_path(&path, &db).unwrap();
+
+        assert_eq!(matches.iter().map(|m| m.to).collect::<Vec<_>>(), vec![9, 27]);
+
+        File::create(&path).unwrap();
+
+        assert!(scan_path(&path, &db).unwrap().is_empty());
+        assert!(scan_path(env::temp_dir().join("test_scan_path.missing"), &db).is_err());
+    }
+}
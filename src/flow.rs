@@ -0,0 +1,141 @@
+use std::fmt;
+use std::hash::Hash;
+
+use api::*;
+use errors::Error;
+use common::StreamingDatabase;
+use manager::{StreamManager, StreamQuota};
+use runtime::Feed;
+use stats::ScanStats;
+
+/// The direction of the data of a bidirectional flow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the client to the server.
+    ToServer,
+    /// From the server to the client.
+    ToClient,
+}
+
+impl Direction {
+    /// The opposite direction.
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::ToServer => Direction::ToClient,
+            Direction::ToClient => Direction::ToServer,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}",
+               match *self {
+                   Direction::ToServer => "to_server",
+                   Direction::ToClient => "to_client",
+               })
+    }
+}
+
+/// The client to server and server to client streams of the bidirectional flows, under one key per flow.
+///
+/// The matches of both directions are reported to the same handler, tagged by the direction.
+/// The streams are held by a `StreamManager`, a stream of each direction counts against its quota.
+#[derive(Debug)]
+pub struct FlowStreams<K: Hash + Eq + Clone> {
+    streams: StreamManager<(K, Direction)>,
+}
+
+impl<K: Hash + Eq + Clone> FlowStreams<K> {
+    pub fn new(db: StreamingDatabase) -> Result<FlowStreams<K>, Error> {
+        Ok(FlowStreams { streams: try!(StreamManager::new(db)) })
+    }
+
+    /// Enforce the quota on the streams of the new flows.
+    pub fn with_quota(self, quota: StreamQuota) -> Self {
+        FlowStreams { streams: self.streams.with_quota(quota) }
+    }
+
+    /// The streams of both directions.
+    pub fn streams(&self) -> &StreamManager<(K, Direction)> {
+        &self.streams
+    }
+
+    /// Whether the flow has an open stream in a direction.
+    pub fn contains(&self, key: &K) -> bool {
+        self.streams.contains(&(key.clone(), Direction::ToServer)) ||
+        self.streams.contains(&(key.clone(), Direction::ToClient))
+    }
+
+    /// The totals of the data written to the stream of a flow in a direction.
+    pub fn stats(&self, key: &K, direction: Direction) -> Option<ScanStats> {
+        self.streams.stats(&(key.clone(), direction))
+    }
+
+    /// Scan the data of a flow in a direction, opening the stream of the direction on its first data.
+    pub fn scan<T, F>(&mut self, key: &K, direction: Direction, data: T, mut handler: F) -> Result<Feed, Error>
+        where T: Scannable,
+              F: FnMut(Direction, u32, u64, u64, u32) -> u32
+    {
+        self.streams.scan(&(key.clone(), direction),
+                          data,
+                          |id, from, to, flags| handler(direction, id, from, to, flags))
+    }
+
+    /// Close the streams of both directions of a flow, passing the end of data matches to the handler.
+    ///
+    /// Returns `false` if the flow has no open stream.
+    pub fn close<F>(&mut self, key: &K, mut handler: F) -> Result<bool, Error>
+        where F: FnMut(Direction, u32, u64, u64, u32) -> u32
+    {
+        let to_server = self.streams.close(&(key.clone(), Direction::ToServer),
+                                           |id, from, to, flags| handler(Direction::ToServer, id, from, to, flags));
+        let to_client = self.streams.close(&(key.clone(), Direction::ToClient),
+                                           |id, from, to, flags| handler(Direction::ToClient, id, from, to, flags));
+
+        Ok(try!(to_server) | try!(to_client))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_flow_streams() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["GET /admin", "HTTP/1\\.1 500", "bye$"]).build().unwrap();
+        let mut flows = FlowStreams::new(db).unwrap();
+        let mut matches = Vec::new();
+
+        {
+            let mut on_match = |direction, id, _, to, _| {
+                matches.push((direction, id, to));
+
+                0
+            };
+
+            flows.scan(&1, Direction::ToServer, "GET /ad", &mut on_match).unwrap();
+            flows.scan(&1, Direction::ToClient, "HTTP/1.1 ", &mut on_match).unwrap();
+            flows.scan(&1, Direction::ToServer, "min HTTP/1.1", &mut on_match).unwrap();
+            flows.scan(&1, Direction::ToClient, "500 bye", &mut on_match).unwrap();
+
+            assert_eq!(flows.stats(&1, Direction::ToServer).unwrap().bytes, 19);
+            assert_eq!(flows.stats(&1, Direction::ToClient).unwrap().bytes, 16);
+            assert_eq!(flows.streams().len(), 2);
+
+            assert!(flows.close(&1, &mut on_match).unwrap());
+            assert!(!flows.close(&1, &mut on_match).unwrap());
+        }
+
+        assert!(!flows.contains(&1));
+        assert_eq!(matches,
+                   vec![(Direction::ToServer, 1, 10), (Direction::ToClient, 2, 12), (Direction::ToClient, 3, 16)]);
+        assert_eq!(Direction::ToServer.reverse(), Direction::ToClient);
+        assert_eq!(Direction::ToClient.to_string(), "to_client");
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::ptr;
+
+use raw::*;
+use api::*;
+use errors::Error;
+use runtime::{Feed, RawStream};
+
+/// A copy of a stream forked at its current offset, e.g. to parse the next request of a pipelined connection
+/// speculatively, which is committed back into the original stream or discarded.
+///
+/// Both streams belong to the same database and are scanned with the scratch given to `RawStream::fork`.
+/// The matches before the fork were reported once, by the original stream. After the fork each stream reports
+/// its own matches, so the same data written to both streams reports the same matches twice.
+/// The end-of-data matches of the replaced state are not reported by `commit`, neither are those of
+/// a discarded copy; dropping the guard discards the copy.
+pub struct StreamFork<'a, S: Scratch + 'a> {
+    parent: &'a RawStream,
+    stream: Option<RawStream>,
+    scratch: &'a S,
+}
+
+impl<'a, S: Scratch + 'a> fmt::Debug for StreamFork<'a, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StreamFork{{parent: {:?}, stream: {:?}}}", self.parent, self.stream)
+    }
+}
+
+impl RawStream {
+    /// Fork a copy of the stream, which is scanned with the scratch.
+    pub fn fork<'a, S: Scratch>(&'a self, scratch: &'a S) -> Result<StreamFork<'a, S>, Error> {
+        let stream = try!(self.try_clone());
+
+        Ok(StreamFork {
+            parent: self,
+            stream: Some(stream),
+            scratch: scratch,
+        })
+    }
+}
+
+impl<'a, S: Scratch + 'a> StreamFork<'a, S> {
+    /// The original stream, which can still be written while the copy is open.
+    pub fn parent(&self) -> &RawStream {
+        self.parent
+    }
+
+    /// The forked copy of the stream.
+    pub fn stream(&self) -> &RawStream {
+        self.stream.as_ref().unwrap()
+    }
+
+    /// Write the pending data to the copy, like `RawStream::feed`.
+    pub fn feed<I, F>(&self, chunks: I, handler: F) -> Result<Feed, Error>
+        where I: IntoIterator,
+              I::Item: Scannable,
+              F: FnMut(u32, u64, u64, u32) -> u32
+    {
+        self.stream().feed(chunks, self.scratch, handler)
+    }
+
+    /// Replace the state of the original stream with the state of the copy, and close the copy.
+    ///
+    /// The copy is closed even if its state fails to be copied.
+    pub fn commit(mut self) -> Result<(), Error> {
+        unsafe {
+            check_hs_error!(hs_reset_and_copy_stream(**self.parent,
+                                                     **self.stream(),
+                                                     ptr::null_mut(),
+                                                     None,
+                                                     ptr::null_mut()));
+        }
+
+        let stream = self.stream.take().unwrap();
+
+        debug!("stream {:p} committed to {:p}", *stream, **self.parent);
+
+        Self::close(stream, self.scratch)
+    }
+
+    /// Close the copy without reporting its end-of-data matches.
+    pub fn discard(mut self) -> Result<(), Error> {
+        let stream = self.stream.take().unwrap();
+
+        Self::close(stream, self.scratch)
+    }
+
+    /// Keep the copy as an independent stream, which must be closed by the caller.
+    pub fn into_stream(mut self) -> RawStream {
+        self.stream.take().unwrap()
+    }
+
+    fn close(stream: RawStream, scratch: &S) -> Result<(), Error> {
+        try!(stream.close(scratch, None, None::<&()>));
+
+        Ok(())
+    }
+}
+
+impl<'a, S: Scratch + 'a> Drop for StreamFork<'a, S> {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            if let Err(err) = Self::close(stream, self.scratch) {
+                warn!("fail to discard forked stream, {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_stream_fork() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foobar"]).build().unwrap();
+        let scratch = db.alloc().unwrap();
+        let stream = db.open_stream(0).unwrap();
+        let mut matches = Vec::new();
+
+        macro_rules! feed {
+            ($target:expr, $data:expr) => {
+                assert!(!$target.feed(vec![$data], &scratch, |id, _, to, _| {
+                        matches.push((id, to));
+                        0
+                    })
+                    .unwrap()
+                    .terminated)
+            }
+        }
+
+        feed!(stream, "foo");
+
+        {
+            let fork = stream.fork(&scratch).unwrap();
+
+            fork.feed(vec!["bar"], |id, _, to, _| {
+                    matches.push((id, to));
+                    0
+                })
+                .unwrap();
+
+            feed!(fork.parent(), "bar");
+
+            assert_eq!(matches, vec![(1, 6), (1, 6)]);
+
+            fork.discard().unwrap();
+        }
+
+        matches.clear();
+
+        {
+            let fork = stream.fork(&scratch).unwrap();
+
+            fork.feed(vec!["foo"], |_, _, _, _| 0).unwrap();
+            fork.commit().unwrap();
+        }
+
+        feed!(stream, "bar");
+
+        assert_eq!(matches, vec![(1, 12)]);
+
+        drop(stream.fork(&scratch).unwrap());
+
+        let copy = stream.fork(&scratch).unwrap().into_stream();
+
+        copy.close(&scratch, None, None::<&()>).unwrap();
+        stream.close(&scratch, None, None::<&()>).unwrap();
+    }
+}
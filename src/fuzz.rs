@@ -0,0 +1,151 @@
+use api::*;
+use common::{BlockDatabase, StreamingDatabase, VectoredDatabase};
+use compile::{Pattern, Patterns};
+use runtime::RawScratch;
+
+/// The most patterns compiled from a fuzzing input.
+pub const FUZZ_MAX_PATTERNS: usize = 8;
+
+/// The longest expression compiled from a fuzzing input.
+pub const FUZZ_MAX_EXPRESSION_LEN: usize = 256;
+
+/// The largest bounded repeat allowed in a fuzzed expression, to keep the compile time low.
+pub const FUZZ_MAX_REPEAT: u32 = 1000;
+
+/// The patterns of the fixed database scanned by `fuzz_scan`.
+const FUZZ_PATTERNS: &'static [&'static str] = &["1:/foo(bar)?/s",
+                                                  "2:/\\d{3,}[a-f]/i",
+                                                  "3:/^GET \\/[^ ]*/m",
+                                                  "4:/(a|b)+c$/H",
+                                                  "5:/\\x00\\xff/",
+                                                  "6:/.{8}x/s",
+                                                  "7:/[^\\n]*?\\n/"];
+
+fn on_fuzz_match(_: u32, _: u64, _: u64, _: u32, _: &()) -> u32 {
+    0
+}
+
+/// Whether the expression only has small bounded repeats.
+fn bounded(expression: &str) -> bool {
+    let mut number = None;
+
+    for c in expression.chars() {
+        match c.to_digit(10) {
+            Some(d) => {
+                let n = number.unwrap_or(0u32).saturating_mul(10).saturating_add(d);
+
+                if n > FUZZ_MAX_REPEAT {
+                    return false;
+                }
+
+                number = Some(n);
+            }
+            None => number = None,
+        }
+    }
+
+    true
+}
+
+/// Parse the fuzzing input as patterns, one `id:/expression/flags` per line.
+///
+/// The patterns beyond the resource limits are dropped.
+fn fuzz_patterns(data: &[u8]) -> Patterns {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter(|line| line.len() <= FUZZ_MAX_EXPRESSION_LEN)
+        .filter_map(|line| Pattern::parse(line).ok())
+        .filter(|pattern| !pattern.expression.contains('\0') && bounded(&pattern.expression))
+        .take(FUZZ_MAX_PATTERNS)
+        .collect()
+}
+
+/// A fuzzing entry point exercising the pattern parsing and compilation.
+///
+/// The input is parsed as patterns, one per line, which are checked and compiled with the resource limits,
+/// then the compiled database is serialized, deserialized and scans the input.
+/// The errors are expected, only the panics and the undefined behaviours are bugs.
+pub fn fuzz_compile(data: &[u8]) {
+    let patterns = fuzz_patterns(data);
+
+    for pattern in &patterns {
+        let _ = pattern.info();
+    }
+
+    let db: BlockDatabase = match patterns.build() {
+        Ok(db) => db,
+        Err(_) => return,
+    };
+
+    let _ = db.database_info();
+
+    if let Ok(serialized) = db.serialize() {
+        let _ = BlockDatabase::deserialize(serialized.as_slice());
+    }
+
+    if let Ok(scratch) = db.alloc() {
+        let _ = db.scan(data, 0, &scratch, Some(on_fuzz_match), Some(&()));
+    }
+}
+
+thread_local! {
+    static FUZZ_DATABASES: (BlockDatabase, StreamingDatabase, VectoredDatabase, RawScratch) = {
+        let patterns = FUZZ_PATTERNS.iter()
+            .map(|s| Pattern::parse(s).unwrap())
+            .collect::<Patterns>();
+
+        let block: BlockDatabase = patterns.build().unwrap();
+        let streaming: StreamingDatabase = patterns.build().unwrap();
+        let vectored: VectoredDatabase = patterns.build().unwrap();
+
+        let mut scratch = block.alloc().unwrap();
+
+        streaming.realloc(&mut scratch).unwrap();
+        vectored.realloc(&mut scratch).unwrap();
+
+        (block, streaming, vectored, scratch)
+    };
+}
+
+/// A fuzzing entry point exercising the scanning of a fixed database.
+///
+/// The input is scanned as a block, then split into chunks at the `\n` bytes,
+/// which are scanned in a stream and as vectored data.
+pub fn fuzz_scan(data: &[u8]) {
+    FUZZ_DATABASES.with(|&(ref block, ref streaming, ref vectored, ref scratch)| {
+        let _ = block.scan(data, 0, scratch, Some(on_fuzz_match), Some(&()));
+
+        let chunks = data.split(|&b| b == b'\n').collect::<Vec<_>>();
+
+        if let Ok(stream) = streaming.open_stream(0) {
+            for chunk in &chunks {
+                if stream.scan(*chunk, 0, scratch, Some(on_fuzz_match), Some(&())).is_err() {
+                    break;
+                }
+            }
+
+            let _ = stream.close(scratch, Some(on_fuzz_match), Some(&()));
+        }
+
+        let _ = vectored.scan(&chunks, 0, scratch, Some(on_fuzz_match), Some(&()));
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_fuzz_compile() {
+        fuzz_compile(b"1:/foo/i\n2:/(bar/\n3:/a{100000}/\n\xff/baz/");
+        fuzz_compile(b"");
+        fuzz_compile(b"/\x00/");
+    }
+
+    #[test]
+    fn test_fuzz_scan() {
+        fuzz_scan(b"GET /index.html\nfoobar 1234f aabc\n\x00\xff");
+        fuzz_scan(b"");
+        fuzz_scan(b"\n\n\n");
+    }
+}
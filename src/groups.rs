@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use api::*;
+use errors::Error;
+use common::BlockDatabase;
+
+/// A registry of the group tags of the patterns, like "malware" or "pii",
+/// so one compiled database can serve several policies.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Groups {
+    tags: HashMap<u32, BTreeSet<String>>,
+}
+
+/// A match event callback and its context, filtered by the groups of the pattern.
+pub struct GroupFilter<'a, D: 'a> {
+    groups: &'a Groups,
+    selected: BTreeSet<String>,
+    callback: Option<MatchEventCallback<D>>,
+    context: Option<&'a D>,
+}
+
+impl<'a, D> GroupFilter<'a, D> {
+    /// The match event callback to pass to the scan functions with the `GroupFilter` context.
+    pub fn on_match(id: u32, from: u64, to: u64, flags: u32, filter: &GroupFilter<D>) -> u32 {
+        if !filter.groups.in_any(id, &filter.selected) {
+            return 0;
+        }
+
+        match (filter.callback, filter.context) {
+            (Some(callback), Some(context)) => callback(id, from, to, flags, context),
+            _ => 0,
+        }
+    }
+}
+
+fn on_grouped_match(id: u32, from: u64, to: u64, flags: u32, matches: &RefCell<Vec<Match>>) -> u32 {
+    matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+    0
+}
+
+impl Groups {
+    pub fn new() -> Groups {
+        Groups::default()
+    }
+
+    /// Tag the pattern with a group.
+    pub fn tag<S: Into<String>>(&mut self, id: u32, group: S) -> &mut Self {
+        self.tags.entry(id).or_insert_with(BTreeSet::new).insert(group.into());
+        self
+    }
+
+    /// Remove the tags of a pattern.
+    pub fn untag(&mut self, id: u32) -> Option<BTreeSet<String>> {
+        self.tags.remove(&id)
+    }
+
+    /// The groups of a pattern.
+    pub fn groups(&self, id: u32) -> Vec<&str> {
+        self.tags.get(&id).map_or_else(Vec::new, |tags| tags.iter().map(|tag| tag.as_str()).collect())
+    }
+
+    /// The IDs of the patterns of a group, in ascending order.
+    pub fn ids(&self, group: &str) -> Vec<u32> {
+        let mut ids = self.tags
+            .iter()
+            .filter(|&(_, tags)| tags.contains(group))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        ids.sort();
+        ids
+    }
+
+    /// Whether the pattern is in the group.
+    pub fn contains(&self, id: u32, group: &str) -> bool {
+        self.tags.get(&id).map_or(false, |tags| tags.contains(group))
+    }
+
+    fn in_any(&self, id: u32, groups: &BTreeSet<String>) -> bool {
+        self.tags.get(&id).map_or(false, |tags| !tags.is_disjoint(groups))
+    }
+
+    /// Wrap a match event callback and its context, so only the matches of the patterns
+    /// in one of the groups are passed to the callback.
+    pub fn filter<'a, D, I>(&'a self,
+                            groups: I,
+                            callback: Option<MatchEventCallback<D>>,
+                            context: Option<&'a D>)
+                            -> GroupFilter<'a, D>
+        where I: IntoIterator,
+              I::Item: Into<String>
+    {
+        GroupFilter {
+            groups: self,
+            selected: groups.into_iter().map(|group| group.into()).collect(),
+            callback: callback,
+            context: context,
+        }
+    }
+
+    /// Wrap a match handler, so only the matches of the patterns in one of the groups are passed to the handler.
+    pub fn handler<'a, I, F>(&'a self, groups: I, mut handler: F) -> impl FnMut(u32, u64, u64, u32) -> u32 + 'a
+        where I: IntoIterator,
+              I::Item: Into<String>,
+              F: FnMut(u32, u64, u64, u32) -> u32 + 'a
+    {
+        let selected = groups.into_iter().map(|group| group.into()).collect::<BTreeSet<String>>();
+
+        move |id, from, to, flags| if self.in_any(id, &selected) {
+            handler(id, from, to, flags)
+        } else {
+            0
+        }
+    }
+
+    /// Scan the data, returning the matches aggregated per group,
+    /// a match of a pattern in several groups is reported in each of them.
+    pub fn scan<T, S>(&self, db: &BlockDatabase, data: T, scratch: &S) -> Result<BTreeMap<String, Vec<Match>>, Error>
+        where T: Scannable,
+              S: Scratch
+    {
+        let matches = RefCell::new(Vec::new());
+
+        try!(db.scan(data.as_bytes(), 0, scratch, Some(on_grouped_match), Some(&matches)));
+
+        let mut grouped = BTreeMap::new();
+
+        for m in matches.into_inner() {
+            if let Some(tags) = self.tags.get(&m.id) {
+                for tag in tags {
+                    grouped.entry(tag.clone()).or_insert_with(Vec::new).push(m);
+                }
+            }
+        }
+
+        Ok(grouped)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+
+    use super::super::*;
+
+    fn groups() -> Groups {
+        let mut groups = Groups::new();
+
+        groups.tag(1, "malware").tag(2, "pii").tag(3, "pii").tag(3, "finance");
+        groups
+    }
+
+    fn on_match(id: u32, _: u64, _: u64, _: u32, ids: &RefCell<Vec<u32>>) -> u32 {
+        ids.borrow_mut().push(id);
+
+        0
+    }
+
+    #[test]
+    fn test_groups() {
+        let groups = groups();
+
+        assert_eq!(groups.groups(3), vec!["finance", "pii"]);
+        assert!(groups.groups(4).is_empty());
+        assert_eq!(groups.ids("pii"), vec![2, 3]);
+        assert!(groups.contains(1, "malware"));
+        assert!(!groups.contains(1, "pii"));
+    }
+
+    #[test]
+    fn test_group_scan() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = patterns!(["evil", "ssn", "iban", "other"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let groups = groups();
+        let data = "evil ssn iban other";
+
+        let grouped = groups.scan(&db, data, &s).unwrap();
+
+        assert_eq!(grouped.keys().collect::<Vec<_>>(), vec!["finance", "malware", "pii"]);
+        assert_eq!(grouped["pii"].iter().map(|m| m.id).collect::<Vec<_>>(), vec![2, 3]);
+
+        let ids = RefCell::new(Vec::new());
+        let filter = groups.filter(vec!["malware", "finance"], Some(on_match), Some(&ids));
+
+        db.scan(data, 0, &s, Some(GroupFilter::on_match), Some(&filter)).unwrap();
+
+        assert_eq!(ids.into_inner(), vec![1, 3]);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashSet;
+
+use api::Match;
+
+/// A handler of the match events, which returns non-zero to request that scanning cease.
+///
+/// The trait is object-safe, so handlers of different types can be boxed and stored in a struct,
+/// and it is implemented for the closures taking `(id, from, to, flags)`.
+///
+/// The stream APIs, like `RawStream::feed`, `StreamManager::scan` and `Reassembler::write`, take a handler,
+/// the scanning functions taking a closure are adapted with `as_callback`.
+pub trait MatchHandler {
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32;
+
+    /// Only pass the matches of the expressions with one of the IDs to the handler.
+    fn filter_ids<I>(self, ids: I) -> FilterIds<Self>
+        where I: IntoIterator<Item = u32>,
+              Self: Sized
+    {
+        FilterIds {
+            handler: self,
+            ids: ids.into_iter().collect(),
+        }
+    }
+
+    /// Pass at most `n` matches to the handler, then request that scanning cease.
+    fn take(self, n: usize) -> Take<Self>
+        where Self: Sized
+    {
+        Take {
+            handler: self,
+            remaining: n,
+        }
+    }
+
+    /// Pass each match to the sink too, scanning ceases when either of them requests it.
+    fn tee<S>(self, sink: S) -> Tee<Self, S>
+        where S: MatchHandler,
+              Self: Sized
+    {
+        Tee {
+            handler: self,
+            sink: sink,
+        }
+    }
+
+    /// Transform the matches before passing them to the handler, e.g. to rebase the offsets.
+    fn map<F>(self, f: F) -> Map<Self, F>
+        where F: FnMut(Match) -> Match,
+              Self: Sized
+    {
+        Map { handler: self, f: f }
+    }
+}
+
+impl<F> MatchHandler for F
+    where F: FnMut(u32, u64, u64, u32) -> u32
+{
+    #[inline]
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        self(id, from, to, flags)
+    }
+}
+
+impl<'a> MatchHandler for Box<MatchHandler + 'a> {
+    #[inline]
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        (**self).on_match(id, from, to, flags)
+    }
+}
+
+impl<'a> MatchHandler for Box<MatchHandler + Send + 'a> {
+    #[inline]
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        (**self).on_match(id, from, to, flags)
+    }
+}
+
+/// A handler only passing the matches of some expressions, returned by `MatchHandler::filter_ids`.
+#[derive(Debug)]
+pub struct FilterIds<H> {
+    handler: H,
+    ids: HashSet<u32>,
+}
+
+impl<H: MatchHandler> MatchHandler for FilterIds<H> {
+    #[inline]
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        if self.ids.contains(&id) {
+            self.handler.on_match(id, from, to, flags)
+        } else {
+            0
+        }
+    }
+}
+
+/// A handler passing at most some matches, returned by `MatchHandler::take`.
+#[derive(Debug)]
+pub struct Take<H> {
+    handler: H,
+    remaining: usize,
+}
+
+impl<H: MatchHandler> MatchHandler for Take<H> {
+    #[inline]
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        if self.remaining == 0 {
+            return 1;
+        }
+
+        self.remaining -= 1;
+
+        match self.handler.on_match(id, from, to, flags) {
+            0 if self.remaining > 0 => 0,
+            0 => 1,
+            stop => stop,
+        }
+    }
+}
+
+/// A handler passing the matches to a sink too, returned by `MatchHandler::tee`.
+#[derive(Debug)]
+pub struct Tee<H, S> {
+    handler: H,
+    sink: S,
+}
+
+impl<H, S> Tee<H, S> {
+    /// Take the handler and the sink back.
+    pub fn into_inner(self) -> (H, S) {
+        (self.handler, self.sink)
+    }
+}
+
+impl<H: MatchHandler, S: MatchHandler> MatchHandler for Tee<H, S> {
+    #[inline]
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        let stop = self.handler.on_match(id, from, to, flags);
+
+        stop | self.sink.on_match(id, from, to, flags)
+    }
+}
+
+/// A handler transforming the matches, returned by `MatchHandler::map`.
+pub struct Map<H, F> {
+    handler: H,
+    f: F,
+}
+
+impl<H, F> MatchHandler for Map<H, F>
+    where H: MatchHandler,
+          F: FnMut(Match) -> Match
+{
+    #[inline]
+    fn on_match(&mut self, id: u32, from: u64, to: u64, flags: u32) -> u32 {
+        let m = (self.f)(Match::new(id, from, to, flags));
+
+        self.handler.on_match(m.id, m.from, m.to, m.flags.bits())
+    }
+}
+
+/// Borrow a handler as the closure taken by the scanning functions, e.g. `RawStream::feed`.
+pub fn as_callback<'a, H>(handler: &'a mut H) -> impl FnMut(u32, u64, u64, u32) -> u32 + 'a
+    where H: MatchHandler + ?Sized
+{
+    move |id, from, to, flags| handler.on_match(id, from, to, flags)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[derive(Default)]
+    struct Counter(usize);
+
+    impl MatchHandler for Counter {
+        fn on_match(&mut self, _: u32, _: u64, _: u64, _: u32) -> u32 {
+            self.0 += 1;
+            0
+        }
+    }
+
+    struct Handlers {
+        handlers: Vec<Box<MatchHandler + Send>>,
+    }
+
+    #[test]
+    fn test_match_handler() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foo", "bar"]).build().unwrap();
+        let scratch = db.alloc().unwrap();
+
+        let mut handlers = Handlers {
+            handlers: vec![Box::new(Counter::default()), Box::new(|id, _, _, _| (id == 2) as u32)],
+        };
+
+        for (i, handler) in handlers.handlers.iter_mut().enumerate() {
+            let stream = db.open_stream(0).unwrap();
+            let feed = stream.feed(vec!["foo", "bar", "foo"], &scratch, as_callback(handler)).unwrap();
+
+            assert_eq!(feed.terminated, i == 1);
+
+            stream.close(&scratch, None, None::<&()>).unwrap();
+        }
+
+        let mut counter = Counter::default();
+
+        {
+            let handler: &mut MatchHandler = &mut counter;
+
+            assert_eq!(handler.on_match(1, 0, 3, 0), 0);
+        }
+
+        assert_eq!(counter.0, 1);
+
+        let stream = db.open_stream(0).unwrap();
+        let feed = stream.feed(vec!["foo", "bar"], &scratch, Counter::default().take(1)).unwrap();
+
+        assert!(feed.terminated);
+        assert_eq!(feed.chunks, 1);
+
+        stream.close(&scratch, None, None::<&()>).unwrap();
+    }
+
+    #[test]
+    fn test_handler_combinators() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foo", "bar", "baz"]).build().unwrap();
+        let scratch = db.alloc().unwrap();
+        let data = vec!["foo bar", " baz foo", " bar"];
+
+        let feed = |handler: &mut MatchHandler| {
+            let stream = db.open_stream(0).unwrap();
+            let feed = stream.feed(data.clone(), &scratch, as_callback(handler)).unwrap();
+
+            stream.close(&scratch, None, None::<&()>).unwrap();
+
+            feed
+        };
+
+        let mut matches = Vec::new();
+
+        let counter = {
+            let mut handler = (|id, _, to, _| {
+                    matches.push((id, to));
+                    0
+                })
+                .filter_ids(vec![1, 3])
+                .map(|mut m: Match| {
+                    m.to -= 1;
+                    m
+                })
+                .tee(Counter::default());
+
+            assert!(!feed(&mut handler).terminated);
+
+            handler.into_inner().1
+        };
+
+        assert_eq!(matches, vec![(1, 2), (3, 10), (1, 14)]);
+        assert_eq!(counter.0, 5);
+
+        matches.clear();
+
+        {
+            let mut handler = (|id, _, to, _| {
+                    matches.push((id, to));
+                    0
+                })
+                .take(2);
+
+            let feed = feed(&mut handler);
+
+            assert!(feed.terminated);
+            assert_eq!(feed.chunks, 1);
+        }
+
+        assert_eq!(matches, vec![(1, 3), (2, 7)]);
+
+        let mut handler = Counter::default().take(0);
+
+        assert!(feed(&mut handler).terminated);
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::cmp;
+use std::cell::Cell;
+
+use api::*;
+use constants::*;
+use errors::Error;
+use common::BlockDatabase;
+use compile::{CompileFlags, ExprExt, Pattern, Patterns};
+
+/// The HTTP request methods.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+}
+
+const METHODS: [(Method, &'static str); 9] = [(Method::Get, "GET"),
+                                              (Method::Head, "HEAD"),
+                                              (Method::Post, "POST"),
+                                              (Method::Put, "PUT"),
+                                              (Method::Delete, "DELETE"),
+                                              (Method::Connect, "CONNECT"),
+                                              (Method::Options, "OPTIONS"),
+                                              (Method::Trace, "TRACE"),
+                                              (Method::Patch, "PATCH")];
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        METHODS.iter().find(|&&(method, _)| method == *self).unwrap().1
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The protocol detected from the first bytes of a stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// A HTTP/1.x request, with its method.
+    Http1Request(Method),
+    /// A HTTP/1.x response status line.
+    Http1Response,
+    /// The HTTP/2 connection preface of a client.
+    Http2,
+}
+
+const RESPONSE_ID: usize = 100;
+const HTTP2_ID: usize = 101;
+
+/// The number of bytes needed to detect any of the protocols.
+pub const HTTP_PREAMBLE_SIZE: usize = 24;
+
+/// A classifier of the HTTP preambles, the request lines, the status lines and
+/// the HTTP/2 connection preface, on the first bytes of a stream.
+pub struct HttpClassifier {
+    db: BlockDatabase,
+}
+
+impl fmt::Debug for HttpClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HttpClassifier{{db: {:p}}}", *self.db)
+    }
+}
+
+fn on_protocol(id: u32, _: u64, _: u64, _: u32, found: &Cell<Option<u32>>) -> u32 {
+    found.set(Some(id));
+
+    1
+}
+
+impl HttpClassifier {
+    /// The patterns of the classifier, the ID of a method is its index in `Method`.
+    pub fn patterns() -> Patterns {
+        let mut patterns: Patterns = METHODS.iter()
+            .enumerate()
+            .map(|(id, &(_, name))| {
+                Pattern {
+                    expression: format!(r"\A{} \S", name),
+                    flags: CompileFlags(HS_FLAG_SINGLEMATCH),
+                    id: id,
+                    ext: ExprExt::default(),
+                }
+            })
+            .collect();
+
+        patterns.push(Pattern {
+            expression: String::from(r"\AHTTP/1\.[01] \d{3}"),
+            flags: CompileFlags(HS_FLAG_SINGLEMATCH),
+            id: RESPONSE_ID,
+            ext: ExprExt::default(),
+        });
+        patterns.push(Pattern {
+            expression: String::from(r"\APRI \* HTTP/2\.0\r\n\r\nSM\r\n\r\n"),
+            flags: CompileFlags(HS_FLAG_SINGLEMATCH),
+            id: HTTP2_ID,
+            ext: ExprExt::default(),
+        });
+
+        patterns
+    }
+
+    /// Compile the patterns for the current host.
+    pub fn new() -> Result<HttpClassifier, Error> {
+        Ok(HttpClassifier { db: try!(Self::patterns().build()) })
+    }
+
+    /// The database of the classifier, to allocate the scratch space.
+    pub fn database(&self) -> &BlockDatabase {
+        &self.db
+    }
+
+    /// Detect the protocol from the first bytes of a stream,
+    /// only the first `HTTP_PREAMBLE_SIZE` bytes are scanned.
+    pub fn classify<S: Scratch>(&self, data: &[u8], scratch: &S) -> Result<Option<Protocol>, Error> {
+        let found = Cell::new(None);
+        let data = &data[..cmp::min(data.len(), HTTP_PREAMBLE_SIZE)];
+
+        match self.db.scan(data, 0, scratch, Some(on_protocol), Some(&found)) {
+            Ok(_) | Err(Error::ScanTerminated) => {}
+            Err(err) => return Err(err),
+        }
+
+        Ok(found.get().map(|id| match id as usize {
+            RESPONSE_ID => Protocol::Http1Response,
+            HTTP2_ID => Protocol::Http2,
+            id => Protocol::Http1Request(METHODS[id].0),
+        }))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_http_classifier() {
+        let _ = env_logger::init();
+
+        let classifier = HttpClassifier::new().unwrap();
+        let s = classifier.database().alloc().unwrap();
+
+        assert_eq!(classifier.classify(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n", &s).unwrap(),
+                   Some(Protocol::Http1Request(Method::Get)));
+        assert_eq!(classifier.classify(b"CONNECT example.com:443 HTTP/1.1\r\n", &s).unwrap(),
+                   Some(Protocol::Http1Request(Method::Connect)));
+        assert_eq!(classifier.classify(b"HTTP/1.0 200 OK\r\n", &s).unwrap(),
+                   Some(Protocol::Http1Response));
+        assert_eq!(classifier.classify(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\x00\x00", &s).unwrap(),
+                   Some(Protocol::Http2));
+        assert_eq!(classifier.classify(b"SSH-2.0-OpenSSH_7.4\r\n", &s).unwrap(), None);
+        assert_eq!(classifier.classify(b"xGET / HTTP/1.1\r\n", &s).unwrap(), None);
+        assert_eq!(classifier.classify(b"GE", &s).unwrap(), None);
+
+        assert_eq!(Method::Options.to_string(), "OPTIONS");
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::collections::HashMap;
+
+use errors::Error;
+use compile::Pattern;
+
+/// How the ID numbers are assigned to the patterns of a set.
+pub enum IdStrategy {
+    /// Number the patterns in order, from the first ID.
+    Sequential(usize),
+    /// Derive the ID from a hash of the expression and the flags,
+    /// so the IDs are stable when the rule file is reordered.
+    Hash,
+    /// Ask the function for the ID of the pattern at an index.
+    Custom(Box<Fn(usize, &Pattern) -> usize + Send + Sync>),
+}
+
+impl fmt::Debug for IdStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IdStrategy::Sequential(start) => write!(f, "Sequential({})", start),
+            IdStrategy::Hash => write!(f, "Hash"),
+            IdStrategy::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The 64 bits FNV-1a hash of the expression, the flags and the extended parameters,
+/// folded to 32 bits by xoring its halves, which doesn't depend on the Rust version.
+pub fn expression_id(pattern: &Pattern) -> usize {
+    let ext = if pattern.ext.is_empty() {
+        String::new()
+    } else {
+        pattern.ext.to_string()
+    };
+
+    let h = fnv1a(pattern.expression
+        .as_bytes()
+        .iter()
+        .chain(&[0])
+        .chain(&pattern.flags.0.to_le_bytes())
+        .chain(ext.as_bytes()));
+
+    ((h >> 32) ^ (h & 0xffff_ffff)) as usize
+}
+
+/// The 64 bits FNV-1a hash of the bytes.
+pub(crate) fn fnv1a<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> u64 {
+    let mut h = FNV_OFFSET_BASIS;
+
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(FNV_PRIME);
+    }
+
+    h
+}
+
+/// Assign the ID numbers of the patterns with the strategy.
+///
+/// Returns `Error::CompilerError` if two different patterns get the same ID,
+/// the patterns with the same expression, flags and extended parameters may share an ID.
+pub fn assign_ids(patterns: &mut [Pattern], strategy: &IdStrategy) -> Result<(), Error> {
+    for (i, pattern) in patterns.iter_mut().enumerate() {
+        pattern.id = match *strategy {
+            IdStrategy::Sequential(start) => start + i,
+            IdStrategy::Hash => expression_id(pattern),
+            IdStrategy::Custom(ref f) => f(i, pattern),
+        };
+    }
+
+    check_ids(patterns)
+}
+
+/// Check that different patterns have different ID numbers.
+pub fn check_ids(patterns: &[Pattern]) -> Result<(), Error> {
+    let mut assigned: HashMap<usize, &Pattern> = HashMap::with_capacity(patterns.len());
+
+    for pattern in patterns {
+        if let Some(other) = assigned.insert(pattern.id, pattern) {
+            if other.expression != pattern.expression || other.flags != pattern.flags || other.ext != pattern.ext {
+                return Err(Error::CompilerError(format!("pattern ID {} is assigned to both `{}` and `{}`",
+                                                        pattern.id,
+                                                        other,
+                                                        pattern)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_assign_ids() {
+        let mut patterns = patterns!(["foo", "bar", "baz"]);
+
+        assign_ids(&mut patterns, &IdStrategy::Sequential(100)).unwrap();
+
+        assert_eq!(patterns.iter().map(|p| p.id).collect::<Vec<_>>(), vec![100, 101, 102]);
+
+        assign_ids(&mut patterns, &IdStrategy::Hash).unwrap();
+
+        let ids = patterns.iter().map(|p| p.id).collect::<Vec<_>>();
+
+        let mut reordered = patterns!(["baz", "foo", "bar"]);
+
+        assign_ids(&mut reordered, &IdStrategy::Hash).unwrap();
+
+        assert_eq!(reordered.iter().map(|p| p.id).collect::<Vec<_>>(),
+                   vec![ids[2], ids[0], ids[1]]);
+        assert!(ids.iter().all(|&id| id <= u32::max_value() as usize));
+        assert!(expression_id(&pattern!{"foo", flags => HS_FLAG_CASELESS}) != ids[0]);
+
+        assign_ids(&mut patterns,
+                   &IdStrategy::Custom(Box::new(|i, p| if p.expression == "bar" { 42 } else { i })))
+            .unwrap();
+
+        assert_eq!(patterns.iter().map(|p| p.id).collect::<Vec<_>>(), vec![0, 42, 2]);
+
+        assert!(assign_ids(&mut patterns, &IdStrategy::Custom(Box::new(|_, _| 1))).is_err());
+
+        let mut duplicated = patterns!(["foo", "foo"]);
+
+        assert!(assign_ids(&mut duplicated, &IdStrategy::Hash).is_ok());
+    }
+}
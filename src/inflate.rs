@@ -0,0 +1,317 @@
+use std::io;
+use std::io::Write;
+use std::fmt;
+use std::cmp;
+use std::mem;
+
+use flate2::write::{DeflateDecoder, GzDecoder, ZlibDecoder};
+
+use api::*;
+use runtime::{Feed, RawStream};
+
+/// The compression of the content written to an `InflateStream`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    /// The gzip file format (RFC 1952).
+    Gzip,
+    /// The zlib format (RFC 1950), `deflate` in HTTP.
+    Zlib,
+    /// The raw deflate format (RFC 1951), sent by some servers as `deflate` in HTTP.
+    Deflate,
+}
+
+impl Encoding {
+    /// The encoding of a HTTP `Content-Encoding` value, `None` for identity or unsupported encodings.
+    pub fn from_content_encoding(value: &str) -> Option<Encoding> {
+        match value.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Zlib),
+            _ => None,
+        }
+    }
+}
+
+/// The limits protecting against decompression bombs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InflateLimits {
+    /// The maximum ratio of the decompressed size to the compressed size.
+    pub max_ratio: Option<u64>,
+    /// The maximum decompressed size in bytes.
+    pub max_output: Option<u64>,
+}
+
+impl Default for InflateLimits {
+    fn default() -> Self {
+        InflateLimits {
+            max_ratio: Some(100),
+            max_output: None,
+        }
+    }
+}
+
+impl InflateLimits {
+    /// The maximum number of bytes which may be decompressed from `input` compressed bytes.
+    fn budget(&self, input: u64) -> u64 {
+        let by_ratio = self.max_ratio.map_or(u64::max_value(), |ratio| input.saturating_mul(ratio));
+
+        cmp::min(by_ratio, self.max_output.unwrap_or(u64::max_value()))
+    }
+}
+
+/// The decompressed bytes, failing the decoder when the budget is exhausted.
+struct Sink {
+    buf: Vec<u8>,
+    budget: u64,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.budget == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "decompression limit exceeded"));
+        }
+
+        let len = cmp::min(buf.len() as u64, self.budget) as usize;
+
+        self.budget -= len as u64;
+        self.buf.extend_from_slice(&buf[..len]);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decoder {
+    Gzip(GzDecoder<Sink>),
+    Zlib(ZlibDecoder<Sink>),
+    Deflate(DeflateDecoder<Sink>),
+}
+
+impl Decoder {
+    fn sink(&mut self) -> &mut Sink {
+        match *self {
+            Decoder::Gzip(ref mut d) => d.get_mut(),
+            Decoder::Zlib(ref mut d) => d.get_mut(),
+            Decoder::Deflate(ref mut d) => d.get_mut(),
+        }
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match *self {
+            Decoder::Gzip(ref mut d) => d.write_all(data),
+            Decoder::Zlib(ref mut d) => d.write_all(data),
+            Decoder::Deflate(ref mut d) => d.write_all(data),
+        }
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        match *self {
+            Decoder::Gzip(ref mut d) => d.try_finish(),
+            Decoder::Zlib(ref mut d) => d.try_finish(),
+            Decoder::Deflate(ref mut d) => d.try_finish(),
+        }
+    }
+}
+
+/// A stream decompressing gzip or deflate content before scanning it,
+/// for HTTP response bodies for example.
+///
+/// The match offsets are in the decompressed content. The decompression fails with
+/// `io::ErrorKind::InvalidData` once the limits are exceeded, before the bytes over
+/// the limits are decompressed.
+pub struct InflateStream {
+    stream: RawStream,
+    decoder: Decoder,
+    limits: InflateLimits,
+    input: u64,
+    output: u64,
+}
+
+impl fmt::Debug for InflateStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "InflateStream{{stream: {:?}, limits: {:?}, input: {}, output: {}}}",
+               self.stream,
+               self.limits,
+               self.input,
+               self.output)
+    }
+}
+
+impl InflateStream {
+    /// Decompress the content with the default limits.
+    pub fn new(stream: RawStream, encoding: Encoding) -> InflateStream {
+        InflateStream::with_limits(stream, encoding, InflateLimits::default())
+    }
+
+    pub fn with_limits(stream: RawStream, encoding: Encoding, limits: InflateLimits) -> InflateStream {
+        let sink = Sink {
+            buf: Vec::new(),
+            budget: 0,
+        };
+
+        InflateStream {
+            stream: stream,
+            decoder: match encoding {
+                Encoding::Gzip => Decoder::Gzip(GzDecoder::new(sink)),
+                Encoding::Zlib => Decoder::Zlib(ZlibDecoder::new(sink)),
+                Encoding::Deflate => Decoder::Deflate(DeflateDecoder::new(sink)),
+            },
+            limits: limits,
+            input: 0,
+            output: 0,
+        }
+    }
+
+    /// The number of compressed bytes written and of decompressed bytes scanned.
+    pub fn totals(&self) -> (u64, u64) {
+        (self.input, self.output)
+    }
+
+    /// The underlying stream.
+    pub fn stream(&self) -> &RawStream {
+        &self.stream
+    }
+
+    /// Decompress a chunk of the content and scan the decompressed bytes.
+    pub fn write<S, H>(&mut self, compressed: &[u8], scratch: &S, handler: H) -> io::Result<Feed>
+        where S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        self.input += compressed.len() as u64;
+
+        let budget = self.limits.budget(self.input).saturating_sub(self.output);
+
+        self.decoder.sink().budget = budget;
+
+        let result = self.decoder.write_all(compressed);
+
+        // scan what was decompressed before an error
+        let feed = try!(self.scan(scratch, handler));
+
+        try!(result);
+
+        Ok(feed)
+    }
+
+    /// Finish decompressing the content, and close the stream.
+    pub fn finish<S, H>(mut self, scratch: &S, mut handler: H) -> io::Result<()>
+        where S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let budget = self.limits.budget(self.input).saturating_sub(self.output);
+
+        self.decoder.sink().budget = budget;
+
+        let result = self.decoder.try_finish();
+
+        let feed = try!(self.scan(scratch, &mut handler));
+
+        if feed.terminated {
+            try!(self.stream.close(scratch, None, None::<&()>));
+        } else {
+            let matches = ::std::cell::RefCell::new(Vec::new());
+
+            try!(self.stream.close(scratch, Some(on_close_event), Some(&matches)));
+
+            for m in matches.into_inner() {
+                if handler(m.id, m.from, m.to, m.flags.bits()) != 0 {
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+
+    fn scan<S, H>(&mut self, scratch: &S, handler: H) -> io::Result<Feed>
+        where S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let buf = mem::replace(&mut self.decoder.sink().buf, Vec::new());
+
+        self.output += buf.len() as u64;
+
+        let feed = try!(self.stream.feed(Some(&buf[..]), scratch, handler));
+
+        // reuse the buffer for the next chunk
+        let mut buf = buf;
+
+        buf.clear();
+
+        self.decoder.sink().buf = buf;
+
+        Ok(feed)
+    }
+}
+
+fn on_close_event(id: u32, from: u64, to: u64, flags: u32, matches: &::std::cell::RefCell<Vec<Match>>) -> u32 {
+    matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+    0
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::io;
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use super::super::*;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_inflate_stream() {
+        let _ = env_logger::init();
+
+        assert_eq!(Encoding::from_content_encoding("GZIP"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::from_content_encoding("br"), None);
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let body = gzip(b"some test data, some more test data");
+        let mut st = InflateStream::new(db.open_stream(0).unwrap(), Encoding::Gzip);
+        let mut matches = Vec::new();
+
+        for chunk in body.chunks(7) {
+            st.write(chunk, &s, |_, _, to, _| {
+                    matches.push(to);
+
+                    0
+                })
+                .unwrap();
+        }
+
+        assert_eq!(st.totals(), (body.len() as u64, 35));
+
+        st.finish(&s, |_, _, _, _| 0).unwrap();
+
+        assert_eq!(matches, vec![9, 30]);
+
+        let bomb = gzip(&vec![0; 1024 * 1024]);
+        let mut st = InflateStream::with_limits(db.open_stream(0).unwrap(),
+                                                Encoding::Gzip,
+                                                InflateLimits {
+                                                    max_ratio: Some(10),
+                                                    max_output: None,
+                                                });
+
+        let err = st.write(&bomb, &s, |_, _, _, _| 0).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(st.totals().1 <= bomb.len() as u64 * 10);
+    }
+}
@@ -32,6 +32,13 @@
 extern crate log;
 extern crate libc;
 extern crate regex_syntax;
+extern crate glob;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "flate2")]
+extern crate flate2;
+#[cfg(any(test, feature = "regex"))]
+extern crate regex;
 
 mod raw;
 mod constants;
@@ -40,19 +47,148 @@ mod cptr;
 mod errors;
 mod api;
 mod common;
+mod casefold;
 #[macro_use]
 mod compile;
+mod combination;
 mod runtime;
+mod anchored;
+mod som;
+mod mask;
+mod embed;
+mod bundle;
+mod telemetry;
+mod local;
+mod file;
+mod dir;
+mod swap;
+mod shared;
+mod source;
+mod diff;
+mod pool;
+mod scratch;
+mod alloc;
+mod stats;
+mod earliest;
+mod registry;
+mod channel;
+mod cancel;
+mod template;
+mod vectored;
+mod prefilter;
+mod streamed;
+mod offsets;
+#[cfg(feature = "flate2")]
+mod inflate;
+mod decode;
+mod normalize;
+mod mime;
+mod http;
+mod classify;
+mod sample;
+mod sink;
+mod multi;
+mod progress;
+mod shard;
+mod ids;
+mod groups;
+mod severity;
+mod bloom;
+#[macro_use]
+mod testing;
+mod replay;
+mod literal;
+mod limits;
+mod sandbox;
+mod cache;
+mod handler;
+mod staging;
+mod fork;
+mod scope;
+mod builder;
+mod dedup;
+#[cfg(unix)]
+mod shm;
+mod manager;
+mod flow;
+mod reassembly;
+#[cfg(feature = "fuzzing")]
+mod fuzz;
+#[cfg(feature = "capi")]
+mod capi;
 
 pub use constants::*;
 pub use api::*;
-pub use errors::Error;
+pub use errors::{Error, PatternError};
 pub use common::{RawDatabase, BlockDatabase, StreamingDatabase, VectoredDatabase};
-pub use compile::{CompileFlags, Pattern, Patterns};
-pub use runtime::{RawScratch, RawStream};
-
-#[cfg(test)]
-extern crate regex;
+pub use compile::{CompileFlags, ExtFlags, ExprExt, FlagBits, Pattern, Patterns, empty_matching_patterns};
+pub use combination::{check_combinations, combination_ids};
+pub use runtime::{RawScratch, RawStream, Feed};
+pub use anchored::AnchoredDatabase;
+pub use som::{SomCost, som_costs};
+pub use mask::{PatternMask, Masked};
+pub use embed::{embed_patterns, embed_database};
+pub use bundle::{Bundle, Target, Variant};
+pub use telemetry::{ScratchStats, enable_scratch_telemetry, disable_scratch_telemetry, scratch_telemetry};
+pub use local::{LocalScratch, local_scratch, with_local_scratch, release_local_scratch};
+pub use file::{FileMatch, FileScanner, scan_path};
+pub use dir::{DirOptions, DirStats, FileResult, FileSink, Symlinks, scan_dir};
+pub use swap::HotSwap;
+pub use shared::SharedDatabase;
+pub use source::PatternDatabase;
+pub use diff::{PatternChange, PatternDiff, diff_patterns};
+pub use pool::{InUsePolicy, ScratchPool, PooledScratch};
+pub use scratch::ScratchRef;
+pub use alloc::{AllocDomain, set_alloc_failure_handler, clear_alloc_failure_handler};
+pub use stats::ScanStats;
+pub use earliest::EarliestMatch;
+pub use registry::Registry;
+pub use channel::{Backpressure, MatchReceiver, MatchSender, match_channel};
+pub use cancel::{Cancellable, CancellationToken};
+pub use template::ScratchTemplate;
+pub use vectored::{BlockVectored, Section, scan_header_payload};
+pub use prefilter::{PrefilterStream, Prefiltered, Verifier};
+#[cfg(feature = "regex")]
+pub use prefilter::RegexVerifier;
+pub use streamed::StreamedBlock;
+pub use offsets::{MappedStream, OffsetMap, Segments};
+#[cfg(feature = "flate2")]
+pub use inflate::{Encoding, InflateLimits, InflateStream};
+pub use decode::{DecodeChain, DecodedMatch, Decoding};
+pub use normalize::{CollapseWhitespace, Lowercase, Normalized, Pipeline, Preprocessor};
+pub use mime::{MessageParts, PartMatch};
+pub use http::{HttpClassifier, Method, Protocol, HTTP_PREAMBLE_SIZE};
+pub use classify::{Classifier, ClassifierFlow, Detection};
+pub use sample::{Sample, Sampler, Sampling};
+pub use sink::{JsonLines, MatchSink, scan_into, stream_into};
+pub use multi::{MultiMatch, MultiScanner};
+pub use progress::{CompileProgress, CompileStage, CompileTask};
+pub use shard::Shards;
+pub use ids::{IdStrategy, assign_ids, check_ids, expression_id};
+pub use groups::{GroupFilter, Groups};
+pub use severity::{SeverityPolicy, Verdict, BLOCKING, LOG_ONLY};
+pub use bloom::{LiteralFilter, required_literal, required_literals};
+pub use testing::{Chunkings, MatchTarget, assert_chunkings, scan_matches, stream_matches};
+pub use replay::{Discrepancy, Recorder, Replayer, ScanRecord, database_hash};
+pub use literal::{Literal, Literals, compile_literals};
+pub use limits::{CompileLimits, MAX_TIMED_COMPILES, timed_compiles};
+pub use sandbox::{SafeProfile, SAFE_FLAGS};
+pub use cache::CachedBuilder;
+pub use staging::PatternSetManager;
+pub use fork::StreamFork;
+pub use builder::{AnyDatabase, Builder, Mode, compile_modes};
+pub use dedup::{Duplicate, dedup_patterns};
+#[cfg(unix)]
+pub use shm::{SharedMemoryDatabase, SharedMemoryPublisher};
+pub use scope::{ScanHandle, ScanScope, scan_scope};
+pub use handler::{FilterIds, Map, MatchHandler, Take, Tee, as_callback};
+pub use manager::{OverQuota, StreamManager, StreamQuota};
+pub use flow::{Direction, FlowStreams};
+pub use reassembly::{GapPolicy, Reassembler, DEFAULT_REASSEMBLY_CAPACITY};
+#[cfg(feature = "fuzzing")]
+pub use fuzz::{fuzz_compile, fuzz_scan, FUZZ_MAX_EXPRESSION_LEN, FUZZ_MAX_PATTERNS, FUZZ_MAX_REPEAT};
+#[cfg(feature = "capi")]
+pub use capi::{hsrs_compile, hsrs_database, hsrs_free_database, hsrs_free_error, hsrs_match_callback, hsrs_scan};
 
 #[cfg(test)]
 mod tests {
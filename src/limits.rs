@@ -0,0 +1,282 @@
+use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use api::*;
+use errors::Error;
+use common::RawDatabase;
+use compile::{Patterns, compile_patterns};
+use progress::CompileTask;
+use cancel::CancellationToken;
+
+/// The maximum number of compilations with a time limit running at once, including those abandoned
+/// after their time limit, beyond it a compilation with a time limit fails with `Error::TooManyCompiles`.
+pub const MAX_TIMED_COMPILES: usize = 16;
+
+static TIMED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The number of compilations with a time limit which are running, including those abandoned after their time limit.
+pub fn timed_compiles() -> usize {
+    TIMED.load(Ordering::SeqCst)
+}
+
+/// Take a slot for a compilation with a time limit.
+fn reserve() -> Result<(), Error> {
+    let mut running = TIMED.load(Ordering::SeqCst);
+
+    loop {
+        if running >= MAX_TIMED_COMPILES {
+            return Err(Error::TooManyCompiles(running));
+        }
+
+        match TIMED.compare_exchange(running, running + 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return Ok(()),
+            Err(current) => running = current,
+        }
+    }
+}
+
+fn release() {
+    TIMED.fetch_sub(1, Ordering::SeqCst);
+}
+
+const RUNNING: usize = 0;
+const FINISHED: usize = 1;
+const DETACHED: usize = 2;
+
+/// Moved to the compile thread, so the slot of an abandoned compilation is released when the thread ends.
+struct Worker {
+    state: Arc<AtomicUsize>,
+}
+
+impl Worker {
+    /// Detach the compilation, returns `false` if it already finished.
+    fn detach(state: &AtomicUsize) -> bool {
+        state.compare_exchange(RUNNING, DETACHED, Ordering::SeqCst, Ordering::SeqCst).is_ok()
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if self.state.swap(FINISHED, Ordering::SeqCst) == DETACHED {
+            release();
+
+            debug!("abandoned compilation finished, {} timed compilations still running", timed_compiles());
+        }
+    }
+}
+
+/// Guards around the compilation, so the untrusted or user supplied patterns
+/// can't blow up the memory or the time of a shared service.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CompileLimits {
+    /// The maximum size of the compiled database, in bytes.
+    pub max_database_size: Option<usize>,
+    /// The maximum size of the scratch space of the compiled database, in bytes.
+    pub max_scratch_size: Option<usize>,
+    /// The maximum wall-clock time of the compilation.
+    pub timeout: Option<Duration>,
+    /// The target platform, the current host by default.
+    pub platform: Option<Platform>,
+}
+
+impl CompileLimits {
+    /// No limit.
+    pub fn new() -> CompileLimits {
+        CompileLimits::default()
+    }
+
+    pub fn with_max_database_size(mut self, size: usize) -> Self {
+        self.max_database_size = Some(size);
+        self
+    }
+
+    pub fn with_max_scratch_size(mut self, size: usize) -> Self {
+        self.max_scratch_size = Some(size);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Compile the patterns within the limits.
+    ///
+    /// Returns `Error::CompileTimeout` if the compilation runs out of time. Hyperscan can't interrupt
+    /// the final compilation, so the worker thread is left running detached, holding its CPU and memory
+    /// until the compilation completes, and its result is discarded. A compilation with a time limit
+    /// takes one of `MAX_TIMED_COMPILES` slots until it completes in time or its abandoned thread ends,
+    /// it fails at once with `Error::TooManyCompiles` when none is free.
+    /// Returns `Error::DatabaseTooLarge` or `Error::ScratchTooLarge` if the compiled database is too large.
+    pub fn compile<T: Type + 'static>(&self, patterns: &Patterns) -> Result<RawDatabase<T>, Error> {
+        let db = match self.timeout {
+            Some(timeout) => {
+                if let Err(err) = reserve() {
+                    warn!("{}, refuse to compile {} patterns", err, patterns.len());
+
+                    return Err(err);
+                }
+
+                let state = Arc::new(AtomicUsize::new(RUNNING));
+                let worker = Worker { state: state.clone() };
+                let task = CompileTask::with_platform(CancellationToken::new(),
+                                                      self.platform,
+                                                      patterns.clone(),
+                                                      move |_| {
+                                                          let _ = &worker;
+                                                      });
+
+                match task.wait_timeout(timeout) {
+                    Ok(result) => {
+                        release();
+
+                        try!(result)
+                    }
+                    Err(task) => {
+                        task.cancel();
+
+                        if Worker::detach(&state) {
+                            warn!("compile {} patterns exceeds the time limit {:?}, abandoned",
+                                  patterns.len(),
+                                  timeout);
+                        } else {
+                            release();
+                        }
+
+                        return Err(Error::CompileTimeout);
+                    }
+                }
+            }
+            None => try!(compile_patterns(patterns, 0, &platform_info(self.platform))),
+        };
+
+        try!(self.check(&db));
+
+        Ok(db)
+    }
+
+    /// Check the size of a compiled database and its scratch space.
+    pub fn check<T: Type>(&self, db: &RawDatabase<T>) -> Result<(), Error> {
+        if let Some(limit) = self.max_database_size {
+            let size = try!(db.database_size());
+
+            if size > limit {
+                debug!("database size {} exceeds the limit {}", size, limit);
+
+                return Err(Error::DatabaseTooLarge(size));
+            }
+        }
+
+        if let Some(limit) = self.max_scratch_size {
+            let size = try!(db.required_scratch_size());
+
+            if size > limit {
+                debug!("scratch size {} exceeds the limit {}", size, limit);
+
+                return Err(Error::ScratchTooLarge(size));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::time::Duration;
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_compile_limits() {
+        let _ = env_logger::init();
+
+        let patterns = patterns!(["foo", "bar\\d+", "baz"]);
+
+        let db: BlockDatabase = CompileLimits::new().compile(&patterns).unwrap();
+
+        validate_database(&db);
+
+        let size = db.database_size().unwrap();
+        let scratch_size = db.alloc().unwrap().size().unwrap();
+
+        let limits = CompileLimits::new()
+            .with_max_database_size(size)
+            .with_max_scratch_size(scratch_size)
+            .with_timeout(Duration::from_secs(60));
+
+        let db: BlockDatabase = limits.compile(&patterns).unwrap();
+
+        validate_database(&db);
+
+        assert_eq!(CompileLimits::new().with_max_database_size(size - 1).check(&db),
+                   Err(Error::DatabaseTooLarge(size)));
+        assert_eq!(CompileLimits::new().with_max_scratch_size(scratch_size - 1).check(&db),
+                   Err(Error::ScratchTooLarge(scratch_size)));
+
+        let limits = CompileLimits::new().with_timeout(Duration::from_secs(60));
+
+        assert!(limits.compile::<Block>(&patterns!(["("])).is_err());
+    }
+
+    #[test]
+    fn test_compile_platform() {
+        let _ = env_logger::init();
+
+        let platform = Platform::generic().with_tune(HS_TUNE_FAMILY_SKX).with_avx512();
+
+        assert_eq!(platform.tune(), HS_TUNE_FAMILY_SKX);
+        assert_eq!(platform.cpu_features(),
+                   (HS_CPU_FEATURES_AVX2 | HS_CPU_FEATURES_AVX512) as u64);
+        assert!(Platform::generic().runs_on(&platform));
+        assert!(!platform.runs_on(&Platform::generic().with_avx2()));
+
+        let info = platform.info();
+
+        assert_eq!(info.tune(), HS_TUNE_FAMILY_SKX);
+        assert_eq!(info.cpu_features(), platform.cpu_features());
+
+        let patterns = patterns!(["foo", "bar\\d+"]);
+        let db: BlockDatabase = CompileLimits::new()
+            .with_platform(Platform::generic())
+            .compile(&patterns)
+            .unwrap();
+
+        validate_database(&db);
+
+        assert_eq!(db.runs_on_current_host(), Ok(()));
+
+        let db: BlockDatabase = CompileLimits::new()
+            .with_platform(Platform::host())
+            .with_timeout(Duration::from_secs(60))
+            .compile(&patterns)
+            .unwrap();
+
+        validate_database(&db);
+    }
+
+    #[test]
+    fn test_compile_timeout() {
+        let _ = env_logger::init();
+
+        let patterns = (0..200)
+            .map(|i| {
+                pattern!{format!("[a-z]{{{}}}x\\d{{1,200}}[^y]{{30}}", i + 100), flags => 0, id => i}
+            })
+            .collect::<Patterns>();
+
+        let limits = CompileLimits::new().with_timeout(Duration::from_millis(1));
+
+        assert_eq!(limits.compile::<Block>(&patterns).err(), Some(Error::CompileTimeout));
+        assert!(timed_compiles() <= MAX_TIMED_COMPILES);
+    }
+}
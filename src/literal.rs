@@ -0,0 +1,205 @@
+use std::fmt;
+use std::ptr;
+use std::os::raw::c_uint;
+
+use raw::*;
+use constants::*;
+use api::*;
+use common::RawDatabase;
+use compile::{CompileFlags, FlagBits};
+use errors::{Error, RawCompileErrorPtr};
+
+/// The compile flags supported by the pure literals.
+const LITERAL_FLAGS: u32 = HS_FLAG_CASELESS | HS_FLAG_SINGLEMATCH | HS_FLAG_SOM_LEFTMOST;
+
+/// A pure literal, matched byte for byte without any regular expression semantics,
+/// so the indicators like hashes and hostnames don't need to be escaped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    /// The bytes to match, which may contain NUL bytes.
+    pub bytes: Vec<u8>,
+    /// Flags which modify the behaviour of the literal,
+    /// only `HS_FLAG_CASELESS`, `HS_FLAG_SINGLEMATCH` and `HS_FLAG_SOM_LEFTMOST` are supported.
+    pub flags: CompileFlags,
+    /// ID number to be associated with the corresponding literal in the literals set.
+    pub id: usize,
+}
+
+/// Vec of `Literal`
+pub type Literals = Vec<Literal>;
+
+impl Literal {
+    /// Construct a literal, the unsupported flags are rejected when it is compiled.
+    pub fn new<B: Into<Vec<u8>>, F: FlagBits>(id: usize, bytes: B, flags: F) -> Literal {
+        Literal {
+            bytes: bytes.into(),
+            flags: CompileFlags::from_bits_retain(flags.flag_bits()),
+            id: id,
+        }
+    }
+
+    fn check_flags(&self) -> Result<(), Error> {
+        if self.flags.0 & !LITERAL_FLAGS != 0 {
+            Err(Error::CompilerError(format!("literal #{} has the unsupported flags `{}`", self.id, self.flags)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}:\"", self.id));
+
+        for &b in &self.bytes {
+            match b {
+                b'"' | b'\\' => try!(write!(f, "\\{}", b as char)),
+                0x20..=0x7e => try!(write!(f, "{}", b as char)),
+                _ => try!(write!(f, "\\x{:02x}", b)),
+            }
+        }
+
+        write!(f, "\"{}", self.flags)
+    }
+}
+
+impl<T: Type> RawDatabase<T> {
+    /// The pure literal compiler.
+    ///
+    /// The literal is matched byte for byte, may contain NUL bytes and doesn't need to be escaped.
+    pub fn compile_literal(literal: &[u8], flags: u32, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+        try!(Literal::new(0, literal, flags).check_flags());
+
+        let mut db: RawDatabasePtr = ptr::null_mut();
+        let mut err: RawCompileErrorPtr = ptr::null_mut();
+
+        unsafe {
+            check_compile_error!(hs_compile_lit(literal.as_ptr() as *const i8,
+                                                flags,
+                                                literal.len(),
+                                                T::mode(),
+                                                platform.as_ptr(),
+                                                &mut db,
+                                                &mut err),
+                                 err);
+        }
+
+        debug!("literal of {} bytes compiled to {} database {:p}",
+               literal.len(),
+               T::name(),
+               db);
+
+        Ok(RawDatabase::from_raw(db))
+    }
+}
+
+/// Compile a set of pure literals, given as `(id, bytes, flags)`,
+/// which compiles far faster than the escaped expressions for the big literal sets.
+pub fn compile_literals<T: Type>(literals: &[(u32, &[u8], u32)]) -> Result<RawDatabase<T>, Error> {
+    literals.iter()
+        .map(|&(id, bytes, flags)| Literal::new(id as usize, bytes, flags))
+        .collect::<Literals>()
+        .build()
+}
+
+fn compile_literal_multi<T: Type>(literals: &[Literal], platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+    for literal in literals {
+        try!(literal.check_flags());
+    }
+
+    let ptrs = literals.iter().map(|literal| literal.bytes.as_ptr() as *const i8).collect::<Vec<_>>();
+    let flags = literals.iter().map(|literal| literal.flags.0 as c_uint).collect::<Vec<_>>();
+    let ids = literals.iter().map(|literal| literal.id as c_uint).collect::<Vec<_>>();
+    let lens = literals.iter().map(|literal| literal.bytes.len()).collect::<Vec<_>>();
+
+    let mut db: RawDatabasePtr = ptr::null_mut();
+    let mut err: RawCompileErrorPtr = ptr::null_mut();
+
+    unsafe {
+        check_compile_error!(hs_compile_lit_multi(ptrs.as_ptr(),
+                                                  flags.as_ptr(),
+                                                  ids.as_ptr(),
+                                                  lens.as_ptr(),
+                                                  literals.len() as u32,
+                                                  T::mode(),
+                                                  platform.as_ptr(),
+                                                  &mut db,
+                                                  &mut err),
+                             err,
+                             |index| {
+                                 literals.get(index)
+                                     .map(|l| (l.id, String::from_utf8_lossy(&l.bytes).into_owned()))
+                             });
+    }
+
+    debug!("{} literals compiled to {} database {:p}",
+           literals.len(),
+           T::name(),
+           db);
+
+    Ok(RawDatabase::from_raw(db))
+}
+
+impl<T: Type> DatabaseBuilder<RawDatabase<T>> for Literal {
+    fn build_for_platform(&self, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+        compile_literal_multi(&[self.clone()], platform)
+    }
+}
+
+impl<T: Type> DatabaseBuilder<RawDatabase<T>> for Literals {
+    ///
+    /// The multiple pure literal compiler.
+    ///
+    /// Each literal is labelled with its ID number,
+    /// which is passed into the match callback to identify the literal that has matched.
+    ///
+    fn build_for_platform(&self, platform: &PlatformInfo) -> Result<RawDatabase<T>, Error> {
+        compile_literal_multi(self, platform)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_literal() {
+        let literal = Literal::new(3, &b"a.b\"\0"[..], HS_FLAG_CASELESS);
+
+        assert_eq!(literal.to_string(), "3:\"a.b\\\"\\x00\"i");
+    }
+
+    #[test]
+    fn test_compile_literal() {
+        let _ = env_logger::init();
+
+        let db = BlockDatabase::compile_literal(b"a.b", HS_FLAG_SOM_LEFTMOST, &PlatformInfo::host()).unwrap();
+
+        assert_matches!(&db, "axb a.b", [(0, 7)]);
+
+        assert!(BlockDatabase::compile_literal(b"a.b", HS_FLAG_DOTALL, &PlatformInfo::host()).is_err());
+    }
+
+    #[test]
+    fn test_compile_literals() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = compile_literals(&[(1, &b"evil.com"[..], 0),
+                                                   (2, &b"d41d8cd9\0"[..], HS_FLAG_CASELESS),
+                                                   (3, &b"(x)"[..], HS_FLAG_SINGLEMATCH)])
+            .unwrap();
+
+        validate_database(&db);
+
+        assert_matches!(&db, &b"www.evil.com D41D8CD9\0 (x)(x) evilxcom"[..], [(1, 12), (2, 22), (3, 26)]);
+
+        let db: StreamingDatabase = vec![Literal::new(1, "foo", 0)].build().unwrap();
+
+        validate_database(&db);
+
+        assert!(compile_literals::<Block>(&[(1, &b"foo"[..], HS_FLAG_UTF8)]).is_err());
+    }
+}
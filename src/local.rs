@@ -0,0 +1,166 @@
+use std::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use api::*;
+use errors::Error;
+use common::RawDatabase;
+use runtime::RawScratch;
+
+thread_local! {
+    static SCRATCHES: RefCell<HashMap<usize, RawScratch>> = RefCell::new(HashMap::new());
+}
+
+/// A handle to the scratch space of the current thread for a database,
+/// which is given back to the thread local registry when dropped.
+pub struct LocalScratch {
+    key: usize,
+    scratch: Option<RawScratch>,
+    _marker: PhantomData<*const ()>,
+}
+
+impl fmt::Debug for LocalScratch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LocalScratch{{scratch: {:?}}}", self.scratch)
+    }
+}
+
+impl Deref for LocalScratch {
+    type Target = RawScratch;
+
+    #[inline]
+    fn deref(&self) -> &RawScratch {
+        self.scratch.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for LocalScratch {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut RawScratch {
+        self.scratch.as_mut().unwrap()
+    }
+}
+
+impl Drop for LocalScratch {
+    fn drop(&mut self) {
+        if let Some(scratch) = self.scratch.take() {
+            let key = self.key;
+
+            let _ = SCRATCHES.try_with(|scratches| scratches.borrow_mut().insert(key, scratch));
+        }
+    }
+}
+
+/// Take the scratch space of the current thread for the database,
+/// allocating it on first use.
+///
+/// The scratch is kept in a thread local registry keyed by the database address,
+/// so code deep inside a call stack can scan without having a scratch handle passed down to it.
+///
+/// The scratch is reallocated for the database every time it is taken, which is cheap when
+/// it is already large enough, so a database allocated at the address of a freed one is safe.
+/// While the handle is alive, nested calls for the same database, from a match callback
+/// for example, get a scratch of their own.
+pub fn local_scratch<T: Type>(db: &RawDatabase<T>) -> Result<LocalScratch, Error> {
+    let key = **db as usize;
+
+    let scratch = match SCRATCHES.with(|scratches| scratches.borrow_mut().remove(&key)) {
+        Some(mut scratch) => {
+            try!(scratch.realloc(db));
+
+            scratch
+        }
+        None => {
+            let scratch = try!(db.alloc());
+
+            debug!("allocated local scratch {:?} for {} database {:p}",
+                   scratch,
+                   db.database_name(),
+                   **db);
+
+            scratch
+        }
+    };
+
+    Ok(LocalScratch {
+        key: key,
+        scratch: Some(scratch),
+        _marker: PhantomData,
+    })
+}
+
+/// Run a function with the scratch space of the current thread for the database.
+///
+/// See `local_scratch` for the details.
+pub fn with_local_scratch<T, F, R>(db: &RawDatabase<T>, f: F) -> Result<R, Error>
+    where T: Type,
+          F: FnOnce(&RawScratch) -> R
+{
+    let scratch = try!(local_scratch(db));
+
+    Ok(f(&scratch))
+}
+
+/// Put a scratch space in the thread local registry for the database at the address,
+/// returns the scratch it replaces.
+pub fn insert_local_scratch(key: usize, scratch: RawScratch) -> Option<RawScratch> {
+    SCRATCHES.with(|scratches| scratches.borrow_mut().insert(key, scratch))
+}
+
+/// Free the scratch space of the current thread for the database, if any.
+pub fn release_local_scratch<T: Type>(db: &RawDatabase<T>) -> bool {
+    SCRATCHES.with(|scratches| scratches.borrow_mut().remove(&(**db as usize)).is_some())
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::thread;
+    use std::sync::Arc;
+    use std::cell::Cell;
+
+    use super::super::*;
+
+    #[test]
+    fn test_local_scratch() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+
+        fn callback(_: u32, _: u64, _: u64, _: u32, matches: &Cell<usize>) -> u32 {
+            matches.set(matches.get() + 1);
+
+            0
+        }
+
+        let matches = Cell::new(0);
+
+        let p = with_local_scratch(&db, |s| {
+                db.scan("some test data", 0, s, Some(callback), Some(&matches)).unwrap();
+
+                **s as usize
+            })
+            .unwrap();
+
+        assert_eq!(matches.get(), 1);
+
+        assert_eq!(with_local_scratch(&db, |s| **s as usize).unwrap(), p);
+
+        let nested = with_local_scratch(&db, |_| with_local_scratch(&db, |s| **s as usize).unwrap()).unwrap();
+
+        assert!(nested != p);
+
+        let db = Arc::new(db);
+        let db2 = db.clone();
+
+        let other = thread::spawn(move || with_local_scratch(&*db2, |s| **s as usize).unwrap()).join().unwrap();
+
+        assert!(other != p);
+
+        assert!(release_local_scratch(&*db));
+        assert!(!release_local_scratch(&*db));
+    }
+}
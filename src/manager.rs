@@ -0,0 +1,686 @@
+use std::fmt;
+use std::mem;
+use std::iter;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::hash::Hash;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use api::*;
+use errors::Error;
+use common::StreamingDatabase;
+use runtime::{Feed, RawScratch, RawStream};
+use replay::{database_hash, hex, unhex};
+use ids::fnv1a;
+use handler::MatchHandler;
+use sink::{MatchSink, on_sink_event};
+use stats::ScanStats;
+
+/// What a `StreamManager` does when a new flow would exceed its quota.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverQuota {
+    /// Close the least recently used streams to make room, discarding their end of data matches.
+    EvictLru,
+    /// Refuse the new flow with `Error::QuotaExceeded`.
+    Reject,
+}
+
+/// The limits of the streams opened by a `StreamManager`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StreamQuota {
+    /// The maximum number of open streams.
+    pub max_streams: Option<usize>,
+    /// The maximum total size of the stream states, in bytes.
+    pub max_bytes: Option<usize>,
+    /// What to do with a new flow over the quota.
+    pub over_quota: OverQuota,
+}
+
+impl Default for StreamQuota {
+    fn default() -> Self {
+        StreamQuota {
+            max_streams: None,
+            max_bytes: None,
+            over_quota: OverQuota::EvictLru,
+        }
+    }
+}
+
+impl StreamQuota {
+    /// No limit.
+    pub fn new() -> StreamQuota {
+        StreamQuota::default()
+    }
+
+    pub fn with_max_streams(mut self, count: usize) -> Self {
+        self.max_streams = Some(count);
+        self
+    }
+
+    pub fn with_max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_over_quota(mut self, over_quota: OverQuota) -> Self {
+        self.over_quota = over_quota;
+        self
+    }
+
+    /// The maximum number of streams of `stream_size` bytes.
+    fn capacity(&self, stream_size: usize) -> Option<usize> {
+        let by_bytes = self.max_bytes.map(|bytes| if stream_size == 0 {
+            usize::max_value()
+        } else {
+            bytes / stream_size
+        });
+
+        match (self.max_streams, by_bytes) {
+            (Some(streams), Some(bytes)) => Some(if streams < bytes { streams } else { bytes }),
+            (streams, bytes) => streams.or(bytes),
+        }
+    }
+}
+
+struct Flow {
+    stream: RawStream,
+    used: u64,
+}
+
+fn on_flow_match<H>(id: u32, from: u64, to: u64, flags: u32, handler: &RefCell<H>) -> u32
+    where H: MatchHandler
+{
+    handler.borrow_mut().on_match(id, from, to, flags)
+}
+
+/// The streams of the flows scanned with a database, keyed by a flow ID like a 5-tuple,
+/// which enforces the quotas of the open streams rather than every caller.
+///
+/// The streams left open are closed without reporting their matches when the manager is dropped,
+/// `shutdown` drains them instead.
+pub struct StreamManager<K: Hash + Eq + Clone> {
+    db: StreamingDatabase,
+    scratch: RawScratch,
+    stream_size: usize,
+    quota: StreamQuota,
+    flows: HashMap<K, Flow>,
+    lru: BTreeMap<u64, K>,
+    clock: u64,
+    evicted: u64,
+}
+
+impl<K: Hash + Eq + Clone> fmt::Debug for StreamManager<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "StreamManager{{db: {:?}, streams: {}, quota: {:?}, evicted: {}}}",
+               self.db,
+               self.flows.len(),
+               self.quota,
+               self.evicted)
+    }
+}
+
+impl<K: Hash + Eq + Clone> StreamManager<K> {
+    /// Manage the streams of the database, without any quota.
+    pub fn new(db: StreamingDatabase) -> Result<StreamManager<K>, Error> {
+        let scratch = try!(db.alloc());
+        let stream_size = try!(db.stream_size());
+
+        Ok(StreamManager {
+            db: db,
+            scratch: scratch,
+            stream_size: stream_size,
+            quota: StreamQuota::default(),
+            flows: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            evicted: 0,
+        })
+    }
+
+    /// Enforce the quota on the new flows.
+    pub fn with_quota(mut self, quota: StreamQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    pub fn database(&self) -> &StreamingDatabase {
+        &self.db
+    }
+
+    pub fn quota(&self) -> &StreamQuota {
+        &self.quota
+    }
+
+    /// The number of open streams.
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Whether the flow has an open stream.
+    pub fn contains(&self, key: &K) -> bool {
+        self.flows.contains_key(key)
+    }
+
+    /// The totals of the data written to the stream of a flow.
+    pub fn stats(&self, key: &K) -> Option<ScanStats> {
+        self.flows.get(key).map(|flow| flow.stream.scan_stats())
+    }
+
+    /// The total size of the open stream states, in bytes.
+    pub fn memory(&self) -> usize {
+        self.flows.len() * self.stream_size
+    }
+
+    /// The number of streams evicted to make room for the new flows.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Make room for a new stream, evicting the least recently used streams or refusing it.
+    fn reserve(&mut self) -> Result<(), Error> {
+        let capacity = match self.quota.capacity(self.stream_size) {
+            Some(capacity) => capacity,
+            None => return Ok(()),
+        };
+
+        if capacity == 0 {
+            return Err(Error::QuotaExceeded);
+        }
+
+        while self.flows.len() >= capacity {
+            if self.quota.over_quota == OverQuota::Reject {
+                debug!("refuse new flow, {} streams open", self.flows.len());
+
+                return Err(Error::QuotaExceeded);
+            }
+
+            let oldest = match self.lru.keys().next() {
+                Some(&used) => used,
+                None => break,
+            };
+
+            if let Some(key) = self.lru.remove(&oldest) {
+                if let Some(flow) = self.flows.remove(&key) {
+                    debug!("evict least recently used stream {:?}", flow.stream);
+
+                    self.evicted += 1;
+
+                    try!(flow.stream.close::<()>(&self.scratch, None, None));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scan the data of a flow in its stream, opening the stream on the first data of the flow.
+    ///
+    /// Returns `Error::QuotaExceeded` if the new flow is refused by the quota.
+    pub fn scan<T, H>(&mut self, key: &K, data: T, handler: H) -> Result<Feed, Error>
+        where T: Scannable,
+              H: MatchHandler
+    {
+        self.clock += 1;
+
+        let clock = self.clock;
+
+        if let Some(flow) = self.flows.get_mut(key) {
+            self.lru.remove(&flow.used);
+            self.lru.insert(clock, key.clone());
+
+            flow.used = clock;
+
+            return flow.stream.feed(iter::once(data), &self.scratch, handler);
+        }
+
+        try!(self.reserve());
+
+        let stream = try!(self.db.open_stream(0));
+        let feed = stream.feed(iter::once(data), &self.scratch, handler);
+
+        self.lru.insert(clock, key.clone());
+        self.flows.insert(key.clone(),
+                          Flow {
+                              stream: stream,
+                              used: clock,
+                          });
+
+        feed
+    }
+
+    /// Close the stream of a flow, passing the end of data matches to the handler.
+    ///
+    /// Returns `false` if the flow has no open stream.
+    pub fn close<H>(&mut self, key: &K, handler: H) -> Result<bool, Error>
+        where H: MatchHandler
+    {
+        match self.flows.remove(key) {
+            Some(flow) => {
+                self.lru.remove(&flow.used);
+
+                let handler = RefCell::new(handler);
+
+                try!(flow.stream.close(&self.scratch, Some(on_flow_match::<H>), Some(&handler)));
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Close every open stream, passing the end of data matches, the errors
+    /// and the totals of the closed streams to the sink, so no end of flow match is lost on restart.
+    ///
+    /// Returns the totals of the closed streams, from the least recently used,
+    /// a stream failing to close is still dropped from the manager.
+    pub fn shutdown(&mut self, sink: &mut MatchSink) -> Vec<(K, ScanStats)> {
+        let sink = RefCell::new(sink);
+        let mut closed = Vec::with_capacity(self.flows.len());
+
+        for (_, key) in mem::replace(&mut self.lru, BTreeMap::new()) {
+            if let Some(flow) = self.flows.remove(&key) {
+                if let Err(err) = flow.stream.close(&self.scratch, Some(on_sink_event), Some(&sink)) {
+                    if err != Error::ScanTerminated {
+                        warn!("fail to close stream {:?}, {}", flow.stream, err);
+
+                        sink.borrow_mut().on_error(&err);
+                    }
+                }
+
+                let stats = flow.stream.scan_stats();
+
+                sink.borrow_mut().on_stream_close(stats);
+
+                closed.push((key, stats));
+            }
+        }
+
+        debug!("shutdown {} streams", closed.len());
+
+        closed
+    }
+}
+
+fn invalid_data<E: ToString>(lineno: usize, err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   format!("invalid stream snapshot at line {}, {}", lineno, err.to_string()))
+}
+
+/// The snapshot of the open streams, so the flows survive a restart or an upgrade of the sensor.
+///
+/// A snapshot is written as a header line `streams db=<hash> count=<n>`, with the hash of the serialized database,
+/// then a line per flow like `flow key=<hex> bytes=<n> matches=<n> state=<hex> sum=<hash>`, from the least
+/// recently used, where the key is the hex of its string form, the state is the compressed stream, including
+/// its offset, and the sum is the FNV-1a hash of the state.
+impl<K> StreamManager<K>
+    where K: Hash + Eq + Clone + fmt::Display + FromStr,
+          K::Err: fmt::Display
+{
+    /// Write the snapshot of the open streams, which are left open, returning the number of flows.
+    pub fn snapshot<W: Write>(&self, mut writer: W) -> io::Result<usize> {
+        try!(writeln!(writer,
+                      "streams db={:016x} count={}",
+                      try!(database_hash(&self.db)),
+                      self.flows.len()));
+
+        for key in self.lru.values() {
+            let flow = &self.flows[key];
+            let stats = flow.stream.scan_stats();
+
+            let state = try!(flow.stream.compress());
+
+            try!(writeln!(writer,
+                          "flow key={} bytes={} matches={} state={} sum={:016x}",
+                          hex(key.to_string().as_bytes()),
+                          stats.bytes,
+                          stats.matches,
+                          hex(&state),
+                          fnv1a(&state)));
+        }
+
+        try!(writer.flush());
+
+        debug!("snapshot {} streams", self.flows.len());
+
+        Ok(self.flows.len())
+    }
+
+    /// Write the snapshot of the open streams to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        self.snapshot(BufWriter::new(try!(File::create(path))))
+    }
+
+    /// Restore the streams of a snapshot taken with the same database, returning the number of flows.
+    ///
+    /// The restored flows are subject to the quota and replace the open streams with the same key,
+    /// the flows read before an error stay restored.
+    /// Returns `io::ErrorKind::InvalidData` if the snapshot is malformed, lacks the `streams` header
+    /// with the database hash, or was taken with another database.
+    ///
+    /// The database hash and the checksum of each state catch a stale or corrupt snapshot before a state
+    /// reaches Hyperscan, which doesn't validate it; they don't protect against a forged one,
+    /// so only restore the snapshots written by `snapshot`.
+    pub fn restore<R: BufRead>(&mut self, reader: R) -> io::Result<usize> {
+        let database = try!(database_hash(&self.db));
+        let mut lines = reader.lines();
+        let mut count = None;
+        let mut restored = 0;
+
+        let line = match lines.next() {
+            Some(line) => try!(line),
+            None => return Err(invalid_data(1, "expect the `streams` header")),
+        };
+        let mut fields = line.trim().split(' ');
+
+        if fields.next() != Some("streams") {
+            return Err(invalid_data(1, "expect the `streams` header"));
+        }
+
+        let mut checked = false;
+
+        for field in fields {
+            match field.find('=').map(|off| (&field[..off], &field[off + 1..])) {
+                Some(("db", v)) => {
+                    if try!(u64::from_str_radix(v, 16).map_err(|err| invalid_data(1, err))) != database {
+                        return Err(invalid_data(1, "the snapshot was taken with another database"));
+                    }
+
+                    checked = true;
+                }
+                Some(("count", v)) => count = Some(try!(v.parse::<usize>().map_err(|err| invalid_data(1, err)))),
+                _ => return Err(invalid_data(1, format!("unknown field `{}`", field))),
+            }
+        }
+
+        if !checked {
+            return Err(invalid_data(1, "missing the database hash"));
+        }
+
+        for (n, line) in lines.enumerate() {
+            let line = try!(line);
+            let lineno = n + 2;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut fields = line.trim().split(' ');
+
+            if fields.next() != Some("flow") {
+                return Err(invalid_data(lineno, "expect a `flow` record"));
+            }
+
+            let mut key = None;
+            let mut stats = ScanStats::default();
+            let mut state = None;
+            let mut sum = None;
+
+            for field in fields {
+                match field.find('=').map(|off| (&field[..off], &field[off + 1..])) {
+                    Some(("key", v)) => {
+                        let s = try!(unhex(v)
+                            .ok()
+                            .and_then(|bytes| String::from_utf8(bytes).ok())
+                            .ok_or_else(|| invalid_data(lineno, "invalid key")));
+
+                        key = Some(try!(s.parse::<K>().map_err(|err| invalid_data(lineno, err))));
+                    }
+                    Some(("bytes", v)) => stats.bytes = try!(v.parse().map_err(|err| invalid_data(lineno, err))),
+                    Some(("matches", v)) => stats.matches = try!(v.parse().map_err(|err| invalid_data(lineno, err))),
+                    Some(("state", v)) => state = Some(try!(unhex(v).map_err(|err| invalid_data(lineno, err)))),
+                    Some(("sum", v)) => {
+                        sum = Some(try!(u64::from_str_radix(v, 16).map_err(|err| invalid_data(lineno, err))))
+                    }
+                    _ => return Err(invalid_data(lineno, format!("unknown field `{}`", field))),
+                }
+            }
+
+            let (key, state) = match (key, state, sum) {
+                (Some(key), Some(state), Some(sum)) => {
+                    if fnv1a(&state) != sum {
+                        return Err(invalid_data(lineno, "the stream state is corrupt"));
+                    }
+
+                    (key, state)
+                }
+                _ => return Err(invalid_data(lineno, "missing key, state or sum")),
+            };
+
+            if let Some(flow) = self.flows.remove(&key) {
+                debug!("replace stream {:?} with the restored one", flow.stream);
+
+                self.lru.remove(&flow.used);
+
+                try!(flow.stream.close::<()>(&self.scratch, None, None));
+            } else {
+                try!(self.reserve());
+            }
+
+            // the state was written by `snapshot` for this database, as checked by the hashes
+            let stream = try!(unsafe { self.db.expand_stream(&state) }).with_stats(stats);
+
+            self.clock += 1;
+            self.lru.insert(self.clock, key.clone());
+            self.flows.insert(key,
+                              Flow {
+                                  stream: stream,
+                                  used: self.clock,
+                              });
+
+            restored += 1;
+        }
+
+        if count.map_or(false, |count| count != restored) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      format!("truncated stream snapshot, {} of {:?} flows", restored, count)));
+        }
+
+        debug!("restore {} streams", restored);
+
+        Ok(restored)
+    }
+
+    /// Restore the streams of a snapshot file.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        self.restore(BufReader::new(try!(File::open(path))))
+    }
+}
+
+impl<K: Hash + Eq + Clone> Drop for StreamManager<K> {
+    fn drop(&mut self) {
+        for (_, flow) in self.flows.drain() {
+            if let Err(err) = flow.stream.close::<()>(&self.scratch, None, None) {
+                warn!("fail to close stream {:?}, {}", flow.stream, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::io;
+
+    use super::super::*;
+
+    fn on_match(matches: &mut Vec<(u32, u64)>) -> impl FnMut(u32, u64, u64, u32) -> u32 + '_ {
+        move |id, _, to, _| {
+            matches.push((id, to));
+
+            0
+        }
+    }
+
+    #[test]
+    fn test_stream_manager() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foobar", "end$"]).build().unwrap();
+        let mut manager = StreamManager::new(db).unwrap();
+        let mut matches = Vec::new();
+
+        manager.scan(&1, "foo", on_match(&mut matches)).unwrap();
+        manager.scan(&2, "xfoo", on_match(&mut matches)).unwrap();
+        manager.scan(&1, "bar", on_match(&mut matches)).unwrap();
+        manager.scan(&2, "the end", on_match(&mut matches)).unwrap();
+
+        assert_eq!(manager.len(), 2);
+        assert_eq!(manager.memory(), 2 * manager.database().stream_size().unwrap());
+        assert_eq!(matches, vec![(1, 6)]);
+
+        assert!(manager.close(&2, on_match(&mut matches)).unwrap());
+        assert!(!manager.close(&2, on_match(&mut matches)).unwrap());
+
+        assert_eq!(matches, vec![(1, 6), (2, 11)]);
+        assert_eq!(manager.len(), 1);
+        assert!(manager.contains(&1));
+    }
+
+    #[test]
+    fn test_stream_quota() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = pattern!{"foobar"}.build().unwrap();
+        let quota = StreamQuota::new().with_max_streams(2);
+        let mut manager = StreamManager::new(db).unwrap().with_quota(quota);
+        let mut matches = Vec::new();
+
+        manager.scan(&"a", "foo", on_match(&mut matches)).unwrap();
+        manager.scan(&"b", "foo", on_match(&mut matches)).unwrap();
+        manager.scan(&"a", "b", on_match(&mut matches)).unwrap();
+        manager.scan(&"c", "foo", on_match(&mut matches)).unwrap();
+
+        assert_eq!(manager.len(), 2);
+        assert_eq!(manager.evicted(), 1);
+        assert!(!manager.contains(&"b"));
+
+        manager.scan(&"a", "ar", on_match(&mut matches)).unwrap();
+
+        assert_eq!(matches, vec![(0, 6)]);
+
+        let db: StreamingDatabase = pattern!{"foobar"}.build().unwrap();
+        let stream_size = db.stream_size().unwrap();
+        let quota = StreamQuota::new().with_max_bytes(stream_size).with_over_quota(OverQuota::Reject);
+        let mut manager = StreamManager::new(db).unwrap().with_quota(quota);
+
+        manager.scan(&1, "foo", on_match(&mut matches)).unwrap();
+
+        assert_eq!(manager.scan(&2, "foo", on_match(&mut matches)).err(),
+                   Some(Error::QuotaExceeded));
+
+        manager.close(&1, on_match(&mut matches)).unwrap();
+        manager.scan(&2, "foo", on_match(&mut matches)).unwrap();
+
+        assert_eq!(manager.len(), 1);
+    }
+
+    #[test]
+    fn test_stream_manager_shutdown() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foo", "end$"]).build().unwrap();
+        let mut manager = StreamManager::new(db).unwrap();
+        let mut matches = Vec::new();
+
+        manager.scan(&"a", "foo the", on_match(&mut matches)).unwrap();
+        manager.scan(&"b", "the end", on_match(&mut matches)).unwrap();
+        manager.scan(&"a", " end", on_match(&mut matches)).unwrap();
+
+        let mut sink = Vec::new();
+        let closed = manager.shutdown(&mut sink);
+
+        assert!(manager.is_empty());
+        assert_eq!(sink, vec![Match::new(2, 0, 7, 0), Match::new(2, 0, 11, 0)]);
+        assert_eq!(closed,
+                   vec![("b", ScanStats { bytes: 7, matches: 1 }), ("a", ScanStats { bytes: 11, matches: 2 })]);
+
+        let mut stats = ScanStats::default();
+
+        manager.scan(&"c", "foo", on_match(&mut matches)).unwrap();
+
+        assert_eq!(manager.shutdown(&mut stats).len(), 1);
+        assert_eq!(stats, ScanStats { bytes: 3, matches: 0 });
+    }
+
+    #[test]
+    fn test_stream_manager_snapshot() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foobar", "end$"]).build().unwrap();
+        let mut manager = StreamManager::<String>::new(db).unwrap();
+        let mut matches = Vec::new();
+
+        manager.scan(&"10.0.0.1:80 tcp".to_owned(), "xxfoo", on_match(&mut matches)).unwrap();
+        manager.scan(&"10.0.0.2:53 udp".to_owned(), "the e", on_match(&mut matches)).unwrap();
+
+        let mut snapshot = Vec::new();
+
+        assert_eq!(manager.snapshot(&mut snapshot).unwrap(), 2);
+
+        let db: StreamingDatabase = patterns!(["foobar", "end$"]).build().unwrap();
+        let mut restored = StreamManager::<String>::new(db).unwrap();
+
+        assert_eq!(restored.restore(&snapshot[..]).unwrap(), 2);
+        assert_eq!(restored.len(), 2);
+
+        restored.scan(&"10.0.0.1:80 tcp".to_owned(), "bar", on_match(&mut matches)).unwrap();
+        restored.scan(&"10.0.0.2:53 udp".to_owned(), "nd", on_match(&mut matches)).unwrap();
+
+        let mut sink = Vec::new();
+        let closed = restored.shutdown(&mut sink);
+
+        assert_eq!(matches, vec![(1, 8)]);
+        assert_eq!(sink, vec![Match::new(2, 0, 7, 0)]);
+        assert_eq!(closed,
+                   vec![("10.0.0.1:80 tcp".to_owned(), ScanStats { bytes: 8, matches: 1 }),
+                        ("10.0.0.2:53 udp".to_owned(), ScanStats { bytes: 7, matches: 1 })]);
+
+        let db: StreamingDatabase = pattern!{"other"}.build().unwrap();
+        let mut other = StreamManager::<String>::new(db).unwrap();
+
+        assert_eq!(other.restore(&snapshot[..]).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+
+        let db: StreamingDatabase = patterns!(["foobar", "end$"]).build().unwrap();
+        let mut truncated = StreamManager::<String>::new(db).unwrap();
+        let lines = String::from_utf8(snapshot).unwrap();
+        let first = lines.lines().take(2).collect::<Vec<_>>().join("\n");
+
+        assert_eq!(truncated.restore(first.as_bytes()).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+
+        let db: StreamingDatabase = patterns!(["foobar", "end$"]).build().unwrap();
+        let mut corrupt = StreamManager::<String>::new(db).unwrap();
+        let flipped = lines.replace("state=", "state=ff");
+
+        assert_eq!(corrupt.restore(flipped.as_bytes()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert!(corrupt.is_empty());
+
+        let headless = lines.lines().skip(1).collect::<Vec<_>>().join("\n");
+
+        assert_eq!(corrupt.restore(headless.as_bytes()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(corrupt.restore(&b""[..]).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+
+        let unchecked = lines.lines()
+            .enumerate()
+            .map(|(n, line)| if n == 0 { "streams count=2" } else { line })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        assert_eq!(corrupt.restore(unchecked.as_bytes()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert!(corrupt.is_empty());
+    }
+}
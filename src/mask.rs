@@ -0,0 +1,182 @@
+use std::fmt;
+use std::sync::RwLock;
+use std::collections::BTreeSet;
+
+use api::*;
+use compile::{Pattern, Patterns};
+
+/// A runtime filter of enabled patterns, so noisy rules can be disabled
+/// without recompiling and redeploying the whole pattern set.
+///
+/// The mask can be shared with scanning threads and updated while they are running.
+#[derive(Default)]
+pub struct PatternMask {
+    disabled: RwLock<BTreeSet<usize>>,
+}
+
+impl fmt::Debug for PatternMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PatternMask{{disabled: {:?}}}", self.disabled())
+    }
+}
+
+/// A match event callback and its context, filtered by a `PatternMask`.
+pub struct Masked<'a, D: 'a> {
+    mask: &'a PatternMask,
+    callback: MatchEventCallback<D>,
+    context: &'a D,
+}
+
+impl<'a, D> Masked<'a, D> {
+    /// The match event callback to pass to the scan functions with the `Masked` context.
+    pub fn on_match(id: u32, from: u64, to: u64, flags: u32, masked: &Masked<D>) -> u32 {
+        if masked.mask.is_enabled(id as usize) {
+            (masked.callback)(id, from, to, flags, masked.context)
+        } else {
+            0
+        }
+    }
+}
+
+impl PatternMask {
+    /// Construct a mask with all the patterns enabled.
+    pub fn new() -> PatternMask {
+        PatternMask::default()
+    }
+
+    /// Disable the pattern with the given ID, returns whether it was enabled.
+    pub fn disable(&self, id: usize) -> bool {
+        debug!("disable pattern #{}", id);
+
+        self.disabled.write().unwrap().insert(id)
+    }
+
+    /// Enable the pattern with the given ID, returns whether it was disabled.
+    pub fn enable(&self, id: usize) -> bool {
+        debug!("enable pattern #{}", id);
+
+        self.disabled.write().unwrap().remove(&id)
+    }
+
+    /// Enable all the patterns.
+    pub fn clear(&self) {
+        self.disabled.write().unwrap().clear()
+    }
+
+    #[inline]
+    pub fn is_enabled(&self, id: usize) -> bool {
+        !self.disabled.read().unwrap().contains(&id)
+    }
+
+    /// The IDs of the disabled patterns.
+    pub fn disabled(&self) -> Vec<usize> {
+        self.disabled.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Wrap a match handler, so it only sees matches of the enabled patterns.
+    pub fn filter<'a, F>(&'a self, mut handler: F) -> impl FnMut(u32, u64, u64, u32) -> u32 + 'a
+        where F: FnMut(u32, u64, u64, u32) -> u32 + 'a
+    {
+        move |id, from, to, flags| if self.is_enabled(id as usize) {
+            handler(id, from, to, flags)
+        } else {
+            0
+        }
+    }
+
+    /// Wrap a match event callback and its context,
+    /// so the callback only sees matches of the enabled patterns.
+    ///
+    /// The returned context should be used with `Masked::on_match` as the callback.
+    pub fn wrap<'a, D>(&'a self, callback: MatchEventCallback<D>, context: &'a D) -> Masked<'a, D> {
+        Masked {
+            mask: self,
+            callback: callback,
+            context: context,
+        }
+    }
+
+    /// Select the enabled patterns, to recompile a database with only the enabled subset.
+    pub fn select(&self, patterns: &[Pattern]) -> Patterns {
+        let disabled = self.disabled.read().unwrap();
+
+        patterns.iter().filter(|p| !disabled.contains(&p.id)).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+
+    use super::super::*;
+
+    #[test]
+    fn test_pattern_mask() {
+        let _ = env_logger::init();
+
+        let mask = PatternMask::new();
+
+        assert!(mask.is_enabled(1));
+        assert!(mask.disable(1));
+        assert!(!mask.disable(1));
+        assert!(!mask.is_enabled(1));
+        assert!(mask.disable(3));
+        assert_eq!(mask.disabled(), vec![1, 3]);
+
+        let patterns = patterns!(["foo", "bar", "baz"]);
+
+        assert_eq!(mask.select(&patterns).iter().map(|p| p.id).collect::<Vec<_>>(),
+                   vec![2]);
+
+        assert!(mask.enable(1));
+        assert!(!mask.enable(1));
+
+        mask.clear();
+
+        assert!(mask.disabled().is_empty());
+    }
+
+    #[test]
+    fn test_masked_scan() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = patterns!(["foo", "bar", "baz"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mask = PatternMask::new();
+
+        mask.disable(2);
+
+        fn callback(id: u32, _: u64, _: u64, _: u32, matched: &RefCell<Vec<u32>>) -> u32 {
+            matched.borrow_mut().push(id);
+
+            0
+        }
+
+        let matched = RefCell::new(Vec::new());
+        let masked = mask.wrap(callback, &matched);
+
+        db.scan("foo bar baz", 0, &s, Some(Masked::on_match), Some(&masked)).unwrap();
+
+        assert_eq!(*matched.borrow(), vec![1, 3]);
+
+        let db: StreamingDatabase = patterns!(["foo", "bar", "baz"]).build().unwrap();
+        let s = db.alloc().unwrap();
+        let st = db.open_stream(0).unwrap();
+
+        let mut matched = Vec::new();
+
+        st.feed(vec!["foo ", "bar ", "baz"],
+                  &s,
+                  mask.filter(|id, _, _, _| {
+                      matched.push(id);
+
+                      0
+                  }))
+            .unwrap();
+
+        assert_eq!(matched, vec![1, 3]);
+    }
+}
@@ -0,0 +1,227 @@
+use std::ops::Range;
+use std::cell::RefCell;
+
+use api::*;
+use errors::Error;
+use common::{BlockDatabase, StreamingDatabase, VectoredDatabase};
+
+/// A match found in a part of a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartMatch {
+    /// The index of the part in the message.
+    pub part: usize,
+    /// The ID number of the matched pattern.
+    pub id: u32,
+    /// The offset of the start of the match in the message.
+    pub from: u64,
+    /// The offset of the end of the match in the message.
+    pub to: u64,
+    /// The flags of the match event.
+    pub flags: MatchFlags,
+}
+
+/// The parts of a message, like the MIME parts of an email,
+/// located by the caller's parser and scanned separately.
+#[derive(Debug, Clone)]
+pub struct MessageParts<'a> {
+    message: &'a [u8],
+    parts: Vec<Range<usize>>,
+}
+
+struct Collector<'a> {
+    part: usize,
+    base: u64,
+    parts: &'a [Range<usize>],
+    matches: RefCell<Vec<PartMatch>>,
+}
+
+impl<'a> Collector<'a> {
+    fn new(parts: &'a [Range<usize>]) -> Collector<'a> {
+        Collector {
+            part: 0,
+            base: 0,
+            parts: parts,
+            matches: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn on_part_match(id: u32, from: u64, to: u64, flags: u32, collector: &Collector) -> u32 {
+        collector.matches.borrow_mut().push(PartMatch {
+            part: collector.part,
+            id: id,
+            from: collector.base + from,
+            to: collector.base + to,
+            flags: MatchFlags::from(flags),
+        });
+
+        0
+    }
+
+    /// The offsets of a vectored scan are in the concatenated parts, the match is
+    /// tagged with the part containing its last byte.
+    fn on_vectored_match(id: u32, from: u64, to: u64, flags: u32, collector: &Collector) -> u32 {
+        let mut end = 0;
+
+        for (part, range) in collector.parts.iter().enumerate() {
+            let start = end;
+
+            end += (range.end - range.start) as u64;
+
+            if to <= end {
+                collector.matches.borrow_mut().push(PartMatch {
+                    part: part,
+                    id: id,
+                    from: collector.offset(from),
+                    to: range.start as u64 + to - start,
+                    flags: MatchFlags::from(flags),
+                });
+
+                break;
+            }
+        }
+
+        0
+    }
+
+    /// Translate an offset in the concatenated parts to an offset in the message.
+    fn offset(&self, offset: u64) -> u64 {
+        let mut end = 0;
+
+        for range in self.parts {
+            let len = (range.end - range.start) as u64;
+
+            if offset < end + len {
+                return range.start as u64 + offset - end;
+            }
+
+            end += len;
+        }
+
+        self.parts.last().map_or(offset, |range| range.end as u64)
+    }
+}
+
+impl<'a> MessageParts<'a> {
+    /// The parts are the ranges of the message located by the parser,
+    /// returns `Error::Invalid` if a range is out of the message.
+    pub fn new(message: &'a [u8], parts: Vec<Range<usize>>) -> Result<MessageParts<'a>, Error> {
+        if parts.iter().any(|range| range.start > range.end || range.end > message.len()) {
+            return Err(Error::Invalid);
+        }
+
+        Ok(MessageParts {
+            message: message,
+            parts: parts,
+        })
+    }
+
+    /// The number of parts.
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// The content of a part.
+    pub fn part(&self, index: usize) -> Option<&'a [u8]> {
+        let message = self.message;
+
+        self.parts.get(index).map(|range| &message[range.clone()])
+    }
+
+    /// Scan each part as a separate block.
+    pub fn scan_block<S: Scratch>(&self, db: &BlockDatabase, scratch: &S) -> Result<Vec<PartMatch>, Error> {
+        let mut collector = Collector::new(&self.parts);
+
+        for (part, range) in self.parts.iter().enumerate() {
+            collector.part = part;
+            collector.base = range.start as u64;
+
+            try!(db.scan(&self.message[range.clone()],
+                         0,
+                         scratch,
+                         Some(Collector::on_part_match),
+                         Some(&collector)));
+        }
+
+        Ok(collector.matches.into_inner())
+    }
+
+    /// Scan each part in a separate stream.
+    pub fn scan_streaming<S: Scratch>(&self, db: &StreamingDatabase, scratch: &S) -> Result<Vec<PartMatch>, Error> {
+        let mut collector = Collector::new(&self.parts);
+
+        for (part, range) in self.parts.iter().enumerate() {
+            collector.part = part;
+            collector.base = range.start as u64;
+
+            let stream = try!(db.open_stream(0));
+
+            try!(stream.scan(&self.message[range.clone()],
+                             0,
+                             scratch,
+                             Some(Collector::on_part_match),
+                             Some(&collector)));
+            try!(stream.close(scratch, Some(Collector::on_part_match), Some(&collector)));
+        }
+
+        Ok(collector.matches.into_inner())
+    }
+
+    /// Scan the parts as the entries of a vectored scan, so a match may span several parts,
+    /// the match is tagged with the part containing its end.
+    pub fn scan_vectored<S: Scratch>(&self, db: &VectoredDatabase, scratch: &S) -> Result<Vec<PartMatch>, Error> {
+        let collector = Collector::new(&self.parts);
+        let entries = self.parts.iter().map(|range| &self.message[range.clone()]).collect::<Vec<_>>();
+
+        try!(db.scan(&entries, 0, scratch, Some(Collector::on_vectored_match), Some(&collector)));
+
+        Ok(collector.matches.into_inner())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    const MESSAGE: &'static [u8] = b"--b\r\nfoo test\r\n--b\r\nte\r\n--b\r\nst bar\r\n--b--";
+
+    fn parts() -> MessageParts<'static> {
+        MessageParts::new(MESSAGE, vec![5..13, 20..22, 29..35]).unwrap()
+    }
+
+    fn spans(matches: Vec<PartMatch>) -> Vec<(usize, u64, u64)> {
+        matches.into_iter().map(|m| (m.part, m.from, m.to)).collect()
+    }
+
+    #[test]
+    fn test_message_parts() {
+        let _ = env_logger::init();
+
+        let parts = parts();
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts.part(1), Some(&b"te"[..]));
+        assert!(MessageParts::new(MESSAGE, vec![40..50]).is_err());
+
+        let db: BlockDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        assert_eq!(spans(parts.scan_block(&db, &s).unwrap()), vec![(0, 9, 13)]);
+
+        let db: StreamingDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        assert_eq!(spans(parts.scan_streaming(&db, &s).unwrap()), vec![(0, 9, 13)]);
+
+        let db: VectoredDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        assert_eq!(spans(parts.scan_vectored(&db, &s).unwrap()),
+                   vec![(0, 9, 13), (2, 20, 31)]);
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::cell::RefCell;
+
+use api::*;
+use errors::Error;
+use common::BlockDatabase;
+use runtime::RawScratch;
+
+/// A match located by one of the databases of a `MultiScanner`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MultiMatch {
+    /// The index of the database which located the match.
+    pub db: usize,
+    /// The ID number of the matched pattern.
+    pub id: u32,
+    /// The offset of the start of the match.
+    pub from: u64,
+    /// The offset after the last byte of the match.
+    pub to: u64,
+    /// The flags of the match event.
+    pub flags: MatchFlags,
+}
+
+/// A scanner running several databases over the same buffer.
+///
+/// By default the match events are delivered database after database,
+/// the `ordered` option merges them into a single sequence ordered by end offset.
+pub struct MultiScanner<'a> {
+    dbs: Vec<&'a BlockDatabase>,
+    ordered: bool,
+}
+
+impl<'a> fmt::Debug for MultiScanner<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "MultiScanner{{dbs: {}, ordered: {}}}",
+               self.dbs.len(),
+               self.ordered)
+    }
+}
+
+struct Multiplexing<'a, H: 'a> {
+    db: usize,
+    handler: &'a RefCell<H>,
+}
+
+fn on_multi_event<H>(id: u32, from: u64, to: u64, flags: u32, multiplexing: &Multiplexing<H>) -> u32
+    where H: FnMut(MultiMatch) -> u32
+{
+    (&mut *multiplexing.handler.borrow_mut())(MultiMatch {
+        db: multiplexing.db,
+        id: id,
+        from: from,
+        to: to,
+        flags: MatchFlags::from(flags),
+    })
+}
+
+impl<'a> MultiScanner<'a> {
+    pub fn new(dbs: Vec<&'a BlockDatabase>) -> MultiScanner<'a> {
+        MultiScanner {
+            dbs: dbs,
+            ordered: false,
+        }
+    }
+
+    /// Merge the match events of the databases into a single sequence ordered by end offset,
+    /// the events with the same end offset are delivered in the order of the databases.
+    ///
+    /// The events are buffered until all the databases were scanned,
+    /// so the handler can only cease the delivery, not the scans.
+    pub fn ordered(mut self, ordered: bool) -> MultiScanner<'a> {
+        self.ordered = ordered;
+        self
+    }
+
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    /// The number of databases.
+    pub fn len(&self) -> usize {
+        self.dbs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dbs.is_empty()
+    }
+
+    /// Allocate a scratch space large enough for all the databases,
+    /// returns `Error::Invalid` if there is no database.
+    pub fn alloc(&self) -> Result<RawScratch, Error> {
+        let mut dbs = self.dbs.iter();
+        let mut scratch = match dbs.next() {
+            Some(db) => try!(db.alloc()),
+            None => return Err(Error::Invalid),
+        };
+
+        for db in dbs {
+            try!(db.realloc(&mut scratch));
+        }
+
+        Ok(scratch)
+    }
+
+    /// Scan the data with all the databases, returns `Error::ScanTerminated`
+    /// if the handler returned non-zero to request that scanning cease.
+    pub fn scan<T, S, H>(&self, data: T, scratch: &S, handler: H) -> Result<(), Error>
+        where T: Scannable,
+              S: Scratch,
+              H: FnMut(MultiMatch) -> u32
+    {
+        if self.ordered {
+            self.scan_ordered(data.as_bytes(), scratch, handler)
+        } else {
+            let handler = RefCell::new(handler);
+
+            for db in 0..self.dbs.len() {
+                let multiplexing = Multiplexing {
+                    db: db,
+                    handler: &handler,
+                };
+
+                try!(self.dbs[db].scan(data.as_bytes(),
+                                       0,
+                                       scratch,
+                                       Some(on_multi_event::<H>),
+                                       Some(&multiplexing)));
+            }
+
+            Ok(())
+        }
+    }
+
+    fn scan_ordered<S, H>(&self, data: &[u8], scratch: &S, mut handler: H) -> Result<(), Error>
+        where S: Scratch,
+              H: FnMut(MultiMatch) -> u32
+    {
+        let matches = RefCell::new(Vec::new());
+
+        {
+            let mut collect = |m| {
+                matches.borrow_mut().push(m);
+
+                0
+            };
+            let collector = RefCell::new(&mut collect);
+
+            for db in 0..self.dbs.len() {
+                let multiplexing = Multiplexing {
+                    db: db,
+                    handler: &collector,
+                };
+
+                try!(self.dbs[db].scan(data, 0, scratch, Some(on_multi_event), Some(&multiplexing)));
+            }
+        }
+
+        let mut matches = matches.into_inner();
+
+        // the events of each database are already ordered, the stable sort keeps the order of the databases
+        matches.sort_by_key(|m| m.to);
+
+        trace!("merged {} matches of {} databases", matches.len(), self.dbs.len());
+
+        for m in matches {
+            if handler(m) != 0 {
+                return Err(Error::ScanTerminated);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_multi_scanner() {
+        let _ = env_logger::init();
+
+        let db1: BlockDatabase = patterns!(["foo", "baz"]).build().unwrap();
+        let db2: BlockDatabase = patterns!(["bar"]).build().unwrap();
+
+        let scanner = MultiScanner::new(vec![&db1, &db2]);
+        let s = scanner.alloc().unwrap();
+
+        let mut matches = Vec::new();
+
+        scanner.scan("foo bar baz", &s, |m| {
+                matches.push((m.db, m.id, m.to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(0, 1, 3), (0, 2, 11), (1, 1, 7)]);
+
+        let scanner = scanner.ordered(true);
+        let mut matches = Vec::new();
+
+        scanner.scan("foo bar baz", &s, |m| {
+                matches.push((m.db, m.id, m.to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(0, 1, 3), (1, 1, 7), (0, 2, 11)]);
+
+        let mut delivered = 0;
+
+        assert_eq!(scanner.scan("foo bar baz", &s, |_| {
+                delivered += 1;
+
+                1
+            }),
+                   Err(Error::ScanTerminated));
+        assert_eq!(delivered, 1);
+
+        assert!(MultiScanner::new(vec![]).alloc().is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use std::cell::RefCell;
+
+use api::*;
+use errors::Error;
+use common::BlockDatabase;
+use offsets::OffsetMap;
+
+/// A transform of the input applied before scanning, for rules written against normalized text.
+pub trait Preprocessor {
+    /// Transform the input, pushing the offset in the input of each byte of the output.
+    fn process(&self, input: &[u8], output: &mut Vec<u8>, offsets: &mut Vec<usize>);
+}
+
+/// Lowercase the ASCII letters.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Lowercase;
+
+impl Preprocessor for Lowercase {
+    fn process(&self, input: &[u8], output: &mut Vec<u8>, offsets: &mut Vec<usize>) {
+        output.extend(input.iter().map(|c| c.to_ascii_lowercase()));
+        offsets.extend(0..input.len());
+    }
+}
+
+/// Collapse each run of ASCII whitespaces into a single space.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CollapseWhitespace;
+
+impl Preprocessor for CollapseWhitespace {
+    fn process(&self, input: &[u8], output: &mut Vec<u8>, offsets: &mut Vec<usize>) {
+        let mut space = false;
+
+        for (i, &c) in input.iter().enumerate() {
+            if c.is_ascii_whitespace() {
+                if !space {
+                    output.push(b' ');
+                    offsets.push(i);
+                }
+
+                space = true;
+            } else {
+                output.push(c);
+                offsets.push(i);
+
+                space = false;
+            }
+        }
+    }
+}
+
+/// The normalized data, with the offsets of its bytes in the original data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalized {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+    len: usize,
+}
+
+impl Normalized {
+    /// The normalized data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl OffsetMap for Normalized {
+    fn start(&self, offset: u64) -> u64 {
+        self.offsets.get(offset as usize).map_or(self.len, |&o| o) as u64
+    }
+
+    fn end(&self, offset: u64) -> u64 {
+        if offset == 0 {
+            self.start(0)
+        } else {
+            self.offsets.get(offset as usize - 1).map_or(self.len, |&o| o + 1) as u64
+        }
+    }
+}
+
+/// A chain of preprocessors, applied in order.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<Preprocessor + Send + Sync>>,
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pipeline{{stages: {}}}", self.stages.len())
+    }
+}
+
+struct Translating<'a, H> {
+    map: &'a Normalized,
+    handler: RefCell<H>,
+}
+
+fn on_translated_event<H>(id: u32, from: u64, to: u64, flags: u32, translating: &Translating<H>) -> u32
+    where H: FnMut(u32, u64, u64, u32) -> u32
+{
+    (&mut *translating.handler.borrow_mut())(id, translating.map.start(from), translating.map.end(to), flags)
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Append a preprocessor.
+    pub fn then<P: Preprocessor + Send + Sync + 'static>(mut self, preprocessor: P) -> Pipeline {
+        self.stages.push(Box::new(preprocessor));
+        self
+    }
+
+    /// Apply the preprocessors to the data.
+    pub fn normalize(&self, data: &[u8]) -> Normalized {
+        let mut normalized = Normalized {
+            data: data.to_vec(),
+            offsets: (0..data.len()).collect(),
+            len: data.len(),
+        };
+
+        for stage in &self.stages {
+            let mut data = Vec::with_capacity(normalized.data.len());
+            let mut offsets = Vec::with_capacity(normalized.data.len());
+
+            stage.process(&normalized.data, &mut data, &mut offsets);
+
+            normalized.offsets = offsets.into_iter().map(|o| normalized.offsets[o]).collect();
+            normalized.data = data;
+        }
+
+        normalized
+    }
+
+    /// Scan the normalized data, passing the matches with the offsets in the original data to the handler.
+    pub fn scan<T, S, H>(&self, db: &BlockDatabase, data: T, scratch: &S, handler: H) -> Result<(), Error>
+        where T: Scannable,
+              S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let normalized = self.normalize(data.as_bytes());
+        let translating = Translating {
+            map: &normalized,
+            handler: RefCell::new(handler),
+        };
+
+        try!(db.scan(normalized.data(), 0, scratch, Some(on_translated_event::<H>), Some(&translating)));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_normalize() {
+        let pipeline = Pipeline::new().then(Lowercase).then(CollapseWhitespace);
+
+        let normalized = pipeline.normalize(b"Select \t\r\n * FROM  users");
+
+        assert_eq!(normalized.data(), b"select * from users");
+        assert_eq!((normalized.start(7), normalized.end(8)), (11, 12));
+        assert_eq!((normalized.start(9), normalized.end(19)), (13, 24));
+        assert_eq!(normalized.end(7), 7);
+    }
+
+    #[test]
+    fn test_pipeline_scan() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"select \\* from", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let pipeline = Pipeline::new().then(Lowercase).then(CollapseWhitespace);
+        let mut matches = Vec::new();
+
+        pipeline.scan(&db, "x; SELECT\n  * From t", &s, |_, from, to, _| {
+                matches.push((from, to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(3, 18)]);
+    }
+}
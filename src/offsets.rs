@@ -0,0 +1,258 @@
+use std::fmt;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use api::*;
+use errors::Error;
+use runtime::{Feed, RawStream};
+
+/// A mapping from the offsets of the scanned bytes to the offsets of the original stream,
+/// when the scanned bytes are a transform of the original, like a decompressed body.
+pub trait OffsetMap {
+    /// Translate the start offset of a match.
+    fn start(&self, offset: u64) -> u64;
+
+    /// Translate the end offset of a match.
+    fn end(&self, offset: u64) -> u64 {
+        self.start(offset)
+    }
+}
+
+impl<F: Fn(u64) -> u64> OffsetMap for F {
+    fn start(&self, offset: u64) -> u64 {
+        self(offset)
+    }
+}
+
+/// An offset map built from the segments of the transform, each scanned segment
+/// being produced from a segment of the original stream.
+///
+/// A match is mapped to the original segments covering it, the start offset to the start of
+/// the segment containing the first byte, and the end offset to the end of the segment
+/// containing the last byte.
+#[derive(Debug, Default, Clone)]
+pub struct Segments {
+    // (scanned end, original end) of each segment
+    ends: VecDeque<(u64, u64)>,
+    // (scanned end, original end) of the last forgotten segment
+    base: (u64, u64),
+    scanned: u64,
+    original: u64,
+}
+
+impl Segments {
+    pub fn new() -> Segments {
+        Segments::default()
+    }
+
+    /// Record that `scanned_len` bytes were produced from the next `original_len` bytes of the original stream.
+    pub fn push(&mut self, scanned_len: usize, original_len: usize) {
+        self.scanned += scanned_len as u64;
+        self.original += original_len as u64;
+
+        self.ends.push_back((self.scanned, self.original));
+    }
+
+    /// Forget the segments ending before the scanned offset, which no later match can start in.
+    pub fn forget_before(&mut self, offset: u64) {
+        while self.ends.len() > 1 && self.ends[0].0 <= offset {
+            self.base = self.ends.pop_front().unwrap();
+        }
+    }
+
+    /// The number of bytes scanned and read from the original stream.
+    pub fn totals(&self) -> (u64, u64) {
+        (self.scanned, self.original)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scanned == 0 && self.original == 0
+    }
+
+    /// The index of the first segment ending after the scanned offset.
+    fn segment(&self, offset: u64) -> usize {
+        match self.ends.binary_search_by_key(&offset, |&(scanned, _)| scanned) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+}
+
+impl OffsetMap for Segments {
+    fn start(&self, offset: u64) -> u64 {
+        match self.segment(offset) {
+            0 => self.base.1,
+            i => self.ends[i - 1].1,
+        }
+    }
+
+    fn end(&self, offset: u64) -> u64 {
+        if offset == 0 {
+            return self.start(0);
+        }
+
+        self.ends.get(self.segment(offset - 1)).map_or(self.original, |&(_, original)| original)
+    }
+}
+
+/// A stream whose matches are reported in the coordinates of the original stream.
+pub struct MappedStream<M> {
+    stream: RawStream,
+    map: M,
+}
+
+impl<M: fmt::Debug> fmt::Debug for MappedStream<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MappedStream{{stream: {:?}, map: {:?}}}", self.stream, self.map)
+    }
+}
+
+fn on_close_event(id: u32, from: u64, to: u64, flags: u32, matches: &RefCell<Vec<Match>>) -> u32 {
+    matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+    0
+}
+
+impl<M: OffsetMap> MappedStream<M> {
+    pub fn new(stream: RawStream, map: M) -> MappedStream<M> {
+        MappedStream {
+            stream: stream,
+            map: map,
+        }
+    }
+
+    /// The underlying stream.
+    pub fn stream(&self) -> &RawStream {
+        &self.stream
+    }
+
+    pub fn map(&self) -> &M {
+        &self.map
+    }
+
+    /// The offset map, to be updated as the transform produces bytes.
+    pub fn map_mut(&mut self) -> &mut M {
+        &mut self.map
+    }
+
+    /// Write transformed data to the stream, passing the translated matches to the handler.
+    pub fn scan<T, S, H>(&self, data: T, scratch: &S, mut handler: H) -> Result<Feed, Error>
+        where T: Scannable,
+              S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let map = &self.map;
+
+        self.stream.feed(Some(data),
+                         scratch,
+                         |id, from, to, flags| handler(id, map.start(from), map.end(to), flags))
+    }
+
+    /// Close the stream, passing the translated matches at the end of the data to the handler.
+    pub fn close<S, H>(self, scratch: &S, mut handler: H) -> Result<(), Error>
+        where S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let matches = RefCell::new(Vec::new());
+
+        try!(self.stream.close(scratch, Some(on_close_event), Some(&matches)));
+
+        for m in matches.into_inner() {
+            if handler(m.id, self.map.start(m.from), self.map.end(m.to), m.flags.bits()) != 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unwrap the underlying stream and the offset map.
+    pub fn into_inner(self) -> (RawStream, M) {
+        (self.stream, self.map)
+    }
+}
+
+impl MappedStream<Segments> {
+    /// Record a segment produced from `original_len` bytes of the original stream and write it to the stream.
+    pub fn write<T, S, H>(&mut self, data: T, original_len: usize, scratch: &S, handler: H) -> Result<Feed, Error>
+        where T: Scannable,
+              S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        self.map.push(data.as_bytes().len(), original_len);
+
+        self.scan(data, scratch, handler)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_segments() {
+        let mut segments = Segments::new();
+
+        assert!(segments.is_empty());
+
+        segments.push(3, 4);
+        segments.push(6, 8);
+        segments.push(3, 4);
+
+        assert_eq!(segments.totals(), (12, 16));
+        assert_eq!((segments.start(0), segments.end(3)), (0, 4));
+        assert_eq!((segments.start(2), segments.end(4)), (0, 12));
+        assert_eq!((segments.start(3), segments.end(9)), (4, 12));
+        assert_eq!((segments.start(10), segments.end(12)), (12, 16));
+        assert_eq!(segments.start(12), 16);
+
+        segments.forget_before(9);
+
+        assert_eq!((segments.start(10), segments.end(12)), (12, 16));
+    }
+
+    #[test]
+    fn test_mapped_stream() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut st = MappedStream::new(db.open_stream(0).unwrap(), Segments::new());
+        let mut matches = Vec::new();
+
+        // base64 decoded "foo te" and "st"
+        st.write("foo te", 8, &s, |_, from, to, _| {
+                matches.push((from, to));
+
+                0
+            })
+            .unwrap();
+        st.write("st", 4, &s, |_, from, to, _| {
+                matches.push((from, to));
+
+                0
+            })
+            .unwrap();
+
+        st.close(&s, |_, _, _, _| 0).unwrap();
+
+        assert_eq!(matches, vec![(0, 12)]);
+
+        let st = MappedStream::new(db.open_stream(0).unwrap(), |offset| offset + 100);
+        let mut matches = Vec::new();
+
+        st.scan("a test", &s, |_, from, to, _| {
+                matches.push((from, to));
+
+                0
+            })
+            .unwrap();
+
+        st.close(&s, |_, _, _, _| 0).unwrap();
+
+        assert_eq!(matches, vec![(102, 106)]);
+    }
+}
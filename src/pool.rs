@@ -0,0 +1,321 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::ops::{Deref, DerefMut};
+
+use api::*;
+use errors::Error;
+use runtime::RawScratch;
+
+/// What a pooled scan does when Hyperscan reports its scratch is in use by another call.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InUsePolicy {
+    /// Fail the scan with `Error::ScratchInUse`.
+    Fail,
+    /// Retry the scan once with a fresh clone of the prototype.
+    Retry,
+}
+
+/// A pool of scratch spaces cloned from a prototype, for scanning from many threads.
+///
+/// A scratch is taken from the pool for the duration of a scan, and given back
+/// when the guard is dropped, so the number of scratch spaces follows the peak concurrency.
+pub struct ScratchPool {
+    prototype: Mutex<RawScratch>,
+    // the free scratch spaces with the generation of the prototype they were grown for
+    free: Mutex<Vec<(u64, RawScratch)>>,
+    // bumped by each `realloc`
+    generation: AtomicU64,
+    in_use: InUsePolicy,
+    retries: AtomicU64,
+}
+
+impl fmt::Debug for ScratchPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "ScratchPool{{prototype: {:?}, free: {}, in_use: {:?}, retries: {}}}",
+               *self.prototype.lock().unwrap(),
+               self.free.lock().unwrap().len(),
+               self.in_use,
+               self.retries())
+    }
+}
+
+impl ScratchPool {
+    /// Construct a pool for the database.
+    pub fn new<A: ScratchAllocator<RawScratch>>(db: &A) -> Result<ScratchPool, Error> {
+        Ok(ScratchPool::with_prototype(try!(db.alloc())))
+    }
+
+    /// Construct a pool cloning the scratch.
+    pub fn with_prototype(prototype: RawScratch) -> ScratchPool {
+        ScratchPool {
+            prototype: Mutex::new(prototype),
+            free: Mutex::new(Vec::new()),
+            generation: AtomicU64::new(0),
+            in_use: InUsePolicy::Fail,
+            retries: AtomicU64::new(0),
+        }
+    }
+
+    /// Set what `with_scratch` does when the scratch is in use, `InUsePolicy::Fail` by default.
+    pub fn with_in_use_policy(mut self, policy: InUsePolicy) -> Self {
+        self.in_use = policy;
+        self
+    }
+
+    /// Grow the scratch spaces for another database, so the pool can be shared by several databases.
+    ///
+    /// The scratch spaces taken from the pool while it grows are not grown, they are dropped when given back,
+    /// and a stale free scratch is replaced by a clone of the prototype when it is taken.
+    pub fn realloc<T: Database>(&self, db: &T) -> Result<&Self, Error> {
+        let generation = {
+            let mut prototype = self.prototype.lock().unwrap();
+
+            // bumped first, so the scratch spaces taken meanwhile are cloned from the grown prototype
+            let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+            try!(prototype.realloc(db));
+
+            generation
+        };
+
+        for &mut (ref mut grown, ref mut scratch) in self.free.lock().unwrap().iter_mut() {
+            try!(scratch.realloc(db));
+
+            *grown = generation;
+        }
+
+        Ok(self)
+    }
+
+    /// Take a scratch from the pool, cloning the prototype if none is free or the free one is stale.
+    pub fn get<'a>(&'a self) -> PooledScratch<'a> {
+        let free = self.free.lock().unwrap().pop();
+
+        match free {
+            Some((generation, scratch)) if generation == self.generation.load(Ordering::SeqCst) => {
+                PooledScratch {
+                    pool: self,
+                    scratch: Some(scratch),
+                    generation: generation,
+                }
+            }
+            _ => self.clone_prototype(),
+        }
+    }
+
+    fn clone_prototype<'a>(&'a self) -> PooledScratch<'a> {
+        let prototype = self.prototype.lock().unwrap();
+
+        PooledScratch {
+            pool: self,
+            scratch: Some(prototype.clone()),
+            generation: self.generation.load(Ordering::SeqCst),
+        }
+    }
+
+    /// The number of free scratch spaces in the pool.
+    pub fn free(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    /// The number of scans retried because their scratch was in use.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Run a scan with a scratch taken from the pool.
+    ///
+    /// With `InUsePolicy::Retry`, a scan failing with `Error::ScratchInUse` is retried once with a fresh clone
+    /// of the prototype, both scratch spaces are given back to the pool afterwards.
+    pub fn with_scratch<F, R>(&self, mut f: F) -> Result<R, Error>
+        where F: FnMut(&RawScratch) -> Result<R, Error>
+    {
+        let scratch = self.get();
+
+        match f(&scratch) {
+            Err(Error::ScratchInUse) if self.in_use == InUsePolicy::Retry => {
+                self.retries.fetch_add(1, Ordering::Relaxed);
+
+                warn!("scratch {:?} is in use, retry with a fresh scratch", *scratch);
+
+                let fresh = {
+                    let prototype = self.prototype.lock().unwrap();
+
+                    PooledScratch {
+                        pool: self,
+                        scratch: Some(try!(prototype.try_clone())),
+                        generation: self.generation.load(Ordering::SeqCst),
+                    }
+                };
+
+                f(&fresh)
+            }
+            result => result,
+        }
+    }
+}
+
+/// A scratch taken from a `ScratchPool`, which is given back when dropped.
+pub struct PooledScratch<'a> {
+    pool: &'a ScratchPool,
+    scratch: Option<RawScratch>,
+    generation: u64,
+}
+
+impl<'a> fmt::Debug for PooledScratch<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PooledScratch{{scratch: {:?}}}", self.scratch)
+    }
+}
+
+impl<'a> Deref for PooledScratch<'a> {
+    type Target = RawScratch;
+
+    #[inline]
+    fn deref(&self) -> &RawScratch {
+        self.scratch.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for PooledScratch<'a> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut RawScratch {
+        self.scratch.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for PooledScratch<'a> {
+    fn drop(&mut self) {
+        if let Some(scratch) = self.scratch.take() {
+            if self.generation == self.pool.generation.load(Ordering::SeqCst) {
+                self.pool.free.lock().unwrap().push((self.generation, scratch));
+            } else {
+                debug!("drop scratch {:?} taken before the pool grew", scratch);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scratch_pool() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let pool = ScratchPool::new(&db).unwrap();
+
+        assert_eq!(pool.free(), 0);
+
+        {
+            let s1 = pool.get();
+            let s2 = pool.get();
+
+            assert!(**s1 != **s2);
+
+            db.scan("some test data", 0, &*s1, None, None::<&()>).unwrap();
+        }
+
+        assert_eq!(pool.free(), 2);
+
+        let db2: StreamingDatabase = pattern!{"foo.*bar"}.build().unwrap();
+
+        pool.realloc(&db2).unwrap();
+
+        let db = Arc::new(db);
+        let pool = Arc::new(pool);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                let pool = pool.clone();
+
+                thread::spawn(move || {
+                    db.scan("some test data", 0, &*pool.get(), None, None::<&()>).unwrap();
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert!(pool.free() >= 2);
+    }
+
+    #[test]
+    fn test_scratch_pool_realloc_taken() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let pool = ScratchPool::new(&db).unwrap();
+
+        drop(pool.get());
+
+        assert_eq!(pool.free(), 1);
+
+        let db2: BlockDatabase = patterns!(["foo.*bar", "\\d{4}-\\d{2}"]).build().unwrap();
+
+        {
+            let taken = pool.get();
+
+            pool.realloc(&db2).unwrap();
+
+            db.scan("some test data", 0, &*taken, None, None::<&()>).unwrap();
+        }
+
+        assert_eq!(pool.free(), 0);
+
+        pool.with_scratch(|s| db2.scan("foo bar 2019-01", 0, s, None, None::<&()>).map(|_| ())).unwrap();
+
+        assert_eq!(pool.free(), 1);
+    }
+
+    #[test]
+    fn test_scratch_in_use() {
+        let _ = env_logger::init();
+
+        assert_eq!(Error::from(HS_SCRATCH_IN_USE), Error::ScratchInUse);
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let pool = ScratchPool::new(&db).unwrap();
+
+        let mut calls = 0;
+
+        assert_eq!(pool.with_scratch(|_| {
+                           calls += 1;
+                           Err::<(), _>(Error::ScratchInUse)
+                       }),
+                   Err(Error::ScratchInUse));
+        assert_eq!(calls, 1);
+        assert_eq!(pool.retries(), 0);
+
+        let pool = pool.with_in_use_policy(InUsePolicy::Retry);
+        let mut scratches = Vec::new();
+
+        let result = pool.with_scratch(|s| {
+            scratches.push(**s as usize);
+
+            if scratches.len() == 1 {
+                Err(Error::ScratchInUse)
+            } else {
+                db.scan("some test data", 0, s, None, None::<&()>).map(|_| ())
+            }
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(scratches.len(), 2);
+        assert!(scratches[0] != scratches[1]);
+        assert_eq!(pool.retries(), 1);
+        assert_eq!(pool.free(), 2);
+    }
+}
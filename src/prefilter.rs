@@ -0,0 +1,489 @@
+use std::fmt;
+use std::cmp;
+use std::cell::RefCell;
+use std::collections::HashSet;
+#[cfg(feature = "regex")]
+use std::collections::HashMap;
+
+#[cfg(feature = "regex")]
+use regex::bytes::Regex;
+
+use api::*;
+use constants::*;
+use errors::Error;
+use common::BlockDatabase;
+use compile::{Pattern, compile_patterns};
+use runtime::RawStream;
+
+/// A stream of a prefilter database (`HS_FLAG_PREFILTER`), which keeps a bounded history
+/// of the recent bytes, so the candidate matches can be confirmed by a second-stage regex.
+///
+/// Hyperscan reports a superset of the matches in prefilter mode, and the scanned data
+/// is gone by the time a streaming match is reported. The confirmer is called with the
+/// candidate pattern ID and the window of at most `window` bytes ending at the match,
+/// only the confirmed matches are passed to the handler.
+///
+/// A match longer than the window is confirmed against a truncated window,
+/// so the window should cover the longest match of interest.
+pub struct PrefilterStream<F> {
+    stream: RawStream,
+    window: usize,
+    history: Vec<u8>,
+    start: u64,
+    confirm: F,
+    terminated: bool,
+}
+
+impl<F> fmt::Debug for PrefilterStream<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "PrefilterStream{{stream: {:?}, window: {}, history: {}, start: {}}}",
+               self.stream,
+               self.window,
+               self.history.len(),
+               self.start)
+    }
+}
+
+fn on_candidate(id: u32, from: u64, to: u64, flags: u32, candidates: &RefCell<Vec<Match>>) -> u32 {
+    candidates.borrow_mut().push(Match::new(id, from, to, flags));
+
+    0
+}
+
+impl<F> PrefilterStream<F>
+    where F: FnMut(u32, &[u8]) -> bool
+{
+    /// Wrap the stream, keeping `window` bytes of history for the confirmer.
+    ///
+    /// The stream may already have been fed, the history starts at its current offset,
+    /// taken from its totals, and the candidates are confirmed against the data written after it.
+    pub fn new(stream: RawStream, window: usize, confirm: F) -> PrefilterStream<F> {
+        let start = stream.scan_stats().bytes;
+
+        PrefilterStream {
+            stream: stream,
+            window: window,
+            history: Vec::with_capacity(window),
+            start: start,
+            confirm: confirm,
+            terminated: false,
+        }
+    }
+
+    /// The size of the history window in bytes.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// The recent bytes of the stream, at most `window` bytes.
+    pub fn history(&self) -> &[u8] {
+        &self.history
+    }
+
+    /// Write data to the stream, passing the confirmed matches to the handler.
+    ///
+    /// Once the handler returns non-zero, no more data is scanned and `Error::ScanTerminated` is returned.
+    pub fn scan<T, S, H>(&mut self, data: T, scratch: &S, mut handler: H) -> Result<&Self, Error>
+        where T: Scannable,
+              S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        if self.terminated {
+            return Err(Error::ScanTerminated);
+        }
+
+        let bytes = data.as_bytes();
+        let mut candidates = Vec::new();
+
+        try!(self.stream.feed(Some(bytes), scratch, |id, from, to, flags| {
+            candidates.push(Match::new(id, from, to, flags));
+
+            0
+        }));
+
+        self.history.extend_from_slice(bytes);
+
+        let result = self.confirm_all(&candidates, &mut handler);
+
+        self.trim();
+
+        try!(result);
+
+        Ok(self)
+    }
+
+    /// Close the stream, passing the confirmed matches at the end of the data to the handler.
+    pub fn close<S, H>(mut self, scratch: &S, mut handler: H) -> Result<(), Error>
+        where S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let candidates = RefCell::new(Vec::new());
+
+        try!(self.stream.close(scratch, Some(on_candidate), Some(&candidates)));
+
+        if !self.terminated {
+            match self.confirm_all(&candidates.into_inner(), &mut handler) {
+                Ok(_) | Err(Error::ScanTerminated) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unwrap the underlying stream.
+    pub fn into_inner(self) -> RawStream {
+        self.stream
+    }
+
+    fn confirm_all<H>(&mut self, candidates: &[Match], handler: &mut H) -> Result<(), Error>
+        where H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        for m in candidates {
+            // the history may not cover a candidate spanning the data written before the stream was wrapped
+            let end = cmp::min(m.to.saturating_sub(self.start) as usize, self.history.len());
+            let begin = end.saturating_sub(self.window);
+
+            if (self.confirm)(m.id, &self.history[begin..end]) {
+                if handler(m.id, m.from, m.to, m.flags.bits()) != 0 {
+                    self.terminated = true;
+
+                    return Err(Error::ScanTerminated);
+                }
+            } else {
+                trace!("candidate match of pattern #{} at {} rejected", m.id, m.to);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn trim(&mut self) {
+        let excess = self.history.len() - cmp::min(self.history.len(), self.window);
+
+        if excess > 0 {
+            self.history.drain(..excess);
+            self.start += excess as u64;
+        }
+    }
+}
+
+/// A second-stage verifier of the candidate matches of the prefiltered patterns.
+pub trait Verifier {
+    /// Whether the pattern matches the data ending at the offset `to`.
+    fn verify(&self, id: u32, data: &[u8], to: usize) -> bool;
+}
+
+impl<F: Fn(u32, &[u8], usize) -> bool> Verifier for F {
+    fn verify(&self, id: u32, data: &[u8], to: usize) -> bool {
+        self(id, data, to)
+    }
+}
+
+/// A verifier confirming the candidate matches with the `regex` crate.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone, Default)]
+pub struct RegexVerifier {
+    regexes: HashMap<u32, Regex>,
+}
+
+#[cfg(feature = "regex")]
+impl RegexVerifier {
+    pub fn new() -> RegexVerifier {
+        RegexVerifier::default()
+    }
+
+    /// Add a pattern, translating its flags to the `regex` syntax.
+    ///
+    /// Returns `Error::CompilerError` if the `regex` crate doesn't support the pattern either.
+    pub fn add(&mut self, pattern: &Pattern) -> Result<(), Error> {
+        let mut flags = String::new();
+
+        if pattern.flags.is_set(HS_FLAG_CASELESS) {
+            flags.push('i');
+        }
+        if pattern.flags.is_set(HS_FLAG_MULTILINE) {
+            flags.push('m');
+        }
+        if pattern.flags.is_set(HS_FLAG_DOTALL) {
+            flags.push('s');
+        }
+        if !pattern.flags.is_set(HS_FLAG_UTF8) {
+            flags.push_str("-u");
+        }
+
+        let flags = if flags.is_empty() {
+            flags
+        } else {
+            format!("(?{})", flags)
+        };
+
+        let re = try!(Regex::new(&format!("{}(?:{})\\z", flags, pattern.expression)).map_err(|err| {
+            Error::CompilerError(format!("pattern `{}` can't be verified, {}", pattern, err))
+        }));
+
+        self.regexes.insert(pattern.id as u32, re);
+
+        Ok(())
+    }
+}
+
+/// The patterns without a regex are not verified.
+#[cfg(feature = "regex")]
+impl Verifier for RegexVerifier {
+    fn verify(&self, id: u32, data: &[u8], to: usize) -> bool {
+        self.regexes.get(&id).map_or(true, |re| re.is_match(&data[..to]))
+    }
+}
+
+/// A block database whose patterns the compiler doesn't support are compiled in prefilter mode (`HS_FLAG_PREFILTER`),
+/// with their candidate matches confirmed by a second-stage verifier before they are reported.
+///
+/// The patterns with the `HS_FLAG_PREFILTER` flag are verified too.
+/// The patterns are checked with their expression info, a pattern the compiler only rejects when building
+/// the database, like a too large pattern, fails the compilation.
+pub struct Prefiltered<V> {
+    db: BlockDatabase,
+    prefiltered: HashSet<u32>,
+    verifier: V,
+}
+
+impl<V> fmt::Debug for Prefiltered<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Prefiltered{{db: {:?}, prefiltered: {:?}}}",
+               self.db,
+               self.prefiltered)
+    }
+}
+
+/// Check the patterns, falling back to the prefilter mode for the patterns the compiler doesn't support.
+fn prefilter_patterns(patterns: &[Pattern]) -> Result<(Vec<Pattern>, Vec<Pattern>), Error> {
+    let mut compiled = Vec::with_capacity(patterns.len());
+    let mut prefiltered = Vec::new();
+
+    for pattern in patterns {
+        if pattern.flags.is_set(HS_FLAG_PREFILTER) {
+            prefiltered.push(pattern.clone());
+        } else if !pattern.flags.is_set(HS_FLAG_COMBINATION) {
+            if let Err(err) = pattern.info() {
+                let mut fallback = pattern.clone();
+
+                fallback.flags.set(HS_FLAG_PREFILTER);
+
+                match (err, fallback.info()) {
+                    (Error::CompilerError(ref reason), Ok(_)) => {
+                        debug!("pattern `{}` compiled in prefilter mode, {}", pattern, reason);
+
+                        prefiltered.push(pattern.clone());
+                        compiled.push(fallback);
+
+                        continue;
+                    }
+                    (err, _) => return Err(err),
+                }
+            }
+        }
+
+        compiled.push(pattern.clone());
+    }
+
+    Ok((compiled, prefiltered))
+}
+
+impl<V: Verifier> Prefiltered<V> {
+    /// Compile the patterns for the current host, the candidate matches of the prefiltered patterns
+    /// are confirmed by the verifier.
+    pub fn compile(patterns: &[Pattern], verifier: V) -> Result<Prefiltered<V>, Error> {
+        let (compiled, prefiltered) = try!(prefilter_patterns(patterns));
+
+        Ok(Prefiltered {
+            db: try!(compile_patterns(&compiled, 0, &PlatformInfo::null())),
+            prefiltered: prefiltered.iter().map(|pattern| pattern.id as u32).collect(),
+            verifier: verifier,
+        })
+    }
+
+    pub fn database(&self) -> &BlockDatabase {
+        &self.db
+    }
+
+    pub fn verifier(&self) -> &V {
+        &self.verifier
+    }
+
+    /// Whether the matches of the pattern are verified.
+    pub fn is_prefiltered(&self, id: u32) -> bool {
+        self.prefiltered.contains(&id)
+    }
+
+    /// Scan a block, passing the native and the confirmed matches to the handler.
+    ///
+    /// Returns `Error::ScanTerminated` if the handler requested that scanning cease.
+    pub fn scan<T, S, H>(&self, data: T, scratch: &S, mut handler: H) -> Result<(), Error>
+        where T: Scannable,
+              S: Scratch,
+              H: FnMut(u32, u64, u64, u32) -> u32
+    {
+        let bytes = data.as_bytes();
+        let candidates = RefCell::new(Vec::new());
+
+        try!(self.db.scan(bytes, 0, scratch, Some(on_candidate), Some(&candidates)));
+
+        for m in candidates.into_inner() {
+            if self.is_prefiltered(m.id) && !self.verifier.verify(m.id, bytes, m.to as usize) {
+                trace!("candidate match of pattern #{} at {} rejected", m.id, m.to);
+
+                continue;
+            }
+
+            if handler(m.id, m.from, m.to, m.flags.bits()) != 0 {
+                return Err(Error::ScanTerminated);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Prefiltered<RegexVerifier> {
+    /// Compile the patterns for the current host, the candidate matches of the prefiltered patterns
+    /// are confirmed with the `regex` crate.
+    pub fn with_regex(patterns: &[Pattern]) -> Result<Prefiltered<RegexVerifier>, Error> {
+        let (compiled, prefiltered) = try!(prefilter_patterns(patterns));
+        let mut verifier = RegexVerifier::new();
+
+        for pattern in &prefiltered {
+            try!(verifier.add(pattern));
+        }
+
+        Ok(Prefiltered {
+            db: try!(compile_patterns(&compiled, 0, &PlatformInfo::null())),
+            prefiltered: prefiltered.iter().map(|pattern| pattern.id as u32).collect(),
+            verifier: verifier,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use regex::bytes::Regex;
+
+    use super::super::*;
+
+    #[test]
+    fn test_prefilter_stream() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = pattern!{"foo", flags => HS_FLAG_PREFILTER}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let re = Regex::new(r"bar.{0,3}foo$").unwrap();
+
+        let mut st = PrefilterStream::new(db.open_stream(0).unwrap(), 8, |_, window| re.is_match(window));
+        let mut matches = Vec::new();
+
+        for chunk in &["barxfo", "o", "zzzzzzfoo"] {
+            st.scan(*chunk, &s, |_, _, to, _| {
+                    matches.push(to);
+
+                    0
+                })
+                .unwrap();
+
+            assert!(st.history().len() <= st.window());
+        }
+
+        assert_eq!(st.history(), b"zzzzzfoo");
+
+        st.close(&s, |_, _, _, _| 0).unwrap();
+
+        assert_eq!(matches, vec![7]);
+
+        let mut st = PrefilterStream::new(db.open_stream(0).unwrap(), 8, |_, _| true);
+
+        assert_eq!(st.scan("foo", &s, |_, _, _, _| 1).err(), Some(Error::ScanTerminated));
+        assert_eq!(st.scan("foo", &s, |_, _, _, _| 0).err(), Some(Error::ScanTerminated));
+
+        st.close(&s, |_, _, _, _| 0).unwrap();
+
+        let stream = db.open_stream(0).unwrap();
+
+        stream.feed(vec!["some data, f"], &s, |_, _, _, _| 0).unwrap();
+
+        let mut windows = Vec::new();
+
+        {
+            let mut st = PrefilterStream::new(stream, 8, |_, window: &[u8]| {
+                windows.push(window.to_vec());
+
+                true
+            });
+
+            st.scan("oo bar foo", &s, |_, _, _, _| 0).unwrap();
+            st.close(&s, |_, _, _, _| 0).unwrap();
+        }
+
+        assert_eq!(windows, vec![b"oo".to_vec(), b" bar foo".to_vec()]);
+    }
+
+    #[test]
+    fn test_prefiltered() {
+        let _ = env_logger::init();
+
+        let patterns = vec![pattern!{"foo\\d+", flags => 0, id => 1},
+                            pattern!{"(a|b)\\1x", flags => 0, id => 2}];
+
+        let db = Prefiltered::compile(&patterns, |_, data: &[u8], to| {
+                let re = Regex::new(r"(aax|bbx)$").unwrap();
+
+                re.is_match(&data[..to])
+            })
+            .unwrap();
+
+        assert!(!db.is_prefiltered(1));
+        assert!(db.is_prefiltered(2));
+
+        let s = db.database().alloc().unwrap();
+        let mut matches = Vec::new();
+
+        db.scan("foo12 abx bbx", &s, |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(1, 4), (1, 5), (2, 13)]);
+        assert_eq!(db.scan("foo1", &s, |_, _, _, _| 1).err(),
+                   Some(Error::ScanTerminated));
+
+        assert!(Prefiltered::compile(&[pattern!{"(foo"}], |_, _: &[u8], _| true).is_err());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_prefiltered_regex() {
+        let _ = env_logger::init();
+
+        let patterns = vec![pattern!{"foo", flags => 0, id => 1},
+                            pattern!{"bar\\d{2}", flags => HS_FLAG_PREFILTER | HS_FLAG_CASELESS, id => 2}];
+
+        let db = Prefiltered::with_regex(&patterns).unwrap();
+        let s = db.database().alloc().unwrap();
+        let mut matches = Vec::new();
+
+        db.scan("FOO BAR1 Bar12", &s, |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(2, 14)]);
+    }
+}
@@ -0,0 +1,317 @@
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+
+use api::*;
+use constants::*;
+use errors::Error;
+use common::RawDatabase;
+use compile::{Patterns, compile_patterns};
+use cancel::CancellationToken;
+
+/// The stage of a compilation running on a worker thread.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompileStage {
+    /// The expressions are parsed and analysed one by one.
+    Checking,
+    /// The whole set is compiled, which can't be interrupted.
+    Compiling,
+    /// The database was compiled.
+    Done,
+}
+
+/// The progress of a compilation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CompileProgress {
+    pub stage: CompileStage,
+    /// The number of patterns processed.
+    pub processed: usize,
+    /// The number of patterns.
+    pub total: usize,
+}
+
+/// A compilation running on a worker thread, which can be waited with a timeout or cancelled.
+///
+/// Hyperscan compiles a set in a single call, so the patterns are first checked one by one,
+/// reporting the progress and checking the cancellation between them.
+/// A cancellation during the final compilation discards its result.
+///
+/// The last progress and whether the compilation finished can be polled, e.g. by a readiness probe.
+pub struct CompileTask<T: Type> {
+    token: CancellationToken,
+    progress: Arc<Mutex<Option<CompileProgress>>>,
+    finished: Arc<AtomicBool>,
+    result: Receiver<Result<RawDatabase<T>, Error>>,
+}
+
+impl<T: Type> fmt::Debug for CompileTask<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "CompileTask<{}>{{progress: {:?}, finished: {}, cancelled: {}}}",
+               T::name(),
+               *self.progress.lock().unwrap(),
+               self.finished.load(Ordering::Acquire),
+               self.token.is_cancelled())
+    }
+}
+
+fn compile<T: Type, F>(patterns: Patterns,
+                       platform: Option<Platform>,
+                       token: &CancellationToken,
+                       mut progress: F)
+                       -> Result<RawDatabase<T>, Error>
+    where F: FnMut(CompileProgress)
+{
+    let total = patterns.len();
+
+    for (i, pattern) in patterns.iter().enumerate() {
+        try!(token.check());
+
+        // the logical combinations are checked by the compiler against the patterns they refer to
+        if !pattern.flags.is_set(HS_FLAG_COMBINATION) {
+            try!(pattern.info());
+        }
+
+        progress(CompileProgress {
+            stage: CompileStage::Checking,
+            processed: i + 1,
+            total: total,
+        });
+    }
+
+    try!(token.check());
+
+    progress(CompileProgress {
+        stage: CompileStage::Compiling,
+        processed: total,
+        total: total,
+    });
+
+    let db = try!(compile_patterns(&patterns, 0, &platform_info(platform)));
+
+    try!(token.check());
+
+    progress(CompileProgress {
+        stage: CompileStage::Done,
+        processed: total,
+        total: total,
+    });
+
+    Ok(db)
+}
+
+impl<T: Type + 'static> CompileTask<T> {
+    /// Compile the patterns for the current host on a worker thread, reporting the progress to the callback.
+    pub fn spawn<F>(patterns: Patterns, progress: F) -> CompileTask<T>
+        where F: FnMut(CompileProgress) + Send + 'static
+    {
+        Self::with_token(CancellationToken::new(), patterns, progress)
+    }
+
+    /// Compile the patterns on a worker thread, cancelled with the token,
+    /// which can be shared by several tasks.
+    pub fn with_token<F>(token: CancellationToken, patterns: Patterns, progress: F) -> CompileTask<T>
+        where F: FnMut(CompileProgress) + Send + 'static
+    {
+        Self::with_platform(token, None, patterns, progress)
+    }
+
+    /// Compile the patterns for the target platform on a worker thread, cancelled with the token.
+    pub fn with_platform<F>(token: CancellationToken,
+                            platform: Option<Platform>,
+                            patterns: Patterns,
+                            mut progress: F)
+                            -> CompileTask<T>
+        where F: FnMut(CompileProgress) + Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+
+        let worker = token.clone();
+        let last = Arc::new(Mutex::new(None));
+        let reported = last.clone();
+        let finished = Arc::new(AtomicBool::new(false));
+        let done = finished.clone();
+
+        thread::spawn(move || {
+            let result = compile(patterns, platform, &worker, |p| {
+                *reported.lock().unwrap() = Some(p);
+
+                progress(p)
+            });
+
+            if let Err(ref err) = result {
+                debug!("background compilation failed, {}", err);
+            }
+
+            let _ = sender.send(result);
+
+            done.store(true, Ordering::Release);
+        });
+
+        CompileTask {
+            token: token,
+            progress: last,
+            finished: finished,
+            result: receiver,
+        }
+    }
+
+    /// The last progress reported by the compilation, `None` before the first pattern was checked.
+    pub fn progress(&self) -> Option<CompileProgress> {
+        *self.progress.lock().unwrap()
+    }
+
+    /// Whether the compilation finished, successfully or not, so `wait` returns without blocking.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// Request that the compilation cease, the task returns `Error::Cancelled`.
+    pub fn cancel(&self) {
+        self.token.cancel()
+    }
+
+    /// A token cancelling the compilation from another thread.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Wait for the compiled database, returns `Error::Failed` if the worker thread panicked.
+    pub fn wait(self) -> Result<RawDatabase<T>, Error> {
+        self.result.recv().unwrap_or(Err(Error::Failed(0)))
+    }
+
+    /// Wait at most the timeout for the compiled database,
+    /// returns the task back if the compilation is still running.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<Result<RawDatabase<T>, Error>, CompileTask<T>> {
+        match self.result.recv_timeout(timeout) {
+            Ok(result) => Ok(result),
+            Err(RecvTimeoutError::Timeout) => Err(self),
+            Err(RecvTimeoutError::Disconnected) => Ok(Err(Error::Failed(0))),
+        }
+    }
+}
+
+impl Patterns {
+    /// Compile the patterns for the current host on a worker thread, reporting the progress to the callback.
+    ///
+    /// The returned task can be polled with `CompileTask::progress` and `CompileTask::is_finished`
+    /// while the service starts without the database.
+    pub fn compile_in_background<T, F>(&self, progress: F) -> CompileTask<T>
+        where T: Type + 'static,
+              F: FnMut(CompileProgress) + Send + 'static
+    {
+        CompileTask::spawn(self.clone(), progress)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::thread;
+    use std::time::Duration;
+    use std::sync::{Arc, Mutex};
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_compile_task() {
+        let _ = env_logger::init();
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let progress = reports.clone();
+
+        let task: CompileTask<Block> = CompileTask::spawn(patterns!(["foo", "bar", "baz"]),
+                                                          move |p| progress.lock().unwrap().push(p));
+
+        let db = task.wait().unwrap();
+
+        validate_database(&db);
+
+        let reports = reports.lock().unwrap();
+
+        assert_eq!(reports.iter().map(|p| (p.stage, p.processed)).collect::<Vec<_>>(),
+                   vec![(CompileStage::Checking, 1),
+                        (CompileStage::Checking, 2),
+                        (CompileStage::Checking, 3),
+                        (CompileStage::Compiling, 3),
+                        (CompileStage::Done, 3)]);
+        assert!(reports.iter().all(|p| p.total == 3));
+
+        let task: CompileTask<Block> = CompileTask::spawn(patterns!(["foo", "("]), |_| {});
+
+        match task.wait_timeout(Duration::from_secs(60)) {
+            Ok(Err(Error::PatternError(ref err))) if err.index == 1 && err.expression == "(" => {}
+            _ => panic!("expected a compile error"),
+        }
+
+        let token = CancellationToken::new();
+        let cancelling = token.clone();
+
+        let task: CompileTask<Block> = CompileTask::with_token(token,
+                                                               patterns!(["foo", "bar"]),
+                                                               move |_| cancelling.cancel());
+
+        assert_eq!(task.wait().err(), Some(Error::Cancelled));
+    }
+
+    #[test]
+    fn test_compile_task_combination() {
+        let _ = env_logger::init();
+
+        let patterns = vec![pattern!{"foo", flags => HS_FLAG_QUIET, id => 1},
+                            pattern!{"bar", flags => HS_FLAG_QUIET, id => 2},
+                            pattern!{"(1 & 2)", flags => HS_FLAG_COMBINATION | HS_FLAG_SINGLEMATCH, id => 3}]
+            .into_iter()
+            .collect::<Patterns>();
+
+        let task: CompileTask<Block> = CompileTask::spawn(patterns, |_| {});
+        let db = task.wait().unwrap();
+
+        validate_database(&db);
+
+        assert_matches!(&db, "foo bar", [(3, 7)]);
+    }
+
+    #[test]
+    fn test_compile_in_background() {
+        let _ = env_logger::init();
+
+        let patterns = patterns!(["foo", "bar"]);
+        let mut task: CompileTask<Streaming> = patterns.compile_in_background(|_| {});
+
+        loop {
+            task = match task.wait_timeout(Duration::from_millis(10)) {
+                Ok(result) => {
+                    validate_database(&result.unwrap());
+                    break;
+                }
+                Err(task) => {
+                    assert!(task.progress().map_or(true, |p| p.total == 2));
+
+                    task
+                }
+            };
+        }
+
+        let task: CompileTask<Block> = patterns.compile_in_background(|_| {});
+
+        while !task.is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(task.progress(),
+                   Some(CompileProgress {
+                       stage: CompileStage::Done,
+                       processed: 2,
+                       total: 2,
+                   }));
+
+        validate_database(&task.wait().unwrap());
+    }
+}
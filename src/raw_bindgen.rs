@@ -733,6 +733,12 @@ pub struct hs_expr_ext {
      * hs_expr_ext::flags field.
      */
     pub edit_distance: ::std::os::raw::c_uint,
+    /**
+     * Allow patterns to approximately match within this Hamming distance. To
+     * use this parameter, set the @ref HS_EXT_FLAG_HAMMING_DISTANCE flag in the
+     * hs_expr_ext::flags field.
+     */
+    pub hamming_distance: ::std::os::raw::c_uint,
 }
 #[test]
 fn bindgen_test_layout_hs_expr_ext() {
@@ -765,6 +771,11 @@ fn bindgen_test_layout_hs_expr_ext() {
                 const _ as usize } , 32usize , concat ! (
                 "Alignment of field: " , stringify ! ( hs_expr_ext ) , "::" ,
                 stringify ! ( edit_distance ) ));
+    assert_eq! (unsafe {
+                & ( * ( 0 as * const hs_expr_ext ) ) . hamming_distance as *
+                const _ as usize } , 36usize , concat ! (
+                "Alignment of field: " , stringify ! ( hs_expr_ext ) , "::" ,
+                stringify ! ( hamming_distance ) ));
 }
 impl Clone for hs_expr_ext {
     fn clone(&self) -> Self { *self }
@@ -1015,6 +1026,61 @@ extern "C" {
                                 error: *mut *mut hs_compile_error_t)
      -> hs_error_t;
 }
+extern "C" {
+    /**
+ * The basic pure literal expression compiler.
+ *
+ * This is the function call with which a pure literal expression (not a
+ * common regular expression) is compiled into a Hyperscan database which
+ * can be passed to the runtime functions (such as @ref hs_scan(),
+ * @ref hs_open_stream(), etc.)
+ *
+ * The literal may contain NUL bytes, its length is given with @a len.
+ * Only the @ref HS_FLAG_CASELESS, @ref HS_FLAG_SINGLEMATCH and
+ * @ref HS_FLAG_SOM_LEFTMOST flags are supported.
+ *
+ * @return
+ *      @ref HS_SUCCESS is returned on successful compilation; @ref
+ *      HS_COMPILER_ERROR on failure, with details provided in the @a error
+ *      parameter.
+ *
+ */
+    pub fn hs_compile_lit(expression: *const ::std::os::raw::c_char,
+                          flags: ::std::os::raw::c_uint, len: usize,
+                          mode: ::std::os::raw::c_uint,
+                          platform: *const hs_platform_info_t,
+                          db: *mut *mut hs_database_t,
+                          error: *mut *mut hs_compile_error_t) -> hs_error_t;
+}
+extern "C" {
+    /**
+ * The multiple pure literal expression compiler.
+ *
+ * This is the function call with which a set of pure literal expressions is
+ * compiled into a database which can be passed to the runtime functions.
+ * Each expression can be labelled with a unique integer which is passed into
+ * the match callback to identify the pattern that has matched.
+ *
+ * The length of each literal is given in the @a lens array.
+ *
+ * @return
+ *      @ref HS_SUCCESS is returned on successful compilation; @ref
+ *      HS_COMPILER_ERROR on failure, with details provided in the @a error
+ *      parameter.
+ *
+ */
+    pub fn hs_compile_lit_multi(expressions:
+                                    *const *const ::std::os::raw::c_char,
+                                flags: *const ::std::os::raw::c_uint,
+                                ids: *const ::std::os::raw::c_uint,
+                                lens: *const usize,
+                                elements: ::std::os::raw::c_uint,
+                                mode: ::std::os::raw::c_uint,
+                                platform: *const hs_platform_info_t,
+                                db: *mut *mut hs_database_t,
+                                error: *mut *mut hs_compile_error_t)
+     -> hs_error_t;
+}
 extern "C" {
     /**
  * Free an error structure generated by @ref hs_compile(), @ref
@@ -1471,6 +1537,81 @@ extern "C" {
                                     context: *mut ::std::os::raw::c_void)
      -> hs_error_t;
 }
+extern "C" {
+    /**
+ * Creates a compressed representation of the provided stream in the buffer
+ * provided. This compressed representation can be converted back into a
+ * stream state by using @ref hs_expand_stream() or @ref
+ * hs_reset_and_expand_stream(). The size of the compressed representation
+ * will be placed into @p used_space.
+ *
+ * If there is not sufficient space in the buffer to hold the compressed
+ * representation, @ref HS_INSUFFICIENT_SPACE will be returned and @p used_space
+ * will be populated with the amount of space required.
+ *
+ * Note: this function does not close the provided stream, you may continue to
+ * use the stream or to free it with @ref hs_close_stream().
+ *
+ * @param stream
+ *      The stream (as created by @ref hs_open_stream()) to be compressed.
+ *
+ * @param buf
+ *      Buffer to write the compressed representation into. Note: if the call is
+ *      just being used to determine the amount of space required, it is allowed
+ *      to pass NULL here and @p buf_space as 0.
+ *
+ * @param buf_space
+ *      The number of bytes in @p buf. If buf_space is too small, the call will
+ *      fail with @ref HS_INSUFFICIENT_SPACE.
+ *
+ * @param used_space
+ *      Pointer to where the amount of used space will be written to. The used
+ *      buffer space is always less than or equal to @p buf_space. If the call
+ *      fails with @ref HS_INSUFFICIENT_SPACE, this pointer will be used to
+ *      write out the amount of buffer space required.
+ *
+ * @return
+ *      @ref HS_SUCCESS on success, @ref HS_INSUFFICIENT_SPACE if the provided
+ *      buffer is too small.
+ */
+    pub fn hs_compress_stream(stream: *const hs_stream_t,
+                              buf: *mut ::std::os::raw::c_char,
+                              buf_space: usize, used_space: *mut usize)
+     -> hs_error_t;
+}
+extern "C" {
+    /**
+ * Decompresses a compressed representation created by @ref hs_compress_stream()
+ * into a new stream.
+ *
+ * Note: @p buf must correspond to a complete compressed representation created
+ * by @ref hs_compress_stream() of a stream that was opened against @p db. It is
+ * not always possible to detect misuse of this API and behaviour is undefined
+ * if these properties are not satisfied.
+ *
+ * @param db
+ *      The compiled pattern database that the compressed stream was opened
+ *      against.
+ *
+ * @param stream
+ *      On success, a pointer to the expanded @ref hs_stream_t will be
+ *      returned; NULL on failure.
+ *
+ * @param buf
+ *      A compressed representation of a stream. These compressed forms are
+ *      created by @ref hs_compress_stream().
+ *
+ * @param buf_size
+ *      The size in bytes of the compressed representation.
+ *
+ * @return
+ *      @ref HS_SUCCESS on success, other values on failure.
+ */
+    pub fn hs_expand_stream(db: *const hs_database_t,
+                            stream: *mut *mut hs_stream_t,
+                            buf: *const ::std::os::raw::c_char,
+                            buf_size: usize) -> hs_error_t;
+}
 extern "C" {
     /**
  * The block (non-streaming) regular expression scanner.
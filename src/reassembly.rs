@@ -0,0 +1,382 @@
+use std::fmt;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use api::*;
+use errors::Error;
+use runtime::{Feed, RawStream};
+use handler::MatchHandler;
+
+/// What a `Reassembler` does when its buffer is full of data after a gap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GapPolicy {
+    /// Refuse the data with `Error::SequenceGap`, the missing data may still arrive.
+    Fail,
+    /// Give up the missing data, reset the stream and resume scanning after the gap,
+    /// so no match spans the missing bytes.
+    Reset,
+}
+
+/// The default size of the reassembly buffer, in bytes.
+pub const DEFAULT_REASSEMBLY_CAPACITY: usize = 64 * 1024;
+
+/// A stream wrapper reordering slightly out of order segments, like TCP segments, before scanning them.
+///
+/// The segments are written with their sequence number, the segments after a gap are buffered
+/// until the missing data arrives, the retransmitted data already scanned is dropped.
+/// The sequence numbers are 64 bits, the caller unwraps the 32 bits TCP sequence numbers.
+///
+/// The matches are reported with the offsets in the sequence space.
+pub struct Reassembler {
+    stream: RawStream,
+    next: u64,
+    base: u64,
+    pending: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+    capacity: usize,
+    policy: GapPolicy,
+    gaps: u64,
+    skipped: u64,
+}
+
+impl fmt::Debug for Reassembler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Reassembler{{stream: {:?}, next: {}, buffered: {}, capacity: {}, policy: {:?}}}",
+               self.stream,
+               self.next,
+               self.buffered,
+               self.capacity,
+               self.policy)
+    }
+}
+
+fn on_reset_event<H>(id: u32, from: u64, to: u64, flags: u32, handler: &RefCell<(&mut H, u64)>) -> u32
+    where H: MatchHandler
+{
+    let (ref mut handler, base) = *handler.borrow_mut();
+
+    handler.on_match(id, from + base, to + base, flags)
+}
+
+impl Reassembler {
+    /// Wrap a new stream, whose first byte has the sequence number `seq`.
+    pub fn new(stream: RawStream, seq: u64) -> Reassembler {
+        Reassembler {
+            stream: stream,
+            next: seq,
+            base: seq,
+            pending: BTreeMap::new(),
+            buffered: 0,
+            capacity: DEFAULT_REASSEMBLY_CAPACITY,
+            policy: GapPolicy::Reset,
+            gaps: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Buffer at most `capacity` bytes after a gap.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn with_gap_policy(mut self, policy: GapPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The underlying stream.
+    pub fn stream(&self) -> &RawStream {
+        &self.stream
+    }
+
+    /// The sequence number of the next byte to scan.
+    pub fn next_seq(&self) -> u64 {
+        self.next
+    }
+
+    /// The number of bytes buffered after a gap.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// The number of gaps skipped and the total of their missing bytes.
+    pub fn skipped(&self) -> (u64, u64) {
+        (self.gaps, self.skipped)
+    }
+
+    /// Write a segment starting at the sequence number, scanning it and the buffered segments it completes.
+    ///
+    /// Returns `Error::SequenceGap` with the sequence number of the missing data if the buffer is full
+    /// with the `GapPolicy::Fail` policy, the segment is dropped.
+    pub fn write<S, H>(&mut self, seq: u64, data: &[u8], scratch: &S, mut handler: H) -> Result<Feed, Error>
+        where S: Scratch,
+              H: MatchHandler
+    {
+        let end = seq + data.len() as u64;
+
+        if end <= self.next {
+            trace!("drop retransmitted segment {}..{}", seq, end);
+
+            return Ok(Feed::default());
+        }
+
+        if seq > self.next {
+            if self.buffered + data.len() > self.capacity && self.policy == GapPolicy::Fail {
+                debug!("reassembly buffer full, missing data at {}", self.next);
+
+                return Err(Error::SequenceGap(self.next));
+            }
+
+            if self.pending.get(&seq).map_or(false, |old| old.len() >= data.len()) {
+                trace!("drop retransmitted segment {}..{} shorter than the buffered one", seq, end);
+
+                return Ok(Feed::default());
+            }
+
+            if let Some(old) = self.pending.insert(seq, data.to_vec()) {
+                self.buffered -= old.len();
+            }
+
+            self.buffered += data.len();
+
+            let mut feed = Feed::default();
+
+            while self.buffered > self.capacity {
+                let scanned = try!(self.skip(scratch, &mut handler));
+
+                feed.chunks += scanned.chunks;
+                feed.bytes += scanned.bytes;
+                feed.terminated |= scanned.terminated;
+            }
+
+            return Ok(feed);
+        }
+
+        let mut feed = try!(self.scan(&data[(self.next - seq) as usize..], scratch, &mut handler));
+        let drained = try!(self.drain(scratch, &mut handler));
+
+        feed.chunks += drained.chunks;
+        feed.bytes += drained.bytes;
+        feed.terminated |= drained.terminated;
+
+        Ok(feed)
+    }
+
+    /// Give up the missing data before the first buffered segment, reset the stream,
+    /// passing its end of data matches to the handler, and scan the buffered segments after the gap.
+    pub fn skip_gap<S, H>(&mut self, scratch: &S, mut handler: H) -> Result<Feed, Error>
+        where S: Scratch,
+              H: MatchHandler
+    {
+        self.skip(scratch, &mut handler)
+    }
+
+    fn skip<S, H>(&mut self, scratch: &S, handler: &mut H) -> Result<Feed, Error>
+        where S: Scratch,
+              H: MatchHandler
+    {
+        let seq = match self.pending.keys().next() {
+            Some(&seq) => seq,
+            None => return Ok(Feed::default()),
+        };
+
+        debug!("skip {} bytes missing at {}", seq - self.next, self.next);
+
+        {
+            let context = RefCell::new((&mut *handler, self.base));
+
+            try!(self.stream.reset(0, scratch, Some(on_reset_event::<H>), Some(&context)));
+        }
+
+        self.gaps += 1;
+        self.skipped += seq - self.next;
+        self.next = seq;
+        self.base = seq;
+
+        self.drain(scratch, handler)
+    }
+
+    /// Close the stream, passing the end of data matches to the handler, the buffered segments are discarded.
+    pub fn close<S, H>(self, scratch: &S, mut handler: H) -> Result<(), Error>
+        where S: Scratch,
+              H: MatchHandler
+    {
+        if !self.pending.is_empty() {
+            debug!("discard {} buffered bytes after {}", self.buffered, self.next);
+        }
+
+        let context = RefCell::new((&mut handler, self.base));
+
+        try!(self.stream.close(scratch, Some(on_reset_event::<H>), Some(&context)));
+
+        Ok(())
+    }
+
+    fn scan<S, H>(&mut self, data: &[u8], scratch: &S, handler: &mut H) -> Result<Feed, Error>
+        where S: Scratch,
+              H: MatchHandler
+    {
+        let base = self.base;
+        let feed = try!(self.stream.feed(Some(data), scratch, |id: u32, from: u64, to: u64, flags: u32| {
+            handler.on_match(id, from + base, to + base, flags)
+        }));
+
+        self.next += data.len() as u64;
+
+        Ok(feed)
+    }
+
+    /// Scan the buffered segments following the scanned data.
+    fn drain<S, H>(&mut self, scratch: &S, handler: &mut H) -> Result<Feed, Error>
+        where S: Scratch,
+              H: MatchHandler
+    {
+        let mut feed = Feed::default();
+
+        loop {
+            let seq = match self.pending.keys().next() {
+                Some(&seq) if seq <= self.next => seq,
+                _ => break,
+            };
+
+            let data = self.pending.remove(&seq).unwrap();
+
+            self.buffered -= data.len();
+
+            let end = seq + data.len() as u64;
+
+            if end > self.next {
+                let scanned = try!(self.scan(&data[(self.next - seq) as usize..], scratch, handler));
+
+                feed.chunks += scanned.chunks;
+                feed.bytes += scanned.bytes;
+                feed.terminated |= scanned.terminated;
+            }
+        }
+
+        Ok(feed)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_reassembly() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = pattern!{"foobar", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut st = Reassembler::new(db.open_stream(0).unwrap(), 1000);
+        let mut matches = Vec::new();
+
+        {
+            let mut on_match = |_, from, to, _| {
+                matches.push((from, to));
+
+                0
+            };
+
+            assert_eq!(st.write(1004, b"bar foo", &s, &mut on_match).unwrap().bytes, 0);
+            assert_eq!(st.buffered(), 7);
+            assert_eq!(st.write(1000, b"xfoo", &s, &mut on_match).unwrap().bytes, 11);
+            assert_eq!(st.write(1002, b"oob", &s, &mut on_match).unwrap().bytes, 0);
+            assert_eq!(st.write(1010, b"obar", &s, &mut on_match).unwrap().bytes, 3);
+        }
+
+        assert_eq!(st.next_seq(), 1014);
+        assert_eq!(st.buffered(), 0);
+        assert_eq!(matches, vec![(1001, 1007), (1008, 1014)]);
+
+        st.close(&s, |_, _, _, _| 0).unwrap();
+    }
+
+    #[test]
+    fn test_reassembly_short_retransmission() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["barbaz"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut st = Reassembler::new(db.open_stream(0).unwrap(), 0);
+        let mut matches = Vec::new();
+
+        {
+            let mut on_match = |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            };
+
+            st.write(5, b"barbaz", &s, &mut on_match).unwrap();
+            st.write(5, b"bar", &s, &mut on_match).unwrap();
+
+            assert_eq!(st.buffered(), 6);
+
+            st.write(5, b"barbazqux", &s, &mut on_match).unwrap();
+
+            assert_eq!(st.buffered(), 9);
+            assert_eq!(st.write(0, b"01234", &s, &mut on_match).unwrap().bytes, 14);
+        }
+
+        assert_eq!(st.buffered(), 0);
+        assert_eq!(matches, vec![(1, 11)]);
+
+        st.close(&s, |_, _, _, _| 0).unwrap();
+    }
+
+    #[test]
+    fn test_reassembly_gap() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foobar", "end$"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut st = Reassembler::new(db.open_stream(0).unwrap(), 0)
+            .with_capacity(8)
+            .with_gap_policy(GapPolicy::Fail);
+        let mut matches = Vec::new();
+
+        st.write(0, b"foo", &s, |_, _, _, _| 0).unwrap();
+        st.write(5, b"bar", &s, |_, _, _, _| 0).unwrap();
+
+        assert_eq!(st.write(8, b"foobar", &s, |_, _, _, _| 0).err(),
+                   Some(Error::SequenceGap(3)));
+        assert_eq!(st.buffered(), 3);
+
+        st.close(&s, |_, _, _, _| 0).unwrap();
+
+        let mut st = Reassembler::new(db.open_stream(0).unwrap(), 0).with_capacity(8);
+
+        {
+            let mut on_match = |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            };
+
+            st.write(0, b"the end", &s, &mut on_match).unwrap();
+            st.write(10, b"foo", &s, &mut on_match).unwrap();
+            st.write(13, b"bar end", &s, &mut on_match).unwrap();
+        }
+
+        assert_eq!(st.skipped(), (1, 3));
+        assert_eq!(st.next_seq(), 20);
+        assert_eq!(matches, vec![(2, 7), (1, 16)]);
+
+        st.close(&s, |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(2, 7), (1, 16), (2, 20)]);
+    }
+}
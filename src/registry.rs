@@ -0,0 +1,252 @@
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+use std::collections::HashMap;
+
+use api::*;
+use errors::Error;
+use compile::{Pattern, Patterns};
+use source::PatternDatabase;
+use shared::SharedDatabase;
+use swap::HotSwap;
+use pool::ScratchPool;
+
+/// A registry of independently compiled databases, one per tenant,
+/// sharing a pool of scratch spaces grown to fit the largest tenant.
+///
+/// Each tenant can be reloaded without disturbing the others,
+/// scans in progress keep using the previous version of its database.
+pub struct Registry<K, T: Type> {
+    tenants: RwLock<HashMap<K, Arc<HotSwap<PatternDatabase<T>>>>>,
+    pool: RwLock<Option<Arc<ScratchPool>>>,
+}
+
+impl<K: fmt::Debug + Hash + Eq, T: Type> fmt::Debug for Registry<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Registry<{}>{{tenants: {:?}, pool: {:?}}}",
+               T::name(),
+               self.tenants.read().unwrap().keys().collect::<Vec<_>>(),
+               *self.pool.read().unwrap())
+    }
+}
+
+impl<K: Hash + Eq, T: Type> Default for Registry<K, T> {
+    fn default() -> Self {
+        Registry {
+            tenants: RwLock::new(HashMap::new()),
+            pool: RwLock::new(None),
+        }
+    }
+}
+
+impl<K: Hash + Eq, T: Type> Registry<K, T> {
+    pub fn new() -> Registry<K, T> {
+        Registry::default()
+    }
+
+    /// Compile the patterns of a tenant for the current host, and publish the database,
+    /// replacing the previous version if the tenant was already registered.
+    pub fn insert(&self, key: K, patterns: Patterns) -> Result<Arc<PatternDatabase<T>>, Error> {
+        let db = try!(PatternDatabase::compile(patterns, &PlatformInfo::null()));
+
+        try!(self.grow(&db));
+
+        let tenant = self.tenants.read().unwrap().get(&key).cloned();
+
+        match tenant {
+            Some(handle) => {
+                handle.publish(db);
+
+                Ok(handle.load())
+            }
+            None => {
+                let handle = Arc::new(HotSwap::new(db));
+                let db = handle.load();
+
+                self.tenants.write().unwrap().insert(key, handle);
+
+                Ok(db)
+            }
+        }
+    }
+
+    /// Extend the database of a tenant with the additions and publish the new database.
+    ///
+    /// Returns `Error::Invalid` if the tenant isn't registered.
+    pub fn extend(&self, key: &K, additions: &[Pattern]) -> Result<Arc<PatternDatabase<T>>, Error> {
+        let handle = try!(self.handle(key).ok_or(Error::Invalid));
+
+        handle.update(|db| {
+            let db = try!(db.extended_with(additions));
+
+            try!(self.grow(&db));
+
+            Ok(db)
+        })
+    }
+
+    /// Unregister a tenant, returns its current database.
+    ///
+    /// The scratch spaces are not shrunk.
+    pub fn remove(&self, key: &K) -> Option<Arc<PatternDatabase<T>>> {
+        self.tenants.write().unwrap().remove(key).map(|handle| handle.load())
+    }
+
+    /// The current database of a tenant.
+    pub fn get(&self, key: &K) -> Option<Arc<PatternDatabase<T>>> {
+        self.handle(key).map(|handle| handle.load())
+    }
+
+    /// A handle to the current compiled database of a tenant,
+    /// which stays valid while the tenant is reloaded or removed.
+    pub fn database(&self, key: &K) -> Option<SharedDatabase<T>> {
+        self.get(key).map(|db| db.shared())
+    }
+
+    /// The hot swap handle of a tenant.
+    pub fn handle(&self, key: &K) -> Option<Arc<HotSwap<PatternDatabase<T>>>> {
+        self.tenants.read().unwrap().get(key).cloned()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.tenants.read().unwrap().contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tenants.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.read().unwrap().is_empty()
+    }
+
+    /// The scratch pool shared by the tenants, once a tenant was registered.
+    pub fn scratch_pool(&self) -> Option<Arc<ScratchPool>> {
+        self.pool.read().unwrap().clone()
+    }
+
+    /// Grow the shared scratch spaces for the database, before it is published.
+    fn grow(&self, db: &PatternDatabase<T>) -> Result<(), Error> {
+        let mut pool = self.pool.write().unwrap();
+
+        match *pool {
+            Some(ref pool) => {
+                try!(pool.realloc(&**db));
+            }
+            None => *pool = Some(Arc::new(try!(ScratchPool::new(&**db)))),
+        }
+
+        Ok(())
+    }
+}
+
+impl<K: Hash + Eq> Registry<K, Block> {
+    /// Scan the data with the current database of a tenant, using a scratch from the shared pool.
+    ///
+    /// Returns `Error::Invalid` if the tenant isn't registered.
+    pub fn scan<S: Scannable, D>(&self,
+                                 key: &K,
+                                 data: S,
+                                 flags: ScanFlags,
+                                 callback: Option<MatchEventCallback<D>>,
+                                 context: Option<&D>)
+                                 -> Result<(), Error> {
+        let db = try!(self.get(key).ok_or(Error::Invalid));
+        let pool = try!(self.scratch_pool().ok_or(Error::Invalid));
+        let mut scratch = pool.get();
+
+        // the scratch may have been taken before the database was published
+        try!(scratch.realloc(&**db));
+
+        try!(db.scan(data, flags, &*scratch, callback, context));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::sync::Arc;
+    use std::thread;
+    use std::cell::RefCell;
+
+    use super::super::*;
+
+    fn matched(registry: &Registry<&'static str, Block>, tenant: &'static str, data: &str) -> Vec<u32> {
+        fn callback(id: u32, _: u64, _: u64, _: u32, ids: &RefCell<Vec<u32>>) -> u32 {
+            ids.borrow_mut().push(id);
+
+            0
+        }
+
+        let ids = RefCell::new(Vec::new());
+
+        registry.scan(&tenant, data, 0, Some(callback), Some(&ids)).unwrap();
+
+        ids.into_inner()
+    }
+
+    #[test]
+    fn test_registry() {
+        let _ = env_logger::init();
+
+        let registry = Registry::<&'static str, Block>::new();
+
+        assert!(registry.is_empty());
+        assert!(registry.scratch_pool().is_none());
+
+        registry.insert("foo", patterns!(["foo"])).unwrap();
+        registry.insert("bar", patterns!(["bar", "b[a-z]+z"])).unwrap();
+
+        assert_eq!(registry.len(), 2);
+        assert_eq!(matched(&registry, "foo", "foo bar baz"), vec![1]);
+        assert_eq!(matched(&registry, "bar", "foo bar baz"), vec![1, 2]);
+        assert_eq!(registry.scan(&"qux", "foo", 0, None, None::<&()>).err(),
+                   Some(Error::Invalid));
+
+        let previous = registry.get(&"foo").unwrap();
+
+        registry.insert("foo", patterns!(["baz"])).unwrap();
+
+        assert_eq!(previous.patterns()[0].expression, "foo");
+        assert_eq!(matched(&registry, "foo", "foo bar baz"), vec![1]);
+
+        registry.extend(&"foo", &[pattern!{"qux", flags => 0, id => 2}]).unwrap();
+
+        assert_eq!(matched(&registry, "foo", "qux baz"), vec![2, 1]);
+        assert_eq!(registry.extend(&"qux", &[]).err(), Some(Error::Invalid));
+
+        let registry = Arc::new(registry);
+
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let registry = registry.clone();
+
+                thread::spawn(move || if i % 2 == 0 {
+                    matched(&registry, "bar", "bar")
+                } else {
+                    registry.insert("bar", patterns!(["bar"])).unwrap();
+
+                    vec![1]
+                })
+            })
+            .collect();
+
+        for t in threads {
+            assert_eq!(t.join().unwrap(), vec![1]);
+        }
+
+        let db = registry.database(&"bar").unwrap();
+
+        assert!(registry.remove(&"bar").is_some());
+        assert!(!registry.contains(&"bar"));
+        assert_eq!(db.handles(), 1);
+
+        let scratch = db.alloc().unwrap();
+
+        db.scan("bar", 0, &scratch, None, None::<&()>).unwrap();
+    }
+}
@@ -0,0 +1,514 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use api::*;
+use errors::Error;
+use common::{RawDatabase, BlockDatabase, StreamingDatabase};
+use shared::SharedDatabase;
+use ids::fnv1a;
+use sink::{scan_into, stream_into};
+
+/// The FNV-1a hash of the serialized database, to tell which database a scan was recorded with.
+pub fn database_hash<T: Type>(db: &RawDatabase<T>) -> Result<u64, Error> {
+    Ok(fnv1a(try!(db.serialize()).as_slice()))
+}
+
+/// A scan recorded in the replay log.
+///
+/// A record is written as one line, like
+/// `stream db=<hash> chunks=3,4 digests=<hash>,<hash> matches=1:0:5:0 data=666f6f,62617a21`,
+/// where the matches are `id:from:to:flags` separated by `;` and the data is only captured on request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanRecord {
+    /// Whether the chunks were scanned in a stream, or as a block.
+    pub streaming: bool,
+    /// The hash of the serialized database.
+    pub database: u64,
+    /// The size of the chunks, a block scan has one chunk.
+    pub chunks: Vec<usize>,
+    /// The FNV-1a hash of the chunks.
+    pub digests: Vec<u64>,
+    /// The captured chunks.
+    pub data: Option<Vec<Vec<u8>>>,
+    /// The reported matches.
+    pub matches: Vec<Match>,
+}
+
+impl ScanRecord {
+    fn new(streaming: bool, database: u64, chunks: &[&[u8]], capture: bool, matches: Vec<Match>) -> ScanRecord {
+        ScanRecord {
+            streaming: streaming,
+            database: database,
+            chunks: chunks.iter().map(|chunk| chunk.len()).collect(),
+            digests: chunks.iter().map(|chunk| fnv1a(*chunk)).collect(),
+            data: if capture {
+                Some(chunks.iter().map(|chunk| chunk.to_vec()).collect())
+            } else {
+                None
+            },
+            matches: matches,
+        }
+    }
+}
+
+fn join<T, F: Fn(&T) -> String>(items: &[T], sep: &str, f: F) -> String {
+    items.iter().map(f).collect::<Vec<_>>().join(sep)
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn unhex(s: &str) -> Result<Vec<u8>, Error> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err(Error::Invalid);
+    }
+
+    (0..s.len() / 2)
+        .map(|i| u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(Error::from))
+        .collect()
+}
+
+fn split<'a>(s: &'a str, sep: char) -> Vec<&'a str> {
+    if s.is_empty() {
+        Vec::new()
+    } else {
+        s.split(sep).collect()
+    }
+}
+
+fn parse_match(s: &str) -> Result<Match, Error> {
+    let fields = s.split(':').collect::<Vec<_>>();
+
+    if fields.len() != 4 {
+        return Err(Error::Invalid);
+    }
+
+    Ok(Match::new(try!(fields[0].parse()),
+                  try!(fields[1].parse()),
+                  try!(fields[2].parse()),
+                  try!(fields[3].parse())))
+}
+
+impl fmt::Display for ScanRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f,
+                    "{} db={:016x} chunks={} digests={} matches={}",
+                    if self.streaming { "stream" } else { "block" },
+                    self.database,
+                    join(&self.chunks, ",", |size| size.to_string()),
+                    join(&self.digests, ",", |digest| format!("{:016x}", digest)),
+                    join(&self.matches,
+                         ";",
+                         |m| format!("{}:{}:{}:{}", m.id, m.from, m.to, m.flags.bits()))));
+
+        if let Some(ref data) = self.data {
+            try!(write!(f, " data={}", join(data, ",", |chunk| hex(chunk))));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ScanRecord {
+    type Err = Error;
+
+    /// Parse a record, returns `Error::Invalid` if a field is missing, or the captured data doesn't match its digest.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(' ');
+
+        let streaming = match fields.next() {
+            Some("block") => false,
+            Some("stream") => true,
+            _ => return Err(Error::Invalid),
+        };
+
+        let mut record = ScanRecord {
+            streaming: streaming,
+            database: 0,
+            chunks: Vec::new(),
+            digests: Vec::new(),
+            data: None,
+            matches: Vec::new(),
+        };
+        let mut database = None;
+        let mut data = None;
+
+        for field in fields {
+            let mut kv = field.splitn(2, '=');
+
+            match (kv.next(), kv.next()) {
+                (Some("db"), Some(v)) => database = Some(try!(u64::from_str_radix(v, 16))),
+                (Some("chunks"), Some(v)) => {
+                    record.chunks = try!(split(v, ',').into_iter().map(|size| size.parse()).collect())
+                }
+                (Some("digests"), Some(v)) => {
+                    record.digests = try!(split(v, ',')
+                        .into_iter()
+                        .map(|digest| u64::from_str_radix(digest, 16))
+                        .collect())
+                }
+                (Some("matches"), Some(v)) => {
+                    record.matches = try!(split(v, ';').into_iter().map(parse_match).collect())
+                }
+                (Some("data"), Some(v)) => data = Some(v),
+                _ => return Err(Error::Invalid),
+            }
+        }
+
+        record.database = try!(database.ok_or(Error::Invalid));
+
+        if record.chunks.len() != record.digests.len() || (!record.streaming && record.chunks.len() != 1) {
+            return Err(Error::Invalid);
+        }
+
+        if let Some(data) = data {
+            let chunks = if record.chunks.is_empty() {
+                split(data, ',')
+            } else {
+                data.split(',').collect()
+            };
+
+            record.data = Some(try!(chunks.into_iter().map(unhex).collect()));
+        }
+
+        if let Some(ref data) = record.data {
+            if data.len() != record.chunks.len() ||
+               data.iter()
+                .zip(record.chunks.iter().zip(&record.digests))
+                .any(|(chunk, (&size, &digest))| chunk.len() != size || fnv1a(chunk) != digest) {
+                return Err(Error::Invalid);
+            }
+        }
+
+        Ok(record)
+    }
+}
+
+/// Record the scans to a replay log, so a match discrepancy seen in production can be replayed in development.
+///
+/// Only the size and the digest of the chunks are logged by default, the data itself is
+/// captured with `capture_data`. Logging stops at the first I/O error, which is returned by `into_inner`.
+///
+/// The hash of a database is computed at its first recorded scan and reused afterwards,
+/// the recorder keeps a handle of each recorded database, so it stays alive until the recorder is dropped.
+#[derive(Debug)]
+pub struct Recorder<W: Write> {
+    writer: W,
+    capture: bool,
+    err: Option<io::Error>,
+    blocks: Vec<(SharedDatabase<Block>, u64)>,
+    streams: Vec<(SharedDatabase<Streaming>, u64)>,
+}
+
+/// The hash of a database, computed once per database.
+fn cached_hash<T: Type>(hashes: &mut Vec<(SharedDatabase<T>, u64)>, db: &SharedDatabase<T>) -> Result<u64, Error> {
+    if let Some(&(_, hash)) = hashes.iter().find(|&&(ref recorded, _)| recorded.ptr_eq(db)) {
+        return Ok(hash);
+    }
+
+    let hash = try!(database_hash(db));
+
+    hashes.push((db.clone(), hash));
+
+    Ok(hash)
+}
+
+impl Recorder<BufWriter<File>> {
+    /// Create the replay log file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Recorder<BufWriter<File>>> {
+        Ok(Recorder::new(BufWriter::new(try!(File::create(path)))))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Recorder<W> {
+        Recorder {
+            writer: writer,
+            capture: false,
+            err: None,
+            blocks: Vec::new(),
+            streams: Vec::new(),
+        }
+    }
+
+    /// Also log the scanned data, so the scans can be replayed.
+    pub fn capture_data(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Flush the writer and return it, or the first error.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        match self.err.take() {
+            Some(err) => Err(err),
+            None => {
+                try!(self.writer.flush());
+
+                Ok(self.writer)
+            }
+        }
+    }
+
+    fn record(&mut self, record: ScanRecord) {
+        if self.err.is_none() {
+            if let Err(err) = writeln!(self.writer, "{}", record) {
+                warn!("fail to write replay record, {}", err);
+
+                self.err = Some(err);
+            }
+        }
+    }
+
+    /// Scan a block and log it with the matches.
+    pub fn scan<T, S>(&mut self, db: &SharedDatabase<Block>, data: T, scratch: &S) -> Result<Vec<Match>, Error>
+        where T: Scannable,
+              S: Scratch
+    {
+        let data = data.as_bytes();
+        let mut matches = Vec::new();
+
+        try!(scan_into(db, data, scratch, &mut matches));
+
+        let record = ScanRecord::new(false, try!(cached_hash(&mut self.blocks, db)), &[data], self.capture, matches.clone());
+
+        self.record(record);
+
+        Ok(matches)
+    }
+
+    /// Scan the chunks in a new stream and log it with the matches.
+    pub fn stream<I, S>(&mut self, db: &SharedDatabase<Streaming>, chunks: I, scratch: &S) -> Result<Vec<Match>, Error>
+        where I: IntoIterator,
+              I::Item: Scannable,
+              S: Scratch
+    {
+        let chunks = chunks.into_iter().collect::<Vec<_>>();
+        let chunks = chunks.iter().map(|chunk| chunk.as_bytes()).collect::<Vec<_>>();
+        let mut matches = Vec::new();
+
+        try!(stream_into(db, chunks.iter().cloned(), scratch, &mut matches));
+
+        let record = ScanRecord::new(true, try!(cached_hash(&mut self.streams, db)), &chunks, self.capture, matches.clone());
+
+        self.record(record);
+
+        Ok(matches)
+    }
+}
+
+/// A replayed scan whose matches differ from the recorded ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    /// The index of the record in the replay log.
+    pub index: usize,
+    /// The recorded matches.
+    pub recorded: Vec<Match>,
+    /// The matches of the replayed scan.
+    pub replayed: Vec<Match>,
+}
+
+/// Re-run the scans of a replay log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Replayer {
+    records: Vec<ScanRecord>,
+}
+
+impl Replayer {
+    /// Read the replay log file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Replayer> {
+        Replayer::read(BufReader::new(try!(File::open(path))))
+    }
+
+    /// Read the replay log, one record per line.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Replayer> {
+        let mut records = Vec::new();
+
+        for (n, line) in reader.lines().enumerate() {
+            let line = try!(line);
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            records.push(try!(line.trim().parse().map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidData,
+                               format!("invalid replay record at line {}, {}", n + 1, err))
+            })));
+        }
+
+        Ok(Replayer { records: records })
+    }
+
+    /// The records of the replay log.
+    pub fn records(&self) -> &[ScanRecord] {
+        &self.records
+    }
+
+    /// Re-run the block scans recorded with the database and captured data,
+    /// returns the scans whose matches differ from the recorded ones.
+    pub fn replay<S: Scratch>(&self, db: &BlockDatabase, scratch: &S) -> Result<Vec<Discrepancy>, Error> {
+        self.replay_records(false, try!(database_hash(db)), |chunks| {
+            let mut matches = Vec::new();
+
+            try!(scan_into(db, chunks[0].as_slice(), scratch, &mut matches));
+
+            Ok(matches)
+        })
+    }
+
+    /// Re-run the stream scans recorded with the database and captured data,
+    /// returns the scans whose matches differ from the recorded ones.
+    pub fn replay_streams<S: Scratch>(&self, db: &StreamingDatabase, scratch: &S) -> Result<Vec<Discrepancy>, Error> {
+        self.replay_records(true, try!(database_hash(db)), |chunks| {
+            let mut matches = Vec::new();
+
+            try!(stream_into(db, chunks.iter().map(|chunk| chunk.as_slice()), scratch, &mut matches));
+
+            Ok(matches)
+        })
+    }
+
+    fn replay_records<F>(&self, streaming: bool, database: u64, mut scan: F) -> Result<Vec<Discrepancy>, Error>
+        where F: FnMut(&[Vec<u8>]) -> Result<Vec<Match>, Error>
+    {
+        let mut discrepancies = Vec::new();
+
+        for (index, record) in self.records.iter().enumerate() {
+            if record.streaming != streaming || record.database != database {
+                continue;
+            }
+
+            let data = match record.data {
+                Some(ref data) => data,
+                None => {
+                    debug!("skip record #{} without captured data", index);
+
+                    continue;
+                }
+            };
+
+            let replayed = try!(scan(data));
+
+            if replayed != record.matches {
+                debug!("record #{} replayed {} matches, recorded {}",
+                       index,
+                       replayed.len(),
+                       record.matches.len());
+
+                discrepancies.push(Discrepancy {
+                    index: index,
+                    recorded: record.matches.clone(),
+                    replayed: replayed,
+                });
+            }
+        }
+
+        Ok(discrepancies)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::str;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scan_record() {
+        let record = ScanRecord {
+            streaming: true,
+            database: 0x1234,
+            chunks: vec![3, 0],
+            digests: vec![0xa5, 0xcbf29ce484222325],
+            data: None,
+            matches: vec![Match::new(1, 0, 3, 0), Match::new(2, 1, 3, 0)],
+        };
+
+        let line = record.to_string();
+
+        assert_eq!(line,
+                   "stream db=0000000000001234 chunks=3,0 digests=00000000000000a5,cbf29ce484222325 \
+                    matches=1:0:3:0;2:1:3:0");
+        assert_eq!(line.parse::<ScanRecord>().unwrap(), record);
+
+        assert!("block db=1 chunks=1,2 digests=1,2 matches=".parse::<ScanRecord>().is_err());
+        assert!("block chunks=0 digests=cbf29ce484222325 matches=".parse::<ScanRecord>().is_err());
+        assert!("block db=1 chunks=0 digests=cbf29ce484222325 matches= data=".parse::<ScanRecord>().is_ok());
+        assert!("block db=1 chunks=1 digests=0 matches= data=00".parse::<ScanRecord>().is_err());
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let _ = env_logger::init();
+
+        let db = SharedDatabase::<Block>::new(patterns!(["foo", "bar"]).build().unwrap());
+        let s = db.alloc().unwrap();
+
+        let mut recorder = Recorder::new(Vec::new()).capture_data(true);
+
+        assert_eq!(recorder.scan(&db, "foo bar", &s).unwrap().len(), 2);
+        assert_eq!(recorder.scan(&db, "baz", &s).unwrap().len(), 0);
+        assert_eq!(recorder.blocks.len(), 1);
+        assert_eq!(db.handles(), 2);
+
+        let log = recorder.into_inner().unwrap();
+        let replayer = Replayer::read(log.as_slice()).unwrap();
+
+        assert_eq!(replayer.records().len(), 2);
+        assert_eq!(replayer.records()[0].data, Some(vec![b"foo bar".to_vec()]));
+        assert!(replayer.replay(&db, &s).unwrap().is_empty());
+
+        let changed: BlockDatabase = patterns!(["foo", "baz"]).build().unwrap();
+        let s = changed.alloc().unwrap();
+
+        assert!(replayer.replay(&changed, &s).unwrap().is_empty());
+
+        let mut records = replayer.records().to_vec();
+
+        records[1].database = database_hash(&changed).unwrap();
+
+        let log = records.iter().map(|record| record.to_string()).collect::<Vec<_>>().join("\n");
+        let discrepancies = Replayer::read(log.as_bytes()).unwrap().replay(&changed, &s).unwrap();
+
+        assert_eq!(discrepancies,
+                   vec![Discrepancy {
+                            index: 1,
+                            recorded: vec![],
+                            replayed: vec![Match::new(2, 0, 3, 0)],
+                        }]);
+
+        assert!(Replayer::read("block db=1".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_record_streams() {
+        let _ = env_logger::init();
+
+        let db = SharedDatabase::<Streaming>::new(pattern!{"test"}.build().unwrap());
+        let s = db.alloc().unwrap();
+
+        let mut recorder = Recorder::new(Vec::new());
+
+        assert_eq!(recorder.stream(&db, vec!["some te", "st data"], &s).unwrap(),
+                   vec![Match::new(0, 0, 9, 0)]);
+
+        let log = recorder.into_inner().unwrap();
+        let line = str::from_utf8(&log).unwrap();
+
+        assert!(line.starts_with("stream db="));
+        assert!(line.contains(" chunks=7,7 "));
+        assert!(line.ends_with(" matches=0:0:9:0\n"));
+
+        let replayer = Replayer::read(log.as_slice()).unwrap();
+
+        assert_eq!(replayer.records()[0].data, None);
+        assert!(replayer.replay_streams(&db, &s).unwrap().is_empty());
+    }
+}
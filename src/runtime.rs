@@ -1,13 +1,19 @@
 use std::fmt;
 use std::ptr;
-use std::mem;
-use std::os::raw::c_uint;
+use std::cell::Cell;
+use std::os::raw::{c_int, c_uint, c_ulonglong, c_void};
+use std::sync::Arc;
 use std::ops::{Deref, DerefMut};
 
 use raw::*;
 use api::*;
+use constants::*;
 use errors::Error;
 use common::{RawDatabase, BlockDatabase, VectoredDatabase, StreamingDatabase};
+use telemetry::track_scratch;
+use stats::{ScanCounters, ScanStats, counted};
+use shared::SharedDatabase;
+use handler::MatchHandler;
 
 /// A large enough region of scratch space to support a given database.
 ///
@@ -15,10 +21,19 @@ pub struct RawScratch(RawScratchPtr);
 
 impl fmt::Debug for RawScratch {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "RawScratch({:p})", self.0)
+        let mut size = 0;
+
+        if self.0.is_null() || unsafe { hs_scratch_size(self.0, &mut size) } != HS_SUCCESS {
+            write!(f, "RawScratch{{s: {:p}}}", self.0)
+        } else {
+            write!(f, "RawScratch{{s: {:p}, size: {}}}", self.0, size)
+        }
     }
 }
 
+/// A scratch space can be moved to another thread, as long as it is used by one caller at a time.
+unsafe impl Send for RawScratch {}
+
 impl RawScratch {
     /// Allocate a "scratch" space for use by Hyperscan.
     ///
@@ -39,6 +54,8 @@ impl RawScratch {
             **db
         );
 
+        track_scratch(ptr::null_mut(), s, Some(**db));
+
         Ok(RawScratch(s))
     }
 }
@@ -51,23 +68,37 @@ impl Drop for RawScratch {
 
             trace!("freed scratch at {:p}", self.0);
 
+            track_scratch(self.0, ptr::null_mut(), None);
+
             self.0 = ptr::null_mut();
         }
     }
 }
 
-impl Clone for RawScratch {
-    #[inline]
-    fn clone(&self) -> Self {
+impl RawScratch {
+    /// Clone the scratch space, reporting the allocation failure instead of panicking.
+    pub fn try_clone(&self) -> Result<RawScratch, Error> {
         let mut s: RawScratchPtr = ptr::null_mut();
 
         unsafe {
-            assert_hs_error!(hs_clone_scratch(self.0, &mut s));
+            check_hs_error!(hs_clone_scratch(self.0, &mut s));
         }
 
         trace!("cloned scratch from {:p} to {:p}", self.0, s);
 
-        RawScratch(s)
+        track_scratch(ptr::null_mut(), s, None);
+
+        Ok(RawScratch(s))
+    }
+}
+
+impl Clone for RawScratch {
+    #[inline]
+    fn clone(&self) -> Self {
+        match self.try_clone() {
+            Ok(s) => s,
+            Err(err) => panic!("panic, err={}", err),
+        }
     }
 }
 
@@ -96,10 +127,14 @@ impl Scratch for RawScratch {
 
     #[inline]
     fn realloc<T: Database>(&mut self, db: &T) -> Result<&Self, Error> {
+        let old = self.0;
+
         unsafe {
             check_hs_error!(hs_alloc_scratch(**db, &mut self.0));
         }
 
+        track_scratch(old, self.0, Some(**db));
+
         trace!(
             "reallocated scratch {:p} for {} database {:p}",
             self.0,
@@ -138,15 +173,21 @@ impl<T: Scannable, S: Scratch> BlockScanner<T, S> for BlockDatabase {
         unsafe {
             let bytes = data.as_bytes();
 
-            check_hs_error!(hs_scan(
-                **self,
-                bytes.as_ptr() as *const i8,
-                bytes.len() as u32,
-                flags as u32,
-                **scratch,
-                mem::transmute(callback),
-                mem::transmute(context),
-            ));
+            let (ret, matches) = counted(callback, context, |on_event, ctxt| {
+                hs_scan(
+                    **self,
+                    bytes.as_ptr() as *const i8,
+                    bytes.len() as u32,
+                    flags as u32,
+                    **scratch,
+                    on_event,
+                    ctxt,
+                )
+            });
+
+            self.counters().add(ret, bytes.len(), matches);
+
+            check_hs_error!(ret);
 
             trace!(
                 "block scan {} bytes with {} database at {:p}",
@@ -164,7 +205,7 @@ impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
     #[inline]
     fn scan<D>(
         &self,
-        data: &Vec<T>,
+        data: &[T],
         flags: ScanFlags,
         scratch: &S,
         callback: Option<MatchEventCallback<D>>,
@@ -181,16 +222,22 @@ impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
         }
 
         unsafe {
-            check_hs_error!(hs_scan_vector(
-                **self,
-                ptrs.as_slice().as_ptr() as *const *const i8,
-                lens.as_slice().as_ptr() as *const c_uint,
-                data.len() as u32,
-                flags as u32,
-                **scratch,
-                mem::transmute(callback),
-                mem::transmute(context),
-            ));
+            let (ret, matches) = counted(callback, context, |on_event, ctxt| {
+                hs_scan_vector(
+                    **self,
+                    ptrs.as_slice().as_ptr() as *const *const i8,
+                    lens.as_slice().as_ptr() as *const c_uint,
+                    data.len() as u32,
+                    flags as u32,
+                    **scratch,
+                    on_event,
+                    ctxt,
+                )
+            });
+
+            self.counters().add(ret, lens.iter().fold(0, |sum, &len| sum + len as usize), matches);
+
+            check_hs_error!(ret);
         }
 
         trace!(
@@ -208,6 +255,7 @@ impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
 impl StreamingScanner<RawStream, RawScratch> for StreamingDatabase {
     fn open_stream(&self, flags: StreamFlags) -> Result<RawStream, Error> {
         let mut id: RawStreamPtr = ptr::null_mut();
+        let state_size = try!(self.stream_size());
 
         unsafe {
             check_hs_error!(hs_open_stream(**self, flags, &mut id));
@@ -220,16 +268,220 @@ impl StreamingScanner<RawStream, RawScratch> for StreamingDatabase {
             **self
         );
 
-        Ok(RawStream(id))
+        Ok(RawStream {
+            id: id,
+            state_size: state_size,
+            counters: ScanCounters::default(),
+            db_counters: self.counters().clone(),
+            db: None,
+        })
+    }
+}
+
+impl StreamingDatabase {
+    /// Expand a stream compressed by `RawStream::compress` for a stream opened with this database.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must be a complete compressed stream of this database, as returned by `RawStream::compress`.
+    /// Hyperscan doesn't validate them, expanding corrupt or foreign bytes is undefined behaviour.
+    pub unsafe fn expand_stream(&self, bytes: &[u8]) -> Result<RawStream, Error> {
+        let mut id: RawStreamPtr = ptr::null_mut();
+        let state_size = try!(self.stream_size());
+
+        check_hs_error!(hs_expand_stream(**self, &mut id, bytes.as_ptr() as *const i8, bytes.len()));
+
+        trace!(
+            "stream expanded at {:p} from {} bytes for {} database at {:p}",
+            id,
+            bytes.len(),
+            self.database_name(),
+            **self
+        );
+
+        Ok(RawStream {
+            id: id,
+            state_size: state_size,
+            counters: ScanCounters::default(),
+            db_counters: self.counters().clone(),
+            db: None,
+        })
     }
 }
 
+/// Forward a match event to the closure passed as the context.
+unsafe extern "C" fn on_match_event<F>(id: c_uint,
+                                       from: c_ulonglong,
+                                       to: c_ulonglong,
+                                       flags: c_uint,
+                                       context: *mut c_void)
+                                       -> c_int
+    where F: FnMut(u32, u64, u64, u32) -> u32
+{
+    let handler = &mut *(context as *mut F);
+
+    handler(id, from, to, flags) as c_int
+}
+
+/// The progress of feeding chunks of data to a stream.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Feed {
+    /// The number of chunks pulled from the iterator.
+    pub chunks: usize,
+
+    /// The number of bytes written to the stream.
+    pub bytes: usize,
+
+    /// Whether the handler requested that scanning cease.
+    pub terminated: bool,
+}
+
 /// A pattern matching state can be maintained across multiple blocks of target data
-pub struct RawStream(RawStreamPtr);
+pub struct RawStream {
+    id: RawStreamPtr,
+    state_size: usize,
+    counters: ScanCounters,
+    db_counters: Arc<ScanCounters>,
+    // the database the stream state points into, when the stream was opened with a shared handle
+    db: Option<SharedDatabase<Streaming>>,
+}
+
+impl RawStream {
+    /// The totals of the bytes written to the stream and the match events delivered.
+    pub fn scan_stats(&self) -> ScanStats {
+        self.counters.stats()
+    }
+
+    /// Carry the totals of a stream over to its expanded copy.
+    pub(crate) fn with_stats(mut self, stats: ScanStats) -> RawStream {
+        self.counters = ScanCounters::new(stats);
+        self
+    }
+
+    /// Keep the database alive for as long as the stream and its copies are open.
+    pub(crate) fn holding(mut self, db: SharedDatabase<Streaming>) -> RawStream {
+        self.db = Some(db);
+        self
+    }
+
+    /// A compressed representation of the stream state, including the stream offset,
+    /// which can be expanded back into a stream with `StreamingDatabase::expand_stream`.
+    ///
+    /// The stream is left open.
+    pub fn compress(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0; self.state_size];
+        let mut used = 0;
+
+        unsafe {
+            let mut ret = hs_compress_stream(self.id, buf.as_mut_ptr() as *mut i8, buf.len(), &mut used);
+
+            if ret == HS_INSUFFICIENT_SPACE {
+                buf.resize(used, 0);
+
+                ret = hs_compress_stream(self.id, buf.as_mut_ptr() as *mut i8, buf.len(), &mut used);
+            }
+
+            check_hs_error!(ret);
+        }
+
+        buf.truncate(used);
+
+        trace!("stream {:p} compressed from {} to {} bytes", self.id, self.state_size, used);
+
+        Ok(buf)
+    }
+
+    /// Duplicate the stream, including its stream state, its offset and the database it holds.
+    pub fn try_clone(&self) -> Result<RawStream, Error> {
+        let mut id: RawStreamPtr = ptr::null_mut();
+
+        unsafe {
+            check_hs_error!(hs_copy_stream(&mut id, self.id));
+        }
+
+        debug!("stream cloned from {:p} to {:p}", self.id, id);
+
+        Ok(RawStream {
+            id: id,
+            state_size: self.state_size,
+            counters: ScanCounters::new(self.counters.stats()),
+            db_counters: self.db_counters.clone(),
+            db: self.db.clone(),
+        })
+    }
+
+    #[inline]
+    fn account(&self, ret: hs_error_t, bytes: usize, matches: u64) {
+        self.counters.add(ret, bytes, matches);
+        self.db_counters.add(ret, bytes, matches);
+    }
+
+    unsafe fn feed_chunk<F>(&self, bytes: &[u8], scratch: RawScratchPtr, handler: &mut F) -> hs_error_t
+        where F: FnMut(u32, u64, u64, u32) -> u32
+    {
+        hs_scan_stream(self.id,
+                       bytes.as_ptr() as *const i8,
+                       bytes.len() as u32,
+                       0,
+                       scratch,
+                       Some(on_match_event::<F>),
+                       handler as *mut F as *mut c_void)
+    }
+
+    /// Write the chunks of an iterator to the stream, until it is exhausted
+    /// or the handler returns non-zero to request that scanning cease.
+    ///
+    /// No more chunks are pulled from the iterator once scanning was terminated,
+    /// so bounded inspection policies can be expressed with a lazy iterator.
+    /// The chunk in which scanning was terminated is counted as fully written.
+    pub fn feed<I, S, H>(&self, chunks: I, scratch: &S, mut handler: H) -> Result<Feed, Error>
+        where I: IntoIterator,
+              I::Item: Scannable,
+              S: Scratch,
+              H: MatchHandler
+    {
+        let mut feed = Feed::default();
+        let matches = Cell::new(0);
+        let mut counting = |id, from, to, flags| {
+            matches.set(matches.get() + 1);
+
+            handler.on_match(id, from, to, flags)
+        };
+
+        for chunk in chunks {
+            let bytes = chunk.as_bytes();
+
+            feed.chunks += 1;
+
+            let ret = unsafe { self.feed_chunk(bytes, **scratch, &mut counting) };
+
+            self.account(ret, bytes.len(), matches.replace(0));
+
+            match ret {
+                HS_SUCCESS => feed.bytes += bytes.len(),
+                HS_SCAN_TERMINATED => {
+                    feed.bytes += bytes.len();
+                    feed.terminated = true;
+
+                    break;
+                }
+                err => return Err(Error::from(err)),
+            }
+        }
+
+        trace!("stream fed {} bytes in {} chunks at {:p}, terminated: {}",
+               feed.bytes,
+               feed.chunks,
+               self.id,
+               feed.terminated);
+
+        Ok(feed)
+    }
+}
 
 impl fmt::Debug for RawStream {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "RawStream({:p})", self.0)
+        write!(f, "RawStream{{id: {:p}, state_size: {}}}", self.id, self.state_size)
     }
 }
 
@@ -238,28 +490,20 @@ impl Deref for RawStream {
 
     #[inline]
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.id
     }
 }
 
 impl DerefMut for RawStream {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.id
     }
 }
 
 impl Clone for RawStream {
     fn clone(&self) -> Self {
-        let mut id: RawStreamPtr = ptr::null_mut();
-
-        unsafe {
-            assert_hs_error!(hs_copy_stream(&mut id, self.0));
-        }
-
-        debug!("stream cloned from {:p} to {:p}", self.0, id);
-
-        RawStream(id)
+        self.try_clone().unwrap()
     }
 }
 
@@ -271,15 +515,16 @@ impl<S: Scratch> Stream<S> for RawStream {
         context: Option<&D>,
     ) -> Result<&Self, Error> {
         unsafe {
-            check_hs_error!(hs_close_stream(
-                self.0,
-                **scratch,
-                mem::transmute(callback),
-                mem::transmute(context),
-            ));
+            let (ret, matches) = counted(callback, context, |on_event, ctxt| {
+                hs_close_stream(self.id, **scratch, on_event, ctxt)
+            });
+
+            self.account(ret, 0, matches);
+
+            check_hs_error!(ret);
         }
 
-        trace!("stream closed at {:p}", self.0);
+        trace!("stream closed at {:p}", self.id);
 
         Ok(&self)
     }
@@ -292,16 +537,16 @@ impl<S: Scratch> Stream<S> for RawStream {
         context: Option<&D>,
     ) -> Result<&Self, Error> {
         unsafe {
-            check_hs_error!(hs_reset_stream(
-                self.0,
-                flags,
-                **scratch,
-                mem::transmute(callback),
-                mem::transmute(context),
-            ));
+            let (ret, matches) = counted(callback, context, |on_event, ctxt| {
+                hs_reset_stream(self.id, flags, **scratch, on_event, ctxt)
+            });
+
+            self.account(ret, 0, matches);
+
+            check_hs_error!(ret);
         }
 
-        trace!("stream reset at {:p}", self.0);
+        trace!("stream reset at {:p}", self.id);
 
         Ok(&self)
     }
@@ -321,21 +566,27 @@ impl<T: Scannable, S: Scratch> BlockScanner<T, S> for RawStream {
         let bytes = data.as_bytes();
 
         unsafe {
-            check_hs_error!(hs_scan_stream(
-                self.0,
-                bytes.as_ptr() as *const i8,
-                bytes.len() as u32,
-                flags as u32,
-                **scratch,
-                mem::transmute(callback),
-                mem::transmute(context),
-            ));
+            let (ret, matches) = counted(callback, context, |on_event, ctxt| {
+                hs_scan_stream(
+                    self.id,
+                    bytes.as_ptr() as *const i8,
+                    bytes.len() as u32,
+                    flags as u32,
+                    **scratch,
+                    on_event,
+                    ctxt,
+                )
+            });
+
+            self.account(ret, bytes.len(), matches);
+
+            check_hs_error!(ret);
         }
 
         trace!(
             "stream scan {} bytes with stream at {:p}",
             bytes.len(),
-            self.0
+            self.id
         );
 
         Ok(&self)
@@ -347,6 +598,9 @@ pub mod tests {
     extern crate env_logger;
 
     use std::ptr;
+    use std::io::IoSlice;
+
+    use regex::Regex;
 
     use super::super::*;
 
@@ -366,6 +620,10 @@ pub mod tests {
 
         assert!(s.size().unwrap() > SCRATCH_SIZE);
 
+        assert!(Regex::new(r"RawScratch\{s: \w+, size: \d+\}")
+            .unwrap()
+            .is_match(&format!("{:?}", s)));
+
         let mut s2 = s.clone();
 
         assert!(*s2 != ptr::null_mut());
@@ -437,6 +695,72 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_vectored_scan_owned() {
+        let _ = env_logger::init();
+
+        let db: VectoredDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = RawScratch::alloc(&db).unwrap();
+
+        fn callback(id: u32, from: u64, to: u64, _: u32, _: &VectoredDatabase) -> u32 {
+            assert_eq!(id, 0);
+            assert_eq!(from, 3);
+            assert_eq!(to, 7);
+
+            1
+        }
+
+        let data = vec![String::from("foo"), String::from("te"), String::from("st")];
+
+        assert_eq!(db.scan(&data, 0, &s, Some(callback), Some(&db)).err(),
+                   Some(Error::ScanTerminated));
+
+        let data = vec![b"foo".to_vec(), b"test".to_vec()];
+
+        assert_eq!(db.scan(data.as_slice(), 0, &s, Some(callback), Some(&db)).err(),
+                   Some(Error::ScanTerminated));
+    }
+
+    #[test]
+    fn test_scan_io_slices() {
+        let _ = env_logger::init();
+
+        let buf = b"foo tebar stuff";
+        let bufs = [IoSlice::new(&buf[..6]), IoSlice::new(b"st"), IoSlice::new(&buf[9..])];
+
+        let db: VectoredDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = RawScratch::alloc(&db).unwrap();
+
+        fn callback(id: u32, from: u64, to: u64, _: u32, _: &VectoredDatabase) -> u32 {
+            assert_eq!(id, 0);
+            assert_eq!(from, 4);
+            assert_eq!(to, 8);
+
+            1
+        }
+
+        assert_eq!(db.scan(&bufs[..], 0, &s, Some(callback), Some(&db)).err(),
+                   Some(Error::ScanTerminated));
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let s = RawScratch::alloc(&db).unwrap();
+        let st = db.open_stream(0).unwrap();
+        let mut matches = Vec::new();
+
+        let feed = st.feed(&bufs, &s, |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(feed.chunks, 3);
+        assert_eq!(feed.bytes, 14);
+        assert_eq!(matches, vec![(0, 8)]);
+
+        st.close(&s, None, None::<&()>).unwrap();
+    }
+
     #[test]
     fn test_streaming_scan() {
         let _ = env_logger::init();
@@ -446,6 +770,10 @@ pub mod tests {
         let s = RawScratch::alloc(&db).unwrap();
         let st = db.open_stream(0).unwrap();
 
+        assert!(Regex::new(r"RawStream\{id: \w+, state_size: \d+\}")
+            .unwrap()
+            .is_match(&format!("{:?}", st)));
+
         let data = vec!["foo", "test", "bar"];
 
         fn callback(id: u32, from: u64, to: u64, flags: u32, _: &StreamingDatabase) -> u32 {
@@ -463,4 +791,112 @@ pub mod tests {
 
         st.close(&s, Some(callback), Some(&db)).unwrap();
     }
+
+    #[test]
+    fn test_streaming_feed() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+
+        let s = RawScratch::alloc(&db).unwrap();
+        let st = db.open_stream(0).unwrap();
+
+        let mut matches = Vec::new();
+
+        let feed = st.feed(vec!["foo", "te", "st", "bar"], &s, |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(feed,
+                   Feed {
+                       chunks: 4,
+                       bytes: 12,
+                       terminated: false,
+                   });
+        assert_eq!(matches, vec![(0, 7)]);
+
+        let st = db.open_stream(0).unwrap();
+        let mut pulled = 0;
+
+        let feed = st.feed(vec!["foo", "test", "bar", "test"].into_iter().inspect(|_| pulled += 1),
+                  &s,
+                  |_, _, _, _| 1)
+            .unwrap();
+
+        assert_eq!(feed,
+                   Feed {
+                       chunks: 2,
+                       bytes: 7,
+                       terminated: true,
+                   });
+        assert_eq!(pulled, 2);
+    }
+
+    #[test]
+    fn test_stream_compress() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+
+        let s = RawScratch::alloc(&db).unwrap();
+        let st = db.open_stream(0).unwrap();
+
+        st.feed(vec!["foo", "te"], &s, |_, _, _, _| 0).unwrap();
+
+        let bytes = st.compress().unwrap();
+
+        assert!(!bytes.is_empty());
+
+        let expanded = unsafe { db.expand_stream(&bytes) }.unwrap();
+        let mut matches = Vec::new();
+
+        expanded.feed(vec!["st"], &s, |id, _, to, _| {
+                matches.push((id, to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(0, 7)]);
+
+        st.close::<()>(&s, None, None).unwrap();
+        expanded.close::<()>(&s, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_scan_stats() {
+        let _ = env_logger::init();
+
+        fn callback(_: u32, _: u64, _: u64, _: u32, _: &()) -> u32 {
+            0
+        }
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let s = RawScratch::alloc(&db).unwrap();
+
+        db.scan("test foo test", 0, &s, Some(callback), Some(&())).unwrap();
+        db.scan("bar", 0, &s, None, None::<&()>).unwrap();
+
+        assert_eq!(db.scan_stats(), ScanStats { bytes: 16, matches: 2 });
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let s = RawScratch::alloc(&db).unwrap();
+        let st = db.open_stream(0).unwrap();
+
+        st.scan("foo te", 0, &s, Some(callback), Some(&())).unwrap();
+        st.scan("st", 0, &s, Some(callback), Some(&())).unwrap();
+        st.feed(vec!["test", "bar"], &s, |_, _, _, _| 0).unwrap();
+
+        assert_eq!(st.scan_stats(), ScanStats { bytes: 15, matches: 2 });
+
+        let st2 = db.open_stream(0).unwrap();
+
+        st2.scan("test", 0, &s, Some(callback), Some(&())).unwrap();
+
+        assert_eq!(st2.scan_stats(), ScanStats { bytes: 4, matches: 1 });
+        assert_eq!(db.scan_stats(), ScanStats { bytes: 19, matches: 3 });
+    }
 }
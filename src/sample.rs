@@ -0,0 +1,233 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use api::*;
+use errors::Error;
+use common::{BlockDatabase, StreamingDatabase};
+use runtime::RawStream;
+
+/// Which blocks or flows are scanned by a `Sampler`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sampling {
+    /// Scan everything.
+    All,
+    /// Scan every Nth unit, starting with the first one, `Every(0)` is the same as `All`.
+    Every(u64),
+    /// Scan each unit with the probability, in `0.0..=1.0`.
+    Probability(f64),
+}
+
+/// The sampling metadata of a scanned block or flow.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sample {
+    /// The index of the unit, counting the skipped ones.
+    pub index: u64,
+    /// The number of units skipped since the previous sample.
+    pub skipped: u64,
+    /// The expected fraction of the units which are scanned,
+    /// to extrapolate the match counts of the whole traffic.
+    pub rate: f64,
+}
+
+/// A sampler scanning only a part of the blocks or flows, so an overloaded sensor
+/// degrades gracefully instead of dropping everything.
+///
+/// The sampler can be shared between threads, the probabilistic sampling is deterministic for a seed.
+#[derive(Debug)]
+pub struct Sampler {
+    sampling: Sampling,
+    seen: AtomicU64,
+    sampled: AtomicU64,
+    last: AtomicU64,
+    state: AtomicU64,
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The SplitMix64 generator, good enough to pick the samples.
+pub(crate) fn splitmix64(state: &AtomicU64) -> u64 {
+    let mut z = state.fetch_add(GOLDEN_GAMMA, Ordering::Relaxed).wrapping_add(GOLDEN_GAMMA);
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A uniform random number in `0.0..1.0`.
+fn uniform(state: &AtomicU64) -> f64 {
+    (splitmix64(state) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Sampler {
+    pub fn new(sampling: Sampling) -> Sampler {
+        Sampler::with_seed(sampling, 0)
+    }
+
+    /// Create a sampler with the seed of the probabilistic sampling.
+    pub fn with_seed(sampling: Sampling, seed: u64) -> Sampler {
+        Sampler {
+            sampling: sampling,
+            seen: AtomicU64::new(0),
+            sampled: AtomicU64::new(0),
+            last: AtomicU64::new(0),
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    pub fn sampling(&self) -> Sampling {
+        self.sampling
+    }
+
+    /// The expected fraction of the units which are scanned.
+    pub fn rate(&self) -> f64 {
+        match self.sampling {
+            Sampling::All | Sampling::Every(0) => 1.0,
+            Sampling::Every(n) => 1.0 / n as f64,
+            Sampling::Probability(p) => p.max(0.0).min(1.0),
+        }
+    }
+
+    /// The number of units seen and the number of units sampled.
+    pub fn totals(&self) -> (u64, u64) {
+        (self.seen.load(Ordering::Relaxed), self.sampled.load(Ordering::Relaxed))
+    }
+
+    /// Decide whether the next unit is scanned, returns its sampling metadata if so.
+    pub fn sample(&self) -> Option<Sample> {
+        let index = self.seen.fetch_add(1, Ordering::Relaxed);
+
+        let sampled = match self.sampling {
+            Sampling::All | Sampling::Every(0) => true,
+            Sampling::Every(n) => index % n == 0,
+            Sampling::Probability(p) => uniform(&self.state) < p,
+        };
+
+        if !sampled {
+            return None;
+        }
+
+        self.sampled.fetch_add(1, Ordering::Relaxed);
+
+        let last = self.last.swap(index + 1, Ordering::Relaxed);
+
+        Some(Sample {
+            index: index,
+            skipped: index.saturating_sub(last),
+            rate: self.rate(),
+        })
+    }
+
+    /// Scan the block if it is sampled, returns `None` if it was skipped.
+    pub fn scan<T, S, D>(&self,
+                         db: &BlockDatabase,
+                         data: T,
+                         flags: ScanFlags,
+                         scratch: &S,
+                         callback: Option<MatchEventCallback<D>>,
+                         context: Option<&D>)
+                         -> Result<Option<Sample>, Error>
+        where T: Scannable,
+              S: Scratch
+    {
+        match self.sample() {
+            Some(sample) => {
+                try!(db.scan(data.as_bytes(), flags, scratch, callback, context));
+
+                Ok(Some(sample))
+            }
+            None => {
+                trace!("skipped block of {} bytes", data.as_bytes().len());
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Open a stream for a new flow if it is sampled, returns `None` if the flow should be skipped.
+    pub fn open_stream(&self, db: &StreamingDatabase, flags: StreamFlags) -> Result<Option<(RawStream, Sample)>, Error> {
+        match self.sample() {
+            Some(sample) => Ok(Some((try!(db.open_stream(flags)), sample))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::Cell;
+
+    use super::super::*;
+
+    #[test]
+    fn test_sampling() {
+        let sampler = Sampler::new(Sampling::Every(3));
+
+        let samples = (0..7).map(|_| sampler.sample()).collect::<Vec<_>>();
+
+        assert_eq!(samples.iter().filter(|s| s.is_some()).count(), 3);
+        assert_eq!(samples[3],
+                   Some(Sample {
+                       index: 3,
+                       skipped: 2,
+                       rate: 1.0 / 3.0,
+                   }));
+        assert_eq!(sampler.totals(), (7, 3));
+
+        assert!((0..10).all(|_| Sampler::new(Sampling::All).sample().is_some()));
+        assert!((0..10).all(|_| Sampler::new(Sampling::Probability(0.0)).sample().is_none()));
+
+        let sampler = Sampler::with_seed(Sampling::Probability(0.25), 42);
+
+        for _ in 0..10000 {
+            sampler.sample();
+        }
+
+        let (seen, sampled) = sampler.totals();
+
+        assert_eq!(seen, 10000);
+        assert!(sampled > 2000 && sampled < 3000);
+
+        let a = Sampler::with_seed(Sampling::Probability(0.5), 7);
+        let b = Sampler::with_seed(Sampling::Probability(0.5), 7);
+
+        assert!((0..100).all(|_| a.sample().is_some() == b.sample().is_some()));
+    }
+
+    fn on_match(_: u32, _: u64, _: u64, _: u32, matches: &Cell<usize>) -> u32 {
+        matches.set(matches.get() + 1);
+
+        0
+    }
+
+    #[test]
+    fn test_sampled_scan() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let sampler = Sampler::new(Sampling::Every(2));
+        let matches = Cell::new(0);
+
+        let samples = (0..4)
+            .map(|_| sampler.scan(&db, "some test data", 0, &s, Some(on_match), Some(&matches)).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(matches.get(), 2);
+        assert_eq!(samples.iter().map(|s| s.map(|s| s.index)).collect::<Vec<_>>(),
+                   vec![Some(0), None, Some(2), None]);
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let sampler = Sampler::new(Sampling::Every(2));
+
+        let (stream, sample) = sampler.open_stream(&db, 0).unwrap().unwrap();
+
+        assert_eq!(sample.index, 0);
+        assert!(sampler.open_stream(&db, 0).unwrap().is_none());
+
+        stream.close(&s, None, None::<&()>).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use api::*;
+use constants::*;
+use errors::Error;
+use common::RawDatabase;
+use compile::{CompileFlags, ExprExt, Pattern, Patterns};
+use limits::CompileLimits;
+
+/// The flags kept on the untrusted patterns by default.
+pub const SAFE_FLAGS: u32 = HS_FLAG_CASELESS | HS_FLAG_DOTALL | HS_FLAG_MULTILINE | HS_FLAG_SINGLEMATCH |
+                            HS_FLAG_UTF8;
+
+/// A compile profile for the user supplied patterns, so a multi-tenant service can accept customer regexes.
+///
+/// The flags outside of `allowed_flags` are stripped, like `HS_FLAG_ALLOWEMPTY` which reports a match at every offset,
+/// or `HS_FLAG_SOM_LEFTMOST` and `HS_FLAG_UCP` which are expensive; the approximate matching is stripped too.
+/// The patterns are then checked against the length and complexity limits with their expression info,
+/// and the database is compiled within the resource limits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SafeProfile {
+    /// The flags kept on the patterns.
+    pub allowed_flags: u32,
+    /// The maximum number of patterns.
+    pub max_patterns: usize,
+    /// The maximum length of an expression, in bytes.
+    pub max_expression_len: usize,
+    /// The minimum length of a match, to reject the patterns matching almost everywhere.
+    pub min_width: usize,
+    /// The maximum bounded length of a match, to reject the large bounded repeats like `a{10000}`.
+    pub max_width: usize,
+    /// The resource limits of the compilation.
+    pub limits: CompileLimits,
+}
+
+impl Default for SafeProfile {
+    fn default() -> Self {
+        SafeProfile {
+            allowed_flags: SAFE_FLAGS,
+            max_patterns: 1000,
+            max_expression_len: 1024,
+            min_width: 1,
+            max_width: 4096,
+            limits: CompileLimits::new()
+                .with_max_database_size(16 * 1024 * 1024)
+                .with_max_scratch_size(4 * 1024 * 1024)
+                .with_timeout(Duration::from_secs(10)),
+        }
+    }
+}
+
+impl SafeProfile {
+    pub fn new() -> SafeProfile {
+        SafeProfile::default()
+    }
+
+    pub fn with_allowed_flags(mut self, flags: u32) -> Self {
+        self.allowed_flags = flags;
+        self
+    }
+
+    pub fn with_max_patterns(mut self, count: usize) -> Self {
+        self.max_patterns = count;
+        self
+    }
+
+    pub fn with_max_expression_len(mut self, len: usize) -> Self {
+        self.max_expression_len = len;
+        self
+    }
+
+    pub fn with_min_width(mut self, width: usize) -> Self {
+        self.min_width = width;
+        self
+    }
+
+    pub fn with_max_width(mut self, width: usize) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: CompileLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Strip the dangerous flags and parameters of the pattern, then check it against the length and complexity limits.
+    pub fn sanitize(&self, pattern: &Pattern) -> Result<Pattern, Error> {
+        if pattern.expression.len() > self.max_expression_len {
+            return Err(Error::CompilerError(format!("pattern #{} is longer than {} bytes",
+                                                    pattern.id,
+                                                    self.max_expression_len)));
+        }
+
+        let mut safe = pattern.clone();
+
+        safe.flags = CompileFlags(pattern.flags.0 & self.allowed_flags);
+
+        if safe.flags != pattern.flags {
+            debug!("strip flags `{}` of pattern #{}",
+                   CompileFlags(pattern.flags.0 & !self.allowed_flags),
+                   pattern.id);
+        }
+
+        if pattern.ext.is_approximate() {
+            debug!("strip approximate matching of pattern #{}", pattern.id);
+
+            let mut ext = ExprExt::new();
+
+            if let Some(offset) = pattern.ext.min_offset() {
+                ext = ext.with_min_offset(offset);
+            }
+            if let Some(offset) = pattern.ext.max_offset() {
+                ext = ext.with_max_offset(offset);
+            }
+            if let Some(length) = pattern.ext.min_length() {
+                ext = ext.with_min_length(length);
+            }
+
+            safe.ext = ext;
+        }
+
+        let info = try!(safe.info());
+
+        if info.min_width < self.min_width {
+            return Err(Error::CompilerError(format!("pattern `{}` can match less than {} bytes",
+                                                    safe,
+                                                    self.min_width)));
+        }
+
+        if let Some(width) = info.bounded_width() {
+            if width > self.max_width {
+                return Err(Error::CompilerError(format!("pattern `{}` can match {} bytes, more than {}",
+                                                        safe,
+                                                        width,
+                                                        self.max_width)));
+            }
+        }
+
+        Ok(safe)
+    }
+
+    /// Sanitize the patterns and compile them within the resource limits.
+    pub fn compile<T: Type + 'static>(&self, patterns: &Patterns) -> Result<RawDatabase<T>, Error> {
+        if patterns.len() > self.max_patterns {
+            return Err(Error::CompilerError(format!("{} patterns are more than {}",
+                                                    patterns.len(),
+                                                    self.max_patterns)));
+        }
+
+        let mut safe = Patterns::with_capacity(patterns.len());
+
+        for pattern in patterns {
+            safe.push(try!(self.sanitize(pattern)));
+        }
+
+        self.limits.compile(&safe)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_sanitize() {
+        let _ = env_logger::init();
+
+        let profile = SafeProfile::new().with_max_expression_len(16).with_max_width(100);
+
+        let p = profile.sanitize(&pattern!{"foo", flags => HS_FLAG_CASELESS | HS_FLAG_SOM_LEFTMOST | HS_FLAG_UCP})
+            .unwrap();
+
+        assert_eq!(p.flags, CompileFlags(HS_FLAG_CASELESS));
+
+        let ext = ExprExt::new().with_min_offset(4).with_edit_distance(1);
+        let p = profile.sanitize(&pattern!{"foo", flags => 0, id => 1, ext => ext}).unwrap();
+
+        assert_eq!(p.ext, ExprExt::new().with_min_offset(4));
+
+        assert!(profile.sanitize(&pattern!{"a{1000}"}).is_err());
+        assert!(profile.sanitize(&pattern!{"foo.*bar"}).is_ok());
+        assert!(profile.sanitize(&pattern!{"x*", flags => HS_FLAG_ALLOWEMPTY}).is_err());
+        assert!(profile.sanitize(&pattern!{"0123456789abcdefg"}).is_err());
+    }
+
+    #[test]
+    fn test_safe_compile() {
+        let _ = env_logger::init();
+
+        let profile = SafeProfile::new();
+
+        let db: BlockDatabase = profile.compile(&patterns!(["foo", "bar\\d+"], flags => HS_FLAG_SOM_LEFTMOST))
+            .unwrap();
+
+        assert_matches!(&db, "foo bar12", [(1, 3), (2, 8), (2, 9)]);
+
+        assert!(profile.with_max_patterns(1).compile::<Block>(&patterns!(["foo", "bar"])).is_err());
+
+        let limits = CompileLimits::new().with_max_database_size(16);
+
+        match profile.with_limits(limits).compile::<Block>(&patterns!(["foo"])) {
+            Err(Error::DatabaseTooLarge(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
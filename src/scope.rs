@@ -0,0 +1,133 @@
+use std::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Scope, ScopedJoinHandle};
+
+use api::*;
+use errors::Error;
+use common::BlockDatabase;
+use pool::ScratchPool;
+
+type Pools = Arc<Mutex<HashMap<usize, Arc<ScratchPool>>>>;
+
+/// A scope spawning scans on threads which are joined when the scope ends, returned by `scan_scope`.
+///
+/// Each scan takes a scratch from a pool per database, which clones a scratch for each thread
+/// scanning concurrently and reuses it for the following scans.
+pub struct ScanScope<'scope, 'env: 'scope> {
+    scope: &'scope Scope<'scope, 'env>,
+    pools: Pools,
+}
+
+impl<'scope, 'env> fmt::Debug for ScanScope<'scope, 'env> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ScanScope{{databases: {}}}", self.pools.lock().unwrap().len())
+    }
+}
+
+/// A scan running in a `ScanScope`.
+#[derive(Debug)]
+pub struct ScanHandle<'scope>(ScopedJoinHandle<'scope, Result<Vec<Match>, Error>>);
+
+impl<'scope> ScanHandle<'scope> {
+    /// Wait for the matches of the scan, returns `Error::Failed` if the scanning thread panicked.
+    pub fn join(self) -> Result<Vec<Match>, Error> {
+        self.0.join().unwrap_or(Err(Error::Failed(0)))
+    }
+
+    /// Whether the scan finished.
+    pub fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+}
+
+fn on_scope_match(id: u32, from: u64, to: u64, flags: u32, matches: &RefCell<Vec<Match>>) -> u32 {
+    matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+    0
+}
+
+fn pool(pools: &Pools, db: &BlockDatabase) -> Result<Arc<ScratchPool>, Error> {
+    let mut pools = pools.lock().unwrap();
+    let key = db as *const BlockDatabase as usize;
+
+    if let Some(pool) = pools.get(&key) {
+        return Ok(pool.clone());
+    }
+
+    let pool = Arc::new(try!(ScratchPool::new(db)));
+
+    pools.insert(key, pool.clone());
+
+    Ok(pool)
+}
+
+impl<'scope, 'env> ScanScope<'scope, 'env> {
+    /// Scan the data with the database on a new thread, collecting all the matches.
+    pub fn spawn_scan<T>(&self, db: &'env BlockDatabase, data: T) -> ScanHandle<'scope>
+        where T: Scannable + Send + 'env
+    {
+        let pools = self.pools.clone();
+
+        ScanHandle(self.scope.spawn(move || {
+            let pool = try!(pool(&pools, db));
+            let matches = RefCell::new(Vec::new());
+
+            try!(pool.with_scratch(|scratch| {
+                db.scan(data.as_bytes(), 0, scratch, Some(on_scope_match), Some(&matches))
+                    .map(|_| ())
+            }));
+
+            Ok(matches.into_inner())
+        }))
+    }
+}
+
+/// Run the closure with a scope spawning parallel scans, and join all the scans before returning.
+///
+/// The scans can borrow the databases and the data of the caller, like `std::thread::scope`,
+/// which also panics if a scan panicked and wasn't joined.
+pub fn scan_scope<'env, F, R>(f: F) -> R
+    where F: for<'scope> FnOnce(&ScanScope<'scope, 'env>) -> R
+{
+    thread::scope(|scope| {
+        f(&ScanScope {
+            scope: scope,
+            pools: Arc::new(Mutex::new(HashMap::new())),
+        })
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scan_scope() {
+        let _ = env_logger::init();
+
+        let foo: BlockDatabase = patterns!(["foo", "bar"]).build().unwrap();
+        let baz: BlockDatabase = patterns!([{"baz", id => 3}]).build().unwrap();
+        let data = vec!["foo bar", "bar", "baz foo", "qux"];
+
+        let matches = scan_scope(|scope| {
+            let mut handles = Vec::new();
+
+            for &s in &data {
+                handles.push(scope.spawn_scan(&foo, s));
+            }
+
+            handles.push(scope.spawn_scan(&baz, data[2].as_bytes()));
+
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap().iter().map(|m| (m.id, m.to)).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(matches,
+                   vec![vec![(1, 3), (2, 7)], vec![(2, 3)], vec![(1, 7)], vec![], vec![(3, 3)]]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::ops::Deref;
+
+use api::*;
+use errors::Error;
+use runtime::RawScratch;
+use pool::PooledScratch;
+use local::LocalScratch;
+
+/// A scratch space borrowed, taken from a pool, or taken from the thread local registry.
+///
+/// Higher level helpers accept `Into<ScratchRef>`, so they don't need an overload
+/// for each way of managing scratch spaces, and `ScratchRef` can be passed
+/// to the scan functions as any `Scratch`.
+pub enum ScratchRef<'a> {
+    Borrowed(&'a mut RawScratch),
+    Pooled(PooledScratch<'a>),
+    Local(LocalScratch),
+}
+
+impl<'a> fmt::Debug for ScratchRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScratchRef::Borrowed(ref s) => write!(f, "Borrowed({:?})", s),
+            ScratchRef::Pooled(ref s) => write!(f, "Pooled({:?})", s),
+            ScratchRef::Local(ref s) => write!(f, "Local({:?})", s),
+        }
+    }
+}
+
+impl<'a> ScratchRef<'a> {
+    /// The underlying scratch space.
+    pub fn scratch(&self) -> &RawScratch {
+        match *self {
+            ScratchRef::Borrowed(ref s) => s,
+            ScratchRef::Pooled(ref s) => s,
+            ScratchRef::Local(ref s) => s,
+        }
+    }
+
+    /// The underlying scratch space.
+    pub fn scratch_mut(&mut self) -> &mut RawScratch {
+        match *self {
+            ScratchRef::Borrowed(ref mut s) => s,
+            ScratchRef::Pooled(ref mut s) => s,
+            ScratchRef::Local(ref mut s) => s,
+        }
+    }
+}
+
+impl<'a> From<&'a mut RawScratch> for ScratchRef<'a> {
+    fn from(s: &'a mut RawScratch) -> Self {
+        ScratchRef::Borrowed(s)
+    }
+}
+
+impl<'a> From<PooledScratch<'a>> for ScratchRef<'a> {
+    fn from(s: PooledScratch<'a>) -> Self {
+        ScratchRef::Pooled(s)
+    }
+}
+
+impl<'a> From<LocalScratch> for ScratchRef<'a> {
+    fn from(s: LocalScratch) -> Self {
+        ScratchRef::Local(s)
+    }
+}
+
+impl<'a> Deref for ScratchRef<'a> {
+    type Target = RawScratchPtr;
+
+    #[inline]
+    fn deref(&self) -> &RawScratchPtr {
+        &**self.scratch()
+    }
+}
+
+impl<'a> Scratch for ScratchRef<'a> {
+    #[inline]
+    fn size(&self) -> Result<usize, Error> {
+        self.scratch().size()
+    }
+
+    #[inline]
+    fn realloc<T: Database>(&mut self, db: &T) -> Result<&Self, Error> {
+        try!(self.scratch_mut().realloc(db));
+
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    fn count_matches<'a, S: Into<ScratchRef<'a>>>(db: &BlockDatabase, data: &str, scratch: S) -> usize {
+        fn callback(_: u32, _: u64, _: u64, _: u32, n: &::std::cell::Cell<usize>) -> u32 {
+            n.set(n.get() + 1);
+
+            0
+        }
+
+        let n = ::std::cell::Cell::new(0);
+
+        db.scan(data, 0, &scratch.into(), Some(callback), Some(&n)).unwrap();
+
+        n.get()
+    }
+
+    #[test]
+    fn test_scratch_ref() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let mut s = db.alloc().unwrap();
+        let pool = ScratchPool::new(&db).unwrap();
+
+        assert_eq!(count_matches(&db, "test", &mut s), 1);
+        assert_eq!(count_matches(&db, "test test", pool.get()), 2);
+        assert_eq!(count_matches(&db, "test test test", local_scratch(&db).unwrap()), 3);
+
+        let mut r = ScratchRef::from(pool.get());
+
+        assert_eq!(r.size().unwrap(), s.size().unwrap());
+
+        let db2: StreamingDatabase = pattern!{"foo.*bar"}.build().unwrap();
+
+        r.realloc(&db2).unwrap();
+    }
+}
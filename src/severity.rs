@@ -0,0 +1,127 @@
+use std::cell::RefCell;
+
+use api::*;
+use errors::Error;
+use common::BlockDatabase;
+use groups::Groups;
+
+/// The group of the patterns whose matches terminate the scan.
+pub const BLOCKING: &'static str = "blocking";
+
+/// The group of the patterns whose matches are only collected.
+pub const LOG_ONLY: &'static str = "log-only";
+
+/// The outcome of a scan with a `SeverityPolicy`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Verdict {
+    /// The match of a blocking pattern which terminated the scan.
+    pub blocked: Option<Match>,
+    /// The matches of the log-only patterns located before the scan terminated.
+    pub logged: Vec<Match>,
+}
+
+impl Verdict {
+    pub fn is_blocked(&self) -> bool {
+        self.blocked.is_some()
+    }
+}
+
+/// A scan policy where the first match of a pattern tagged `blocking` terminates the scan,
+/// while the matches of the patterns tagged `log-only` are collected.
+///
+/// The matches of the patterns in neither group are ignored,
+/// a pattern in both groups is blocking.
+#[derive(Debug, Clone)]
+pub struct SeverityPolicy<'a> {
+    groups: &'a Groups,
+    blocking: String,
+    log_only: String,
+}
+
+struct Judging<'a> {
+    policy: &'a SeverityPolicy<'a>,
+    verdict: RefCell<Verdict>,
+}
+
+fn on_judged_match(id: u32, from: u64, to: u64, flags: u32, judging: &Judging) -> u32 {
+    let groups = judging.policy.groups;
+    let mut verdict = judging.verdict.borrow_mut();
+
+    if groups.contains(id, &judging.policy.blocking) {
+        debug!("blocking pattern {} matched at {}, terminate scanning", id, to);
+
+        verdict.blocked = Some(Match::new(id, from, to, flags));
+
+        1
+    } else {
+        if groups.contains(id, &judging.policy.log_only) {
+            verdict.logged.push(Match::new(id, from, to, flags));
+        }
+
+        0
+    }
+}
+
+impl<'a> SeverityPolicy<'a> {
+    /// Use the `blocking` and `log-only` groups of the registry.
+    pub fn new(groups: &'a Groups) -> SeverityPolicy<'a> {
+        Self::with_groups(groups, BLOCKING, LOG_ONLY)
+    }
+
+    /// Use other groups for the blocking and the log-only patterns.
+    pub fn with_groups<S: Into<String>>(groups: &'a Groups, blocking: S, log_only: S) -> SeverityPolicy<'a> {
+        SeverityPolicy {
+            groups: groups,
+            blocking: blocking.into(),
+            log_only: log_only.into(),
+        }
+    }
+
+    /// Scan the data until a blocking pattern matches.
+    pub fn scan<T, S>(&self, db: &BlockDatabase, data: T, scratch: &S) -> Result<Verdict, Error>
+        where T: Scannable,
+              S: Scratch
+    {
+        let judging = Judging {
+            policy: self,
+            verdict: RefCell::new(Verdict::default()),
+        };
+
+        match db.scan(data.as_bytes(), 0, scratch, Some(on_judged_match), Some(&judging)) {
+            Ok(_) | Err(Error::ScanTerminated) => Ok(judging.verdict.into_inner()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_severity_policy() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = patterns!(["warn", "exploit", "note"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut groups = Groups::new();
+
+        groups.tag(1, LOG_ONLY).tag(2, BLOCKING);
+
+        let policy = SeverityPolicy::new(&groups);
+
+        let verdict = policy.scan(&db, "warn note warn exploit warn", &s).unwrap();
+
+        assert!(verdict.is_blocked());
+        assert_eq!(verdict.blocked.map(|m| (m.id, m.to)), Some((2, 22)));
+        assert_eq!(verdict.logged.iter().map(|m| m.to).collect::<Vec<_>>(), vec![4, 14]);
+
+        let verdict = policy.scan(&db, "warn note", &s).unwrap();
+
+        assert!(!verdict.is_blocked());
+        assert_eq!(verdict.logged.len(), 1);
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::cmp;
+use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use api::*;
+use errors::Error;
+use common::{RawDatabase, BlockDatabase};
+use compile::Patterns;
+use runtime::RawScratch;
+use multi::MultiScanner;
+
+/// A large pattern set split into several databases,
+/// which are compiled in parallel and scanned one after another.
+pub struct Shards<T: Type> {
+    dbs: Vec<RawDatabase<T>>,
+}
+
+impl<T: Type> fmt::Debug for Shards<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Shards<{}>{{dbs: {:?}}}", T::name(), self.dbs)
+    }
+}
+
+/// Split the patterns into at most `count` shards of consecutive patterns.
+fn split(patterns: &Patterns, count: usize) -> Vec<Patterns> {
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+
+    let size = (patterns.len() + cmp::max(count, 1) - 1) / cmp::max(count, 1);
+
+    patterns.chunks(size).map(Patterns::from).collect()
+}
+
+impl<T: Type + 'static> Shards<T> {
+    /// Split the patterns into `count` shards, compiled in parallel on all the CPUs.
+    pub fn compile(patterns: &Patterns, count: usize) -> Result<Shards<T>, Error> {
+        Self::compile_with_threads(patterns, count, num_cpus())
+    }
+
+    /// Split the patterns into `count` shards, compiled in parallel on at most `threads` threads.
+    ///
+    /// Returns the error of the first shard which fails to compile.
+    pub fn compile_with_threads(patterns: &Patterns, count: usize, threads: usize) -> Result<Shards<T>, Error> {
+        Self::compile_for_platform(patterns, count, threads, None)
+    }
+
+    /// Split the patterns into `count` shards, compiled for the target platform in parallel on at most `threads` threads.
+    pub fn compile_for_platform(patterns: &Patterns,
+                                count: usize,
+                                threads: usize,
+                                platform: Option<Platform>)
+                                -> Result<Shards<T>, Error> {
+        let shards = Arc::new(split(patterns, count));
+        let results = Arc::new(Mutex::new((0..shards.len()).map(|_| None).collect::<Vec<_>>()));
+        let next = Arc::new(AtomicUsize::new(0));
+
+        let workers = (0..cmp::min(cmp::max(threads, 1), shards.len()))
+            .map(|_| {
+                let shards = shards.clone();
+                let results = results.clone();
+                let next = next.clone();
+
+                thread::spawn(move || loop {
+                    let shard = next.fetch_add(1, Ordering::Relaxed);
+
+                    if shard >= shards.len() {
+                        break;
+                    }
+
+                    let db: Result<RawDatabase<T>, Error> = shards[shard].build_for_platform(&platform_info(platform));
+
+                    results.lock().unwrap()[shard] = Some(db);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for worker in workers {
+            if worker.join().is_err() {
+                return Err(Error::Failed(0));
+            }
+        }
+
+        let results = match Arc::try_unwrap(results) {
+            Ok(results) => results.into_inner().unwrap(),
+            Err(_) => return Err(Error::Failed(0)),
+        };
+
+        let mut dbs = Vec::with_capacity(results.len());
+
+        for result in results {
+            match result {
+                Some(db) => dbs.push(try!(db)),
+                None => return Err(Error::Failed(0)),
+            }
+        }
+
+        debug!("compiled {} patterns into {} shards", patterns.len(), dbs.len());
+
+        Ok(Shards { dbs: dbs })
+    }
+}
+
+impl<T: Type> Shards<T> {
+    /// The number of shards.
+    pub fn len(&self) -> usize {
+        self.dbs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dbs.is_empty()
+    }
+
+    /// The databases of the shards.
+    pub fn databases(&self) -> &[RawDatabase<T>] {
+        &self.dbs
+    }
+
+    /// Allocate a scratch space large enough for all the shards,
+    /// returns `Error::Invalid` if there is no shard.
+    pub fn alloc(&self) -> Result<RawScratch, Error> {
+        let mut dbs = self.dbs.iter();
+        let mut scratch = match dbs.next() {
+            Some(db) => try!(db.alloc()),
+            None => return Err(Error::Invalid),
+        };
+
+        for db in dbs {
+            try!(db.realloc(&mut scratch));
+        }
+
+        Ok(scratch)
+    }
+}
+
+impl Shards<Block> {
+    /// A scanner running all the shards over a buffer.
+    pub fn scanner<'a>(&'a self) -> MultiScanner<'a> {
+        MultiScanner::new(self.dbs.iter().collect::<Vec<&BlockDatabase>>())
+    }
+}
+
+/// The number of CPUs of the host.
+#[cfg(unix)]
+fn num_cpus() -> usize {
+    let n = unsafe { ::libc::sysconf(::libc::_SC_NPROCESSORS_ONLN) };
+
+    if n > 0 { n as usize } else { 1 }
+}
+
+#[cfg(not(unix))]
+fn num_cpus() -> usize {
+    1
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_shards() {
+        let _ = env_logger::init();
+
+        let patterns = patterns!(["foo", "bar", "baz", "qux", "quux"]);
+
+        let shards: Shards<Block> = Shards::compile_with_threads(&patterns, 2, 4).unwrap();
+
+        assert_eq!(shards.len(), 2);
+
+        let s = shards.alloc().unwrap();
+        let mut matches = Vec::new();
+
+        shards.scanner()
+            .ordered(true)
+            .scan("quux baz foo", &s, |m| {
+                matches.push((m.db, m.id));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(1, 5), (0, 3), (0, 1)]);
+
+        let shards: Shards<Streaming> = Shards::compile(&patterns, 10).unwrap();
+
+        assert_eq!(shards.len(), 5);
+
+        let patterns = patterns!(["foo", "bar", "(", "qux"]);
+
+        assert!(Shards::<Block>::compile_with_threads(&patterns, 4, 2).is_err());
+        assert!(Shards::<Block>::compile(&Patterns::new(), 4).unwrap().alloc().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::sync::Arc;
+use std::ops::Deref;
+
+use api::*;
+use errors::Error;
+use common::RawDatabase;
+use runtime::{RawScratch, RawStream};
+
+/// A cheap handle to a database shared by several owners and threads.
+///
+/// Cloning the handle only bumps a reference count, the `hs_database_t` is freed
+/// when the last handle is dropped, so it outlives the scans holding a handle on other threads.
+///
+/// The streams opened or expanded with a handle hold their own handle,
+/// so dropping the database while its streams are still open doesn't free the state they point into.
+pub struct SharedDatabase<T: Type>(Arc<RawDatabase<T>>);
+
+impl<T: Type> fmt::Debug for SharedDatabase<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "SharedDatabase<{}>{{db: {:p}, handles: {}}}",
+               T::name(),
+               **self.0,
+               self.handles())
+    }
+}
+
+impl<T: Type> Clone for SharedDatabase<T> {
+    fn clone(&self) -> Self {
+        SharedDatabase(self.0.clone())
+    }
+}
+
+impl<T: Type> SharedDatabase<T> {
+    pub fn new(db: RawDatabase<T>) -> SharedDatabase<T> {
+        SharedDatabase(Arc::new(db))
+    }
+
+    /// The number of handles to the database.
+    pub fn handles(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// Whether both handles point to the same database.
+    pub fn ptr_eq(&self, other: &SharedDatabase<T>) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Take the database back if this is the last handle, returns the handle otherwise.
+    pub fn try_unwrap(self) -> Result<RawDatabase<T>, SharedDatabase<T>> {
+        Arc::try_unwrap(self.0).map_err(SharedDatabase)
+    }
+}
+
+impl SharedDatabase<Streaming> {
+    /// Open and initialise a stream, which keeps the database alive until it is dropped.
+    pub fn open_stream(&self, flags: StreamFlags) -> Result<RawStream, Error> {
+        Ok(try!(self.0.open_stream(flags)).holding(self.clone()))
+    }
+
+    /// Expand a compressed stream, which keeps the database alive until it is dropped.
+    ///
+    /// # Safety
+    ///
+    /// See `StreamingDatabase::expand_stream`.
+    pub unsafe fn expand_stream(&self, bytes: &[u8]) -> Result<RawStream, Error> {
+        Ok(try!(self.0.expand_stream(bytes)).holding(self.clone()))
+    }
+}
+
+impl<T: Type> From<RawDatabase<T>> for SharedDatabase<T> {
+    fn from(db: RawDatabase<T>) -> Self {
+        SharedDatabase::new(db)
+    }
+}
+
+impl<T: Type> Deref for SharedDatabase<T> {
+    type Target = RawDatabase<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Type> ScratchAllocator<RawScratch> for SharedDatabase<T> {
+    #[inline]
+    fn alloc(&self) -> Result<RawScratch, Error> {
+        self.0.alloc()
+    }
+
+    #[inline]
+    fn realloc(&self, s: &mut RawScratch) -> Result<&Self, Error> {
+        try!(self.0.realloc(s));
+
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::thread;
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_shared_database() {
+        let _ = env_logger::init();
+
+        let patterns = patterns!(["foo", "bar", "baz"]);
+        let db = SharedDatabase::new(patterns.build().unwrap());
+
+        validate_database(&*db);
+
+        let pool = ScratchPool::new(&db).unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+
+                thread::spawn(move || db.matches(b"foo bar").unwrap())
+            })
+            .collect();
+
+        for t in threads {
+            assert_eq!(t.join().unwrap().len(), 2);
+        }
+
+        db.scan("baz", 0, &*pool.get(), None, None::<&()>).unwrap();
+
+        let other = db.clone();
+
+        assert!(other.ptr_eq(&db));
+
+        let db = db.try_unwrap().unwrap_err();
+
+        drop(other);
+
+        validate_database(&db.try_unwrap().unwrap());
+
+        let task = CompileTask::<Block>::spawn(patterns, |_| {});
+        let db: SharedDatabase<Block> = task.wait().unwrap().into();
+
+        assert_eq!(db.handles(), 1);
+    }
+
+    #[test]
+    fn test_shared_stream() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foo.*bar"]).build().unwrap();
+        let db = SharedDatabase::new(db);
+        let scratch = db.alloc().unwrap();
+        let stream = db.open_stream(0).unwrap();
+
+        assert_eq!(db.handles(), 2);
+
+        let copy = stream.clone();
+
+        assert_eq!(db.handles(), 3);
+
+        drop(db);
+
+        let mut matches = Vec::new();
+
+        stream.feed(vec!["foo", "bar"], &scratch, |id, _, to, _| {
+                matches.push((id, to));
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches, vec![(1, 6)]);
+
+        stream.close(&scratch, None, None::<&()>).unwrap();
+        copy.close(&scratch, None, None::<&()>).unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use std::io;
+use std::fmt;
+use std::ptr;
+use std::fs::File;
+use std::ffi::CString;
+use std::ops::Deref;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use libc;
+
+use raw::*;
+use api::*;
+use errors::Error;
+use common::RawDatabase;
+
+/// The database is placed after a header, aligned to a cache line.
+const HEADER_SIZE: usize = 64;
+
+/// Written last by the publisher, once the database is in place.
+const MAGIC: u64 = 0x6873_7273_686d_0001;
+
+fn invalid_data<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// The name of a POSIX shared memory object, which starts with a slash.
+fn shm_name(name: &str) -> io::Result<CString> {
+    let name = if name.starts_with('/') {
+        name.to_owned()
+    } else {
+        format!("/{}", name)
+    };
+
+    CString::new(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+fn shm_open(name: &CString, flags: libc::c_int) -> io::Result<File> {
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, 0o644 as libc::c_uint) };
+
+    if fd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+/// A shared mapping of a whole shared memory object.
+struct Segment {
+    p: *mut libc::c_void,
+    len: usize,
+}
+
+unsafe impl Send for Segment {}
+unsafe impl Sync for Segment {}
+
+impl Segment {
+    fn map(file: &File, len: usize, prot: libc::c_int) -> io::Result<Segment> {
+        let p = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+
+        if p == libc::MAP_FAILED {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Segment { p: p, len: len })
+        }
+    }
+
+    fn magic(&self) -> &AtomicU64 {
+        unsafe { &*(self.p as *const AtomicU64) }
+    }
+
+    fn mode(&self) -> *mut u32 {
+        unsafe { (self.p as *mut u8).offset(8) as *mut u32 }
+    }
+
+    fn size(&self) -> *mut u64 {
+        unsafe { (self.p as *mut u8).offset(16) as *mut u64 }
+    }
+
+    fn db_ptr(&self) -> RawDatabasePtr {
+        unsafe { (self.p as *mut u8).offset(HEADER_SIZE as isize) as RawDatabasePtr }
+    }
+
+    /// The database placed in the segment, which must never be freed.
+    fn database<T: Type>(&self) -> ManuallyDrop<RawDatabase<T>> {
+        ManuallyDrop::new(RawDatabase::from_raw(self.db_ptr()))
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.p, self.len);
+        }
+    }
+}
+
+/// A database placed in a named shared memory segment by a compiling process,
+/// for the worker processes to scan with `SharedMemoryDatabase::attach` without a copy of their own.
+///
+/// The segment is unlinked when the publisher is dropped, the attached workers keep their mapping.
+pub struct SharedMemoryPublisher<T: Type> {
+    name: CString,
+    segment: Segment,
+    db: ManuallyDrop<RawDatabase<T>>,
+}
+
+impl<T: Type> fmt::Debug for SharedMemoryPublisher<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "SharedMemoryPublisher<{}>{{name: {:?}, len: {}}}",
+               T::name(),
+               self.name,
+               self.segment.len)
+    }
+}
+
+impl<T: Type> SharedMemoryPublisher<T> {
+    /// Place the database in the shared memory segment with the name, replacing a previously published one.
+    ///
+    /// The database is serialized and deserialized into the segment, the workers attached to a replaced
+    /// segment keep scanning the old database until they attach again.
+    pub fn publish(name: &str, db: &RawDatabase<T>) -> io::Result<SharedMemoryPublisher<T>> {
+        let serialized = try!(db.serialize());
+        let bytes = serialized.as_slice();
+        let size = try!(bytes.database_size());
+        let name = try!(shm_name(name));
+
+        unsafe {
+            libc::shm_unlink(name.as_ptr());
+        }
+
+        let file = try!(shm_open(&name, libc::O_CREAT | libc::O_EXCL | libc::O_RDWR));
+
+        // unlink the name if the database can't be placed
+        let unlink = |err: io::Error| {
+            unsafe {
+                libc::shm_unlink(name.as_ptr());
+            }
+
+            err
+        };
+
+        try!(file.set_len((HEADER_SIZE + size) as u64).map_err(&unlink));
+
+        let segment = try!(Segment::map(&file, HEADER_SIZE + size, libc::PROT_READ | libc::PROT_WRITE)
+            .map_err(&unlink));
+
+        try!(Self::deserialize_at(bytes, &segment).map_err(|err| unlink(err.into())));
+
+        unsafe {
+            ptr::write(segment.mode(), T::mode());
+            ptr::write(segment.size(), size as u64);
+        }
+
+        segment.magic().store(MAGIC, Ordering::Release);
+
+        debug!("published {} database of {} bytes to shared memory {:?}",
+               T::name(),
+               size,
+               name);
+
+        Ok(SharedMemoryPublisher {
+            name: name,
+            db: segment.database(),
+            segment: segment,
+        })
+    }
+
+    fn deserialize_at(bytes: &[u8], segment: &Segment) -> Result<(), Error> {
+        unsafe {
+            check_hs_error!(hs_deserialize_database_at(bytes.as_ptr() as *const i8, bytes.len(), segment.db_ptr()));
+        }
+
+        Ok(())
+    }
+
+    /// The name of the shared memory segment.
+    pub fn name(&self) -> &str {
+        self.name.to_str().unwrap()
+    }
+}
+
+impl<T: Type> Deref for SharedMemoryPublisher<T> {
+    type Target = RawDatabase<T>;
+
+    fn deref(&self) -> &RawDatabase<T> {
+        &self.db
+    }
+}
+
+impl<T: Type> Drop for SharedMemoryPublisher<T> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::shm_unlink(self.name.as_ptr());
+        }
+    }
+}
+
+/// A database published in a named shared memory segment, mapped read only.
+///
+/// The scratch and the streams are allocated per worker as usual, only the database is shared.
+/// The database must not be deserialized into, since the mapping is read only.
+pub struct SharedMemoryDatabase<T: Type> {
+    segment: Segment,
+    db: ManuallyDrop<RawDatabase<T>>,
+}
+
+impl<T: Type> fmt::Debug for SharedMemoryDatabase<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "SharedMemoryDatabase<{}>{{db: {:p}, len: {}}}",
+               T::name(),
+               **self.db,
+               self.segment.len)
+    }
+}
+
+impl<T: Type> SharedMemoryDatabase<T> {
+    /// Map the database published with the name.
+    ///
+    /// Returns `io::ErrorKind::NotFound` if nothing is published with the name,
+    /// and `io::ErrorKind::InvalidData` if the segment doesn't hold a complete database of the mode.
+    pub fn attach(name: &str) -> io::Result<SharedMemoryDatabase<T>> {
+        let name = try!(shm_name(name));
+        let file = try!(shm_open(&name, libc::O_RDONLY));
+        let len = try!(file.metadata()).len() as usize;
+
+        if len < HEADER_SIZE {
+            return Err(invalid_data(format!("shared memory {:?} is too small", name)));
+        }
+
+        let segment = try!(Segment::map(&file, len, libc::PROT_READ));
+
+        if segment.magic().load(Ordering::Acquire) != MAGIC {
+            return Err(invalid_data(format!("shared memory {:?} holds no published database", name)));
+        }
+
+        let (mode, size) = unsafe { (ptr::read(segment.mode()), ptr::read(segment.size())) };
+
+        if mode != T::mode() {
+            return Err(invalid_data(Error::DbModeError));
+        }
+
+        if HEADER_SIZE as u64 + size > len as u64 {
+            return Err(invalid_data(format!("shared memory {:?} is truncated", name)));
+        }
+
+        debug!("attached {} database of {} bytes from shared memory {:?}",
+               T::name(),
+               size,
+               name);
+
+        Ok(SharedMemoryDatabase {
+            db: segment.database(),
+            segment: segment,
+        })
+    }
+}
+
+impl<T: Type> Deref for SharedMemoryDatabase<T> {
+    type Target = RawDatabase<T>;
+
+    fn deref(&self) -> &RawDatabase<T> {
+        &self.db
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::io;
+    use std::process;
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_shared_memory_database() {
+        let _ = env_logger::init();
+
+        let name = format!("hyperscan-test-{}", process::id());
+        let db: BlockDatabase = patterns!(["foo", "bar"]).build().unwrap();
+
+        let publisher = SharedMemoryPublisher::publish(&name, &db).unwrap();
+
+        assert_eq!(publisher.name(), format!("/{}", name));
+
+        validate_database(&*publisher);
+
+        let attached = SharedMemoryDatabase::<Block>::attach(&name).unwrap();
+
+        validate_database(&*attached);
+
+        assert_matches!(&*attached, "foo bar", [(1, 3), (2, 7)]);
+        assert_eq!(SharedMemoryDatabase::<Streaming>::attach(&name).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+
+        drop(publisher);
+
+        assert_eq!(SharedMemoryDatabase::<Block>::attach(&name).unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+        assert_matches!(&*attached, "bar", [(2, 3)]);
+    }
+}
@@ -0,0 +1,244 @@
+use std::io;
+use std::io::Write;
+use std::cell::RefCell;
+
+use api::*;
+use errors::Error;
+use common::{BlockDatabase, StreamingDatabase};
+use stats::ScanStats;
+use channel::MatchSender;
+
+/// A destination of the scan results, so the output of the high-level scan APIs is pluggable.
+pub trait MatchSink {
+    /// A match was located, returns non-zero to request that scanning cease.
+    fn on_match(&mut self, m: Match) -> u32;
+
+    /// The scan failed.
+    fn on_error(&mut self, _err: &Error) {}
+
+    /// The stream was closed, with the totals of its data.
+    fn on_stream_close(&mut self, _stats: ScanStats) {}
+}
+
+impl MatchSink for Vec<Match> {
+    fn on_match(&mut self, m: Match) -> u32 {
+        self.push(m);
+
+        0
+    }
+}
+
+impl MatchSink for MatchSender {
+    fn on_match(&mut self, m: Match) -> u32 {
+        if self.send(m) { 0 } else { 1 }
+    }
+}
+
+/// Record the bytes scanned by the closed streams and the match events.
+impl MatchSink for ScanStats {
+    fn on_match(&mut self, _: Match) -> u32 {
+        self.matches += 1;
+
+        0
+    }
+
+    fn on_stream_close(&mut self, stats: ScanStats) {
+        self.bytes += stats.bytes;
+    }
+}
+
+/// A sink writing one JSON object per line.
+///
+/// Writing stops at the first I/O error, which ceases scanning and is returned by `into_inner`.
+#[derive(Debug)]
+pub struct JsonLines<W: Write> {
+    writer: W,
+    err: Option<io::Error>,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(writer: W) -> JsonLines<W> {
+        JsonLines {
+            writer: writer,
+            err: None,
+        }
+    }
+
+    /// Flush the writer and return it, or the first error.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        match self.err.take() {
+            Some(err) => Err(err),
+            None => {
+                try!(self.writer.flush());
+
+                Ok(self.writer)
+            }
+        }
+    }
+
+    fn write_line(&mut self, line: String) -> u32 {
+        if self.err.is_none() {
+            if let Err(err) = writeln!(self.writer, "{}", line) {
+                warn!("fail to write JSON line, {}", err);
+
+                self.err = Some(err);
+            }
+        }
+
+        if self.err.is_some() { 1 } else { 0 }
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+impl<W: Write> MatchSink for JsonLines<W> {
+    fn on_match(&mut self, m: Match) -> u32 {
+        self.write_line(format!(r#"{{"id":{},"from":{},"to":{},"flags":{}}}"#,
+                                m.id,
+                                m.from,
+                                m.to,
+                                m.flags.bits()))
+    }
+
+    fn on_error(&mut self, err: &Error) {
+        self.write_line(format!(r#"{{"error":"{}"}}"#, escape(&err.to_string())));
+    }
+
+    fn on_stream_close(&mut self, stats: ScanStats) {
+        self.write_line(format!(r#"{{"closed":{{"bytes":{},"matches":{}}}}}"#,
+                                stats.bytes,
+                                stats.matches));
+    }
+}
+
+pub(crate) fn on_sink_event(id: u32, from: u64, to: u64, flags: u32, sink: &RefCell<&mut MatchSink>) -> u32 {
+    sink.borrow_mut().on_match(Match::new(id, from, to, flags))
+}
+
+/// Report an error to the sink, unless the sink itself requested that scanning cease.
+fn report<T>(result: Result<T, Error>, sink: &RefCell<&mut MatchSink>) -> Result<T, Error> {
+    if let Err(ref err) = result {
+        if *err != Error::ScanTerminated {
+            sink.borrow_mut().on_error(err);
+        }
+    }
+
+    result
+}
+
+/// Scan a block, passing the matches and the error to the sink.
+pub fn scan_into<T, S>(db: &BlockDatabase, data: T, scratch: &S, sink: &mut MatchSink) -> Result<(), Error>
+    where T: Scannable,
+          S: Scratch
+{
+    let sink = RefCell::new(sink);
+
+    try!(report(db.scan(data.as_bytes(), 0, scratch, Some(on_sink_event), Some(&sink)),
+                &sink));
+
+    Ok(())
+}
+
+/// Scan the chunks in a new stream, passing the matches, the error and the totals of the closed stream to the sink.
+pub fn stream_into<I, S>(db: &StreamingDatabase, chunks: I, scratch: &S, sink: &mut MatchSink) -> Result<(), Error>
+    where I: IntoIterator,
+          I::Item: Scannable,
+          S: Scratch
+{
+    let sink = RefCell::new(sink);
+    let stream = try!(report(db.open_stream(0), &sink));
+
+    let fed = report(stream.feed(chunks,
+                                 scratch,
+                                 |id, from, to, flags| on_sink_event(id, from, to, flags, &sink)),
+                     &sink);
+    let closed = report(stream.close(scratch, Some(on_sink_event), Some(&sink)), &sink);
+
+    try!(fed);
+    try!(closed);
+
+    sink.borrow_mut().on_stream_close(stream.scan_stats());
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::str;
+
+    use super::super::*;
+
+    #[test]
+    fn test_match_sink() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut matches = Vec::new();
+
+        scan_into(&db, "some test data", &s, &mut matches).unwrap();
+
+        assert_eq!(matches, vec![Match::new(0, 5, 9, 0)]);
+
+        let mut json = JsonLines::new(Vec::new());
+
+        scan_into(&db, "test test", &s, &mut json).unwrap();
+        json.on_error(&Error::CompilerError(String::from("bad \"pattern\"")));
+
+        assert_eq!(str::from_utf8(&json.into_inner().unwrap()).unwrap(),
+                   "{\"id\":0,\"from\":0,\"to\":4,\"flags\":0}\n\
+                    {\"id\":0,\"from\":5,\"to\":9,\"flags\":0}\n\
+                    {\"error\":\"bad \\\"pattern\\\"\"}\n");
+
+        let (mut sender, receiver) = match_channel(1, Backpressure::Terminate);
+
+        assert_eq!(scan_into(&db, "test test", &s, &mut sender).err(),
+                   Some(Error::ScanTerminated));
+        assert_eq!(receiver.len(), 1);
+    }
+
+    #[test]
+    fn test_stream_sink() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let mut stats = ScanStats::default();
+
+        stream_into(&db, vec!["some te", "st data", "test"], &s, &mut stats).unwrap();
+
+        assert_eq!(stats,
+                   ScanStats {
+                       bytes: 18,
+                       matches: 2,
+                   });
+
+        let mut json = JsonLines::new(Vec::new());
+
+        stream_into(&db, vec!["te", "st"], &s, &mut json).unwrap();
+
+        assert_eq!(str::from_utf8(&json.into_inner().unwrap()).unwrap(),
+                   "{\"id\":0,\"from\":0,\"to\":4,\"flags\":0}\n\
+                    {\"closed\":{\"bytes\":4,\"matches\":1}}\n");
+    }
+}
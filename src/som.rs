@@ -0,0 +1,91 @@
+use constants::*;
+use api::*;
+use errors::Error;
+use compile::{Pattern, compile_patterns};
+use common::StreamingDatabase;
+
+/// The memory cost of start of match reporting for a pattern.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SomCost {
+    /// ID number of the pattern.
+    pub id: usize,
+
+    /// The growth of the scratch space in bytes when `HS_FLAG_SOM_LEFTMOST` is enabled.
+    pub scratch_size: isize,
+
+    /// The growth of the stream state in bytes when `HS_FLAG_SOM_LEFTMOST` is enabled.
+    pub stream_size: isize,
+}
+
+fn measure(patterns: &[Pattern], horizon: u32, platform: &PlatformInfo) -> Result<(usize, usize), Error> {
+    let db: StreamingDatabase = try!(compile_patterns(patterns, horizon, platform));
+    let scratch = try!(db.alloc());
+
+    Ok((try!(scratch.size()), try!(db.stream_size())))
+}
+
+/// Report the memory cost of enabling `HS_FLAG_SOM_LEFTMOST` for each pattern of a set.
+///
+/// The set is compiled as a streaming database with the given `HS_MODE_SOM_HORIZON_*` mode,
+/// once with and once without start of match reporting for each pattern in turn,
+/// while the flags of the other patterns are left unchanged.
+///
+/// Patterns using `HS_FLAG_SINGLEMATCH` or `HS_FLAG_PREFILTER` are skipped,
+/// since start of match reporting is not supported for them.
+pub fn som_costs(patterns: &[Pattern], horizon: u32, platform: &PlatformInfo) -> Result<Vec<SomCost>, Error> {
+    let mut costs = Vec::with_capacity(patterns.len());
+    let mut variant = patterns.to_vec();
+
+    for (i, pattern) in patterns.iter().enumerate() {
+        if pattern.flags.is_set(HS_FLAG_SINGLEMATCH) || pattern.flags.is_set(HS_FLAG_PREFILTER) {
+            continue;
+        }
+
+        variant[i].flags.0 = pattern.flags.0 & !HS_FLAG_SOM_LEFTMOST;
+
+        let (scratch_without, stream_without) = try!(measure(&variant, horizon, platform));
+
+        variant[i].flags.0 = pattern.flags.0 | HS_FLAG_SOM_LEFTMOST;
+
+        let (scratch_with, stream_with) = try!(measure(&variant, horizon, platform));
+
+        variant[i].flags = pattern.flags;
+
+        let cost = SomCost {
+            id: pattern.id,
+            scratch_size: scratch_with as isize - scratch_without as isize,
+            stream_size: stream_with as isize - stream_without as isize,
+        };
+
+        debug!("start of match cost of pattern `{}`: {:?}", pattern, cost);
+
+        costs.push(cost);
+    }
+
+    Ok(costs)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_som_costs() {
+        let _ = env_logger::init();
+
+        let patterns = patterns!(["foo.*bar", "test"]);
+
+        let costs = som_costs(&patterns, HS_MODE_SOM_HORIZON_LARGE, &PlatformInfo::null()).unwrap();
+
+        assert_eq!(costs.len(), 2);
+        assert_eq!(costs[0].id, 1);
+        assert!(costs[0].stream_size > 0);
+        assert_eq!(costs[1].id, 2);
+
+        let patterns = patterns!(["test"], flags => HS_FLAG_SINGLEMATCH);
+
+        assert!(som_costs(&patterns, HS_MODE_SOM_HORIZON_SMALL, &PlatformInfo::null()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::sync::Arc;
+use std::ops::Deref;
+
+use api::*;
+use errors::Error;
+use compile::{Pattern, Patterns, compile_patterns};
+use common::RawDatabase;
+use swap::HotSwap;
+use shared::SharedDatabase;
+
+/// A database which retains the patterns and the platform it was compiled from,
+/// so it can be recompiled with changes.
+///
+/// Hyperscan databases are immutable, adding a pattern means compiling a new database.
+pub struct PatternDatabase<T: Type> {
+    db: SharedDatabase<T>,
+    patterns: Patterns,
+    platform: Option<(u32, u64)>,
+}
+
+impl<T: Type> fmt::Debug for PatternDatabase<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "PatternDatabase<{}>{{db: {:p}, patterns: {}}}",
+               T::name(),
+               **self.db,
+               self.patterns.len())
+    }
+}
+
+impl<T: Type> PatternDatabase<T> {
+    /// Compile the patterns for the platform, `PlatformInfo::null()` for the current host.
+    pub fn compile(patterns: Patterns, platform: &PlatformInfo) -> Result<PatternDatabase<T>, Error> {
+        let platform = if platform.as_ptr().is_null() {
+            None
+        } else {
+            Some((platform.tune(), platform.cpu_features()))
+        };
+
+        Self::compile_for(patterns, platform)
+    }
+
+    fn compile_for(patterns: Patterns, platform: Option<(u32, u64)>) -> Result<PatternDatabase<T>, Error> {
+        let db = try!(compile_patterns(&patterns, 0, &Self::platform_info(platform)));
+
+        Ok(PatternDatabase {
+            db: db.into(),
+            patterns: patterns,
+            platform: platform,
+        })
+    }
+
+    fn platform_info(platform: Option<(u32, u64)>) -> PlatformInfo {
+        match platform {
+            Some((tune, cpu_features)) => PlatformInfo::new(tune, cpu_features),
+            None => PlatformInfo::null(),
+        }
+    }
+
+    /// The patterns the database was compiled from.
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// The platform the database was compiled for.
+    pub fn platform(&self) -> PlatformInfo {
+        Self::platform_info(self.platform)
+    }
+
+    /// Merge the patterns with the additions and compile a new database for the same platform.
+    ///
+    /// The additions are appended with their IDs unchanged, except those with the same expression,
+    /// flags, extended parameters and ID as an existing pattern, which are skipped.
+    pub fn extended_with(&self, additions: &[Pattern]) -> Result<PatternDatabase<T>, Error> {
+        let mut patterns = self.patterns.clone();
+
+        for pattern in additions {
+            if !patterns.iter().any(|p| {
+                p.id == pattern.id && p.flags == pattern.flags && p.expression == pattern.expression &&
+                p.ext == pattern.ext
+            }) {
+                patterns.push(pattern.clone());
+            }
+        }
+
+        debug!("extend {} database with {} patterns, {} patterns in total",
+               T::name(),
+               additions.len(),
+               patterns.len());
+
+        Self::compile_for(patterns, self.platform)
+    }
+
+    /// A handle to the compiled database, which keeps it alive after the pattern database is dropped.
+    pub fn shared(&self) -> SharedDatabase<T> {
+        self.db.clone()
+    }
+
+    /// Take the compiled database, which is copied if handles returned by `shared` are still alive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the database can't be copied, e.g. out of memory.
+    pub fn into_inner(self) -> RawDatabase<T> {
+        match self.db.try_unwrap() {
+            Ok(db) => db,
+            Err(shared) => {
+                debug!("copy {} database shared by {} handles", T::name(), shared.handles());
+
+                shared.serialize()
+                    .and_then(|bytes| RawDatabase::deserialize(bytes.as_slice()))
+                    .expect("copy shared database")
+            }
+        }
+    }
+}
+
+impl<T: Type> Deref for PatternDatabase<T> {
+    type Target = RawDatabase<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+impl<T: Type> HotSwap<PatternDatabase<T>> {
+    /// Extend the current database with the additions and publish the new database.
+    pub fn extend(&self, additions: &[Pattern]) -> Result<Arc<PatternDatabase<T>>, Error> {
+        self.update(|db| db.extended_with(additions))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_extended_with() {
+        let _ = env_logger::init();
+
+        let db = PatternDatabase::<Block>::compile(patterns!(["foo", "bar"]), &PlatformInfo::null()).unwrap();
+
+        assert_eq!(db.patterns().len(), 2);
+
+        fn callback(id: u32, _: u64, _: u64, _: u32, ids: &::std::cell::RefCell<Vec<u32>>) -> u32 {
+            ids.borrow_mut().push(id);
+
+            0
+        }
+
+        let extended = db.extended_with(&[pattern!{"baz", flags => 0, id => 3}, pattern!{"foo", flags => 0, id => 1}])
+            .unwrap();
+
+        assert_eq!(extended.patterns().len(), 3);
+
+        let ids = ::std::cell::RefCell::new(Vec::new());
+        let s = extended.alloc().unwrap();
+
+        extended.scan("foo bar baz", 0, &s, Some(callback), Some(&ids)).unwrap();
+
+        assert_eq!(*ids.borrow(), vec![1, 2, 3]);
+
+        let handle = HotSwap::new(db);
+        let previous = handle.load();
+
+        let current = handle.extend(&[pattern!{"qux", flags => 0, id => 4}]).unwrap();
+
+        assert_eq!(previous.patterns().len(), 2);
+        assert_eq!(current.patterns().len(), 3);
+        assert_eq!(handle.load().patterns().len(), 3);
+
+        assert!(handle.extend(&[pattern!{"(", flags => 0, id => 5}]).is_err());
+        assert_eq!(handle.load().patterns().len(), 3);
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let _ = env_logger::init();
+
+        let db = PatternDatabase::<Block>::compile(patterns!(["foo", "bar"]), &PlatformInfo::null()).unwrap();
+        let shared = db.shared();
+
+        let owned: BlockDatabase = db.into_inner();
+
+        assert!(*owned != **shared);
+        assert_matches!(&owned, "foo bar", [(1, 3), (2, 7)]);
+
+        let db = PatternDatabase::<Block>::compile(patterns!(["baz"]), &PlatformInfo::null()).unwrap();
+
+        assert_matches!(&db.into_inner(), "baz", [(1, 3)]);
+    }
+}
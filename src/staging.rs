@@ -0,0 +1,237 @@
+use std::fmt;
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex};
+
+use api::*;
+use errors::Error;
+use compile::{Pattern, Patterns};
+use source::PatternDatabase;
+use swap::HotSwap;
+use diff::{PatternDiff, diff_patterns};
+
+struct Staged {
+    patterns: Patterns,
+    // bumped by each change of the staged patterns
+    generation: u64,
+    // the generation being compiled, if any
+    building: Option<u64>,
+}
+
+/// A mutable pattern set, whose changes are staged and compiled off-thread
+/// before the new database is published to the scanners.
+///
+/// The patterns are keyed by ID, adding a pattern replaces the patterns with the same ID.
+/// A rebuild only starts when the staged patterns differ from the published ones,
+/// and a rebuild finishing after a newer one never replaces the newer database.
+pub struct PatternSetManager<T: Type> {
+    live: Arc<HotSwap<PatternDatabase<T>>>,
+    staged: Arc<Mutex<Staged>>,
+    // the generation of the published database
+    published: Arc<Mutex<u64>>,
+}
+
+impl<T: Type> fmt::Debug for PatternSetManager<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let staged = self.staged.lock().unwrap();
+
+        write!(f,
+               "PatternSetManager<{}>{{staged: {}, generation: {}, published: {}}}",
+               T::name(),
+               staged.patterns.len(),
+               staged.generation,
+               *self.published.lock().unwrap())
+    }
+}
+
+impl<T: Type + 'static> PatternSetManager<T> {
+    /// Compile and publish the initial patterns for the current host.
+    pub fn new(patterns: Patterns) -> Result<PatternSetManager<T>, Error> {
+        let db = try!(PatternDatabase::compile(patterns.clone(), &PlatformInfo::null()));
+
+        Ok(PatternSetManager {
+            live: Arc::new(HotSwap::new(db)),
+            staged: Arc::new(Mutex::new(Staged {
+                patterns: patterns,
+                generation: 0,
+                building: None,
+            })),
+            published: Arc::new(Mutex::new(0)),
+        })
+    }
+
+    /// The handle the scanners load the published database from.
+    pub fn handle(&self) -> Arc<HotSwap<PatternDatabase<T>>> {
+        self.live.clone()
+    }
+
+    /// The published database.
+    pub fn current(&self) -> Arc<PatternDatabase<T>> {
+        self.live.load()
+    }
+
+    /// The staged patterns.
+    pub fn patterns(&self) -> Patterns {
+        self.staged.lock().unwrap().patterns.clone()
+    }
+
+    /// Stage a pattern, returns the replaced patterns with the same ID.
+    pub fn add(&self, pattern: Pattern) -> Vec<Pattern> {
+        let mut staged = self.staged.lock().unwrap();
+
+        let replaced = Self::take_id(&mut staged.patterns, pattern.id);
+
+        staged.patterns.push(pattern);
+        staged.generation += 1;
+
+        replaced
+    }
+
+    /// Stage the removal of the patterns with the ID, returns the removed patterns.
+    pub fn remove(&self, id: usize) -> Vec<Pattern> {
+        let mut staged = self.staged.lock().unwrap();
+
+        let removed = Self::take_id(&mut staged.patterns, id);
+
+        if !removed.is_empty() {
+            staged.generation += 1;
+        }
+
+        removed
+    }
+
+    fn take_id(patterns: &mut Patterns, id: usize) -> Vec<Pattern> {
+        let (taken, kept) = patterns.drain(..).partition(|p| p.id == id);
+
+        **patterns = kept;
+
+        taken
+    }
+
+    /// The staged changes which are not published yet.
+    pub fn pending(&self) -> PatternDiff {
+        diff_patterns(self.current().patterns(), &self.staged.lock().unwrap().patterns)
+    }
+
+    /// Whether the staged patterns changed since the published database was compiled.
+    pub fn is_dirty(&self) -> bool {
+        self.staged.lock().unwrap().generation != *self.published.lock().unwrap()
+    }
+
+    /// Compile the staged patterns on a worker thread and publish the new database,
+    /// unless they are already published or being compiled.
+    ///
+    /// The worker returns the published database, or `None` if a newer one was published first.
+    /// A failed compilation leaves the published database in place and the changes staged.
+    pub fn rebuild(&self) -> Option<JoinHandle<Result<Option<Arc<PatternDatabase<T>>>, Error>>> {
+        let (patterns, generation) = {
+            let mut staged = self.staged.lock().unwrap();
+
+            if staged.generation == *self.published.lock().unwrap() || staged.building == Some(staged.generation) {
+                return None;
+            }
+
+            staged.building = Some(staged.generation);
+
+            (staged.patterns.clone(), staged.generation)
+        };
+
+        let live = self.live.clone();
+        let staged = self.staged.clone();
+        let published = self.published.clone();
+
+        Some(thread::spawn(move || {
+            debug!("rebuilding {} database with {} patterns, generation {}",
+                   T::name(),
+                   patterns.len(),
+                   generation);
+
+            let result = PatternDatabase::compile(patterns, &PlatformInfo::null());
+
+            {
+                let mut staged = staged.lock().unwrap();
+
+                if staged.building == Some(generation) {
+                    staged.building = None;
+                }
+            }
+
+            let db = try!(result);
+            let mut published = published.lock().unwrap();
+
+            if *published > generation {
+                debug!("discard {} database of generation {}, generation {} was published",
+                       T::name(),
+                       generation,
+                       *published);
+
+                return Ok(None);
+            }
+
+            live.publish(db);
+
+            *published = generation;
+
+            Ok(Some(live.load()))
+        }))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+    use super::super::common::tests::*;
+
+    #[test]
+    fn test_pattern_set_manager() {
+        let _ = env_logger::init();
+
+        let manager: PatternSetManager<Block> = PatternSetManager::new(patterns!(["foo", "bar"])).unwrap();
+        let handle = manager.handle();
+
+        assert!(!manager.is_dirty());
+        assert!(manager.rebuild().is_none());
+
+        let old = handle.load();
+
+        assert_eq!(manager.add(pattern!{"baz", flags => 0, id => 3}).len(), 0);
+        assert_eq!(manager.add(pattern!{"qux", flags => 0, id => 2}), vec![pattern!{"bar", flags => 0, id => 2}]);
+        assert!(manager.remove(5).is_empty());
+
+        assert!(manager.is_dirty());
+
+        let pending = manager.pending();
+
+        assert_eq!(pending.added, vec![pattern!{"baz", flags => 0, id => 3}]);
+        assert_eq!(pending.changed.len(), 1);
+        assert!(pending.removed.is_empty());
+
+        let task = manager.rebuild().unwrap();
+
+        assert!(manager.rebuild().is_none());
+
+        let db = task.join().unwrap().unwrap().unwrap();
+
+        assert!(!manager.is_dirty());
+        assert!(manager.pending().is_empty());
+        assert_eq!(db.patterns().len(), 3);
+        assert_eq!(handle.load().patterns(), db.patterns());
+        assert_eq!(old.patterns().len(), 2);
+
+        validate_database(&**handle.load());
+
+        assert_eq!(manager.remove(3).len(), 1);
+
+        manager.add(pattern!{"("});
+
+        assert!(manager.rebuild().unwrap().join().unwrap().is_err());
+        assert!(manager.is_dirty());
+        assert_eq!(manager.current().patterns().len(), 3);
+
+        manager.remove(0);
+
+        assert_eq!(manager.rebuild().unwrap().join().unwrap().unwrap().unwrap().patterns().len(), 2);
+        assert!(!manager.is_dirty());
+    }
+}
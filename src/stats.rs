@@ -0,0 +1,89 @@
+use std::mem;
+use std::os::raw::{c_int, c_uint, c_ulonglong, c_void};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use raw::*;
+use constants::*;
+use api::MatchEventCallback;
+
+/// The totals of the data scanned and the match events delivered.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ScanStats {
+    /// The number of bytes scanned.
+    pub bytes: u64,
+    /// The number of match events delivered to the callbacks.
+    pub matches: u64,
+}
+
+/// Counters of the data scanned by a database or a stream.
+#[derive(Debug, Default)]
+pub struct ScanCounters {
+    bytes: AtomicU64,
+    matches: AtomicU64,
+}
+
+impl ScanCounters {
+    pub fn new(stats: ScanStats) -> ScanCounters {
+        ScanCounters {
+            bytes: AtomicU64::new(stats.bytes),
+            matches: AtomicU64::new(stats.matches),
+        }
+    }
+
+    /// Account a scan call, unless it failed.
+    #[inline]
+    pub fn add(&self, ret: hs_error_t, bytes: usize, matches: u64) {
+        if ret == HS_SUCCESS || ret == HS_SCAN_TERMINATED {
+            self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+            self.matches.fetch_add(matches, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> ScanStats {
+        ScanStats {
+            bytes: self.bytes.load(Ordering::Relaxed),
+            matches: self.matches.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Counting<'a, D: 'a> {
+    callback: MatchEventCallback<D>,
+    context: &'a D,
+    matches: u64,
+}
+
+/// Forward a match event to the callback, counting the delivered events.
+unsafe extern "C" fn on_counted_event<D>(id: c_uint,
+                                         from: c_ulonglong,
+                                         to: c_ulonglong,
+                                         flags: c_uint,
+                                         context: *mut c_void)
+                                         -> c_int {
+    let counting = &mut *(context as *mut Counting<D>);
+
+    counting.matches += 1;
+
+    (counting.callback)(id, from, to, flags, counting.context) as c_int
+}
+
+/// Call a Hyperscan scan function with the match event callback and its context,
+/// returns the result of the call and the number of match events delivered.
+pub unsafe fn counted<D, F>(callback: Option<MatchEventCallback<D>>, context: Option<&D>, f: F) -> (hs_error_t, u64)
+    where F: FnOnce(match_event_handler, *mut c_void) -> hs_error_t
+{
+    match (callback, context) {
+        (Some(callback), Some(context)) => {
+            let mut counting = Counting {
+                callback: callback,
+                context: context,
+                matches: 0,
+            };
+
+            let ret = f(Some(on_counted_event::<D>), &mut counting as *mut Counting<D> as *mut c_void);
+
+            (ret, counting.matches)
+        }
+        (callback, context) => (f(mem::transmute(callback), mem::transmute(context)), 0),
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+use api::*;
+use errors::Error;
+use common::StreamingDatabase;
+
+/// A block scanner over a streaming database, scanning each block in a temporary stream,
+/// so one compiled database can serve both per-packet and full-flow scanning.
+///
+/// The matches at the end of the block, like those anchored with `$`, are reported when
+/// the temporary stream is closed, as in block mode.
+pub struct StreamedBlock<'a>(pub &'a StreamingDatabase);
+
+impl<'a> fmt::Debug for StreamedBlock<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StreamedBlock{{db: {:p}}}", **self.0)
+    }
+}
+
+impl<'a, T: Scannable, S: Scratch> BlockScanner<T, S> for StreamedBlock<'a> {
+    fn scan<D>(&self,
+               data: T,
+               flags: ScanFlags,
+               scratch: &S,
+               callback: Option<MatchEventCallback<D>>,
+               context: Option<&D>)
+               -> Result<&Self, Error> {
+        let stream = try!(self.0.open_stream(0));
+
+        match stream.scan(data, flags, scratch, callback, context) {
+            Ok(_) => {
+                try!(stream.close(scratch, callback, context));
+            }
+            Err(err) => {
+                try!(stream.close(scratch, None, None::<&()>));
+
+                return Err(err);
+            }
+        }
+
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+
+    use super::super::*;
+
+    #[test]
+    fn test_streamed_block() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["test", "bar$"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        fn callback(id: u32, _: u64, to: u64, _: u32, matches: &RefCell<Vec<(u32, u64)>>) -> u32 {
+            matches.borrow_mut().push((id, to));
+
+            0
+        }
+
+        let matches = RefCell::new(Vec::new());
+
+        StreamedBlock(&db).scan("foo test bar", 0, &s, Some(callback), Some(&matches)).unwrap();
+        StreamedBlock(&db).scan("test", 0, &s, Some(callback), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(1, 8), (2, 12), (1, 4)]);
+
+        fn terminate(_: u32, _: u64, _: u64, _: u32, _: &()) -> u32 {
+            1
+        }
+
+        assert_eq!(StreamedBlock(&db).scan("test", 0, &s, Some(terminate), Some(&())).err(),
+                   Some(Error::ScanTerminated));
+        assert_eq!(db.scan_stats().bytes, 20);
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// A handle to the current version of a database, which can be replaced while
+/// scanning threads are using it.
+///
+/// Readers take a reference to the current version with `load`, and keep using it
+/// until they are done, even if a new version is published in the meantime.
+pub struct HotSwap<D> {
+    current: RwLock<Arc<D>>,
+    updating: Mutex<()>,
+}
+
+impl<D: fmt::Debug> fmt::Debug for HotSwap<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HotSwap{{current: {:?}}}", self.load())
+    }
+}
+
+impl<D> HotSwap<D> {
+    pub fn new(db: D) -> HotSwap<D> {
+        HotSwap {
+            current: RwLock::new(Arc::new(db)),
+            updating: Mutex::new(()),
+        }
+    }
+
+    /// The current version of the database.
+    pub fn load(&self) -> Arc<D> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Replace the current version of the database, returns the previous one.
+    pub fn publish(&self, db: D) -> Arc<D> {
+        let _guard = self.updating.lock().unwrap();
+
+        self.swap(Arc::new(db))
+    }
+
+    fn swap(&self, db: Arc<D>) -> Arc<D> {
+        let mut current = self.current.write().unwrap();
+
+        ::std::mem::replace(&mut *current, db)
+    }
+
+    /// Build a new version from the current one and publish it.
+    ///
+    /// Updates are serialized, so concurrent updates build on each other,
+    /// while readers keep using the current version until the new one is published.
+    pub fn update<F, E>(&self, f: F) -> Result<Arc<D>, E>
+        where F: FnOnce(&D) -> Result<D, E>
+    {
+        let _guard = self.updating.lock().unwrap();
+
+        let db = Arc::new(try!(f(&*self.load())));
+
+        self.swap(db.clone());
+
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_hot_swap() {
+        let handle = HotSwap::new(1);
+        let current = handle.load();
+
+        assert_eq!(*handle.publish(2), 1);
+        assert_eq!(*current, 1);
+        assert_eq!(*handle.load(), 2);
+
+        assert_eq!(*handle.update(|v| Ok::<_, ()>(v + 1)).unwrap(), 3);
+        assert_eq!(handle.update(|_| Err("failed")), Err("failed"));
+        assert_eq!(*handle.load(), 3);
+
+        let handle = Arc::new(handle);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+
+                ::std::thread::spawn(move || handle.update(|v| Ok::<_, ()>(v + 1)).unwrap())
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(*handle.load(), 7);
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use raw::*;
+use api::*;
+use constants::*;
+
+/// Aggregate usage of the scratch spaces allocated while the telemetry was enabled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScratchStats {
+    /// The number of live scratch spaces.
+    pub live: usize,
+    /// The total size in bytes of the live scratch spaces.
+    pub total_bytes: usize,
+    /// The peak of the total size in bytes.
+    pub peak_bytes: usize,
+    /// The peak scratch size in bytes required by each database, keyed by the database address.
+    pub database_peaks: HashMap<usize, usize>,
+}
+
+#[derive(Default)]
+struct Registry {
+    scratches: HashMap<usize, usize>,
+    stats: ScratchStats,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+/// Start tracking the scratch spaces allocated, cloned, reallocated and freed from now on.
+pub fn enable_scratch_telemetry() {
+    let mut registry = REGISTRY.lock().unwrap();
+
+    if registry.is_none() {
+        *registry = Some(Registry::default());
+    }
+
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Stop tracking the scratch spaces and drop the collected statistics.
+pub fn disable_scratch_telemetry() {
+    ENABLED.store(false, Ordering::Release);
+
+    *REGISTRY.lock().unwrap() = None;
+}
+
+/// A snapshot of the scratch statistics, or `None` if the telemetry is disabled.
+pub fn scratch_telemetry() -> Option<ScratchStats> {
+    REGISTRY.lock().unwrap().as_ref().map(|registry| registry.stats.clone())
+}
+
+/// Record a scratch space replaced by another one, either may be null.
+pub fn track_scratch(old: RawScratchPtr, new: RawScratchPtr, db: Option<RawDatabasePtr>) {
+    if !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+
+    let mut size = 0;
+
+    if !new.is_null() && unsafe { hs_scratch_size(new, &mut size) } != HS_SUCCESS {
+        return;
+    }
+
+    if let Some(ref mut registry) = *REGISTRY.lock().unwrap() {
+        let stats = &mut registry.stats;
+
+        if let Some(old_size) = registry.scratches.remove(&(old as usize)) {
+            stats.live -= 1;
+            stats.total_bytes -= old_size;
+        }
+
+        if !new.is_null() {
+            registry.scratches.insert(new as usize, size);
+
+            stats.live += 1;
+            stats.total_bytes += size;
+
+            if stats.total_bytes > stats.peak_bytes {
+                stats.peak_bytes = stats.total_bytes;
+            }
+
+            if let Some(db) = db {
+                let peak = stats.database_peaks.entry(db as usize).or_insert(0);
+
+                if size > *peak {
+                    *peak = size;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scratch_telemetry() {
+        let _ = env_logger::init();
+
+        enable_scratch_telemetry();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+        let size = s.size().unwrap();
+
+        let stats = scratch_telemetry().unwrap();
+
+        assert!(stats.live >= 1);
+        assert!(stats.total_bytes >= size);
+        assert!(stats.peak_bytes >= stats.total_bytes);
+        assert_eq!(stats.database_peaks[&(*db as usize)], size);
+
+        drop(s);
+
+        disable_scratch_telemetry();
+
+        assert_eq!(scratch_telemetry(), None);
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::sync::Arc;
+
+use api::*;
+use errors::Error;
+use common::RawDatabase;
+use runtime::RawScratch;
+use local::insert_local_scratch;
+
+/// A scratch space allocated once for a database, and cloned for each new thread.
+///
+/// Cloning with `hs_clone_scratch` is cheaper than allocating for the database,
+/// and the prototype is never used for scanning, so it can be cloned from many threads at once.
+pub struct ScratchTemplate {
+    prototype: RawScratch,
+    db: usize,
+}
+
+/// The prototype is only read by `hs_clone_scratch`, never scanned with.
+unsafe impl Sync for ScratchTemplate {}
+
+impl fmt::Debug for ScratchTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ScratchTemplate{{prototype: {:?}, db: {:#x}}}", self.prototype, self.db)
+    }
+}
+
+impl ScratchTemplate {
+    /// Allocate the prototype for the database.
+    pub fn new<T: Type>(db: &RawDatabase<T>) -> Result<ScratchTemplate, Error> {
+        Ok(ScratchTemplate {
+            prototype: try!(db.alloc()),
+            db: **db as usize,
+        })
+    }
+
+    /// Grow the prototype for another database, so the clones can be used with both.
+    ///
+    /// The clones installed with `install` remain keyed by the first database.
+    pub fn realloc<T: Type>(&mut self, db: &RawDatabase<T>) -> Result<&Self, Error> {
+        try!(self.prototype.realloc(db));
+
+        Ok(self)
+    }
+
+    /// The size of the scratch spaces in bytes.
+    pub fn size(&self) -> Result<usize, Error> {
+        self.prototype.size()
+    }
+
+    /// Clone a scratch space from the prototype.
+    pub fn scratch(&self) -> Result<RawScratch, Error> {
+        self.prototype.try_clone()
+    }
+
+    /// Clone a scratch space into the thread local registry of the current thread,
+    /// so `local_scratch` for the database doesn't allocate on first use.
+    pub fn install(&self) -> Result<(), Error> {
+        let scratch = try!(self.scratch());
+
+        insert_local_scratch(self.db, scratch);
+
+        Ok(())
+    }
+
+    /// A thread start handler installing a clone in each thread of a pool,
+    /// with the signature of the `rayon::ThreadPoolBuilder::start_handler` closure.
+    ///
+    /// When cloning fails, the thread falls back to allocating on first use.
+    pub fn start_handler(template: &Arc<ScratchTemplate>) -> impl Fn(usize) + Send + Sync + 'static {
+        let template = template.clone();
+
+        move |index| if let Err(err) = template.install() {
+            warn!("fail to install scratch for thread #{}, {}", index, err);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scratch_template() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let template = ScratchTemplate::new(&db).unwrap();
+
+        assert_eq!(template.size().unwrap(), db.alloc().unwrap().size().unwrap());
+
+        let db = Arc::new(db);
+        let template = Arc::new(template);
+        let start = Arc::new(ScratchTemplate::start_handler(&template));
+
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let db = db.clone();
+                let template = template.clone();
+                let start = start.clone();
+
+                thread::spawn(move || {
+                    let s = template.scratch().unwrap();
+
+                    db.scan("some test data", 0, &s, None, None::<&()>).unwrap();
+
+                    start(i);
+
+                    with_local_scratch(&db, |s| db.scan("test", 0, s, None, None::<&()>)).unwrap().unwrap();
+
+                    assert!(release_local_scratch(&db));
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::cell::RefCell;
+use std::sync::atomic::AtomicU64;
+
+use api::*;
+use errors::Error;
+use common::{BlockDatabase, StreamingDatabase};
+use compile::{Pattern, Patterns};
+use sample::splitmix64;
+
+/// Something which can be scanned in the tests of the rules:
+/// a compiled block database, or the patterns compiled into a throwaway one.
+pub trait MatchTarget {
+    /// Scan the data with a throwaway scratch, returning all the matches.
+    fn matches(&self, data: &[u8]) -> Result<Vec<Match>, Error>;
+}
+
+fn on_test_match(id: u32, from: u64, to: u64, flags: u32, matches: &RefCell<Vec<Match>>) -> u32 {
+    matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+    0
+}
+
+impl MatchTarget for BlockDatabase {
+    fn matches(&self, data: &[u8]) -> Result<Vec<Match>, Error> {
+        let scratch = try!(self.alloc());
+        let matches = RefCell::new(Vec::new());
+
+        try!(self.scan(data, 0, &scratch, Some(on_test_match), Some(&matches)));
+
+        Ok(matches.into_inner())
+    }
+}
+
+impl MatchTarget for Pattern {
+    fn matches(&self, data: &[u8]) -> Result<Vec<Match>, Error> {
+        let db: BlockDatabase = try!(self.build());
+
+        db.matches(data)
+    }
+}
+
+impl MatchTarget for Patterns {
+    fn matches(&self, data: &[u8]) -> Result<Vec<Match>, Error> {
+        let db: BlockDatabase = try!(self.build());
+
+        db.matches(data)
+    }
+}
+
+/// A pattern in the `id:/expression/flags` format, see `Pattern::parse`.
+impl MatchTarget for str {
+    fn matches(&self, data: &[u8]) -> Result<Vec<Match>, Error> {
+        try!(Pattern::parse(self)).matches(data)
+    }
+}
+
+impl<'a, T: MatchTarget + ?Sized> MatchTarget for &'a T {
+    fn matches(&self, data: &[u8]) -> Result<Vec<Match>, Error> {
+        (**self).matches(data)
+    }
+}
+
+/// Scan the data with a database, a pattern or a pattern set, allocating the throwaway
+/// database and scratch, so the rules can be unit tested without any setup.
+pub fn scan_matches<M, T>(target: M, data: T) -> Result<Vec<Match>, Error>
+    where M: MatchTarget,
+          T: Scannable
+{
+    target.matches(data.as_bytes())
+}
+
+/// Feed the chunks to a new stream of the database with a throwaway scratch,
+/// returning all the matches, including those at the end of the stream.
+pub fn stream_matches<I>(db: &StreamingDatabase, chunks: I) -> Result<Vec<Match>, Error>
+    where I: IntoIterator,
+          I::Item: Scannable
+{
+    let scratch = try!(db.alloc());
+    let stream = try!(db.open_stream(0));
+    let matches = RefCell::new(Vec::new());
+
+    let fed = stream.feed(chunks, &scratch, |id, from, to, flags| {
+        matches.borrow_mut().push(Match::new(id, from, to, flags));
+
+        0
+    });
+
+    try!(stream.close(&scratch, Some(on_test_match), Some(&matches)));
+    try!(fed);
+
+    Ok(matches.into_inner())
+}
+
+/// The chunkings of a corpus, as the lengths of the chunks fed to a stream.
+///
+/// The corpus comes first in a single chunk, then one byte at a time,
+/// then in chunks of random lengths, deterministic for a seed.
+#[derive(Debug)]
+pub struct Chunkings {
+    len: usize,
+    round: usize,
+    state: AtomicU64,
+}
+
+impl Chunkings {
+    pub fn new(len: usize, seed: u64) -> Chunkings {
+        Chunkings {
+            len: len,
+            round: 0,
+            state: AtomicU64::new(seed),
+        }
+    }
+}
+
+impl Iterator for Chunkings {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let chunks = match self.round {
+            _ if self.len == 0 => vec![],
+            0 => vec![self.len],
+            1 => vec![1; self.len],
+            _ => {
+                // a maximum length per round, so both the short and the long chunks are covered
+                let max = 1 + (splitmix64(&self.state) as usize) % self.len;
+                let mut chunks = Vec::new();
+                let mut remaining = self.len;
+
+                while remaining > 0 {
+                    let len = 1 + (splitmix64(&self.state) as usize) % max;
+                    let len = if len > remaining { remaining } else { len };
+
+                    chunks.push(len);
+                    remaining -= len;
+                }
+
+                chunks
+            }
+        };
+
+        self.round += 1;
+
+        Some(chunks)
+    }
+}
+
+/// Assert that the database reports the same matches for the corpus
+/// whatever the chunking, over `rounds` chunkings of `Chunkings`.
+///
+/// A mismatch means a match is lost or duplicated at the chunk boundaries,
+/// the panic message reports the chunking to reproduce it.
+pub fn assert_chunkings(db: &StreamingDatabase, corpus: &[u8], rounds: usize, seed: u64) {
+    let expected = match stream_matches(db, Some(corpus)) {
+        Ok(matches) => matches,
+        Err(err) => panic!("assertion failed: stream matches, {}", err),
+    };
+
+    for chunks in Chunkings::new(corpus.len(), seed).take(rounds) {
+        let mut offset = 0;
+        let found = stream_matches(db,
+                                   chunks.iter().map(|&len| {
+                                       offset += len;
+
+                                       &corpus[offset - len..offset]
+                                   }));
+
+        match found {
+            Ok(ref found) if *found == expected => {}
+            Ok(found) => {
+                panic!("assertion failed: `(found == expected)` with the chunks {:?}\n   found: {:?}\nexpected: {:?}",
+                       chunks,
+                       found,
+                       expected)
+            }
+            Err(err) => panic!("assertion failed: stream matches with the chunks {:?}, {}", chunks, err),
+        }
+    }
+}
+
+/// Assert the `(id, to)` matches of a database, a pattern or a pattern set on the input.
+///
+/// ```ignore
+/// assert_matches!("/foo/i", "FOO bar foo", [(0, 3), (0, 11)]);
+/// assert_matches!(patterns!(["foo", "bar"]), "foo bar", [(1, 3), (2, 7)]);
+/// assert_matches!(&db, "nothing", []);
+/// ```
+#[macro_export]
+macro_rules! assert_matches {
+    ( $target:expr, $data:expr, $expected:expr ) => {{
+        let expected: &[(u32, u64)] = &$expected;
+        let matches = match $crate::scan_matches($target, $data) {
+            Ok(matches) => matches,
+            Err(err) => panic!("assertion failed: scan matches, {}", err),
+        };
+        let found = matches.iter().map(|m| (m.id, m.to)).collect::<Vec<(u32, u64)>>();
+
+        assert!(found.as_slice() == expected,
+                "assertion failed: `(found == expected)`\n   found: {:?}\nexpected: {:?}",
+                found,
+                expected);
+    }};
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_assert_matches() {
+        let _ = env_logger::init();
+
+        assert_matches!("/foo/i", "FOO bar foo", [(0, 3), (0, 11)]);
+        assert_matches!("3:/bar/", "FOO bar foo", [(3, 7)]);
+        assert_matches!(pattern!{"foo"}, "bar", []);
+        assert_matches!(patterns!(["foo", "bar"]), "foo bar", vec![(1, 3), (2, 7)]);
+
+        let db: BlockDatabase = patterns!(["foo", "bar"]).build().unwrap();
+
+        assert_matches!(&db, b"bar".as_ref(), [(2, 3)]);
+
+        assert!(scan_matches("(foo", "foo").is_err());
+        assert_eq!(scan_matches(&db, "foo").unwrap(), vec![Match::new(1, 0, 3, 0)]);
+    }
+
+    #[test]
+    fn test_chunkings() {
+        let mut chunkings = Chunkings::new(10, 7);
+
+        assert_eq!(chunkings.next(), Some(vec![10]));
+        assert_eq!(chunkings.next(), Some(vec![1; 10]));
+
+        for chunks in chunkings.take(100) {
+            assert_eq!(chunks.iter().sum::<usize>(), 10);
+            assert!(chunks.iter().all(|&len| len > 0));
+        }
+
+        assert_eq!(Chunkings::new(0, 0).next(), Some(vec![]));
+        assert_eq!(Chunkings::new(10, 7).nth(5), Chunkings::new(10, 7).nth(5));
+    }
+
+    #[test]
+    fn test_assert_chunkings() {
+        let _ = env_logger::init();
+
+        let db: StreamingDatabase = patterns!(["foo.*bar", "\\d{3}", "baz$", "^qux"]).build().unwrap();
+        let corpus = b"qux foo 12345 bar foobar baz";
+
+        assert_eq!(stream_matches(&db, vec!["qux foo 12", "345 bar foobar baz"])
+                       .unwrap()
+                       .iter()
+                       .map(|m| (m.id, m.to))
+                       .collect::<Vec<_>>(),
+                   vec![(4, 3), (2, 11), (2, 12), (2, 13), (1, 17), (1, 24), (3, 28)]);
+
+        assert_chunkings(&db, corpus, 50, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_assert_matches_failed() {
+        assert_matches!("foo", "foo foo", [(0, 3)]);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::cell::RefCell;
+
+use api::*;
+use errors::Error;
+use common::VectoredDatabase;
+
+/// A vectored scanner over a block-only scanner, scanning the slices one by one
+/// and shifting the match offsets by the length of the preceding slices.
+///
+/// This lets engines which only expose block scanning, like Chimera, be used where
+/// a `VectoredScanner` is expected. Unlike a vectored database, a match spanning
+/// several slices isn't reported, and the patterns anchored at the start of data
+/// match at the start of every slice.
+pub struct BlockVectored<'a, B: 'a>(pub &'a B);
+
+impl<'a, B> fmt::Debug for BlockVectored<'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BlockVectored{{scanner: {:p}}}", self.0)
+    }
+}
+
+/// A match event callback and its context, with the offsets shifted.
+struct Shifted<'a, D: 'a> {
+    offset: u64,
+    callback: MatchEventCallback<D>,
+    context: &'a D,
+}
+
+fn on_shifted_event<D>(id: u32, from: u64, to: u64, flags: u32, shifted: &Shifted<D>) -> u32 {
+    (shifted.callback)(id, shifted.offset + from, shifted.offset + to, flags, shifted.context)
+}
+
+impl<'a, B, T, S> VectoredScanner<T, S> for BlockVectored<'a, B>
+    where B: for<'b> BlockScanner<&'b [u8], S>,
+          T: Scannable,
+          S: Scratch
+{
+    fn scan<D>(&self,
+               data: &[T],
+               flags: ScanFlags,
+               scratch: &S,
+               callback: Option<MatchEventCallback<D>>,
+               context: Option<&D>)
+               -> Result<&Self, Error> {
+        let mut offset = 0;
+
+        for d in data {
+            let bytes = d.as_bytes();
+
+            match (callback, context) {
+                (Some(callback), Some(context)) => {
+                    let shifted = Shifted {
+                        offset: offset,
+                        callback: callback,
+                        context: context,
+                    };
+
+                    try!(self.0.scan(bytes, flags, scratch, Some(on_shifted_event), Some(&shifted)));
+                }
+                _ => {
+                    try!(self.0.scan(bytes, flags, scratch, None, None::<&()>));
+                }
+            }
+
+            offset += bytes.len() as u64;
+        }
+
+        Ok(self)
+    }
+}
+
+/// The section of a message scanned with `scan_header_payload`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Section {
+    Headers,
+    Payload,
+}
+
+impl Section {
+    /// The section containing the byte before the offset, with the headers of `headers_len` bytes.
+    pub fn of(offset: u64, headers_len: usize) -> Section {
+        if offset <= headers_len as u64 {
+            Section::Headers
+        } else {
+            Section::Payload
+        }
+    }
+}
+
+fn on_section_event<H>(id: u32, from: u64, to: u64, flags: u32, context: &RefCell<(&mut H, usize)>) -> u32
+    where H: FnMut(Section, Match) -> u32
+{
+    let (ref mut handler, headers_len) = *context.borrow_mut();
+
+    handler(Section::of(to, headers_len), Match::new(id, from, to, flags))
+}
+
+/// Scan the headers and the payload of a message as one vectored call, so the patterns may span the boundary,
+/// passing the matches to the handler with the section containing their last byte.
+///
+/// The match offsets are in the concatenation of the headers and the payload, a match spanning the boundary
+/// ends in the payload and starts before `headers.len()`, the start is only reported with `HS_FLAG_SOM_LEFTMOST`.
+pub fn scan_header_payload<S, H>(db: &VectoredDatabase,
+                                 headers: &[u8],
+                                 payload: &[u8],
+                                 scratch: &S,
+                                 mut handler: H)
+                                 -> Result<(), Error>
+    where S: Scratch,
+          H: FnMut(Section, Match) -> u32
+{
+    let context = RefCell::new((&mut handler, headers.len()));
+
+    try!(db.scan(&[headers, payload], 0, scratch, Some(on_section_event::<H>), Some(&context)));
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+
+    use super::super::*;
+
+    #[test]
+    fn test_block_vectored() {
+        let _ = env_logger::init();
+
+        let db: BlockDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        fn callback(_: u32, from: u64, to: u64, _: u32, matches: &RefCell<Vec<(u64, u64)>>) -> u32 {
+            matches.borrow_mut().push((from, to));
+
+            0
+        }
+
+        let matches = RefCell::new(Vec::new());
+
+        BlockVectored(&db)
+            .scan(&["foo test", "bar", "test te", "st"], 0, &s, Some(callback), Some(&matches))
+            .unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(4, 8), (11, 15)]);
+
+        fn terminate(_: u32, _: u64, _: u64, _: u32, _: &()) -> u32 {
+            1
+        }
+
+        assert_eq!(BlockVectored(&db).scan(&["test"], 0, &s, Some(terminate), Some(&())).err(),
+                   Some(Error::ScanTerminated));
+    }
+
+    #[test]
+    fn test_scan_header_payload() {
+        let _ = env_logger::init();
+
+        let db: VectoredDatabase = patterns!(["Host: evil", "\r\n\r\nGET", "cmd=\\w+"], flags => HS_FLAG_SOM_LEFTMOST)
+            .build()
+            .unwrap();
+        let s = db.alloc().unwrap();
+
+        let headers = b"POST / HTTP/1.1\r\nHost: evil\r\n";
+        let payload = b"\r\nGET cmd=ls";
+        let mut matches = Vec::new();
+
+        scan_header_payload(&db, headers, payload, &s, |section, m| {
+                matches.push((section, m.id, m.from, m.to));
+
+                0
+            })
+            .unwrap();
+
+        assert_eq!(matches,
+                   vec![(Section::Headers, 1, 17, 27),
+                        (Section::Payload, 2, 27, 34),
+                        (Section::Payload, 3, 35, 40),
+                        (Section::Payload, 3, 35, 41)]);
+
+        assert_eq!(scan_header_payload(&db, headers, payload, &s, |_, _| 1).err(),
+                   Some(Error::ScanTerminated));
+    }
+}